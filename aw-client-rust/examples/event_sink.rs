@@ -0,0 +1,41 @@
+//! Forwards a stream of events into a bucket through an `EventSink`
+//!
+//! Expects an aw-server to be running on 127.0.0.1:5600, run with
+//! `cargo run -p aw-client-rust --example event_sink`.
+use std::time::Duration;
+
+use aw_client_rust::{AwClient, Event, RequestError};
+use futures_util::StreamExt;
+use serde_json::{json, Map};
+
+const BUCKET: &str = "aw-client-rust-example-sink";
+
+async fn forward_events(client: &AwClient) -> Result<(), RequestError> {
+    client.create_bucket_simple(BUCKET, "example").await?;
+
+    let start = chrono::Utc::now();
+    let events = futures_util::stream::iter(0..1000).map(|i| {
+        let mut data = Map::new();
+        data.insert("n".to_string(), json!(i));
+        Ok(Event::new(
+            start + chrono::Duration::seconds(i),
+            chrono::Duration::seconds(1),
+            data,
+        ))
+    });
+    // Inserted in batches of up to 100 events, the remaining ones when the stream ends
+    let sink = client.event_sink(BUCKET, 100, Duration::from_secs(5));
+    events.forward(sink).await
+}
+
+fn main() {
+    let client = AwClient::new("127.0.0.1", 5600, "aw-client-rust-example").unwrap();
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    match runtime.block_on(forward_events(&client)) {
+        Ok(()) => println!("Inserted the events into {BUCKET}"),
+        Err(err) => eprintln!("Failed to insert the events: {err}"),
+    }
+}
@@ -1,14 +1,41 @@
+use std::cell::RefCell;
 use std::future::Future;
+use std::time::Duration;
 use std::{collections::HashMap, error::Error};
 
 use chrono::{DateTime, Utc};
 
-use aw_models::{Bucket, Event};
+use aw_models::{
+    Bucket, BucketCreationResult, BucketQuality, BucketState, BucketsExport, Capabilities,
+    DistinctValue, Event, EventId, VacuumResult,
+};
 
 use super::AwClient as AsyncAwClient;
+use super::{
+    is_unreachable, request_id, AwClientBuilder, BucketDiff, CanonicalActivity, EventCursor,
+    ExportEstimate, RequestError,
+};
+
+thread_local! {
+    /// Id sent with the requests of the current thread, see `AwClient::with_request_id`
+    static REQUEST_ID: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Number of times `AwClient::events_iter` retries fetching a page before giving up
+pub const EVENTS_ITER_RETRIES: u32 = 5;
+
+/// Whether a request may succeed if it is retried
+fn is_transient(err: &reqwest::Error) -> bool {
+    is_unreachable(err) || err.status().is_some_and(|status| status.is_server_error())
+}
 
 pub struct AwClient {
     client: AsyncAwClient,
+    // Kept for the lifetime of the client, pooled connections are only reused within the
+    // runtime which opened them
+    runtime: tokio::runtime::Runtime,
+    // For creating another async client to use from a different runtime
+    builder: AwClientBuilder,
     pub baseurl: reqwest::Url,
     pub name: String,
     pub hostname: String,
@@ -20,55 +47,151 @@ impl std::fmt::Debug for AwClient {
     }
 }
 
-fn block_on<F: Future>(f: F) -> F::Output {
-    tokio::runtime::Builder::new_current_thread()
-        .enable_all()
-        .build()
-        .expect("build shell runtime")
-        .block_on(f)
-}
-
 macro_rules! proxy_method
 {
     ($name:tt, $ret:ty, $($v:ident: $t:ty),*) => {
         pub fn $name(&self, $($v: $t),*) -> Result<$ret, reqwest::Error>
-        { block_on(self.client.$name($($v),*)) }
+        { self.block_on(self.client.$name($($v),*)) }
     };
 }
 
-impl AwClient {
-    pub fn new(host: &str, port: u16, name: &str) -> Result<AwClient, Box<dyn Error>> {
-        let async_client = AsyncAwClient::new(host, port, name)?;
+impl AwClientBuilder {
+    pub fn build_blocking(self) -> Result<AwClient, Box<dyn Error>> {
+        let builder = self.clone();
+        let async_client = self.build()?;
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
 
         Ok(AwClient {
             baseurl: async_client.baseurl.clone(),
             name: async_client.name.clone(),
             hostname: async_client.hostname.clone(),
             client: async_client,
+            runtime,
+            builder,
         })
     }
+}
+
+impl AwClient {
+    pub fn new(host: &str, port: u16, name: &str) -> Result<AwClient, Box<dyn Error>> {
+        AwClientBuilder::new(host, port, name).build_blocking()
+    }
+
+    pub fn builder(host: &str, port: u16, name: &str) -> AwClientBuilder {
+        AwClientBuilder::new(host, port, name)
+    }
+
+    fn block_on<F: Future>(&self, f: F) -> F::Output {
+        match REQUEST_ID.with(|id| id.borrow().clone()) {
+            Some(request_id) => self.runtime.block_on(request_id::scope(request_id, f)),
+            None => self.runtime.block_on(f),
+        }
+    }
+
+    /// Calls `f` with `request_id` sent in the `X-Request-Id` header of the requests made on this
+    /// thread meanwhile, see the async `AwClient::with_request_id`
+    ///
+    /// # Example
+    /// ```ignore
+    /// client.with_request_id("watcher-42", |client| client.heartbeat(bucket, &event, 5.0))?;
+    /// ```
+    pub fn with_request_id<T>(&self, request_id: &str, f: impl FnOnce(&Self) -> T) -> T {
+        let outer = REQUEST_ID.with(|id| id.replace(Some(request_id.to_string())));
+        let result = f(self);
+        REQUEST_ID.with(|id| *id.borrow_mut() = outer);
+        result
+    }
+
+    pub fn queue_len(&self) -> usize {
+        self.block_on(self.client.queue_len())
+    }
+
+    pub fn copy_bucket_to<F>(
+        &self,
+        bucketname: &str,
+        dest: &AwClient,
+        progress: F,
+    ) -> Result<u64, Box<dyn Error>>
+    where
+        F: FnMut(u64),
+    {
+        // The pooled connections of `dest` belong to its own runtime, which isn't running here
+        let dest_client = dest.builder.clone().build()?;
+        let copied = self.block_on(self.client.copy_bucket_to(
+            bucketname,
+            &dest_client,
+            progress,
+        ))?;
+        Ok(copied)
+    }
 
     proxy_method!(get_bucket, Bucket, bucketname: &str);
     proxy_method!(get_buckets, HashMap<String, Bucket>,);
+    proxy_method!(get_bucket_states, HashMap<String, BucketState>,);
     proxy_method!(create_bucket, (), bucket: &Bucket);
     proxy_method!(create_bucket_simple, (), bucketname: &str, buckettype: &str);
+    proxy_method!(
+        create_bucket_with_data,
+        (),
+        bucketname: &str,
+        buckettype: &str,
+        data: serde_json::Map<String, serde_json::Value>
+    );
+    proxy_method!(
+        ensure_bucket_versioned,
+        (),
+        bucketname: &str,
+        buckettype: &str,
+        version: u64
+    );
+    proxy_method!(
+        get_bucket_with_etag,
+        (Bucket, Option<String>),
+        bucketname: &str
+    );
+    proxy_method!(
+        create_buckets,
+        HashMap<String, BucketCreationResult>,
+        buckets: &[Bucket],
+        strict: bool
+    );
     proxy_method!(delete_bucket, (), bucketname: &str);
+    proxy_method!(clone_bucket, (), bucketname: &str, new_id: &str);
+    proxy_method!(import, (), export: &BucketsExport, preserve_ids: bool);
     proxy_method!(
         get_events,
-        Vec<Event>,
+        Option<Vec<Event>>,
         bucketname: &str,
         start: Option<DateTime<Utc>>,
         stop: Option<DateTime<Utc>>,
+        limit: Option<u64>,
+        ascending: Option<bool>,
+        since: Option<DateTime<Utc>>
+    );
+    proxy_method!(
+        get_events_after_id,
+        Vec<Event>,
+        bucketname: &str,
+        after_id: i64,
         limit: Option<u64>
     );
+    proxy_method!(
+        get_events_fields,
+        Vec<serde_json::Map<String, serde_json::Value>>,
+        bucketname: &str,
+        start: Option<DateTime<Utc>>,
+        stop: Option<DateTime<Utc>>,
+        limit: Option<u64>,
+        fields: &[&str]
+    );
     proxy_method!(
         query,
         Vec<serde_json::Value>,
         query: &str,
         timeperiods: Vec<(DateTime<Utc>, DateTime<Utc>)>
     );
-    proxy_method!(insert_event, (), bucketname: &str, event: &Event);
-    proxy_method!(insert_events, (), bucketname: &str, events: Vec<Event>);
     proxy_method!(
         heartbeat,
         (),
@@ -76,7 +199,191 @@ impl AwClient {
         event: &Event,
         pulsetime: f64
     );
-    proxy_method!(delete_event, (), bucketname: &str, event_id: i64);
+    proxy_method!(
+        patch_event,
+        Event,
+        bucketname: &str,
+        event_id: &EventId,
+        patch: &serde_json::Map<String, serde_json::Value>
+    );
+    proxy_method!(delete_event, (), bucketname: &str, event_id: &EventId);
     proxy_method!(get_event_count, i64, bucketname: &str);
+    proxy_method!(flush_queue, usize,);
+    proxy_method!(
+        get_distinct_values,
+        Vec<DistinctValue>,
+        bucketname: &str,
+        key: &str,
+        start: Option<DateTime<Utc>>,
+        stop: Option<DateTime<Utc>>
+    );
+    proxy_method!(
+        get_bucket_quality,
+        BucketQuality,
+        bucketname: &str,
+        start: Option<DateTime<Utc>>,
+        stop: Option<DateTime<Utc>>,
+        min_gap: Option<Duration>
+    );
+    proxy_method!(head_event_count, i64, bucketname: &str);
+    proxy_method!(get_first_event, Option<Event>, bucketname: &str);
+    proxy_method!(get_last_event, Option<Event>, bucketname: &str);
+    proxy_method!(vacuum, VacuumResult,);
     proxy_method!(get_info, aw_models::Info,);
+
+    pub fn patch_bucket(
+        &self,
+        bucketname: &str,
+        patch: &serde_json::Map<String, serde_json::Value>,
+        expected_etag: Option<&str>,
+    ) -> Result<Bucket, RequestError> {
+        self.block_on(self.client.patch_bucket(bucketname, patch, expected_etag))
+    }
+
+    pub fn insert_event(
+        &self,
+        bucketname: &str,
+        event: &Event,
+    ) -> Result<Option<EventId>, RequestError> {
+        self.block_on(self.client.insert_event(bucketname, event))
+    }
+
+    pub fn insert_events(
+        &self,
+        bucketname: &str,
+        events: Vec<Event>,
+    ) -> Result<Vec<EventId>, RequestError> {
+        self.block_on(self.client.insert_events(bucketname, events))
+    }
+
+    pub fn wait_for_event(
+        &self,
+        bucketname: &str,
+        after: DateTime<Utc>,
+        timeout: Duration,
+    ) -> Result<Option<Event>, RequestError> {
+        self.block_on(self.client.wait_for_event(bucketname, after, timeout))
+    }
+
+    pub fn estimate_export_size(&self) -> Result<ExportEstimate, RequestError> {
+        self.block_on(self.client.estimate_export_size())
+    }
+
+    pub fn capabilities(&self) -> Result<Capabilities, RequestError> {
+        self.block_on(self.client.capabilities())
+    }
+
+    pub fn wait_until_ready(&self, timeout: Duration) -> Result<(), RequestError> {
+        self.block_on(self.client.wait_until_ready(timeout))
+    }
+
+    pub fn stream_insert_events<I>(
+        &self,
+        bucketname: &str,
+        events: I,
+    ) -> Result<u64, reqwest::Error>
+    where
+        I: IntoIterator<Item = Event>,
+        I::IntoIter: Send + Sync + 'static,
+    {
+        self.block_on(self.client.stream_insert_events(bucketname, events))
+    }
+
+    pub fn get_canonical_activity(
+        &self,
+        window_bucket: &str,
+        afk_bucket: &str,
+        categories: &[(Vec<String>, serde_json::Value)],
+        start: DateTime<Utc>,
+        stop: DateTime<Utc>,
+    ) -> Result<CanonicalActivity, RequestError> {
+        self.block_on(self.client.get_canonical_activity(
+            window_bucket,
+            afk_bucket,
+            categories,
+            start,
+            stop,
+        ))
+    }
+
+    /// Iterates over the events of a bucket from `start` to `stop`, oldest first, fetching them
+    /// in pages of `page_size` events as the iterator advances
+    ///
+    /// Only one page is held in memory at a time, so a bucket of any size can be processed. A
+    /// page which fails to be fetched because the server is unreachable or has an internal error
+    /// is retried up to `EVENTS_ITER_RETRIES` times with an exponential backoff. Other errors,
+    /// and errors which remain after the retries, are yielded as an `Err` item which ends the
+    /// iteration.
+    pub fn events_iter<'a>(
+        &'a self,
+        bucketname: &'a str,
+        start: Option<DateTime<Utc>>,
+        stop: Option<DateTime<Utc>>,
+        page_size: u64,
+    ) -> impl Iterator<Item = Result<Event, RequestError>> + 'a {
+        let page_size = page_size.max(1);
+        let mut cursor = EventCursor::new(start);
+        let mut limit = page_size;
+        let mut page = std::collections::VecDeque::new();
+        let mut done = false;
+        std::iter::from_fn(move || loop {
+            if let Some(event) = page.pop_front() {
+                return Some(Ok(event));
+            }
+            if done {
+                return None;
+            }
+            let events = match self.get_events_page(bucketname, cursor.timestamp, stop, limit) {
+                Ok(events) => events,
+                Err(err) => {
+                    done = true;
+                    return Some(Err(err.into()));
+                }
+            };
+            let page_was_full = events.len() as u64 == limit;
+            page.extend(cursor.advance(events));
+            if page.is_empty() && page_was_full {
+                // The whole page was taken up by events which were already yielded, such as
+                // many events starting at the same time, so fetch a larger one
+                limit *= 2;
+                continue;
+            }
+            limit = page_size;
+            done = !page_was_full;
+        })
+    }
+
+    fn get_events_page(
+        &self,
+        bucketname: &str,
+        start: Option<DateTime<Utc>>,
+        stop: Option<DateTime<Utc>>,
+        limit: u64,
+    ) -> Result<Vec<Event>, reqwest::Error> {
+        let mut backoff = Duration::from_millis(100);
+        let mut retries = 0;
+        loop {
+            let res = self.get_events(bucketname, start, stop, Some(limit), Some(true), None);
+            match res {
+                Ok(events) => return Ok(events.unwrap_or_default()),
+                Err(err) if retries < EVENTS_ITER_RETRIES && is_transient(&err) => {
+                    warn!("Fetching events failed, retrying in {:?}: {}", backoff, err);
+                    std::thread::sleep(backoff);
+                    backoff = (backoff * 2).min(Duration::from_secs(10));
+                    retries += 1;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    pub fn diff_buckets(
+        &self,
+        src: &str,
+        dst: &str,
+        start: Option<DateTime<Utc>>,
+        stop: Option<DateTime<Utc>>,
+    ) -> Result<BucketDiff, RequestError> {
+        self.block_on(self.client.diff_buckets(src, dst, start, stop))
+    }
 }
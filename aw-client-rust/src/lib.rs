@@ -4,21 +4,234 @@ extern crate gethostname;
 extern crate reqwest;
 extern crate serde_json;
 extern crate tokio;
+#[macro_use]
+extern crate log;
 
 pub mod blocking;
+mod queue;
+mod request_id;
+mod sink;
 
-use std::{collections::HashMap, error::Error};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::future::Future;
+use std::path::PathBuf;
+use std::{error::Error, fmt, time::Duration};
 
 use chrono::{DateTime, Utc};
+use futures_util::future::Either;
+use futures_util::{Stream, StreamExt};
 use serde_json::{json, Map};
+use tokio::io::{AsyncWrite, AsyncWriteExt};
 
-pub use aw_models::{Bucket, BucketMetadata, Event};
+pub use aw_models::{
+    Bucket, BucketCreationResult, BucketMetadata, BucketQuality, BucketState, BucketsExport,
+    Capabilities, DistinctValue, Event, EventId, QualityGaps, QualityIssue, QueryStreamFrame,
+    VacuumResult, SCHEMA_VERSION_KEY,
+};
+pub use reqwest::Certificate;
+
+pub use queue::DEFAULT_QUEUE_MAX_LEN;
+use queue::{OfflineQueue, QueuedRequest};
+use request_id::HttpClient;
+pub use request_id::REQUEST_ID_HEADER;
+pub use sink::EventSink;
+
+#[derive(Debug)]
+pub enum RequestError {
+    Request(reqwest::Error),
+    /// The server did not become ready within the given time
+    Timeout(Duration),
+    /// The server responded with something else than expected
+    InvalidResponse(String),
+    /// Writing the response failed
+    Io(std::io::Error),
+    /// The query failed on the server, holds the message of the server
+    Query(String),
+    /// A conditional update was refused because the resource changed since it was read, holds
+    /// the message of the server
+    PreconditionFailed(String),
+}
+
+impl fmt::Display for RequestError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RequestError::Request(err) => write!(f, "{err}"),
+            RequestError::Timeout(timeout) => {
+                write!(f, "Server was not ready after {timeout:?}")
+            }
+            RequestError::InvalidResponse(msg) => write!(f, "Invalid response: {msg}"),
+            RequestError::Io(err) => write!(f, "Failed to write response: {err}"),
+            RequestError::Query(msg) => write!(f, "Query failed: {msg}"),
+            RequestError::PreconditionFailed(msg) => write!(f, "Precondition failed: {msg}"),
+        }
+    }
+}
+
+impl Error for RequestError {}
+
+impl From<reqwest::Error> for RequestError {
+    fn from(err: reqwest::Error) -> Self {
+        RequestError::Request(err)
+    }
+}
+
+impl From<std::io::Error> for RequestError {
+    fn from(err: std::io::Error) -> Self {
+        RequestError::Io(err)
+    }
+}
+
+/// Position when paging through the events of a bucket from oldest to newest
+///
+/// Pages are fetched with `start` at the newest timestamp seen so far, so that events starting
+/// at the same time as the last one of a page are not missed. A page then also contains events
+/// which were already seen, those starting at the cursor and those overlapping it, which the
+/// server clips to start at the cursor. These are recognized by their ids.
+struct EventCursor {
+    timestamp: Option<DateTime<Utc>>,
+    /// Ids of the seen events which end at or after `timestamp`
+    seen: HashSet<EventId>,
+}
+
+impl EventCursor {
+    fn new(timestamp: Option<DateTime<Utc>>) -> EventCursor {
+        EventCursor {
+            timestamp,
+            seen: HashSet::new(),
+        }
+    }
+
+    /// Moves the cursor past `events`, a page in ascending order, returning the unseen ones
+    fn advance(&mut self, events: Vec<Event>) -> Vec<Event> {
+        let newest = events.iter().map(|e| e.timestamp).max();
+        let timestamp = self.timestamp.max(newest);
+
+        let mut seen = HashSet::new();
+        let mut unseen = Vec::new();
+        for event in events {
+            let is_new = match &event.id {
+                Some(id) => !self.seen.contains(id),
+                None => true,
+            };
+            if let (Some(id), Some(timestamp)) = (&event.id, timestamp) {
+                if event.calculate_endtime() >= timestamp {
+                    seen.insert(id.clone());
+                }
+            }
+            if is_new {
+                unseen.push(event);
+            }
+        }
+        self.timestamp = timestamp;
+        self.seen = seen;
+        unseen
+    }
+}
+
+/// Number of events fetched and inserted at a time by `AwClient::copy_bucket_to`
+pub const COPY_PAGE_SIZE: u64 = 1000;
+
+/// Whether a request failed because the server could not be reached, rather than being refused
+fn is_unreachable(err: &reqwest::Error) -> bool {
+    err.is_connect() || err.is_timeout()
+}
+
+/// Difference between the events of two buckets, see `AwClient::diff_buckets`
+#[derive(Debug, Default)]
+pub struct BucketDiff {
+    /// Events in the source bucket with no event at the same timestamp in the destination
+    pub only_in_src: Vec<Event>,
+    /// Events in the destination bucket with no event at the same timestamp in the source
+    pub only_in_dst: Vec<Event>,
+    /// Pairs of (src, dst) events with the same timestamp but different duration or data
+    pub differing: Vec<(Event, Event)>,
+    /// Number of events which are identical in both buckets
+    pub identical: usize,
+}
+
+/// Longest time between the polls of `AwClient::wait_for_event`
+pub const WAIT_FOR_EVENT_MAX_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Estimated size in bytes of an event in an export, see `ExportEstimate`
+///
+/// Based on typical window watcher events: the timestamp, duration and id take about 70 bytes
+/// and an app and window title about 80 more.
+pub const EXPORT_EVENT_SIZE_ESTIMATE: u64 = 150;
+
+/// Estimated size in bytes of the metadata of a bucket in an export, see `ExportEstimate`
+pub const EXPORT_BUCKET_SIZE_ESTIMATE: u64 = 300;
+
+/// Rough size of an export of all buckets, see `AwClient::estimate_export_size`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExportEstimate {
+    pub buckets: u64,
+    pub events: u64,
+    /// Approximate size of the export as JSON, assuming `EXPORT_EVENT_SIZE_ESTIMATE` bytes per
+    /// event and `EXPORT_BUCKET_SIZE_ESTIMATE` bytes per bucket. Events with long titles or a lot
+    /// of data make the export larger than this.
+    pub bytes: u64,
+}
+
+/// The query behind the standard ActivityWatch activity report
+///
+/// Window events are limited to the time the user was not AFK and categorized, the placeholders
+/// `{window_bucket}`, `{afk_bucket}` and `{categories}` have to be replaced with query literals.
+pub const CANONICAL_ACTIVITY_QUERY: &str = r#"window_events = flood(query_bucket({window_bucket}));
+not_afk = flood(query_bucket({afk_bucket}));
+not_afk = filter_keyvals(not_afk, "status", ["not-afk"]);
+events = filter_period_intersect(window_events, not_afk);
+events = categorize(events, {categories});
+cat_events = sort_by_duration(merge_events_by_keys(events, ["$category"]));
+RETURN = {"events": events, "cat_events": cat_events, "duration": sum_durations(events)};"#;
+
+/// Time spent in a category, see `CanonicalActivity`
+#[derive(Debug, Clone, PartialEq)]
+pub struct CategoryDuration {
+    pub category: Vec<String>,
+    pub duration: chrono::Duration,
+}
+
+/// Result of `AwClient::get_canonical_activity`
+#[derive(Debug, Clone)]
+pub struct CanonicalActivity {
+    /// Window events while not AFK, with their category in the `$category` data key
+    pub events: Vec<Event>,
+    /// Time spent per category, longest first
+    pub categories: Vec<CategoryDuration>,
+    /// Total time not AFK
+    pub duration: chrono::Duration,
+}
+
+/// Formats a JSON value as a literal in the query language, which unlike JSON only escapes quotes
+fn to_query_literal(value: &serde_json::Value) -> String {
+    use serde_json::Value;
+    match value {
+        Value::Null => "null".to_string(),
+        Value::Bool(b) => b.to_string(),
+        Value::Number(n) => n.to_string(),
+        Value::String(s) => format!("\"{}\"", s.replace('"', "\\\"")),
+        Value::Array(list) => {
+            let items: Vec<String> = list.iter().map(to_query_literal).collect();
+            format!("[{}]", items.join(", "))
+        }
+        Value::Object(map) => {
+            let items: Vec<String> = map
+                .iter()
+                .map(|(k, v)| format!("\"{}\": {}", k.replace('"', "\\\""), to_query_literal(v)))
+                .collect();
+            format!("{{{}}}", items.join(", "))
+        }
+    }
+}
 
 pub struct AwClient {
-    client: reqwest::Client,
+    client: HttpClient,
     pub baseurl: reqwest::Url,
     pub name: String,
     pub hostname: String,
+    // Held while the queue is flushed, so that requests sent meanwhile are kept in order
+    queue: Option<tokio::sync::Mutex<OfflineQueue>>,
+    admin_token: Option<String>,
 }
 
 impl std::fmt::Debug for AwClient {
@@ -31,21 +244,209 @@ fn get_hostname() -> String {
     return gethostname::gethostname().to_string_lossy().to_string();
 }
 
-impl AwClient {
-    pub fn new(host: &str, port: u16, name: &str) -> Result<AwClient, Box<dyn Error>> {
-        let baseurl = reqwest::Url::parse(&format!("http://{}:{}", host, port))?;
+/// Idle pooled connections are kept for this long by default, long enough for watchers which
+/// send a heartbeat every few seconds to keep reusing the same connection
+pub const DEFAULT_POOL_IDLE_TIMEOUT: Duration = Duration::from_secs(90);
+
+/// Default interval of TCP keepalive probes on idle connections
+pub const DEFAULT_TCP_KEEPALIVE: Duration = Duration::from_secs(60);
+
+/// Builder for an `AwClient` with custom connection settings
+///
+/// # Example
+/// ```ignore
+/// let client = AwClient::builder("127.0.0.1", 5600, "aw-watcher-example")
+///     .pool_idle_timeout(Duration::from_secs(300))
+///     .build()?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct AwClientBuilder {
+    host: String,
+    port: u16,
+    name: String,
+    timeout: Duration,
+    pool_idle_timeout: Option<Duration>,
+    tcp_keepalive: Option<Duration>,
+    https: bool,
+    accept_invalid_certs: bool,
+    root_certificates: Vec<Certificate>,
+    offline_queue: Option<PathBuf>,
+    offline_queue_max_len: usize,
+    request_ids: bool,
+    admin_token: Option<String>,
+}
+
+impl AwClientBuilder {
+    pub fn new(host: &str, port: u16, name: &str) -> AwClientBuilder {
+        AwClientBuilder {
+            host: host.to_string(),
+            port,
+            name: name.to_string(),
+            timeout: Duration::from_secs(120),
+            pool_idle_timeout: Some(DEFAULT_POOL_IDLE_TIMEOUT),
+            tcp_keepalive: Some(DEFAULT_TCP_KEEPALIVE),
+            https: false,
+            accept_invalid_certs: false,
+            root_certificates: Vec::new(),
+            offline_queue: None,
+            offline_queue_max_len: DEFAULT_QUEUE_MAX_LEN,
+            request_ids: false,
+            admin_token: None,
+        }
+    }
+
+    /// Timeout of a whole request, from connecting until the response body has been read
+    pub fn timeout(mut self, timeout: Duration) -> AwClientBuilder {
+        self.timeout = timeout;
+        self
+    }
+
+    /// How long idle connections are kept in the pool for reuse, `None` keeps them forever
+    pub fn pool_idle_timeout<D>(mut self, timeout: D) -> AwClientBuilder
+    where
+        D: Into<Option<Duration>>,
+    {
+        self.pool_idle_timeout = timeout.into();
+        self
+    }
+
+    /// Interval of TCP keepalive probes on idle connections, `None` disables them
+    pub fn tcp_keepalive<D>(mut self, interval: D) -> AwClientBuilder
+    where
+        D: Into<Option<Duration>>,
+    {
+        self.tcp_keepalive = interval.into();
+        self
+    }
+
+    /// Connects over HTTPS, for a server behind a reverse proxy which terminates TLS
+    pub fn https(mut self, enabled: bool) -> AwClientBuilder {
+        self.https = enabled;
+        self
+    }
+
+    /// Trusts `cert` as a root certificate in addition to the system ones, the safe way to
+    /// connect to a server with a self-signed certificate
+    ///
+    /// # Example
+    /// ```ignore
+    /// let cert = Certificate::from_pem(&std::fs::read("my-ca.pem")?)?;
+    /// let client = AwClient::builder("aw.home.lan", 443, "aw-watcher-example")
+    ///     .https(true)
+    ///     .add_root_certificate(cert)
+    ///     .build()?;
+    /// ```
+    pub fn add_root_certificate(mut self, cert: Certificate) -> AwClientBuilder {
+        self.root_certificates.push(cert);
+        self
+    }
+
+    /// Disables all verification of the server's TLS certificate, verification is on by default
+    ///
+    /// # Warning
+    ///
+    /// With this enabled any certificate is accepted, including expired ones and ones for another
+    /// host, so anyone on the network path can impersonate the server and read or alter all of
+    /// the data sent to it. Prefer `add_root_certificate` with the certificate of the server, and
+    /// only use this for testing or on networks where this is acceptable.
+    pub fn danger_accept_invalid_certs(mut self, accept: bool) -> AwClientBuilder {
+        self.accept_invalid_certs = accept;
+        self
+    }
+
+    /// Keeps events in a queue file at `path` while the server can't be reached
+    ///
+    /// When inserting events or sending a heartbeat fails because the server could not be
+    /// connected to or did not answer in time, the request is added to the queue and the call
+    /// succeeds. Queued requests are replayed in order before the next request is sent, or with
+    /// `AwClient::flush_queue`. Inserted events which already exist on the server, as the
+    /// request may have arrived after all, are skipped then.
+    ///
+    /// The queue is a file of JSON lines with one request per line, either
+    /// `{"type": "insert", "bucket": ..., "event": ...}` or
+    /// `{"type": "heartbeat", "bucket": ..., "pulsetime": ..., "event": ...}`.
+    pub fn offline_queue<P: Into<PathBuf>>(mut self, path: P) -> AwClientBuilder {
+        self.offline_queue = Some(path.into());
+        self
+    }
+
+    /// Maximum number of requests in the offline queue, once it is full the oldest ones are
+    /// dropped. Defaults to `DEFAULT_QUEUE_MAX_LEN`.
+    pub fn offline_queue_max_len(mut self, max_len: usize) -> AwClientBuilder {
+        self.offline_queue_max_len = max_len;
+        self
+    }
+
+    /// Sends every request with a new UUID in the `X-Request-Id` header, which the server logs
+    /// with the request, for tracing requests through the server logs. Off by default.
+    ///
+    /// A request can be given an id of its own with `AwClient::with_request_id` either way.
+    pub fn request_ids(mut self, enabled: bool) -> AwClientBuilder {
+        self.request_ids = enabled;
+        self
+    }
+
+    /// Token for the admin endpoints such as `AwClient::vacuum`, the `admin_token` in the config
+    /// of the server
+    pub fn admin_token(mut self, token: &str) -> AwClientBuilder {
+        self.admin_token = Some(token.to_string());
+        self
+    }
+
+    pub fn build(self) -> Result<AwClient, Box<dyn Error>> {
+        let scheme = if self.https { "https" } else { "http" };
+        let baseurl = reqwest::Url::parse(&format!("{}://{}:{}", scheme, self.host, self.port))?;
         let hostname = get_hostname();
-        let client = reqwest::Client::builder()
-            .timeout(std::time::Duration::from_secs(120))
-            .build()?;
+        let mut client = reqwest::Client::builder()
+            .timeout(self.timeout)
+            .pool_idle_timeout(self.pool_idle_timeout)
+            .tcp_keepalive(self.tcp_keepalive)
+            .danger_accept_invalid_certs(self.accept_invalid_certs);
+        for cert in self.root_certificates {
+            client = client.add_root_certificate(cert);
+        }
+        let client = HttpClient::new(client.build()?, self.request_ids);
+
+        let max_len = self.offline_queue_max_len;
+        let queue = self
+            .offline_queue
+            .map(|path| tokio::sync::Mutex::new(OfflineQueue::new(path, max_len)));
 
         Ok(AwClient {
             client,
             baseurl,
-            name: name.to_string(),
+            name: self.name,
             hostname,
+            queue,
+            admin_token: self.admin_token,
         })
     }
+}
+
+impl AwClient {
+    pub fn new(host: &str, port: u16, name: &str) -> Result<AwClient, Box<dyn Error>> {
+        AwClientBuilder::new(host, port, name).build()
+    }
+
+    pub fn builder(host: &str, port: u16, name: &str) -> AwClientBuilder {
+        AwClientBuilder::new(host, port, name)
+    }
+
+    /// Runs `request` with `request_id` sent in the `X-Request-Id` header of the requests it
+    /// makes, whether or not `AwClientBuilder::request_ids` is on
+    ///
+    /// Only the requests `request` sends itself get the id, not the ones of streams it returns
+    /// which are polled afterwards.
+    ///
+    /// # Example
+    /// ```ignore
+    /// client
+    ///     .with_request_id("watcher-42", client.heartbeat(bucket, &event, 5.0))
+    ///     .await?;
+    /// ```
+    pub async fn with_request_id<F: Future>(&self, request_id: &str, request: F) -> F::Output {
+        request_id::scope(request_id.to_string(), request).await
+    }
 
     pub async fn get_bucket(&self, bucketname: &str) -> Result<Bucket, reqwest::Error> {
         let url = format!("{}/api/0/buckets/{}", self.baseurl, bucketname);
@@ -60,11 +461,41 @@ impl AwClient {
         Ok(bucket)
     }
 
+    /// Get a bucket together with the ETag of its metadata, for a conditional `patch_bucket`
+    ///
+    /// The ETag is `None` if the server doesn't send one.
+    pub async fn get_bucket_with_etag(
+        &self,
+        bucketname: &str,
+    ) -> Result<(Bucket, Option<String>), reqwest::Error> {
+        let url = format!("{}/api/0/buckets/{}", self.baseurl, bucketname);
+        let response = self.client.get(url).send().await?.error_for_status()?;
+        let etag = response
+            .headers()
+            .get(reqwest::header::ETAG)
+            .and_then(|etag| etag.to_str().ok())
+            .map(|etag| etag.to_string());
+        Ok((response.json().await?, etag))
+    }
+
     pub async fn get_buckets(&self) -> Result<HashMap<String, Bucket>, reqwest::Error> {
         let url = format!("{}/api/0/buckets/", self.baseurl);
         self.client.get(url).send().await?.json().await
     }
 
+    /// Last update time and event count of every bucket, to find which buckets changed since
+    /// they were last synced without fetching their events
+    pub async fn get_bucket_states(&self) -> Result<HashMap<String, BucketState>, reqwest::Error> {
+        let url = format!("{}/api/0/bucket-states", self.baseurl);
+        self.client
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+    }
+
     pub async fn create_bucket(&self, bucket: &Bucket) -> Result<(), reqwest::Error> {
         let url = format!("{}/api/0/buckets/{}", self.baseurl, bucket.id);
         self.client.post(url).json(bucket).send().await?;
@@ -75,6 +506,19 @@ impl AwClient {
         &self,
         bucketname: &str,
         buckettype: &str,
+    ) -> Result<(), reqwest::Error> {
+        self.create_bucket_with_data(bucketname, buckettype, Map::default())
+            .await
+    }
+
+    /// Same as `create_bucket_simple`, but with custom bucket data such as a display name
+    ///
+    /// The bucket metadata (start/end) is always computed by the server from the events.
+    pub async fn create_bucket_with_data(
+        &self,
+        bucketname: &str,
+        buckettype: &str,
+        data: Map<String, serde_json::Value>,
     ) -> Result<(), reqwest::Error> {
         let bucket = Bucket {
             bid: None,
@@ -82,7 +526,7 @@ impl AwClient {
             client: self.name.clone(),
             _type: buckettype.to_string(),
             hostname: self.hostname.clone(),
-            data: Map::default(),
+            data,
             metadata: BucketMetadata::default(),
             events: None,
             created: None,
@@ -91,12 +535,184 @@ impl AwClient {
         self.create_bucket(&bucket).await
     }
 
+    /// Makes sure a bucket exists with at least the given schema version in its data
+    ///
+    /// Creates the bucket if it is missing. If it exists without a version or with an older one,
+    /// the version stored under `SCHEMA_VERSION_KEY` is updated while the events and the rest of
+    /// the data are left as they are. A newer version is never lowered.
+    pub async fn ensure_bucket_versioned(
+        &self,
+        bucketname: &str,
+        buckettype: &str,
+        version: u64,
+    ) -> Result<(), reqwest::Error> {
+        let mut data = Map::new();
+        data.insert(SCHEMA_VERSION_KEY.to_string(), json!(version));
+        match self.get_bucket(bucketname).await {
+            Ok(bucket) => {
+                if bucket.schema_version().is_none_or(|v| v < version) {
+                    self.send_bucket_patch(bucketname, &data, None)
+                        .await?
+                        .error_for_status()?;
+                }
+                Ok(())
+            }
+            Err(err) if err.status() == Some(reqwest::StatusCode::NOT_FOUND) => {
+                self.create_bucket_with_data(bucketname, buckettype, data)
+                    .await
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Updates the data of a bucket with a JSON merge patch, keys set to null are removed
+    ///
+    /// If `expected_etag` is set, from `get_bucket_with_etag`, the bucket is only updated if it
+    /// hasn't changed since, otherwise `RequestError::PreconditionFailed` is returned.
+    pub async fn patch_bucket(
+        &self,
+        bucketname: &str,
+        patch: &Map<String, serde_json::Value>,
+        expected_etag: Option<&str>,
+    ) -> Result<Bucket, RequestError> {
+        let response = self
+            .send_bucket_patch(bucketname, patch, expected_etag)
+            .await?;
+        if response.status() == reqwest::StatusCode::PRECONDITION_FAILED {
+            let body: serde_json::Value = response.json().await.unwrap_or_default();
+            let msg = body["message"].as_str().unwrap_or("bucket was changed");
+            return Err(RequestError::PreconditionFailed(msg.to_string()));
+        }
+        Ok(response.error_for_status()?.json().await?)
+    }
+
+    async fn send_bucket_patch(
+        &self,
+        bucketname: &str,
+        patch: &Map<String, serde_json::Value>,
+        expected_etag: Option<&str>,
+    ) -> Result<reqwest::Response, reqwest::Error> {
+        let url = format!("{}/api/0/buckets/{}", self.baseurl, bucketname);
+        let mut request = self.client.patch(url).json(patch);
+        if let Some(etag) = expected_etag {
+            request = request.header(reqwest::header::IF_MATCH, etag);
+        }
+        request.send().await
+    }
+
+    /// Creates several buckets at once, returning the result for each bucket by id
+    ///
+    /// Buckets which already exist are skipped, unless `strict` is set in which case no bucket is
+    /// created and the request fails with 409 Conflict.
+    pub async fn create_buckets(
+        &self,
+        buckets: &[Bucket],
+        strict: bool,
+    ) -> Result<HashMap<String, BucketCreationResult>, reqwest::Error> {
+        let url = format!("{}/api/0/buckets/?strict={}", self.baseurl, strict);
+        self.client
+            .post(url)
+            .json(buckets)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+    }
+
     pub async fn delete_bucket(&self, bucketname: &str) -> Result<(), reqwest::Error> {
         let url = format!("{}/api/0/buckets/{}", self.baseurl, bucketname);
         self.client.delete(url).send().await?;
         Ok(())
     }
 
+    /// Copies a bucket and all its events to the new bucket `new_id`, whose events get new ids
+    ///
+    /// Fails with 409 Conflict if `new_id` already exists.
+    pub async fn clone_bucket(&self, bucketname: &str, new_id: &str) -> Result<(), reqwest::Error> {
+        let url = format!("{}/api/0/buckets/{}/clone", self.baseurl, bucketname);
+        self.client
+            .post(url)
+            .query(&[("new_id", new_id)])
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    /// Imports the buckets of an export, the buckets must not exist yet
+    ///
+    /// New ids are assigned to the events unless `preserve_ids` is set. With `preserve_ids` the
+    /// ids from the export are kept, which requires that none of them is used by any event on the
+    /// server, otherwise the request fails with 409 Conflict and the bucket is not imported.
+    pub async fn import(
+        &self,
+        export: &BucketsExport,
+        preserve_ids: bool,
+    ) -> Result<(), reqwest::Error> {
+        let url = format!(
+            "{}/api/0/import?preserve_ids={}",
+            self.baseurl, preserve_ids
+        );
+        self.client
+            .post(url)
+            .json(export)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    /// Estimates how large an export of all buckets would be from the event counts of the
+    /// buckets, without downloading any events
+    ///
+    /// The byte count is only an approximation, see `ExportEstimate`.
+    pub async fn estimate_export_size(&self) -> Result<ExportEstimate, RequestError> {
+        let states = self.get_bucket_states().await?;
+        let buckets = states.len() as u64;
+        let events = states
+            .values()
+            .map(|state| state.event_count.max(0) as u64)
+            .sum::<u64>();
+        Ok(ExportEstimate {
+            buckets,
+            events,
+            bytes: buckets * EXPORT_BUCKET_SIZE_ESTIMATE + events * EXPORT_EVENT_SIZE_ESTIMATE,
+        })
+    }
+
+    /// Writes the export of a bucket to `writer` as it is received, without keeping the whole
+    /// export in memory
+    ///
+    /// The output is the JSON document returned by `/api/0/buckets/<bucket_id>/export`, a
+    /// [`BucketsExport`] containing only this bucket with all its events, which can be imported
+    /// again with [`AwClient::import`]. Returns the number of bytes written.
+    pub async fn export_bucket_to<W>(
+        &self,
+        bucketname: &str,
+        writer: &mut W,
+    ) -> Result<u64, RequestError>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        let url = format!("{}/api/0/buckets/{}/export", self.baseurl, bucketname);
+        let mut stream = self
+            .client
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .bytes_stream();
+        let mut written = 0;
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk?;
+            writer.write_all(&chunk).await?;
+            written += chunk.len() as u64;
+        }
+        writer.flush().await?;
+        Ok(written)
+    }
+
     pub async fn query(
         &self,
         query: &str,
@@ -124,13 +740,125 @@ impl AwClient {
             .await
     }
 
+    /// Like `query`, but yields the results as `(timeperiod index, item)` while the server
+    /// evaluates the timeperiods, instead of once all are done
+    ///
+    /// A timeperiod evaluating to a list, such as a list of events, yields one item per element,
+    /// anything else yields the whole result as one item. The server evaluates each timeperiod
+    /// as a whole, so the items of a timeperiod only arrive once it is done, see
+    /// `QueryStreamFrame` for the framing. A failing query yields a `RequestError::Query` and
+    /// ends the stream. Servers without the streaming endpoint respond with 404, the query is
+    /// then run with `query` and its buffered results are yielded the same way.
+    pub async fn query_streaming(
+        &self,
+        query: &str,
+        timeperiods: Vec<(DateTime<Utc>, DateTime<Utc>)>,
+    ) -> Result<
+        impl Stream<Item = Result<(usize, serde_json::Value), RequestError>> + '_,
+        RequestError,
+    > {
+        let url = format!("{}/api/0/query/stream", self.baseurl);
+        let timeperiods_str: Vec<String> = timeperiods
+            .iter()
+            .map(|(start, stop)| format!("{}/{}", start.to_rfc3339(), stop.to_rfc3339()))
+            .collect();
+        let response = self
+            .client
+            .post(url)
+            .json(&json!({
+                "query": query.split('\n').collect::<Vec<&str>>(),
+                "timeperiods": timeperiods_str,
+            }))
+            .send()
+            .await?;
+
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            let results = self.query(query, timeperiods).await?;
+            let items = results
+                .into_iter()
+                .enumerate()
+                .flat_map(|(i, result)| match result {
+                    serde_json::Value::Array(items) => {
+                        items.into_iter().map(|item| Ok((i, item))).collect()
+                    }
+                    value => vec![Ok((i, value))],
+                })
+                .collect::<Vec<_>>();
+            return Ok(Either::Right(futures_util::stream::iter(items)));
+        }
+
+        struct FrameState<S> {
+            bytes: S,
+            buffer: Vec<u8>,
+            finished: bool,
+        }
+        let state = FrameState {
+            bytes: Box::pin(response.error_for_status()?.bytes_stream()),
+            buffer: Vec::new(),
+            finished: false,
+        };
+        let items = futures_util::stream::unfold(state, |mut state| async move {
+            while !state.finished {
+                if let Some(end) = state.buffer.iter().position(|b| *b == b'\n') {
+                    let line: Vec<u8> = state.buffer.drain(..=end).collect();
+                    if line.iter().all(|b| b.is_ascii_whitespace()) {
+                        continue;
+                    }
+                    let item = match serde_json::from_slice(&line) {
+                        Ok(QueryStreamFrame::Item { timeperiod, item }) => Ok((timeperiod, item)),
+                        Ok(QueryStreamFrame::Value { timeperiod, value }) => {
+                            Ok((timeperiod, value))
+                        }
+                        Ok(QueryStreamFrame::Error { message, .. }) => {
+                            state.finished = true;
+                            Err(RequestError::Query(message))
+                        }
+                        Ok(QueryStreamFrame::Done) => return None,
+                        Err(err) => {
+                            state.finished = true;
+                            Err(RequestError::InvalidResponse(format!(
+                                "invalid query stream line: {err}"
+                            )))
+                        }
+                    };
+                    return Some((item, state));
+                }
+                match state.bytes.next().await {
+                    Some(Ok(chunk)) => state.buffer.extend_from_slice(&chunk),
+                    Some(Err(err)) => {
+                        state.finished = true;
+                        return Some((Err(err.into()), state));
+                    }
+                    None => {
+                        state.finished = true;
+                        let err = RequestError::InvalidResponse(
+                            "query stream ended before the query was done".to_string(),
+                        );
+                        return Some((Err(err), state));
+                    }
+                }
+            }
+            None
+        });
+        Ok(Either::Left(items))
+    }
+
+    /// Get events in a bucket
+    ///
+    /// Events are returned newest first, or oldest first if `ascending` is set. The limit keeps
+    /// the first events in that order.
+    ///
+    /// If `since` is set, the events are only fetched if the bucket has been modified since then,
+    /// otherwise `None` is returned.
     pub async fn get_events(
         &self,
         bucketname: &str,
         start: Option<DateTime<Utc>>,
         stop: Option<DateTime<Utc>>,
         limit: Option<u64>,
-    ) -> Result<Vec<Event>, reqwest::Error> {
+        ascending: Option<bool>,
+        since: Option<DateTime<Utc>>,
+    ) -> Result<Option<Vec<Event>>, reqwest::Error> {
         let mut url = reqwest::Url::parse(
             format!("{}/api/0/buckets/{}/events", self.baseurl, bucketname).as_str(),
         )
@@ -149,28 +877,299 @@ impl AwClient {
             url.query_pairs_mut()
                 .append_pair("limit", s.to_string().as_str());
         };
-        self.client.get(url).send().await?.json().await
+        if let Some(ascending) = ascending {
+            url.query_pairs_mut()
+                .append_pair("order", if ascending { "asc" } else { "desc" });
+        };
+        let mut request = self.client.get(url);
+        if let Some(s) = since {
+            request = request.header(
+                reqwest::header::IF_MODIFIED_SINCE,
+                s.format("%a, %d %b %Y %H:%M:%S GMT").to_string(),
+            );
+        };
+        let response = request.send().await?;
+        if response.status() == reqwest::StatusCode::NOT_MODIFIED {
+            return Ok(None);
+        }
+        Ok(Some(response.json().await?))
+    }
+
+    /// Get the events of a bucket with an integer id greater than `after_id` in id order, as a
+    /// cursor for incremental sync
+    ///
+    /// Passing the id of the last event received gets the events inserted since, without the
+    /// duplicates or skips of a timestamp cursor when events share a timestamp. Events with UUID
+    /// ids are returned in insertion order but can't be resumed after.
+    pub async fn get_events_after_id(
+        &self,
+        bucketname: &str,
+        after_id: i64,
+        limit: Option<u64>,
+    ) -> Result<Vec<Event>, reqwest::Error> {
+        let mut url = reqwest::Url::parse(
+            format!("{}/api/0/buckets/{}/events", self.baseurl, bucketname).as_str(),
+        )
+        .unwrap();
+        url.query_pairs_mut()
+            .append_pair("after_id", after_id.to_string().as_str());
+        if let Some(s) = limit {
+            url.query_pairs_mut()
+                .append_pair("limit", s.to_string().as_str());
+        };
+        self.client
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+    }
+
+    /// Get only the given fields of the events in a bucket, oldest first
+    ///
+    /// Fields are `id`, `timestamp`, `duration` and `data`, or `data.<key>` for a single key of
+    /// the data, see `aw_models::EventFields`. Data keys which an event doesn't have are left out
+    /// of its data, the events are returned as JSON objects as they may lack fields of `Event`.
+    pub async fn get_events_fields(
+        &self,
+        bucketname: &str,
+        start: Option<DateTime<Utc>>,
+        stop: Option<DateTime<Utc>>,
+        limit: Option<u64>,
+        fields: &[&str],
+    ) -> Result<Vec<Map<String, serde_json::Value>>, reqwest::Error> {
+        let mut url = reqwest::Url::parse(
+            format!("{}/api/0/buckets/{}/events", self.baseurl, bucketname).as_str(),
+        )
+        .unwrap();
+        url.query_pairs_mut()
+            .append_pair("order", "asc")
+            .append_pair("fields", &fields.join(","));
+        if let Some(s) = start {
+            url.query_pairs_mut()
+                .append_pair("start", s.to_rfc3339().as_str());
+        };
+        if let Some(s) = stop {
+            url.query_pairs_mut()
+                .append_pair("end", s.to_rfc3339().as_str());
+        };
+        if let Some(s) = limit {
+            url.query_pairs_mut()
+                .append_pair("limit", s.to_string().as_str());
+        };
+        self.client
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
     }
 
+    /// Follows a bucket like `tail -f`, yielding the events inserted after the call as they appear
+    ///
+    /// The bucket is polled every `poll_interval` for events starting at or after the newest one
+    /// seen so far, see `EventCursor` for how events are only yielded once. Events which are
+    /// extended by heartbeats after being yielded are not yielded again. The stream never ends,
+    /// a failed poll yields the error and polling continues.
+    pub fn tail_events<'a>(
+        &'a self,
+        bucketname: &'a str,
+        poll_interval: Duration,
+    ) -> impl Stream<Item = Result<Event, reqwest::Error>> + 'a {
+        struct TailState {
+            started: bool,
+            cursor: EventCursor,
+            pending: VecDeque<Event>,
+        }
+        let state = TailState {
+            started: false,
+            cursor: EventCursor::new(None),
+            pending: VecDeque::new(),
+        };
+
+        futures_util::stream::unfold(state, move |mut state| async move {
+            loop {
+                if let Some(event) = state.pending.pop_front() {
+                    return Some((Ok(event), state));
+                }
+                if state.started {
+                    tokio::time::sleep(poll_interval).await;
+                }
+                // The newest event at the start is the initial cursor and is not yielded itself
+                let events = if state.started {
+                    let start = state.cursor.timestamp;
+                    self.get_events(bucketname, start, None, None, Some(true), None)
+                        .await
+                } else {
+                    self.get_events(bucketname, None, None, Some(1), None, None)
+                        .await
+                };
+                let events = match events {
+                    Ok(events) => events.unwrap_or_default(),
+                    Err(err) => return Some((Err(err), state)),
+                };
+                let new_events = state.cursor.advance(events);
+                if state.started {
+                    state.pending.extend(new_events);
+                }
+                state.started = true;
+            }
+        })
+    }
+
+    /// Waits for the first event in the bucket starting after `after`, `None` if there is none
+    /// within `timeout`
+    ///
+    /// The server has no long-polling endpoint, so the bucket is polled with conditional requests
+    /// which are answered with an empty 304 while nothing changes. The first poll is immediate,
+    /// the interval then doubles from 100ms up to `WAIT_FOR_EVENT_MAX_POLL_INTERVAL`. If several
+    /// events have arrived after `after`, the earliest of them is returned. Events starting at or
+    /// before `after` are never returned, even if they are extended by heartbeats.
+    pub async fn wait_for_event(
+        &self,
+        bucketname: &str,
+        after: DateTime<Utc>,
+        timeout: Duration,
+    ) -> Result<Option<Event>, RequestError> {
+        let deadline = tokio::time::Instant::now() + timeout;
+        let mut interval = Duration::from_millis(100);
+        let mut since: Option<DateTime<Utc>> = None;
+        loop {
+            // If-Modified-Since has second precision, so changes in the second of the previous
+            // poll are fetched again rather than missed
+            let poll_time = Utc::now() - chrono::Duration::seconds(1);
+            let events = self
+                .get_events(bucketname, Some(after), None, None, Some(true), since)
+                .await?;
+            if let Some(events) = events {
+                let first = events
+                    .into_iter()
+                    .filter(|event| event.timestamp > after)
+                    .min_by_key(|event| event.timestamp);
+                if first.is_some() {
+                    return Ok(first);
+                }
+                since = Some(poll_time);
+            }
+            let now = tokio::time::Instant::now();
+            if now >= deadline {
+                return Ok(None);
+            }
+            tokio::time::sleep(interval.min(deadline - now)).await;
+            interval = (interval * 2).min(WAIT_FOR_EVENT_MAX_POLL_INTERVAL);
+        }
+    }
+
+    /// Inserts an event, returning the id the server gave it or `None` if it was put in the
+    /// offline queue, see `insert_events`
     pub async fn insert_event(
         &self,
         bucketname: &str,
         event: &Event,
-    ) -> Result<(), reqwest::Error> {
-        let url = format!("{}/api/0/buckets/{}/events", self.baseurl, bucketname);
-        let eventlist = vec![event.clone()];
-        self.client.post(url).json(&eventlist).send().await?;
-        Ok(())
+    ) -> Result<Option<EventId>, RequestError> {
+        let ids = self.insert_events(bucketname, vec![event.clone()]).await?;
+        Ok(ids.into_iter().next())
     }
 
+    /// Inserts events, returning the ids the server gave them in the order of `events`
+    ///
+    /// If the events were put in the offline queue instead of being sent, see
+    /// `AwClientBuilder::offline_queue`, they have no ids yet and none are returned.
     pub async fn insert_events(
         &self,
         bucketname: &str,
         events: Vec<Event>,
-    ) -> Result<(), reqwest::Error> {
+    ) -> Result<Vec<EventId>, RequestError> {
+        let inserted = match &self.queue {
+            Some(queue) => {
+                let requests: Vec<QueuedRequest> = events
+                    .into_iter()
+                    .map(|event| QueuedRequest::Insert {
+                        bucket: bucketname.to_string(),
+                        event,
+                    })
+                    .collect();
+                self.send_or_queue(queue, requests).await?
+            }
+            None => Some(self.post_events(bucketname, &events).await?),
+        };
+        inserted
+            .unwrap_or_default()
+            .into_iter()
+            .map(|event| {
+                event.id.ok_or_else(|| {
+                    RequestError::InvalidResponse(
+                        "Server returned an inserted event without an id".to_string(),
+                    )
+                })
+            })
+            .collect()
+    }
+
+    /// A `Sink` inserting the events sent into it into the bucket in batches, see `EventSink`
+    pub fn event_sink(
+        &self,
+        bucketname: &str,
+        max_batch: usize,
+        max_delay: Duration,
+    ) -> EventSink<'_> {
+        EventSink::new(self, bucketname, max_batch, max_delay)
+    }
+
+    /// Inserts the events, returning them as stored by the server
+    async fn post_events(
+        &self,
+        bucketname: &str,
+        events: &[Event],
+    ) -> Result<Vec<Event>, reqwest::Error> {
         let url = format!("{}/api/0/buckets/{}/events", self.baseurl, bucketname);
-        self.client.post(url).json(&events).send().await?;
-        Ok(())
+        self.client
+            .post(url)
+            .json(events)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+    }
+
+    /// Inserts events by streaming them to the server as newline-delimited JSON, so neither the
+    /// client nor the server needs to keep all of them in memory
+    ///
+    /// Returns the number of inserted events.
+    pub async fn stream_insert_events<I>(
+        &self,
+        bucketname: &str,
+        events: I,
+    ) -> Result<u64, reqwest::Error>
+    where
+        I: IntoIterator<Item = Event>,
+        I::IntoIter: Send + Sync + 'static,
+    {
+        let url = format!(
+            "{}/api/0/buckets/{}/events/stream",
+            self.baseurl, bucketname
+        );
+        let lines = events.into_iter().map(|event| {
+            serde_json::to_vec(&event).map(|mut line| {
+                line.push(b'\n');
+                line
+            })
+        });
+        let body = reqwest::Body::wrap_stream(futures_util::stream::iter(lines));
+        let res: serde_json::Value = self
+            .client
+            .post(url)
+            .header(reqwest::header::CONTENT_TYPE, "application/x-ndjson")
+            .body(body)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(res["inserted"].as_u64().unwrap_or(0))
     }
 
     pub async fn heartbeat(
@@ -178,6 +1177,26 @@ impl AwClient {
         bucketname: &str,
         event: &Event,
         pulsetime: f64,
+    ) -> Result<(), reqwest::Error> {
+        match &self.queue {
+            Some(queue) => {
+                let request = QueuedRequest::Heartbeat {
+                    bucket: bucketname.to_string(),
+                    event: event.clone(),
+                    pulsetime,
+                };
+                self.send_or_queue(queue, vec![request]).await?;
+                Ok(())
+            }
+            None => self.post_heartbeat(bucketname, event, pulsetime).await,
+        }
+    }
+
+    async fn post_heartbeat(
+        &self,
+        bucketname: &str,
+        event: &Event,
+        pulsetime: f64,
     ) -> Result<(), reqwest::Error> {
         let url = format!(
             "{}/api/0/buckets/{}/heartbeat?pulsetime={}",
@@ -187,10 +1206,197 @@ impl AwClient {
         Ok(())
     }
 
+    /// Replays the requests in the offline queue, see `AwClientBuilder::offline_queue`
+    ///
+    /// Returns the number of requests which were sent, requests which could not be sent stay in
+    /// the queue. Without an offline queue this does nothing.
+    pub async fn flush_queue(&self) -> Result<usize, reqwest::Error> {
+        match &self.queue {
+            Some(queue) => self.replay_queue(&*queue.lock().await).await,
+            None => Ok(0),
+        }
+    }
+
+    /// Number of requests waiting in the offline queue
+    pub async fn queue_len(&self) -> usize {
+        match &self.queue {
+            Some(queue) => match queue.lock().await.read() {
+                Ok(requests) => requests.len(),
+                Err(err) => {
+                    warn!("Failed to read offline queue: {}", err);
+                    0
+                }
+            },
+            None => 0,
+        }
+    }
+
+    /// Sends `requests` after the ones already in the queue, or queues them all if the server
+    /// can't be reached
+    ///
+    /// Returns the events inserted by `requests`, `None` if they were queued.
+    async fn send_or_queue(
+        &self,
+        queue: &tokio::sync::Mutex<OfflineQueue>,
+        requests: Vec<QueuedRequest>,
+    ) -> Result<Option<Vec<Event>>, reqwest::Error> {
+        let queue = queue.lock().await;
+        let result = match self.replay_queue(&queue).await {
+            Ok(_) => self.send_requests(&requests, false).await,
+            Err(err) => Err(err),
+        };
+        match result {
+            Err(err) if is_unreachable(&err) => match queue.push(&requests) {
+                Ok(()) => Ok(None),
+                Err(io_err) => {
+                    warn!("Failed to add requests to the offline queue: {}", io_err);
+                    Err(err)
+                }
+            },
+            result => result.map(Some),
+        }
+    }
+
+    async fn replay_queue(&self, queue: &OfflineQueue) -> Result<usize, reqwest::Error> {
+        let requests = match queue.read() {
+            Ok(requests) => requests,
+            Err(err) => {
+                warn!("Failed to read offline queue: {}", err);
+                return Ok(0);
+            }
+        };
+        let mut sent = 0;
+        while sent < requests.len() {
+            // Consecutive inserts into the same bucket are sent together
+            let mut end = sent + 1;
+            if let QueuedRequest::Insert { bucket, .. } = &requests[sent] {
+                while end < requests.len()
+                    && end - sent < COPY_PAGE_SIZE as usize
+                    && matches!(&requests[end], QueuedRequest::Insert { bucket: b, .. } if b == bucket)
+                {
+                    end += 1;
+                }
+            }
+            if let Err(err) = self.send_requests(&requests[sent..end], true).await {
+                if let Err(io_err) = queue.write(&requests[sent..]) {
+                    warn!("Failed to update offline queue: {}", io_err);
+                }
+                return Err(err);
+            }
+            sent = end;
+        }
+        if sent > 0 {
+            if let Err(io_err) = queue.write(&[]) {
+                warn!("Failed to clear offline queue: {}", io_err);
+            }
+        }
+        Ok(sent)
+    }
+
+    /// Sends a heartbeat or inserts into a single bucket, returning the inserted events. With
+    /// `skip_existing` inserted events which already exist on the server are left out.
+    ///
+    /// Inserts which the server rejects are dropped with a warning when `skip_existing` is set,
+    /// so that a queued insert which can never succeed doesn't block the queue.
+    async fn send_requests(
+        &self,
+        requests: &[QueuedRequest],
+        skip_existing: bool,
+    ) -> Result<Vec<Event>, reqwest::Error> {
+        let mut events = Vec::new();
+        let mut bucketname = None;
+        for request in requests {
+            match request {
+                QueuedRequest::Heartbeat {
+                    bucket,
+                    event,
+                    pulsetime,
+                } => {
+                    self.post_heartbeat(bucket, event, *pulsetime).await?;
+                    return Ok(Vec::new());
+                }
+                QueuedRequest::Insert { bucket, event } => {
+                    bucketname = Some(bucket);
+                    events.push(event.clone());
+                }
+            }
+        }
+        let bucketname = match bucketname {
+            Some(bucketname) => bucketname,
+            None => return Ok(Vec::new()),
+        };
+        if skip_existing {
+            events = self.without_existing_events(bucketname, events).await?;
+            if events.is_empty() {
+                return Ok(Vec::new());
+            }
+        }
+        match self.post_events(bucketname, &events).await {
+            Err(err) if skip_existing && err.is_status() => {
+                warn!(
+                    "Dropping {} queued events rejected by the server: {}",
+                    events.len(),
+                    err
+                );
+                Ok(Vec::new())
+            }
+            result => result,
+        }
+    }
+
+    async fn without_existing_events(
+        &self,
+        bucketname: &str,
+        events: Vec<Event>,
+    ) -> Result<Vec<Event>, reqwest::Error> {
+        let start = events.iter().map(|e| e.timestamp).min();
+        let end = events.iter().map(|e| e.calculate_endtime()).max();
+        let existing = match self
+            .get_events(bucketname, start, end, None, Some(true), None)
+            .await
+        {
+            Ok(existing) => existing.unwrap_or_default(),
+            Err(err) if is_unreachable(&err) => return Err(err),
+            // The insert fails the same way, e.g. if the bucket doesn't exist
+            Err(_) => return Ok(events),
+        };
+        Ok(events
+            .into_iter()
+            .filter(|event| {
+                !existing.iter().any(|e| {
+                    e.timestamp == event.timestamp
+                        && e.duration == event.duration
+                        && e.data == event.data
+                })
+            })
+            .collect())
+    }
+
+    /// Updates the data of an event with a JSON merge patch, keys set to null are removed
+    pub async fn patch_event(
+        &self,
+        bucketname: &str,
+        event_id: &EventId,
+        patch: &Map<String, serde_json::Value>,
+    ) -> Result<Event, reqwest::Error> {
+        let url = format!(
+            "{}/api/0/buckets/{}/events/{}",
+            self.baseurl, bucketname, event_id
+        );
+        self.client
+            .patch(url)
+            .json(patch)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+    }
+
     pub async fn delete_event(
         &self,
         bucketname: &str,
-        event_id: i64,
+        event_id: &EventId,
     ) -> Result<(), reqwest::Error> {
         let url = format!(
             "{}/api/0/buckets/{}/events/{}",
@@ -217,8 +1423,344 @@ impl AwClient {
         Ok(count)
     }
 
+    /// The distinct values of a top-level data key in the events between `start` and `stop`,
+    /// with the number of events having each value, most common first
+    pub async fn get_distinct_values(
+        &self,
+        bucketname: &str,
+        key: &str,
+        start: Option<DateTime<Utc>>,
+        stop: Option<DateTime<Utc>>,
+    ) -> Result<Vec<DistinctValue>, reqwest::Error> {
+        let mut url = reqwest::Url::parse(
+            format!(
+                "{}/api/0/buckets/{}/events/distinct",
+                self.baseurl, bucketname
+            )
+            .as_str(),
+        )
+        .unwrap();
+        url.query_pairs_mut()
+            .append_pair("key", key)
+            .append_pair("counts", "true");
+        if let Some(s) = start {
+            url.query_pairs_mut()
+                .append_pair("start", s.to_rfc3339().as_str());
+        };
+        if let Some(s) = stop {
+            url.query_pairs_mut()
+                .append_pair("end", s.to_rfc3339().as_str());
+        };
+        self.client
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+    }
+
+    /// A health report of the events between `start` and `stop`, counting overlapping,
+    /// out-of-order and zero-duration events and the gaps longer than `min_gap`
+    pub async fn get_bucket_quality(
+        &self,
+        bucketname: &str,
+        start: Option<DateTime<Utc>>,
+        stop: Option<DateTime<Utc>>,
+        min_gap: Option<Duration>,
+    ) -> Result<BucketQuality, reqwest::Error> {
+        let mut url = reqwest::Url::parse(
+            format!("{}/api/0/buckets/{}/quality", self.baseurl, bucketname).as_str(),
+        )
+        .unwrap();
+        if let Some(s) = start {
+            url.query_pairs_mut()
+                .append_pair("start", s.to_rfc3339().as_str());
+        };
+        if let Some(s) = stop {
+            url.query_pairs_mut()
+                .append_pair("end", s.to_rfc3339().as_str());
+        };
+        if let Some(min_gap) = min_gap {
+            url.query_pairs_mut()
+                .append_pair("min_gap", min_gap.as_secs_f64().to_string().as_str());
+        };
+        self.client
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+    }
+
+    /// The event in the bucket which starts first, `None` if the bucket is empty
+    pub async fn get_first_event(&self, bucketname: &str) -> Result<Option<Event>, reqwest::Error> {
+        self.get_boundary_event(bucketname, "first").await
+    }
+
+    /// The event in the bucket which starts last, `None` if the bucket is empty
+    pub async fn get_last_event(&self, bucketname: &str) -> Result<Option<Event>, reqwest::Error> {
+        self.get_boundary_event(bucketname, "last").await
+    }
+
+    async fn get_boundary_event(
+        &self,
+        bucketname: &str,
+        which: &str,
+    ) -> Result<Option<Event>, reqwest::Error> {
+        let url = format!(
+            "{}/api/0/buckets/{}/events/{}",
+            self.baseurl, bucketname, which
+        );
+        let response = self.client.get(url).send().await?.error_for_status()?;
+        if response.status() == reqwest::StatusCode::NO_CONTENT {
+            return Ok(None);
+        }
+        Ok(Some(response.json().await?))
+    }
+
+    /// Total event count of the bucket from a HEAD request, which the server answers from its
+    /// cached count, meant for polling the counts of many buckets
+    pub async fn head_event_count(&self, bucketname: &str) -> Result<i64, reqwest::Error> {
+        let url = format!("{}/api/0/buckets/{}/events", self.baseurl, bucketname);
+        let res = self.client.head(url).send().await?.error_for_status()?;
+        let count: i64 = match res
+            .headers()
+            .get("X-Event-Count")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse().ok())
+        {
+            Some(count) => count,
+            None => panic!("could not parse X-Event-Count header of head_event_count response"),
+        };
+        Ok(count)
+    }
+
+    /// Compares the events of two buckets within a time range
+    ///
+    /// Since event ids are local to a bucket, events are matched by their timestamp and are
+    /// identical if their duration and data are also equal. If several events share a timestamp,
+    /// identical events are matched first and the rest are paired up in order.
+    pub async fn diff_buckets(
+        &self,
+        src: &str,
+        dst: &str,
+        start: Option<DateTime<Utc>>,
+        stop: Option<DateTime<Utc>>,
+    ) -> Result<BucketDiff, RequestError> {
+        let (src_events, dst_events) = futures_util::try_join!(
+            self.get_events(src, start, stop, None, None, None),
+            self.get_events(dst, start, stop, None, None, None),
+        )?;
+        let src_events = src_events.unwrap_or_default();
+        let dst_events = dst_events.unwrap_or_default();
+
+        let mut dst_by_timestamp: HashMap<DateTime<Utc>, Vec<Event>> = HashMap::new();
+        for event in dst_events {
+            dst_by_timestamp
+                .entry(event.timestamp)
+                .or_default()
+                .push(event);
+        }
+
+        let mut diff = BucketDiff::default();
+        let mut unmatched = Vec::new();
+        for event in src_events {
+            let matched = dst_by_timestamp
+                .get_mut(&event.timestamp)
+                .and_then(|candidates| {
+                    let i = candidates.iter().position(|e| e == &event)?;
+                    Some(candidates.remove(i))
+                });
+            match matched {
+                Some(_) => diff.identical += 1,
+                None => unmatched.push(event),
+            }
+        }
+        for event in unmatched {
+            match dst_by_timestamp.get_mut(&event.timestamp) {
+                Some(candidates) if !candidates.is_empty() => {
+                    let other = candidates.remove(0);
+                    diff.differing.push((event, other));
+                }
+                _ => diff.only_in_src.push(event),
+            }
+        }
+        diff.only_in_dst = dst_by_timestamp.into_values().flatten().collect();
+        diff.only_in_dst
+            .sort_by_key(|e| std::cmp::Reverse(e.timestamp));
+
+        Ok(diff)
+    }
+
+    /// Copies a bucket with all its events to the server of `dest`, returning the number of
+    /// events copied
+    ///
+    /// The bucket is created on `dest` if it doesn't exist. Events are copied oldest first in
+    /// pages of `COPY_PAGE_SIZE` and get new ids on `dest`, `progress` is called with the number
+    /// of events copied so far after each page.
+    ///
+    /// If the bucket on `dest` already has events it is treated as an earlier copy which was
+    /// interrupted, and only the events starting after its last event are copied. Running the
+    /// copy again after a failure therefore resumes it, but events which were added to the
+    /// bucket on `dest` by other means are not merged with the copied ones.
+    pub async fn copy_bucket_to<F>(
+        &self,
+        bucketname: &str,
+        dest: &AwClient,
+        mut progress: F,
+    ) -> Result<u64, reqwest::Error>
+    where
+        F: FnMut(u64),
+    {
+        let bucket = self.get_bucket(bucketname).await?;
+        dest.create_bucket(&bucket).await?;
+
+        let resumed_from = dest.get_last_event(bucketname).await?.map(|e| e.timestamp);
+        let mut cursor = EventCursor::new(resumed_from);
+        let mut limit = COPY_PAGE_SIZE;
+        let mut copied = 0;
+        loop {
+            let events = self
+                .get_events(
+                    bucketname,
+                    cursor.timestamp,
+                    None,
+                    Some(limit),
+                    Some(true),
+                    None,
+                )
+                .await?
+                .unwrap_or_default();
+            let page_was_full = events.len() as u64 == limit;
+
+            let page: Vec<Event> = cursor
+                .advance(events)
+                .into_iter()
+                .filter(|event| resumed_from.is_none_or(|resumed| event.timestamp > resumed))
+                .map(|event| Event { id: None, ..event })
+                .collect();
+            if page.is_empty() {
+                if !page_was_full {
+                    return Ok(copied);
+                }
+                // The whole page was taken up by events which were already copied, such as
+                // many events starting at the same time, so fetch a larger one
+                limit *= 2;
+                continue;
+            }
+            limit = COPY_PAGE_SIZE;
+            copied += page.len() as u64;
+            // Bypasses the offline queue, the copy should fail if the destination is unreachable
+            dest.post_events(bucketname, &page).await?;
+            progress(copied);
+        }
+    }
+
+    /// Runs `CANONICAL_ACTIVITY_QUERY` for a time range
+    ///
+    /// `categories` are (category, rule) pairs as used by the `categorize` query function, such
+    /// as `(["Work"], {"type": "regex", "regex": "Editor"})`.
+    pub async fn get_canonical_activity(
+        &self,
+        window_bucket: &str,
+        afk_bucket: &str,
+        categories: &[(Vec<String>, serde_json::Value)],
+        start: DateTime<Utc>,
+        stop: DateTime<Utc>,
+    ) -> Result<CanonicalActivity, RequestError> {
+        let categories = serde_json::Value::Array(
+            categories
+                .iter()
+                .map(|(category, rule)| json!([category, rule]))
+                .collect(),
+        );
+        let query = CANONICAL_ACTIVITY_QUERY
+            .replace("{window_bucket}", &to_query_literal(&window_bucket.into()))
+            .replace("{afk_bucket}", &to_query_literal(&afk_bucket.into()))
+            .replace("{categories}", &to_query_literal(&categories));
+
+        let mut results = self.query(&query, vec![(start, stop)]).await?;
+        if results.len() != 1 {
+            return Err(RequestError::InvalidResponse(format!(
+                "Expected 1 query result, got {}",
+                results.len()
+            )));
+        }
+        let mut result = results.remove(0);
+        let invalid = |err: serde_json::Error| RequestError::InvalidResponse(err.to_string());
+        let events: Vec<Event> =
+            serde_json::from_value(result["events"].take()).map_err(invalid)?;
+        let cat_events: Vec<Event> =
+            serde_json::from_value(result["cat_events"].take()).map_err(invalid)?;
+        let duration: f64 = serde_json::from_value(result["duration"].take()).map_err(invalid)?;
+
+        let mut categories = Vec::new();
+        for event in cat_events {
+            let category = event.data.get("$category").cloned().unwrap_or_default();
+            categories.push(CategoryDuration {
+                category: serde_json::from_value(category).map_err(invalid)?,
+                duration: event.duration,
+            });
+        }
+        Ok(CanonicalActivity {
+            events,
+            categories,
+            duration: chrono::Duration::milliseconds((duration * 1000.0) as i64),
+        })
+    }
+
+    /// Shrinks the database on the server after large deletions
+    ///
+    /// This can take a while on large databases, since the server handles no writes meanwhile.
+    /// Needs the `AwClientBuilder::admin_token` of the server.
+    pub async fn vacuum(&self) -> Result<VacuumResult, reqwest::Error> {
+        let url = format!("{}/api/0/admin/vacuum", self.baseurl);
+        let mut request = self.client.post(url);
+        if let Some(token) = &self.admin_token {
+            request = request.bearer_auth(token);
+        }
+        request.send().await?.error_for_status()?.json().await
+    }
+
     pub async fn get_info(&self) -> Result<aw_models::Info, reqwest::Error> {
         let url = format!("{}/api/0/info", self.baseurl);
         self.client.get(url).send().await?.json().await
     }
+
+    /// What the server supports, servers from before capabilities were reported support none
+    pub async fn capabilities(&self) -> Result<Capabilities, RequestError> {
+        let url = format!("{}/api/0/info", self.baseurl);
+        let info: aw_models::Info = self
+            .client
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(info.capabilities.unwrap_or_default())
+    }
+
+    /// Polls the server with an exponential backoff until it responds or `timeout` has passed
+    pub async fn wait_until_ready(&self, timeout: Duration) -> Result<(), RequestError> {
+        let url = format!("{}/api/0/info", self.baseurl);
+        let start = tokio::time::Instant::now();
+        let mut backoff = Duration::from_millis(50);
+        loop {
+            let res = self.client.get(&url).send().await;
+            if let Ok(res) = res.and_then(|res| res.error_for_status()) {
+                if res.status() == reqwest::StatusCode::OK {
+                    return Ok(());
+                }
+            }
+            let elapsed = start.elapsed();
+            if elapsed >= timeout {
+                return Err(RequestError::Timeout(timeout));
+            }
+            tokio::time::sleep(backoff.min(timeout - elapsed)).await;
+            backoff = (backoff * 2).min(Duration::from_secs(1));
+        }
+    }
 }
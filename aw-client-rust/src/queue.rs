@@ -0,0 +1,155 @@
+//! On-disk queue of events which could not be sent because the server was unreachable
+//!
+//! The queue is a file of JSON lines, one request per line and oldest first:
+//!
+//! ```ignore
+//! {"type":"insert","bucket":"aw-watcher-window_host","event":{"timestamp":...,"duration":...,"data":{...}}}
+//! {"type":"heartbeat","bucket":"aw-watcher-afk_host","pulsetime":60.0,"event":{...}}
+//! ```
+//!
+//! Inserts of several events are queued as one line per event. When the queue holds its maximum
+//! number of lines the oldest ones are dropped to make room for new ones, so that recent data is
+//! kept during a long time offline.
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::PathBuf;
+
+use aw_models::Event;
+use serde_json::json;
+
+/// Number of requests kept in the offline queue by default
+pub const DEFAULT_QUEUE_MAX_LEN: usize = 100_000;
+
+/// A request to the server which is replayed once it is reachable again
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum QueuedRequest {
+    Insert {
+        bucket: String,
+        event: Event,
+    },
+    Heartbeat {
+        bucket: String,
+        event: Event,
+        pulsetime: f64,
+    },
+}
+
+impl QueuedRequest {
+    fn to_line(&self) -> String {
+        let value = match self {
+            QueuedRequest::Insert { bucket, event } => {
+                json!({"type": "insert", "bucket": bucket, "event": event})
+            }
+            QueuedRequest::Heartbeat {
+                bucket,
+                event,
+                pulsetime,
+            } => json!({
+                "type": "heartbeat",
+                "bucket": bucket,
+                "pulsetime": pulsetime,
+                "event": event,
+            }),
+        };
+        value.to_string()
+    }
+
+    fn from_line(line: &str) -> Result<QueuedRequest, String> {
+        let value: serde_json::Value = serde_json::from_str(line).map_err(|e| e.to_string())?;
+        let bucket = match value["bucket"].as_str() {
+            Some(bucket) => bucket.to_string(),
+            None => return Err("missing bucket".to_string()),
+        };
+        let event: Event =
+            serde_json::from_value(value["event"].clone()).map_err(|e| e.to_string())?;
+        match (value["type"].as_str(), value["pulsetime"].as_f64()) {
+            (Some("insert"), _) => Ok(QueuedRequest::Insert { bucket, event }),
+            (Some("heartbeat"), Some(pulsetime)) => Ok(QueuedRequest::Heartbeat {
+                bucket,
+                event,
+                pulsetime,
+            }),
+            _ => Err("unknown request type".to_string()),
+        }
+    }
+}
+
+/// The file of an offline queue, see the module docs for its format
+///
+/// Not synchronized by itself, `AwClient` only accesses it while holding its queue lock.
+#[derive(Debug)]
+pub(crate) struct OfflineQueue {
+    path: PathBuf,
+    max_len: usize,
+}
+
+impl OfflineQueue {
+    pub(crate) fn new(path: PathBuf, max_len: usize) -> OfflineQueue {
+        OfflineQueue { path, max_len }
+    }
+
+    /// All queued requests, oldest first, lines which can't be parsed are skipped
+    pub(crate) fn read(&self) -> io::Result<Vec<QueuedRequest>> {
+        let file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err),
+        };
+        let mut requests = Vec::new();
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            match QueuedRequest::from_line(&line) {
+                Ok(request) => requests.push(request),
+                Err(err) => warn!("Skipping invalid line in offline queue: {}", err),
+            }
+        }
+        Ok(requests)
+    }
+
+    /// Replaces the content of the queue, removing the file if `requests` is empty
+    pub(crate) fn write(&self, requests: &[QueuedRequest]) -> io::Result<()> {
+        if requests.is_empty() {
+            return match fs::remove_file(&self.path) {
+                Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+                _ => Ok(()),
+            };
+        }
+        // Written to a temporary file first so that a crash can't leave a half-written queue
+        let mut tmp_path = self.path.clone().into_os_string();
+        tmp_path.push(".tmp");
+        let mut file = File::create(&tmp_path)?;
+        for request in requests {
+            writeln!(file, "{}", request.to_line())?;
+        }
+        file.sync_all()?;
+        fs::rename(&tmp_path, &self.path)
+    }
+
+    /// Adds requests to the end of the queue, dropping the oldest ones if it gets too long
+    pub(crate) fn push(&self, requests: &[QueuedRequest]) -> io::Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut queued = self.read()?;
+        if queued.len() + requests.len() > self.max_len {
+            queued.extend_from_slice(requests);
+            let evicted = queued.len() - self.max_len;
+            warn!(
+                "Offline queue {:?} is full, dropping the {} oldest requests",
+                self.path, evicted
+            );
+            return self.write(&queued[evicted..]);
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        for request in requests {
+            writeln!(file, "{}", request.to_line())?;
+        }
+        file.sync_all()
+    }
+}
@@ -0,0 +1,66 @@
+//! The `X-Request-Id` header, which the server logs together with the request so that a request
+//! such as a heartbeat can be traced from a watcher through the server logs
+//!
+//! The header is only sent if turned on with `AwClientBuilder::request_ids`, which sends a new
+//! UUID with every request, or for the requests of `AwClient::with_request_id`.
+use std::future::Future;
+
+use reqwest::{IntoUrl, Method, RequestBuilder};
+
+/// Name of the header carrying the id of a request
+pub const REQUEST_ID_HEADER: &str = "X-Request-Id";
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+/// Runs `future` with `request_id` sent as the id of the requests it makes
+pub(crate) async fn scope<F: Future>(request_id: String, future: F) -> F::Output {
+    REQUEST_ID.scope(request_id, future).await
+}
+
+/// A `reqwest::Client` adding the `X-Request-Id` header to the requests it builds
+#[derive(Clone)]
+pub(crate) struct HttpClient {
+    inner: reqwest::Client,
+    /// Whether requests outside of a `scope` get a new UUID
+    generate: bool,
+}
+
+impl HttpClient {
+    pub(crate) fn new(inner: reqwest::Client, generate: bool) -> HttpClient {
+        HttpClient { inner, generate }
+    }
+
+    fn request<U: IntoUrl>(&self, method: Method, url: U) -> RequestBuilder {
+        let request = self.inner.request(method, url);
+        let request_id = REQUEST_ID
+            .try_with(|id| id.clone())
+            .ok()
+            .or_else(|| self.generate.then(|| uuid::Uuid::new_v4().to_string()));
+        match request_id {
+            Some(request_id) => request.header(REQUEST_ID_HEADER, request_id),
+            None => request,
+        }
+    }
+
+    pub(crate) fn get<U: IntoUrl>(&self, url: U) -> RequestBuilder {
+        self.request(Method::GET, url)
+    }
+
+    pub(crate) fn head<U: IntoUrl>(&self, url: U) -> RequestBuilder {
+        self.request(Method::HEAD, url)
+    }
+
+    pub(crate) fn post<U: IntoUrl>(&self, url: U) -> RequestBuilder {
+        self.request(Method::POST, url)
+    }
+
+    pub(crate) fn patch<U: IntoUrl>(&self, url: U) -> RequestBuilder {
+        self.request(Method::PATCH, url)
+    }
+
+    pub(crate) fn delete<U: IntoUrl>(&self, url: U) -> RequestBuilder {
+        self.request(Method::DELETE, url)
+    }
+}
@@ -0,0 +1,149 @@
+//! A `Sink` which inserts events into a bucket in batches, see `EventSink`
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use futures_util::Sink;
+
+use crate::{AwClient, RequestError};
+use aw_models::Event;
+
+type InsertFuture<'a> = Pin<Box<dyn Future<Output = Result<(), RequestError>> + Send + 'a>>;
+
+/// Inserts the events sent into it into a bucket, in batches of up to `max_batch` events
+///
+/// A batch is inserted with `AwClient::insert_events` once it is full, or when an event is sent
+/// while the oldest event in it has waited for more than `max_delay`. Only one batch is sent at a
+/// time, while it is being sent the sink isn't ready for new events, so that a slow server slows
+/// down the stream feeding the sink instead of events piling up in memory.
+///
+/// Flushing or closing the sink inserts the events buffered so far and waits for them to be
+/// inserted, note that `StreamExt::forward` flushes the sink whenever the stream has no event
+/// ready. Events still buffered or being sent when the sink is dropped without being closed
+/// are lost.
+///
+/// If inserting a batch fails its events are dropped and the error is returned by the next call
+/// on the sink. Use an offline queue, see `AwClientBuilder::offline_queue`, to keep the events
+/// when the server is unreachable.
+///
+/// # Example
+/// ```ignore
+/// let sink = client.event_sink("aw-watcher-example_host", 100, Duration::from_secs(10));
+/// futures::stream::iter(events).map(Ok).forward(sink).await?;
+/// ```
+pub struct EventSink<'a> {
+    client: &'a AwClient,
+    bucket: String,
+    max_batch: usize,
+    max_delay: Duration,
+    buffer: Vec<Event>,
+    oldest: Option<Instant>,
+    in_flight: Option<InsertFuture<'a>>,
+}
+
+impl<'a> EventSink<'a> {
+    pub(crate) fn new(
+        client: &'a AwClient,
+        bucket: &str,
+        max_batch: usize,
+        max_delay: Duration,
+    ) -> EventSink<'a> {
+        EventSink {
+            client,
+            bucket: bucket.to_string(),
+            max_batch: max_batch.max(1),
+            max_delay,
+            buffer: Vec::new(),
+            oldest: None,
+            in_flight: None,
+        }
+    }
+
+    /// Number of events waiting to be sent, not counting a batch which is being sent
+    pub fn buffered(&self) -> usize {
+        self.buffer.len()
+    }
+
+    fn batch_due(&self) -> bool {
+        self.buffer.len() >= self.max_batch
+            || self
+                .oldest
+                .is_some_and(|oldest| oldest.elapsed() >= self.max_delay)
+    }
+
+    fn start_batch(&mut self) {
+        if self.buffer.is_empty() || self.in_flight.is_some() {
+            return;
+        }
+        let events = std::mem::take(&mut self.buffer);
+        self.oldest = None;
+        let client = self.client;
+        let bucket = self.bucket.clone();
+        self.in_flight = Some(Box::pin(async move {
+            client.insert_events(&bucket, events).await.map(|_| ())
+        }));
+    }
+
+    fn poll_in_flight(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), RequestError>> {
+        match self.in_flight.as_mut() {
+            Some(insert) => {
+                let res = match insert.as_mut().poll(cx) {
+                    Poll::Ready(res) => res,
+                    Poll::Pending => return Poll::Pending,
+                };
+                self.in_flight = None;
+                Poll::Ready(res)
+            }
+            None => Poll::Ready(Ok(())),
+        }
+    }
+}
+
+impl<'a> Sink<Event> for EventSink<'a> {
+    type Error = RequestError;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let this = self.get_mut();
+        loop {
+            match this.poll_in_flight(cx) {
+                Poll::Ready(Ok(())) => (),
+                other => return other,
+            }
+            if !this.batch_due() {
+                return Poll::Ready(Ok(()));
+            }
+            this.start_batch();
+        }
+    }
+
+    fn start_send(self: Pin<&mut Self>, event: Event) -> Result<(), Self::Error> {
+        let this = self.get_mut();
+        if this.buffer.is_empty() {
+            this.oldest = Some(Instant::now());
+        }
+        this.buffer.push(event);
+        if this.batch_due() {
+            this.start_batch();
+        }
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let this = self.get_mut();
+        loop {
+            match this.poll_in_flight(cx) {
+                Poll::Ready(Ok(())) => (),
+                other => return other,
+            }
+            if this.buffer.is_empty() {
+                return Poll::Ready(Ok(()));
+            }
+            this.start_batch();
+        }
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.poll_flush(cx)
+    }
+}
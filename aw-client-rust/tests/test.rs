@@ -9,10 +9,16 @@ extern crate tokio_test;
 #[cfg(test)]
 mod test {
     use aw_client_rust::blocking::AwClient;
+    use aw_client_rust::BucketCreationResult;
+    use aw_client_rust::CategoryDuration;
     use aw_client_rust::Event;
+    use aw_client_rust::EventId;
+    use aw_client_rust::RequestError;
     use chrono::{DateTime, Duration, Utc};
+    use futures_util::StreamExt;
     use serde_json::Map;
-    use std::sync::Mutex;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
     use std::thread;
     use tokio_test::block_on;
 
@@ -20,34 +26,34 @@ mod test {
     // FIXME: Bind to a port that is free for certain and use that for the client instead
     static PORT: u16 = 41293;
 
-    fn wait_for_server(timeout_s: u32, client: &AwClient) {
-        for i in 0.. {
-            match client.get_info() {
-                Ok(_) => break,
-                Err(err) => {
-                    if i == timeout_s - 1 {
-                        panic!("Timed out starting aw-server after {timeout_s}s: {err:?}");
-                    }
-                }
-            }
-            use std::time;
-            let duration = time::Duration::from_secs(1);
-            thread::sleep(duration);
-        }
-    }
+    const ADMIN_TOKEN: &str = "test-admin-token";
 
     fn setup_testserver() -> rocket::Shutdown {
+        setup_testserver_at(PORT)
+    }
+
+    fn setup_testserver_at(port: u16) -> rocket::Shutdown {
+        let mut aw_config = aw_server::config::AWConfig::default();
+        aw_config.port = port;
+        launch_testserver(build_testserver(aw_config))
+    }
+
+    fn build_testserver(
+        mut aw_config: aw_server::config::AWConfig,
+    ) -> rocket::Rocket<rocket::Build> {
         use aw_server::endpoints::AssetResolver;
         use aw_server::endpoints::ServerState;
 
         let state = ServerState {
-            datastore: Mutex::new(aw_datastore::Datastore::new_in_memory(false)),
+            datastore: Arc::new(Mutex::new(aw_datastore::Datastore::new_in_memory(false))),
             asset_resolver: AssetResolver::new(None),
             device_id: "test_id".to_string(),
         };
-        let mut aw_config = aw_server::config::AWConfig::default();
-        aw_config.port = PORT;
-        let server = aw_server::endpoints::build_rocket(state, aw_config);
+        aw_config.admin_token = Some(ADMIN_TOKEN.to_string());
+        aw_server::endpoints::build_rocket(state, aw_config)
+    }
+
+    fn launch_testserver(server: rocket::Rocket<rocket::Build>) -> rocket::Shutdown {
         let server = block_on(server.ignite()).unwrap();
         let shutdown_handler = server.shutdown();
 
@@ -58,19 +64,168 @@ mod test {
         shutdown_handler
     }
 
+    #[test]
+    fn test_wait_until_ready_timeout() {
+        // No server is running on this port
+        let client: AwClient = AwClient::new("127.0.0.1", PORT + 1, "aw-client-rust-test")
+            .expect("Client creation failed");
+        let res = client.wait_until_ready(std::time::Duration::from_millis(200));
+        assert!(matches!(res, Err(RequestError::Timeout(_))));
+    }
+
+    /// Minimal HTTP/1.1 server which keeps connections alive and answers every request with an
+    /// empty 200 response, returns its port and the number of accepted connections
+    fn setup_keepalive_server() -> (u16, Arc<AtomicUsize>) {
+        use std::io::{BufRead, BufReader, Read, Write};
+        use std::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let connections = Arc::new(AtomicUsize::new(0));
+        let accepted = connections.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                accepted.fetch_add(1, Ordering::SeqCst);
+                thread::spawn(move || {
+                    let mut reader = BufReader::new(stream.try_clone().unwrap());
+                    loop {
+                        let mut content_length = 0;
+                        loop {
+                            let mut line = String::new();
+                            if reader.read_line(&mut line).unwrap_or(0) == 0 {
+                                return;
+                            }
+                            let line = line.trim_end().to_lowercase();
+                            if line.is_empty() {
+                                break;
+                            }
+                            if let Some(len) = line.strip_prefix("content-length:") {
+                                content_length = len.trim().parse().unwrap();
+                            }
+                        }
+                        let mut body = vec![0; content_length];
+                        reader.read_exact(&mut body).unwrap();
+                        stream
+                            .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+                            .unwrap();
+                    }
+                });
+            }
+        });
+        (port, connections)
+    }
+
+    #[test]
+    fn test_https_builder() {
+        let client = AwClient::builder("127.0.0.1", 5600, "aw-client-rust-test").build_blocking();
+        assert_eq!(client.unwrap().baseurl.scheme(), "http");
+
+        let client = AwClient::builder("127.0.0.1", 5600, "aw-client-rust-test")
+            .https(true)
+            .danger_accept_invalid_certs(true)
+            .build_blocking()
+            .unwrap();
+        assert_eq!(client.baseurl.as_str(), "https://127.0.0.1:5600/");
+    }
+
+    #[test]
+    fn test_connection_reuse() {
+        let (port, connections) = setup_keepalive_server();
+        let client = AwClient::builder("127.0.0.1", port, "aw-client-rust-test")
+            .pool_idle_timeout(std::time::Duration::from_secs(10))
+            .tcp_keepalive(std::time::Duration::from_secs(5))
+            .build_blocking()
+            .unwrap();
+        let event = Event {
+            id: None,
+            timestamp: Utc::now(),
+            duration: Duration::seconds(0),
+            data: Map::new(),
+        };
+
+        // Heartbeats sent a little while apart all use the same connection
+        for _ in 0..5 {
+            client.heartbeat("test", &event, 10.0).unwrap();
+            thread::sleep(std::time::Duration::from_millis(100));
+        }
+        assert_eq!(connections.load(Ordering::SeqCst), 1);
+    }
+
+    /// Sends heartbeats to aw-server with the given keep-alive, returns the number of
+    /// connections they were sent over
+    fn count_server_connections(port: u16, keep_alive_secs: u32) -> usize {
+        use rocket::fairing::AdHoc;
+        use std::collections::HashSet;
+
+        let mut aw_config = aw_server::config::AWConfig::default();
+        aw_config.port = port;
+        aw_config.keep_alive_secs = keep_alive_secs;
+        // A connection is told apart from the others by the port of its client side
+        let client_ports = Arc::new(Mutex::new(HashSet::new()));
+        let seen = client_ports.clone();
+        let server =
+            build_testserver(aw_config).attach(AdHoc::on_request("Client ports", move |req, _| {
+                if let Some(remote) = req.remote() {
+                    seen.lock().unwrap().insert(remote.port());
+                }
+                Box::pin(async {})
+            }));
+        let shutdown_handler = launch_testserver(server);
+
+        let client = AwClient::new("127.0.0.1", port, "aw-client-rust-test").unwrap();
+        client
+            .wait_until_ready(std::time::Duration::from_secs(20))
+            .unwrap();
+        client.create_bucket_simple("test", "test").unwrap();
+        let event = Event {
+            id: None,
+            timestamp: Utc::now(),
+            duration: Duration::seconds(0),
+            data: Map::new(),
+        };
+        for _ in 0..5 {
+            client.heartbeat("test", &event, 10.0).unwrap();
+            thread::sleep(std::time::Duration::from_millis(100));
+        }
+
+        shutdown_handler.notify();
+        let count = client_ports.lock().unwrap().len();
+        count
+    }
+
+    #[test]
+    fn test_server_connection_reuse() {
+        // Connections are closed after every response unless keep-alive is enabled
+        assert!(count_server_connections(PORT + 8, 0) > 1);
+        assert_eq!(count_server_connections(PORT + 9, 120), 1);
+    }
+
     #[test]
     fn test_full() {
         let clientname = "aw-client-rust-test";
 
-        let client: AwClient =
-            AwClient::new("127.0.0.1", PORT, clientname).expect("Client creation failed");
+        let client: AwClient = AwClient::builder("127.0.0.1", PORT, clientname)
+            .admin_token(ADMIN_TOKEN)
+            .build_blocking()
+            .expect("Client creation failed");
 
         let shutdown_handler = setup_testserver();
 
-        wait_for_server(20, &client);
+        client
+            .wait_until_ready(std::time::Duration::from_secs(20))
+            .expect("Timed out starting aw-server");
 
-        let info = client.get_info().unwrap();
+        let info = client
+            .with_request_id("aw-client-rust-test", |client| client.get_info())
+            .unwrap();
         assert!(info.testing);
+        let capabilities = client.capabilities().unwrap();
+        assert!(capabilities.query_streaming);
+        assert!(!capabilities.daily_aggregates);
+        assert!(capabilities
+            .query_functions
+            .contains(&"query_bucket".to_string()));
 
         let bucketname = format!("aw-client-rust-test_{}", client.hostname);
         let buckettype = "test-type";
@@ -82,6 +237,66 @@ mod test {
         assert!(bucket.id == bucketname);
         println!("{}", bucket.id);
 
+        // Bucket with custom data
+        let bucketname_data = format!("aw-client-rust-test-data_{}", client.hostname);
+        let mut data = Map::new();
+        data.insert("name".to_string(), "Test bucket".into());
+        client
+            .create_bucket_with_data(&bucketname_data, buckettype, data.clone())
+            .unwrap();
+        let bucket_data = client.get_bucket(&bucketname_data).unwrap();
+        assert_eq!(bucket_data.data, data);
+        client.delete_bucket(&bucketname_data).unwrap();
+
+        // Bucket with a schema version, which is only ever raised
+        let bucketname_versioned = format!("aw-client-rust-test-versioned_{}", client.hostname);
+        client
+            .ensure_bucket_versioned(&bucketname_versioned, buckettype, 1)
+            .unwrap();
+        let mut versioned = client.get_bucket(&bucketname_versioned).unwrap();
+        assert_eq!(versioned.schema_version(), Some(1));
+        versioned
+            .data
+            .insert("name".to_string(), "Versioned".into());
+        let (_, etag) = client.get_bucket_with_etag(&bucketname_versioned).unwrap();
+        let etag = etag.unwrap();
+        client
+            .patch_bucket(&bucketname_versioned, &versioned.data, Some(&etag))
+            .unwrap();
+        // The bucket has changed since the ETag was read
+        let res = client.patch_bucket(&bucketname_versioned, &versioned.data, Some(&etag));
+        assert!(matches!(res, Err(RequestError::PreconditionFailed(_))));
+        client
+            .insert_event(&bucketname_versioned, &Event::default())
+            .unwrap();
+        client
+            .ensure_bucket_versioned(&bucketname_versioned, buckettype, 3)
+            .unwrap();
+        client
+            .ensure_bucket_versioned(&bucketname_versioned, buckettype, 2)
+            .unwrap();
+        let versioned = client.get_bucket(&bucketname_versioned).unwrap();
+        assert_eq!(versioned.schema_version(), Some(3));
+        assert_eq!(versioned.data["name"], "Versioned");
+        assert_eq!(client.get_event_count(&bucketname_versioned).unwrap(), 1);
+        client.delete_bucket(&bucketname_versioned).unwrap();
+
+        // Create several buckets at once
+        let bulk_bucket = |id: &str| aw_client_rust::Bucket {
+            id: id.to_string(),
+            ..bucket.clone()
+        };
+        let bulk_name = format!("aw-client-rust-test-bulk_{}", client.hostname);
+        let results = client
+            .create_buckets(&[bulk_bucket(&bucketname), bulk_bucket(&bulk_name)], false)
+            .unwrap();
+        assert_eq!(results[&bucketname], BucketCreationResult::Skipped);
+        assert_eq!(results[&bulk_name], BucketCreationResult::Created);
+        assert!(client
+            .create_buckets(&[bulk_bucket(&bulk_name)], true)
+            .is_err());
+        client.delete_bucket(&bulk_name).unwrap();
+
         let buckets = client.get_buckets().unwrap();
         println!("Buckets: {buckets:?}");
         let mut event = Event {
@@ -96,7 +311,8 @@ mod test {
             data: Map::new(),
         };
         println!("{event:?}");
-        client.insert_event(&bucketname, &event).unwrap();
+        let inserted_id = client.insert_event(&bucketname, &event).unwrap();
+        assert!(inserted_id.is_some());
         // Ugly way to create a UTC from timestamp, see https://github.com/chronotope/chrono/issues/263
         event.timestamp = DateTime::from_utc(
             DateTime::parse_from_rfc3339("2017-12-30T01:00:01+00:00")
@@ -106,9 +322,46 @@ mod test {
         );
         client.heartbeat(&bucketname, &event, 10.0).unwrap();
 
-        let events = client.get_events(&bucketname, None, None, None).unwrap();
+        let events = client
+            .get_events(&bucketname, None, None, None, None, None)
+            .unwrap()
+            .unwrap();
         println!("Events: {events:?}");
         assert!(events[0].duration == Duration::seconds(1));
+        assert_eq!(client.head_event_count(&bucketname).unwrap(), 1);
+        let first = client.get_first_event(&bucketname).unwrap().unwrap();
+        let last = client.get_last_event(&bucketname).unwrap().unwrap();
+        assert_eq!(first.id, events[0].id);
+        assert_eq!(last.id, events[0].id);
+        assert_eq!(first.id, inserted_id);
+
+        // Bucket has not been modified since the last fetch, a date in the same second as the
+        // last change isn't enough as the server can't tell if it happened before or after it
+        let cached = client
+            .get_events(
+                &bucketname,
+                None,
+                None,
+                None,
+                None,
+                Some(Utc::now() + Duration::seconds(1)),
+            )
+            .unwrap();
+        assert!(cached.is_none());
+
+        let projected = client
+            .get_events_fields(&bucketname, None, None, None, &["duration", "data.missing"])
+            .unwrap();
+        assert_eq!(
+            serde_json::Value::Array(projected.into_iter().map(Into::into).collect()),
+            serde_json::json!([{"duration": 1.0, "data": {}}])
+        );
+
+        let after = client
+            .get_events_after_id(&bucketname, 0, Some(10))
+            .unwrap();
+        assert_eq!(after.len(), 1);
+        assert_eq!(after[0].id, inserted_id);
 
         // Query
         let query = format!(
@@ -126,15 +379,550 @@ RETURN = events;",
         let query_result = client.query(&query, vec![timeperiods]).unwrap();
         println!("Query result: {query_result:?}");
 
+        let mut patch = Map::new();
+        patch.insert("title".to_string(), "patched".into());
+        let patched = client
+            .patch_event(&bucketname, events[0].id.as_ref().unwrap(), &patch)
+            .unwrap();
+        assert_eq!(patched.data, patch);
+
+        let values = client
+            .get_distinct_values(&bucketname, "title", None, None)
+            .unwrap();
+        assert_eq!(values.len(), 1);
+        assert_eq!(values[0].value, "patched");
+        assert_eq!(values[0].count, 1);
+
+        let quality = client
+            .get_bucket_quality(&bucketname, None, None, None)
+            .unwrap();
+        assert_eq!(quality.event_count, 1);
+        assert_eq!(quality.overlaps.count, 0);
+
         client
-            .delete_event(&bucketname, events[0].id.unwrap())
+            .delete_event(&bucketname, events[0].id.as_ref().unwrap())
             .unwrap();
 
         let count = client.get_event_count(&bucketname).unwrap();
         assert_eq!(count, 0);
+        assert_eq!(client.head_event_count(&bucketname).unwrap(), 0);
+        assert!(client.get_first_event(&bucketname).unwrap().is_none());
+        let states = client.get_bucket_states().unwrap();
+        assert_eq!(states[&bucketname].event_count, 0);
+
+        // Import with the ids from the export
+        let export_of = |id: &str| aw_client_rust::BucketsExport {
+            buckets: [(
+                id.to_string(),
+                aw_client_rust::Bucket {
+                    id: id.to_string(),
+                    events: Some(aw_models::TryVec::new(vec![Event {
+                        id: Some(EventId::Int(1000)),
+                        ..event.clone()
+                    }])),
+                    ..bucket.clone()
+                },
+            )]
+            .into(),
+        };
+        let import_name = format!("aw-client-rust-test-import_{}", client.hostname);
+        client.import(&export_of(&import_name), true).unwrap();
+        let imported = client
+            .get_events(&import_name, None, None, None, None, None)
+            .unwrap()
+            .unwrap();
+        assert_eq!(imported[0].id, Some(EventId::Int(1000)));
+        let import_name2 = format!("{import_name}-2");
+        assert!(client.import(&export_of(&import_name2), true).is_err());
+        client.import(&export_of(&import_name2), false).unwrap();
+
+        // Clone a bucket, its events get new ids
+        let clone_name = format!("{import_name}-clone");
+        client.clone_bucket(&import_name, &clone_name).unwrap();
+        let cloned = client
+            .get_events(&clone_name, None, None, None, None, None)
+            .unwrap()
+            .unwrap();
+        assert_eq!(cloned.len(), 1);
+        assert_eq!(cloned[0].data, imported[0].data);
+        assert_ne!(cloned[0].id, imported[0].id);
+        assert_eq!(client.get_bucket(&clone_name).unwrap()._type, buckettype);
+        assert!(client.clone_bucket(&import_name, &clone_name).is_err());
+        assert!(client
+            .clone_bucket("nonexistent", "nonexistent-clone")
+            .is_err());
+        client.delete_bucket(&clone_name).unwrap();
+
+        // Stream the export of a bucket to a writer
+        let async_client = aw_client_rust::AwClient::new("127.0.0.1", PORT, clientname).unwrap();
+        let mut exported = Vec::new();
+        let written = block_on(async_client.export_bucket_to(&import_name, &mut exported)).unwrap();
+        assert_eq!(written, exported.len() as u64);
+        let mut export: aw_client_rust::BucketsExport = serde_json::from_slice(&exported).unwrap();
+        let exported_bucket = export.buckets.remove(&import_name).unwrap();
+        assert_eq!(exported_bucket.events.unwrap().take_inner().len(), 1);
+        assert!(export.buckets.is_empty());
+
+        // Follow the bucket, events at the same timestamp are each yielded once
+        let tailed = block_on(async {
+            let mut tail = Box::pin(
+                async_client.tail_events(&import_name, std::time::Duration::from_millis(50)),
+            );
+            let tail_event = |n: i64| {
+                let mut data = Map::new();
+                data.insert("n".to_string(), n.into());
+                Event {
+                    id: None,
+                    timestamp: event.timestamp + Duration::seconds(10),
+                    duration: Duration::seconds(0),
+                    data,
+                }
+            };
+            let insert = |events: Vec<Event>| async {
+                tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+                async_client
+                    .insert_events(&import_name, events)
+                    .await
+                    .unwrap();
+            };
+            let mut tailed = Vec::new();
+            let (first, _) =
+                futures_util::join!(tail.next(), insert(vec![tail_event(1), tail_event(2)]));
+            tailed.push(first.unwrap().unwrap());
+            tailed.push(tail.next().await.unwrap().unwrap());
+            let (third, _) = futures_util::join!(tail.next(), insert(vec![tail_event(3)]));
+            tailed.push(third.unwrap().unwrap());
+            tailed
+        });
+        let ns: Vec<_> = tailed
+            .iter()
+            .map(|e| e.data["n"].as_i64().unwrap())
+            .collect();
+        assert_eq!(ns, vec![1, 2, 3]);
+
+        // Stream a query, one item per event of the bucket
+        let streamed = block_on(async {
+            let query = format!("RETURN = query_bucket(\"{import_name}\");");
+            let timeperiod = (
+                DateTime::from_timestamp(0, 0).unwrap(),
+                Utc::now() + Duration::days(1),
+            );
+            let items = async_client
+                .query_streaming(&query, vec![timeperiod])
+                .await
+                .unwrap();
+            items.collect::<Vec<_>>().await
+        });
+        assert_eq!(streamed.len(), 4);
+        for item in streamed {
+            let (timeperiod, event) = item.unwrap();
+            assert_eq!(timeperiod, 0);
+            assert!(event["timestamp"].is_string());
+        }
+        let failed = block_on(async {
+            let timeperiod = (DateTime::from_timestamp(0, 0).unwrap(), Utc::now());
+            let items = async_client
+                .query_streaming("RETURN = undefined_variable;", vec![timeperiod])
+                .await
+                .unwrap();
+            items.collect::<Vec<_>>().await
+        });
+        assert_eq!(failed.len(), 1);
+        assert!(matches!(
+            failed[0],
+            Err(aw_client_rust::RequestError::Query(_))
+        ));
+
+        client.delete_bucket(&import_name).unwrap();
+        client.delete_bucket(&import_name2).unwrap();
 
         client.delete_bucket(&bucketname).unwrap();
 
+        let estimate_before = client.estimate_export_size().unwrap();
+
+        // Diff two buckets
+        let src = format!("aw-client-rust-test-src_{}", client.hostname);
+        let dst = format!("aw-client-rust-test-dst_{}", client.hostname);
+        client.create_bucket_simple(&src, buckettype).unwrap();
+        client.create_bucket_simple(&dst, buckettype).unwrap();
+        let make_event = |secs: i64, title: &str| {
+            let mut data = Map::new();
+            data.insert("title".to_string(), title.into());
+            Event {
+                id: None,
+                timestamp: DateTime::from_timestamp(secs, 0).unwrap(),
+                duration: Duration::seconds(1),
+                data,
+            }
+        };
+        let ids = client
+            .insert_events(
+                &src,
+                vec![make_event(0, "a"), make_event(10, "b"), make_event(20, "c")],
+            )
+            .unwrap();
+        // Ids are in the order of the inserted events
+        let stored = client
+            .get_events(&src, None, None, None, None, None)
+            .unwrap()
+            .unwrap();
+        let stored_ids: Vec<_> = stored.into_iter().rev().map(|e| e.id.unwrap()).collect();
+        assert_eq!(ids, stored_ids);
+        client
+            .insert_events(
+                &dst,
+                vec![make_event(0, "a"), make_event(10, "x"), make_event(30, "d")],
+            )
+            .unwrap();
+        // Estimated from the event counts of the buckets
+        let estimate = client.estimate_export_size().unwrap();
+        assert_eq!(estimate.buckets, estimate_before.buckets + 2);
+        assert_eq!(estimate.events, estimate_before.events + 6);
+        assert_eq!(
+            estimate.bytes - estimate_before.bytes,
+            2 * aw_client_rust::EXPORT_BUCKET_SIZE_ESTIMATE
+                + 6 * aw_client_rust::EXPORT_EVENT_SIZE_ESTIMATE
+        );
+        let diff = client.diff_buckets(&src, &dst, None, None).unwrap();
+        assert_eq!(diff.identical, 1);
+        assert_eq!(diff.only_in_src, vec![make_event(20, "c")]);
+        assert_eq!(diff.only_in_dst, vec![make_event(30, "d")]);
+        assert_eq!(diff.differing.len(), 1);
+        assert_eq!(diff.differing[0].0, make_event(10, "b"));
+        assert_eq!(diff.differing[0].1, make_event(10, "x"));
+        client.delete_bucket(&src).unwrap();
+        client.delete_bucket(&dst).unwrap();
+
+        // Streamed insert
+        let streamed = format!("aw-client-rust-test-streamed_{}", client.hostname);
+        client.create_bucket_simple(&streamed, buckettype).unwrap();
+        let events: Vec<Event> = (0..3).map(|i| make_event(i * 10, "s")).collect();
+        let inserted = client
+            .stream_insert_events(&streamed, events.clone())
+            .unwrap();
+        assert_eq!(inserted, 3);
+        let mut stored = client
+            .get_events(&streamed, None, None, None, None, None)
+            .unwrap()
+            .unwrap();
+        stored.reverse();
+        for event in stored.iter_mut() {
+            event.id = None;
+        }
+        assert_eq!(stored, events);
+        client.delete_bucket(&streamed).unwrap();
+
+        // Canonical activity
+        let window = format!("aw-watcher-window_{}", client.hostname);
+        let afk = format!("aw-watcher-afk_{}", client.hostname);
+        client
+            .create_bucket_simple(&window, "currentwindow")
+            .unwrap();
+        client.create_bucket_simple(&afk, "afkstatus").unwrap();
+        let make_data_event = |secs: i64, duration: i64, key: &str, value: &str| {
+            let mut data = Map::new();
+            data.insert(key.to_string(), value.into());
+            Event {
+                id: None,
+                timestamp: DateTime::from_timestamp(secs, 0).unwrap(),
+                duration: Duration::seconds(duration),
+                data,
+            }
+        };
+        client
+            .insert_events(
+                &window,
+                vec![
+                    make_data_event(0, 10, "app", "Editor \"1\""),
+                    make_data_event(10, 20, "app", "Browser"),
+                    make_data_event(30, 10, "app", "Editor \"1\""),
+                ],
+            )
+            .unwrap();
+        client
+            .insert_events(
+                &afk,
+                vec![
+                    make_data_event(0, 35, "status", "not-afk"),
+                    make_data_event(35, 5, "status", "afk"),
+                ],
+            )
+            .unwrap();
+        let categories = vec![(
+            vec!["Work".to_string()],
+            serde_json::json!({"type": "regex", "regex": "Editor \"\\d\""}),
+        )];
+        let activity = client
+            .get_canonical_activity(
+                &window,
+                &afk,
+                &categories,
+                DateTime::from_timestamp(0, 0).unwrap(),
+                DateTime::from_timestamp(100, 0).unwrap(),
+            )
+            .unwrap();
+        assert_eq!(activity.events.len(), 3);
+        assert_eq!(activity.duration, Duration::seconds(35));
+        assert_eq!(
+            activity.categories,
+            vec![
+                CategoryDuration {
+                    category: vec!["Uncategorized".to_string()],
+                    duration: Duration::seconds(20),
+                },
+                CategoryDuration {
+                    category: vec!["Work".to_string()],
+                    duration: Duration::seconds(15),
+                },
+            ]
+        );
+        client.delete_bucket(&window).unwrap();
+        client.delete_bucket(&afk).unwrap();
+
+        let vacuum = client.vacuum().unwrap();
+        assert!(vacuum.size_after <= vacuum.size_before);
+
+        shutdown_handler.notify();
+    }
+
+    #[test]
+    fn test_copy_bucket_to() {
+        // Two servers of their own, so the bucket can be copied under the same name
+        let (src_port, dest_port) = (PORT + 2, PORT + 3);
+        let shutdown_src = setup_testserver_at(src_port);
+        let shutdown_dest = setup_testserver_at(dest_port);
+        let src = AwClient::new("127.0.0.1", src_port, "aw-client-rust-test").unwrap();
+        let dest = AwClient::new("127.0.0.1", dest_port, "aw-client-rust-test").unwrap();
+        src.wait_until_ready(std::time::Duration::from_secs(20))
+            .unwrap();
+        dest.wait_until_ready(std::time::Duration::from_secs(20))
+            .unwrap();
+
+        let bucketname = "aw-client-rust-test-copy";
+        src.create_bucket_simple(bucketname, "test").unwrap();
+        let event = |secs: i64| Event {
+            id: None,
+            timestamp: DateTime::from_timestamp(secs, 0).unwrap(),
+            duration: Duration::seconds(1),
+            data: Map::new(),
+        };
+        // More than a page, with several events starting at the same time
+        let events: Vec<Event> = (0..aw_client_rust::COPY_PAGE_SIZE as i64 + 10)
+            .map(|i| event(i / 3))
+            .collect();
+        src.insert_events(bucketname, events.clone()).unwrap();
+
+        let mut pages = Vec::new();
+        let copied = src
+            .copy_bucket_to(bucketname, &dest, |n| pages.push(n))
+            .unwrap();
+        assert_eq!(copied, events.len() as u64);
+        assert_eq!(pages, vec![aw_client_rust::COPY_PAGE_SIZE, copied]);
+        assert_eq!(dest.get_bucket(bucketname).unwrap().id, bucketname);
+        assert_eq!(dest.get_event_count(bucketname).unwrap(), copied as i64);
+
+        // Copying again only copies the events added since
+        src.insert_events(bucketname, vec![event(10_000)]).unwrap();
+        let copied = src.copy_bucket_to(bucketname, &dest, |_| {}).unwrap();
+        assert_eq!(copied, 1);
+        assert_eq!(
+            dest.get_event_count(bucketname).unwrap(),
+            events.len() as i64 + 1
+        );
+
+        shutdown_src.notify();
+        shutdown_dest.notify();
+    }
+
+    #[test]
+    fn test_offline_queue() {
+        let port = PORT + 4;
+        let mut queue_path = std::env::temp_dir();
+        queue_path.push("aw-client-rust-test-queue.jsonl");
+        let _ = std::fs::remove_file(&queue_path);
+        let client = AwClient::builder("127.0.0.1", port, "aw-client-rust-test")
+            .offline_queue(&queue_path)
+            .offline_queue_max_len(3)
+            .build_blocking()
+            .unwrap();
+        let bucketname = "aw-client-rust-test-queue";
+        let event = |secs: i64| Event {
+            id: None,
+            timestamp: DateTime::from_timestamp(secs, 0).unwrap(),
+            duration: Duration::seconds(1),
+            data: Map::new(),
+        };
+
+        // No server is running yet, so the requests are queued
+        client.insert_event(bucketname, &event(0)).unwrap();
+        client.heartbeat(bucketname, &event(10), 5.0).unwrap();
+        assert_eq!(client.queue_len(), 2);
+        // The oldest request is dropped once the queue is full
+        client
+            .insert_events(bucketname, vec![event(20), event(30)])
+            .unwrap();
+        assert_eq!(client.queue_len(), 3);
+        assert!(queue_path.exists());
+
+        let shutdown_handler = setup_testserver_at(port);
+        let direct = AwClient::new("127.0.0.1", port, "aw-client-rust-test").unwrap();
+        direct
+            .wait_until_ready(std::time::Duration::from_secs(20))
+            .unwrap();
+        direct.create_bucket_simple(bucketname, "test").unwrap();
+        // As if the queued insert had reached the server, it isn't inserted twice
+        direct.insert_event(bucketname, &event(30)).unwrap();
+
+        // The queue is replayed in order before the next request
+        client.insert_event(bucketname, &event(40)).unwrap();
+        assert_eq!(client.queue_len(), 0);
+        assert!(!queue_path.exists());
+        assert_eq!(client.flush_queue().unwrap(), 0);
+        let events = direct
+            .get_events(bucketname, None, None, None, Some(true), None)
+            .unwrap()
+            .unwrap();
+        let timestamps: Vec<i64> = events.iter().map(|e| e.timestamp.timestamp()).collect();
+        assert_eq!(timestamps, vec![10, 20, 30, 40]);
+
+        shutdown_handler.notify();
+    }
+
+    #[test]
+    fn test_event_sink() {
+        let port = PORT + 5;
+        let shutdown_handler = setup_testserver_at(port);
+        let client =
+            aw_client_rust::AwClient::new("127.0.0.1", port, "aw-client-rust-test").unwrap();
+        let bucketname = "aw-client-rust-test-sink";
+        let event = |secs: i64| Event {
+            id: None,
+            timestamp: DateTime::from_timestamp(secs, 0).unwrap(),
+            duration: Duration::seconds(1),
+            data: Map::new(),
+        };
+
+        block_on(async {
+            client
+                .wait_until_ready(std::time::Duration::from_secs(20))
+                .await
+                .unwrap();
+            client
+                .create_bucket_simple(bucketname, "test")
+                .await
+                .unwrap();
+
+            let sink = client.event_sink(bucketname, 2, std::time::Duration::from_secs(60));
+            let events = futures_util::stream::iter((0..5).map(|i| Ok(event(i * 10))));
+            events.forward(sink).await.unwrap();
+            let inserted = client
+                .get_events(bucketname, None, None, None, None, None)
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(inserted.len(), 5);
+
+            // Errors of inserting a batch are returned through the sink
+            let unreachable =
+                aw_client_rust::AwClient::new("127.0.0.1", PORT + 1, "aw-client-rust-test")
+                    .unwrap();
+            let sink = unreachable.event_sink(bucketname, 2, std::time::Duration::from_secs(60));
+            let events = futures_util::stream::iter((0..5).map(|i| Ok(event(i * 10))));
+            assert!(events.forward(sink).await.is_err());
+        });
+
+        shutdown_handler.notify();
+    }
+
+    #[test]
+    fn test_events_iter() {
+        let port = PORT + 6;
+        let shutdown_handler = setup_testserver_at(port);
+        let client = AwClient::new("127.0.0.1", port, "aw-client-rust-test").unwrap();
+        client
+            .wait_until_ready(std::time::Duration::from_secs(20))
+            .unwrap();
+        let bucketname = "aw-client-rust-test-iter";
+        client.create_bucket_simple(bucketname, "test").unwrap();
+        let event = |secs: i64| Event {
+            id: None,
+            timestamp: DateTime::from_timestamp(secs, 0).unwrap(),
+            duration: Duration::seconds(1),
+            data: Map::new(),
+        };
+        // Several events at the same time span the boundary between pages
+        let mut events: Vec<Event> = (0..20).map(|i| event(i * 10)).collect();
+        events.extend((0..5).map(|_| event(95)));
+        client.insert_events(bucketname, events).unwrap();
+
+        let timestamps: Vec<i64> = client
+            .events_iter(bucketname, None, None, 4)
+            .map(|e| e.unwrap().timestamp.timestamp())
+            .collect();
+        assert_eq!(timestamps.len(), 25);
+        assert!(timestamps.windows(2).all(|pair| pair[0] <= pair[1]));
+
+        let start = DateTime::from_timestamp(50, 0);
+        let stop = DateTime::from_timestamp(100, 0);
+        assert_eq!(client.events_iter(bucketname, start, stop, 3).count(), 11);
+
+        // A permanent error is yielded once and ends the iteration
+        let results: Vec<_> = client
+            .events_iter("aw-client-rust-test-missing", None, None, 4)
+            .collect();
+        assert_eq!(results.len(), 1);
+        assert!(results[0].is_err());
+
+        shutdown_handler.notify();
+    }
+
+    #[test]
+    fn test_wait_for_event() {
+        let port = PORT + 7;
+        let shutdown_handler = setup_testserver_at(port);
+        let client = AwClient::new("127.0.0.1", port, "aw-client-rust-test").unwrap();
+        client
+            .wait_until_ready(std::time::Duration::from_secs(20))
+            .unwrap();
+        let bucketname = "aw-client-rust-test-wait";
+        client.create_bucket_simple(bucketname, "test").unwrap();
+        let event = |secs: i64| Event {
+            id: None,
+            timestamp: DateTime::from_timestamp(secs, 0).unwrap(),
+            duration: Duration::seconds(20),
+            data: Map::new(),
+        };
+        client
+            .insert_events(bucketname, vec![event(40), event(60), event(50)])
+            .unwrap();
+        let after = |secs: i64| DateTime::from_timestamp(secs, 0).unwrap();
+        let timeout = std::time::Duration::from_secs(10);
+
+        // Already there, the earliest one after the cursor and not the one overlapping it
+        let found = client
+            .wait_for_event(bucketname, after(45), timeout)
+            .unwrap();
+        assert_eq!(found.unwrap().timestamp, after(50));
+
+        // Inserted while waiting
+        let inserter = std::thread::spawn(move || {
+            let client = AwClient::new("127.0.0.1", port, "aw-client-rust-test").unwrap();
+            std::thread::sleep(std::time::Duration::from_millis(500));
+            client
+                .insert_events(bucketname, vec![event(90), event(80)])
+                .unwrap();
+        });
+        let found = client
+            .wait_for_event(bucketname, after(60), timeout)
+            .unwrap();
+        assert_eq!(found.unwrap().timestamp, after(80));
+        inserter.join().unwrap();
+
+        let found = client
+            .wait_for_event(bucketname, after(90), std::time::Duration::from_millis(300))
+            .unwrap();
+        assert!(found.is_none());
+
         shutdown_handler.notify();
     }
 }
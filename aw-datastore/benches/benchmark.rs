@@ -0,0 +1,123 @@
+use criterion::{criterion_group, criterion_main};
+
+#[macro_use]
+extern crate aw_datastore;
+
+#[cfg(test)]
+mod datastore_benchmarks {
+    use chrono::Duration;
+    use criterion::Criterion;
+    use serde_json::json;
+
+    use aw_datastore::Datastore;
+    use aw_models::Bucket;
+    use aw_models::BucketMetadata;
+    use aw_models::Event;
+
+    static BUCKETNAME: &str = "testbucket";
+
+    fn setup_datastore(compress_event_data: bool) -> Datastore {
+        let ds = Datastore::new_in_memory(false);
+        ds.set_compress_event_data(compress_event_data).unwrap();
+        let bucket = Bucket {
+            bid: None,
+            id: BUCKETNAME.to_string(),
+            _type: "testtype".to_string(),
+            client: "testclient".to_string(),
+            hostname: "testhost".to_string(),
+            created: Some(chrono::Utc::now()),
+            data: json_map! {},
+            metadata: BucketMetadata::default(),
+            events: None,
+            last_updated: None,
+        };
+        ds.create_bucket(&bucket).unwrap();
+
+        // Events with large data, similar to a watcher storing full page text
+        let text = "Lorem ipsum dolor sit amet, consectetur adipiscing elit. ".repeat(200);
+        let mut event_list = Vec::new();
+        for i in 0..1000 {
+            event_list.push(Event {
+                id: None,
+                timestamp: chrono::Utc::now() + Duration::seconds(i),
+                duration: Duration::seconds(1),
+                data: json_map! {"number": i, "text": text},
+            });
+        }
+        ds.insert_events(BUCKETNAME, &event_list).unwrap();
+        ds
+    }
+
+    pub fn bench_get_events_uncompressed(c: &mut Criterion) {
+        let ds = setup_datastore(false);
+        c.bench_function("bench get events uncompressed", |b| {
+            b.iter(|| ds.get_events(BUCKETNAME, None, None, None).unwrap())
+        });
+    }
+
+    pub fn bench_get_events_compressed(c: &mut Criterion) {
+        let ds = setup_datastore(true);
+        c.bench_function("bench get events compressed", |b| {
+            b.iter(|| ds.get_events(BUCKETNAME, None, None, None).unwrap())
+        });
+    }
+
+    /// Inserts single events as a watcher would, reporting how many commits they took
+    fn bench_inserts(c: &mut Criterion, name: &str, window: Option<std::time::Duration>) {
+        let path = std::env::temp_dir().join(format!("aw-datastore-bench-{name}.db"));
+        let _ = std::fs::remove_file(&path);
+        let ds = Datastore::new(path.to_str().unwrap().to_string(), false);
+        ds.set_insert_coalescing(window).unwrap();
+        let bucket = Bucket {
+            bid: None,
+            id: BUCKETNAME.to_string(),
+            _type: "testtype".to_string(),
+            client: "testclient".to_string(),
+            hostname: "testhost".to_string(),
+            created: Some(chrono::Utc::now()),
+            data: json_map! {},
+            metadata: BucketMetadata::default(),
+            events: None,
+            last_updated: None,
+        };
+        ds.create_bucket(&bucket).unwrap();
+
+        let commits_before = ds.stats().commits();
+        let mut inserted = 0;
+        c.bench_function(&format!("bench inserts {name}"), |b| {
+            b.iter(|| {
+                let event = Event {
+                    id: None,
+                    timestamp: chrono::Utc::now(),
+                    duration: Duration::seconds(1),
+                    data: json_map! {"number": inserted},
+                };
+                inserted += 1;
+                ds.insert_events(BUCKETNAME, &[event]).unwrap()
+            })
+        });
+        println!(
+            "{} commits for {} inserts",
+            ds.stats().commits() - commits_before,
+            inserted
+        );
+        let _ = std::fs::remove_file(&path);
+    }
+
+    pub fn bench_inserts_uncoalesced(c: &mut Criterion) {
+        bench_inserts(c, "uncoalesced", None);
+    }
+
+    pub fn bench_inserts_coalesced(c: &mut Criterion) {
+        bench_inserts(c, "coalesced", Some(std::time::Duration::from_millis(50)));
+    }
+}
+
+criterion_group!(
+    benches,
+    datastore_benchmarks::bench_get_events_uncompressed,
+    datastore_benchmarks::bench_get_events_compressed,
+    datastore_benchmarks::bench_inserts_uncoalesced,
+    datastore_benchmarks::bench_inserts_coalesced
+);
+criterion_main!(benches);
@@ -0,0 +1,305 @@
+//! Precomputed totals of event time per bucket, local day and category, see
+//! `Datastore::set_daily_aggregates`
+//!
+//! The `daily_aggregates` table has a row per bucket, day and category with the time the events
+//! of that category have in the day. The category of an event is the value of the configured
+//! key of its data, and days are the days of the configured timezone, events crossing midnight
+//! being split between the days. Reading it instead of the events is much cheaper for long
+//! ranges, as a day of heartbeats only has as many rows as it has categories.
+//!
+//! While enabled, the datastore updates the rows of every event it inserts, changes or deletes
+//! in the same transaction as the write, subtracting the old time of an event before adding the
+//! new one. The settings are stored in `daily_aggregates_settings`, so that the aggregates are
+//! kept up to date whenever the database is opened. Disabling clears both tables, enabling
+//! again or with other settings rebuilds the aggregates from all events.
+use std::collections::{BTreeMap, HashMap};
+
+use aw_models::Event;
+use aw_transform::start_of_day;
+use chrono::{DateTime, Duration, Local, NaiveDate, Utc};
+use rusqlite::types::ToSql;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde_json::{Map, Value};
+
+use crate::datastore::decode_event_data;
+use crate::DatastoreError;
+
+/// What the daily aggregates total events by
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DailyAggregates {
+    /// Key of the event data whose value is the category of an event, events without it count
+    /// as the category `null`
+    pub key: String,
+    /// Timezone of the days, the timezone of the system if `None`
+    pub timezone: Option<chrono_tz::Tz>,
+}
+
+/// Time in nanoseconds per day and category
+type Totals = HashMap<(NaiveDate, String), i64>;
+
+/// The bucket, start, end and data of a stored event
+type EventRow = (i64, DateTime<Utc>, DateTime<Utc>, Map<String, Value>);
+
+fn internal_error(context: &str, err: rusqlite::Error) -> DatastoreError {
+    DatastoreError::InternalError(format!("Failed to {context}: {err}"))
+}
+
+impl DailyAggregates {
+    fn day_of(&self, time: DateTime<Utc>) -> NaiveDate {
+        match &self.timezone {
+            Some(tz) => time.with_timezone(tz).date_naive(),
+            None => time.with_timezone(&Local).date_naive(),
+        }
+    }
+
+    fn start_of_day(&self, day: NaiveDate) -> DateTime<Utc> {
+        match &self.timezone {
+            Some(tz) => start_of_day(day, tz),
+            None => start_of_day(day, &Local),
+        }
+    }
+
+    fn category(&self, data: &Map<String, Value>) -> String {
+        serde_json::to_string(data.get(&self.key).unwrap_or(&Value::Null)).unwrap()
+    }
+
+    /// Adds the time from `start` to `end` of an event with `data` to `totals`, split by day
+    fn add_to(
+        &self,
+        totals: &mut Totals,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        data: &Map<String, Value>,
+        sign: i64,
+    ) {
+        let category = self.category(data);
+        let mut time = start;
+        while time < end {
+            let day = self.day_of(time);
+            let day_end = self.start_of_day(day.succ_opt().unwrap()).min(end);
+            let nanos = (day_end - time).num_nanoseconds().unwrap();
+            *totals.entry((day, category.clone())).or_default() += sign * nanos;
+            time = day_end;
+        }
+    }
+}
+
+/// Adds `totals` to the rows of the bucket, dropping rows which are left without time
+fn apply(conn: &Connection, bucketrow: i64, totals: Totals) -> Result<(), DatastoreError> {
+    let mut upsert = conn
+        .prepare_cached(
+            "INSERT INTO daily_aggregates (bucketrow, day, category, duration)
+                VALUES (?1, ?2, ?3, ?4)
+                ON CONFLICT (bucketrow, day, category)
+                    DO UPDATE SET duration = duration + excluded.duration",
+        )
+        .map_err(|err| internal_error("prepare daily aggregates update", err))?;
+    let mut delete_empty = conn
+        .prepare_cached(
+            "DELETE FROM daily_aggregates
+                WHERE bucketrow = ?1 AND day = ?2 AND category = ?3 AND duration <= 0",
+        )
+        .map_err(|err| internal_error("prepare daily aggregates update", err))?;
+    for ((day, category), duration) in totals {
+        if duration == 0 {
+            continue;
+        }
+        upsert
+            .execute(params![bucketrow, day, category, duration])
+            .and_then(|_| delete_empty.execute(params![bucketrow, day, category]))
+            .map_err(|err| internal_error("update daily aggregates", err))?;
+    }
+    Ok(())
+}
+
+/// Reads the settings the aggregates were built with, `None` if they are disabled
+pub(crate) fn load(conn: &Connection) -> Result<Option<DailyAggregates>, DatastoreError> {
+    let row = conn
+        .query_row(
+            "SELECT key, timezone FROM daily_aggregates_settings",
+            [],
+            |row| Ok((row.get::<_, String>(0)?, row.get::<_, Option<String>>(1)?)),
+        )
+        .optional()
+        .map_err(|err| internal_error("read daily aggregates settings", err))?;
+    match row {
+        Some((key, timezone)) => {
+            let timezone = match timezone.map(|tz| tz.parse::<chrono_tz::Tz>()) {
+                Some(Ok(tz)) => Some(tz),
+                Some(Err(err)) => {
+                    return Err(DatastoreError::InternalError(format!(
+                        "Invalid timezone of daily aggregates: {err}"
+                    )))
+                }
+                None => None,
+            };
+            Ok(Some(DailyAggregates { key, timezone }))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Stores the settings and builds the aggregates with them, or clears both if `None`
+pub(crate) fn configure(
+    conn: &Connection,
+    settings: Option<&DailyAggregates>,
+) -> Result<(), DatastoreError> {
+    conn.execute_batch(
+        "DELETE FROM daily_aggregates;
+        DELETE FROM daily_aggregates_settings;",
+    )
+    .map_err(|err| internal_error("clear daily aggregates", err))?;
+    if let Some(settings) = settings {
+        conn.execute(
+            "INSERT INTO daily_aggregates_settings (key, timezone) VALUES (?1, ?2)",
+            params![settings.key, settings.timezone.map(|tz| tz.name())],
+        )
+        .map_err(|err| internal_error("store daily aggregates settings", err))?;
+        rebuild(conn, settings)?;
+    }
+    Ok(())
+}
+
+/// Recomputes the aggregates of every day of every bucket from the events
+pub(crate) fn rebuild(conn: &Connection, settings: &DailyAggregates) -> Result<(), DatastoreError> {
+    conn.execute("DELETE FROM daily_aggregates", [])
+        .map_err(|err| internal_error("rebuild daily aggregates", err))?;
+    let mut stmt = conn
+        .prepare("SELECT bucketrow, starttime, endtime, data FROM events")
+        .map_err(|err| internal_error("prepare daily aggregates rebuild", err))?;
+    let mut rows = stmt
+        .query([])
+        .map_err(|err| internal_error("read events", err))?;
+    let mut totals: HashMap<i64, Totals> = HashMap::new();
+    while let Some(row) = rows
+        .next()
+        .map_err(|err| internal_error("read events", err))?
+    {
+        let (bucketrow, start, end, data) =
+            read_event_row(row).map_err(|err| internal_error("read events", err))?;
+        settings.add_to(totals.entry(bucketrow).or_default(), start, end, &data, 1);
+    }
+    for (bucketrow, totals) in totals {
+        apply(conn, bucketrow, totals)?;
+    }
+    Ok(())
+}
+
+fn read_event_row(row: &rusqlite::Row) -> rusqlite::Result<EventRow> {
+    Ok((
+        row.get(0)?,
+        DateTime::from_timestamp_nanos(row.get(1)?),
+        DateTime::from_timestamp_nanos(row.get(2)?),
+        decode_event_data(row.get_ref(3)?)?,
+    ))
+}
+
+/// Adds the time of events about to be inserted into the bucket
+pub(crate) fn add_events(
+    conn: &Connection,
+    settings: &DailyAggregates,
+    bucketrow: i64,
+    events: &[Event],
+) -> Result<(), DatastoreError> {
+    let mut totals = HashMap::new();
+    for event in events {
+        let end = event.timestamp + event.duration;
+        settings.add_to(&mut totals, event.timestamp, end, &event.data, 1);
+    }
+    apply(conn, bucketrow, totals)
+}
+
+/// Subtracts the time of the stored events matching `condition`, before they are replaced,
+/// changed or deleted
+///
+/// `condition` is a SQL condition on the events table with `params`. The events are subtracted
+/// from the bucket they are in, which isn't necessarily the one written to when an event is
+/// replaced by its id.
+pub(crate) fn remove_events(
+    conn: &Connection,
+    settings: &DailyAggregates,
+    condition: &str,
+    params: &[&dyn ToSql],
+) -> Result<(), DatastoreError> {
+    let mut stmt = conn
+        .prepare_cached(&format!(
+            "SELECT bucketrow, starttime, endtime, data FROM events WHERE {condition}"
+        ))
+        .map_err(|err| internal_error("prepare daily aggregates update", err))?;
+    let rows = stmt
+        .query_map(params, read_event_row)
+        .and_then(|rows| rows.collect::<rusqlite::Result<Vec<_>>>())
+        .map_err(|err| internal_error("read replaced events", err))?;
+    let mut totals: HashMap<i64, Totals> = HashMap::new();
+    for (bucketrow, start, end, data) in rows {
+        settings.add_to(totals.entry(bucketrow).or_default(), start, end, &data, -1);
+    }
+    for (bucketrow, totals) in totals {
+        apply(conn, bucketrow, totals)?;
+    }
+    Ok(())
+}
+
+/// Drops the aggregates of a deleted bucket
+pub(crate) fn remove_bucket(conn: &Connection, bucketrow: i64) -> Result<(), DatastoreError> {
+    conn.execute(
+        "DELETE FROM daily_aggregates WHERE bucketrow = ?1",
+        [bucketrow],
+    )
+    .map(|_| ())
+    .map_err(|err| internal_error("delete daily aggregates", err))
+}
+
+/// An event per local day and category of the bucket, oldest day first, with the start of the
+/// day as timestamp, the category as the value of the key in the data and the time of the
+/// events of that category in the day as duration
+///
+/// The days overlapping `start` to `end` are included as a whole, so events of the same day
+/// outside of the range are counted too.
+pub(crate) fn get(
+    conn: &Connection,
+    settings: &DailyAggregates,
+    bucketrow: i64,
+    start: Option<DateTime<Utc>>,
+    end: Option<DateTime<Utc>>,
+) -> Result<Vec<Event>, DatastoreError> {
+    let first_day = start.map(|start| settings.day_of(start));
+    let last_day = end.map(|end| settings.day_of(end - Duration::nanoseconds(1)));
+    let mut stmt = conn
+        .prepare_cached(
+            "SELECT day, category, duration FROM daily_aggregates
+                WHERE bucketrow = ?1
+                    AND (?2 IS NULL OR day >= ?2)
+                    AND (?3 IS NULL OR day <= ?3)",
+        )
+        .map_err(|err| internal_error("prepare daily aggregates query", err))?;
+    let rows = stmt
+        .query_map(params![bucketrow, first_day, last_day], |row| {
+            Ok((
+                row.get::<_, NaiveDate>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, i64>(2)?,
+            ))
+        })
+        .and_then(|rows| rows.collect::<rusqlite::Result<Vec<_>>>())
+        .map_err(|err| internal_error("read daily aggregates", err))?;
+
+    let mut aggregates: BTreeMap<(NaiveDate, String), Event> = BTreeMap::new();
+    for (day, category, duration) in rows {
+        let value: Value = serde_json::from_str(&category).map_err(|err| {
+            DatastoreError::InternalError(format!("Invalid daily aggregate category: {err}"))
+        })?;
+        let mut data = Map::new();
+        data.insert(settings.key.clone(), value);
+        aggregates.insert(
+            (day, category),
+            Event {
+                id: None,
+                timestamp: settings.start_of_day(day),
+                duration: Duration::nanoseconds(duration),
+                data,
+            },
+        );
+    }
+    Ok(aggregates.into_values().collect())
+}
@@ -1,4 +1,5 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
 
 use chrono::DateTime;
 use chrono::Duration;
@@ -9,169 +10,194 @@ use rusqlite::Connection;
 use serde_json::value::Value;
 
 use aw_models::Bucket;
+use aw_models::BucketCreationResult;
 use aw_models::BucketMetadata;
+use aw_models::BucketState;
+use aw_models::DistinctValue;
 use aw_models::Event;
+use aw_models::EventId;
+use aw_models::TryVec;
+use aw_models::VacuumResult;
 
 use rusqlite::params;
 use rusqlite::types::ToSql;
+use rusqlite::types::ValueRef;
 
 use super::DatastoreError;
-
-fn _get_db_version(conn: &Connection) -> i32 {
-    conn.pragma_query_value(None, "user_version", |row| row.get(0))
-        .unwrap()
-}
-
-/*
- * ### Database version changelog ###
- * 0: Uninitialized database
- * 1: Initialized database
- * 2: Added 'data' field to 'buckets' table
- * 3: see: https://github.com/ActivityWatch/aw-server-rust/pull/52
- * 4: Added 'key_value' table for storing key - value pairs
- */
-static NEWEST_DB_VERSION: i32 = 4;
-
-fn _create_tables(conn: &Connection, version: i32) -> bool {
-    let mut first_init = false;
-
-    if version < 1 {
-        first_init = true;
-        _migrate_v0_to_v1(conn);
-    }
-
-    if version < 2 {
-        _migrate_v1_to_v2(conn);
+use super::DatastoreStats;
+use crate::daily_aggregates::{self, DailyAggregates};
+use crate::migrations;
+
+/// Events with a UUID are identified by it instead of their row id
+fn event_id_from_row(rowid: i64, uuid: Option<String>) -> EventId {
+    match uuid {
+        Some(uuid) => EventId::Uuid(uuid),
+        None => EventId::Int(rowid),
     }
+}
 
-    if version < 3 {
-        _migrate_v2_to_v3(conn);
-    }
+/// Reads an event from a row of `SELECT id, uuid, starttime, endtime, data`
+fn event_from_row(row: &rusqlite::Row) -> rusqlite::Result<Event> {
+    let id = event_id_from_row(row.get(0)?, row.get(1)?);
+    let starttime_ns: i64 = row.get(2)?;
+    let endtime_ns: i64 = row.get(3)?;
+    let data = decode_event_data(row.get_ref(4)?)?;
+
+    let time_seconds: i64 = starttime_ns / 1_000_000_000;
+    let time_subnanos: u32 = (starttime_ns % 1_000_000_000) as u32;
+    let duration_ns = endtime_ns - starttime_ns;
+
+    Ok(Event {
+        id: Some(id),
+        timestamp: DateTime::from_timestamp(time_seconds, time_subnanos).unwrap(),
+        duration: Duration::nanoseconds(duration_ns),
+        data,
+    })
+}
 
-    if version < 4 {
-        _migrate_v3_to_v4(conn);
+/// SQL parameters matching either the `id` or the `uuid` column, the other one is NULL
+fn event_id_params(event_id: &EventId) -> (Option<i64>, Option<&str>) {
+    match event_id {
+        EventId::Int(id) => (Some(*id), None),
+        EventId::Uuid(uuid) => (None, Some(uuid.as_str())),
     }
-
-    first_init
 }
 
-fn _migrate_v0_to_v1(conn: &Connection) {
-    /* Set up bucket table */
-    conn.execute(
-        "
-        CREATE TABLE IF NOT EXISTS buckets (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            name TEXT UNIQUE NOT NULL,
-            type TEXT NOT NULL,
-            client TEXT NOT NULL,
-            hostname TEXT NOT NULL,
-            created TEXT NOT NULL
-        )",
-        &[] as &[&dyn ToSql],
-    )
-    .expect("Failed to create buckets table");
-
-    /* Set up index for bucket table */
-    conn.execute(
-        "CREATE INDEX IF NOT EXISTS bucket_id_index ON buckets(id)",
-        &[] as &[&dyn ToSql],
-    )
-    .expect("Failed to create buckets index");
-
-    /* Set up events table */
-    conn.execute(
-        "
-        CREATE TABLE IF NOT EXISTS events (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            bucketrow INTEGER NOT NULL,
-            starttime INTEGER NOT NULL,
-            endtime INTEGER NOT NULL,
-            data TEXT NOT NULL,
-            FOREIGN KEY (bucketrow) REFERENCES buckets(id)
-        )",
-        &[] as &[&dyn ToSql],
-    )
-    .expect("Failed to create events table");
-
-    /* Set up index for events table */
-    conn.execute(
-        "CREATE INDEX IF NOT EXISTS events_bucketrow_index ON events(bucketrow)",
-        &[] as &[&dyn ToSql],
-    )
-    .expect("Failed to create events_bucketrow index");
-    conn.execute(
-        "CREATE INDEX IF NOT EXISTS events_starttime_index ON events(starttime)",
-        &[] as &[&dyn ToSql],
-    )
-    .expect("Failed to create events_starttime index");
-    conn.execute(
-        "CREATE INDEX IF NOT EXISTS events_endtime_index ON events(endtime)",
-        &[] as &[&dyn ToSql],
+/// SQL condition on events overlapping the interval from `?2` to `?3` in nanoseconds, including
+/// events which only touch it
+const OVERLAPS_INTERVAL: &str = "endtime >= ?2 AND starttime <= ?3";
+
+/// The bounds of an event query in nanoseconds as the parameters of `OVERLAPS_INTERVAL`, from
+/// the epoch and to the end of time if not given
+fn interval_filter_ns(
+    starttime_opt: Option<DateTime<Utc>>,
+    endtime_opt: Option<DateTime<Utc>>,
+) -> (i64, i64) {
+    (
+        starttime_opt.map_or(0, |dt| dt.timestamp_nanos_opt().unwrap()),
+        endtime_opt.map_or(i64::MAX, |dt| dt.timestamp_nanos_opt().unwrap()),
     )
-    .expect("Failed to create events_endtime index");
+}
 
-    /* Update database version */
-    conn.pragma_update(None, "user_version", 1)
-        .expect("Failed to update database version!");
+/// How the events returned by `get_events` are matched and ordered
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GetEventsOptions {
+    /// Include events which only touch the end of the interval, see `get_events`
+    pub inclusive_end: bool,
+    /// Return the oldest event first instead of the newest
+    pub ascending: bool,
 }
 
-fn _migrate_v1_to_v2(conn: &Connection) {
-    info!("Upgrading database to v2, adding data field to buckets");
-    conn.execute(
-        "ALTER TABLE buckets ADD COLUMN data TEXT DEFAULT '{}';",
-        &[] as &[&dyn ToSql],
-    )
-    .expect("Failed to upgrade database when adding data field to buckets");
+impl Default for GetEventsOptions {
+    fn default() -> Self {
+        GetEventsOptions {
+            inclusive_end: true,
+            ascending: false,
+        }
+    }
+}
 
-    conn.pragma_update(None, "user_version", 2)
-        .expect("Failed to update database version!");
+/// Event data is only compressed if its JSON is larger than this many bytes
+const COMPRESSION_THRESHOLD: usize = 4096;
+const COMPRESSION_LEVEL: i32 = 3;
+/// Compressed event data is stored as a blob starting with a format version byte,
+/// uncompressed event data is stored as text like before
+const COMPRESSED_DATA_V1: u8 = 1;
+
+fn encode_event_data(
+    data: &serde_json::map::Map<String, Value>,
+    compress: bool,
+) -> Result<rusqlite::types::Value, DatastoreError> {
+    let data_str = serde_json::to_string(data).unwrap();
+    if !compress || data_str.len() <= COMPRESSION_THRESHOLD {
+        return Ok(rusqlite::types::Value::Text(data_str));
+    }
+    let mut blob = vec![COMPRESSED_DATA_V1];
+    match zstd::stream::copy_encode(data_str.as_bytes(), &mut blob, COMPRESSION_LEVEL) {
+        Ok(()) => Ok(rusqlite::types::Value::Blob(blob)),
+        Err(err) => Err(DatastoreError::InternalError(format!(
+            "Failed to compress event data: {err}"
+        ))),
+    }
 }
 
-fn _migrate_v2_to_v3(conn: &Connection) {
-    // For details about why this migration was necessary, see: https://github.com/ActivityWatch/aw-server-rust/pull/52
-    info!("Upgrading database to v3, replacing the broken data field for buckets");
-
-    // Rename column, marking it as deprecated
-    match conn.execute(
-        "ALTER TABLE buckets RENAME COLUMN data TO data_deprecated;",
-        &[] as &[&dyn ToSql],
-    ) {
-        Ok(_) => (),
-        // This error is okay, it still has the intended effects
-        Err(rusqlite::Error::ExecuteReturnedResults) => (),
-        Err(e) => panic!("Unexpected error: {e:?}"),
+pub(crate) fn decode_event_data(
+    value: ValueRef,
+) -> Result<serde_json::map::Map<String, Value>, rusqlite::Error> {
+    let parsed = match value {
+        ValueRef::Text(text) => serde_json::from_slice(text),
+        ValueRef::Blob(blob) => match blob.split_first() {
+            Some((&COMPRESSED_DATA_V1, compressed)) => match zstd::stream::decode_all(compressed) {
+                Ok(decompressed) => serde_json::from_slice(&decompressed),
+                Err(e) => {
+                    return Err(rusqlite::Error::InvalidColumnName(format!(
+                        "Failed to decompress event data: {e:?}"
+                    )))
+                }
+            },
+            _ => {
+                return Err(rusqlite::Error::InvalidColumnName(
+                    "Unknown event data format".to_string(),
+                ))
+            }
+        },
+        _ => {
+            return Err(rusqlite::Error::InvalidColumnType(
+                4,
+                "data".to_string(),
+                value.data_type(),
+            ))
+        }
     };
+    parsed.map_err(|e| {
+        rusqlite::Error::InvalidColumnName(format!("Failed to parse data to JSON: {e:?}"))
+    })
+}
 
-    // Create new correct column
-    conn.execute(
-        "ALTER TABLE buckets ADD COLUMN data TEXT NOT NULL DEFAULT '{}';",
-        &[] as &[&dyn ToSql],
-    )
-    .expect("Failed to upgrade database when adding new data field to buckets");
-
-    conn.pragma_update(None, "user_version", 3)
-        .expect("Failed to update database version!");
+/// Applies a JSON merge patch as described in RFC 7386
+fn merge_patch(target: &mut Value, patch: &Value) {
+    match patch {
+        Value::Object(patch_map) => {
+            if !target.is_object() {
+                *target = Value::Object(serde_json::map::Map::new());
+            }
+            let target_map = target.as_object_mut().unwrap();
+            for (key, value) in patch_map {
+                if value.is_null() {
+                    target_map.remove(key);
+                } else {
+                    merge_patch(target_map.entry(key.clone()).or_insert(Value::Null), value);
+                }
+            }
+        }
+        _ => *target = patch.clone(),
+    }
 }
 
-fn _migrate_v3_to_v4(conn: &Connection) {
-    info!("Upgrading database to v4, adding table for key-value storage");
-    conn.execute(
-        "CREATE TABLE key_value (
-        key TEXT PRIMARY KEY,
-        value TEXT,
-        last_modified NUMBER NOT NULL
-    );",
-        [],
-    )
-    .expect("Failed to upgrade db and add key-value storage table");
+/// Key in the data of a bucket which, if set to true, makes inserting an event which starts before
+/// the latest event of the bucket fail with `EventOutOfOrder`
+pub const MONOTONIC_TIMESTAMPS_KEY: &str = "monotonic_timestamps";
 
-    conn.pragma_update(None, "user_version", 4)
-        .expect("Failed to update database version!");
-}
+/// Heartbeats identical to the last one received this recently are treated as retries of it
+const HEARTBEAT_REPLAY_WINDOW_SECS: i64 = 30;
 
 pub struct DatastoreInstance {
     buckets_cache: HashMap<String, Bucket>,
     first_init: bool,
+    compress_event_data: bool,
+    uuid_event_ids: bool,
+    /// The last heartbeat of each bucket and when it was received, once its write is committed
+    /// and as long as no other write to the events of the bucket followed it
+    last_heartbeats: HashMap<String, (Event, DateTime<Utc>)>,
+    /// Heartbeats written in the current transaction, see `commit_heartbeats`
+    uncommitted_heartbeats: HashMap<String, (Event, DateTime<Utc>)>,
+    /// Counters of the `Datastore` this instance belongs to
+    stats: Arc<DatastoreStats>,
+    /// What the daily aggregates are totaled by, `None` while they are disabled
+    daily_aggregates: Option<DailyAggregates>,
+    /// Total number of events per bucket, filled on first use and dropped whenever the events of
+    /// the bucket are inserted or deleted
+    event_counts: HashMap<String, i64>,
     pub db_version: i32,
 }
 
@@ -181,25 +207,35 @@ impl DatastoreInstance {
         migrate_enabled: bool,
     ) -> Result<DatastoreInstance, DatastoreError> {
         let mut first_init = false;
-        let db_version = _get_db_version(conn);
+        let mut db_version = migrations::get_version(conn);
+        let newest_version = migrations::newest_schema_version();
 
         if migrate_enabled {
-            first_init = _create_tables(conn, db_version);
+            first_init = db_version == 0;
+            migrations::migrate(conn)?;
+            db_version = migrations::get_version(conn);
         } else if db_version < 0 {
             return Err(DatastoreError::Uninitialized(
                 "Tried to open an uninitialized datastore with migration disabled".to_string(),
             ));
-        } else if db_version != NEWEST_DB_VERSION {
+        } else if db_version != newest_version {
             return Err(DatastoreError::OldDbVersion(format!(
                 "\
                 Tried to open an database with an incompatible database version!
-                Database has version {db_version} while the supported version is {NEWEST_DB_VERSION}"
+                Database has version {db_version} while the supported version is {newest_version}"
             )));
         }
 
         let mut ds = DatastoreInstance {
             buckets_cache: HashMap::new(),
             first_init,
+            compress_event_data: false,
+            uuid_event_ids: false,
+            last_heartbeats: HashMap::new(),
+            uncommitted_heartbeats: HashMap::new(),
+            event_counts: HashMap::new(),
+            stats: Arc::default(),
+            daily_aggregates: daily_aggregates::load(conn)?,
             db_version,
         };
         ds.get_stored_buckets(conn)?;
@@ -270,7 +306,9 @@ impl DatastoreInstance {
                     end: opt_end,
                 },
                 events: None,
-                last_updated: None,
+                // Changes from before this process started are unknown, so
+                // treat the bucket as updated at load time
+                last_updated: Some(Utc::now()),
             })
         }) {
             Ok(buckets) => buckets,
@@ -295,6 +333,43 @@ impl DatastoreInstance {
         Ok(())
     }
 
+    /// Compress large event data when writing, already stored events are read either way
+    pub fn set_compress_event_data(&mut self, enabled: bool) {
+        self.compress_event_data = enabled;
+    }
+
+    /// Give new events a UUID as id instead of an autoincremented integer, so that events from
+    /// different databases don't get the same id. Events inserted with an id keep it.
+    pub fn set_uuid_event_ids(&mut self, enabled: bool) {
+        self.uuid_event_ids = enabled;
+    }
+
+    /// Size of the database, which is the same as the file size when not in memory
+    fn db_size(conn: &Connection) -> Result<u64, DatastoreError> {
+        let pragma = |name: &str| -> Result<i64, DatastoreError> {
+            conn.pragma_query_value(None, name, |row| row.get(0))
+                .map_err(|err| DatastoreError::InternalError(err.to_string()))
+        };
+        Ok((pragma("page_count")? * pragma("page_size")?) as u64)
+    }
+
+    /// Rebuilds the database to give the space of deleted data back to the filesystem
+    ///
+    /// Can't be run inside a transaction.
+    pub fn vacuum(&mut self, conn: &Connection) -> Result<VacuumResult, DatastoreError> {
+        let size_before = DatastoreInstance::db_size(conn)?;
+        if let Err(err) = conn.execute_batch("VACUUM") {
+            return Err(DatastoreError::InternalError(format!(
+                "Failed to vacuum database: {err}"
+            )));
+        }
+        let size_after = DatastoreInstance::db_size(conn)?;
+        Ok(VacuumResult {
+            size_before,
+            size_after,
+        })
+    }
+
     pub fn ensure_legacy_import(&mut self, conn: &Connection) -> Result<bool, ()> {
         use super::legacy_import::legacy_import;
         if !self.first_init {
@@ -324,6 +399,7 @@ impl DatastoreInstance {
             Some(created) => Some(created),
             None => Some(Utc::now()),
         };
+        bucket.last_updated = Some(Utc::now());
         let mut stmt = match conn.prepare(
             "
                 INSERT INTO buckets (name, type, client, hostname, created, data)
@@ -359,7 +435,10 @@ impl DatastoreInstance {
                 self.buckets_cache.insert(bucket.id.clone(), bucket.clone());
                 // Insert events
                 if let Some(events) = events {
-                    self.insert_events(conn, &bucket.id, events.take_inner())?;
+                    // In order so that imports of buckets with monotonic timestamps succeed
+                    let mut events = events.take_inner();
+                    events.sort_by_key(|e| e.timestamp);
+                    self.insert_events(conn, &bucket.id, events)?;
                     bucket.events = None;
                 }
                 Ok(())
@@ -381,21 +460,147 @@ impl DatastoreInstance {
         }
     }
 
+    /// Creates several buckets, buckets which already exist are skipped
+    ///
+    /// If `strict` is set no bucket is created and `BucketAlreadyExists` is returned if any of
+    /// the buckets already exists, or if two of them have the same id.
+    pub fn create_buckets(
+        &mut self,
+        conn: &Connection,
+        buckets: Vec<Bucket>,
+        strict: bool,
+    ) -> Result<HashMap<String, BucketCreationResult>, DatastoreError> {
+        if strict {
+            let mut ids = HashSet::new();
+            for bucket in buckets.iter() {
+                if self.buckets_cache.contains_key(&bucket.id) || !ids.insert(&bucket.id) {
+                    return Err(DatastoreError::BucketAlreadyExists(bucket.id.clone()));
+                }
+            }
+        }
+        let mut results = HashMap::new();
+        for bucket in buckets {
+            let bucket_id = bucket.id.clone();
+            // A later bucket with the same id as an earlier one in the list is ignored
+            if results.contains_key(&bucket_id) {
+                continue;
+            }
+            let result = match self.create_bucket(conn, bucket) {
+                Ok(()) => BucketCreationResult::Created,
+                Err(DatastoreError::BucketAlreadyExists(_)) => BucketCreationResult::Skipped,
+                Err(err) => BucketCreationResult::Failed {
+                    error: format!("{err:?}"),
+                },
+            };
+            results.insert(bucket_id, result);
+        }
+        Ok(results)
+    }
+
+    /// Creates a bucket together with its events from an export
+    ///
+    /// Unless `preserve_ids` is set the ids of the events are dropped and new ones are assigned.
+    /// With `preserve_ids` the ids are kept as they are, as event ids are unique across all
+    /// buckets `EventAlreadyExists` is returned and nothing is created if any of them is already
+    /// used or if two events share an id.
+    pub fn import_bucket(
+        &mut self,
+        conn: &Connection,
+        mut bucket: Bucket,
+        preserve_ids: bool,
+    ) -> Result<(), DatastoreError> {
+        let events = bucket.events.take().map(TryVec::take_inner);
+        let mut events = events.unwrap_or_default();
+        if preserve_ids {
+            let mut stmt = match conn.prepare("SELECT 1 FROM events WHERE id = ?1 OR uuid = ?2") {
+                Ok(stmt) => stmt,
+                Err(err) => {
+                    return Err(DatastoreError::InternalError(format!(
+                        "Failed to prepare import_bucket SQL statement: {err}"
+                    )))
+                }
+            };
+            let mut ids = HashSet::new();
+            for event_id in events.iter().filter_map(|e| e.id.as_ref()) {
+                let (rowid, uuid) = event_id_params(event_id);
+                let exists = match stmt.exists([&rowid as &dyn ToSql, &uuid]) {
+                    Ok(exists) => exists,
+                    Err(err) => {
+                        return Err(DatastoreError::InternalError(format!(
+                            "Failed to check event id {event_id}: {err}"
+                        )))
+                    }
+                };
+                if exists || !ids.insert(event_id) {
+                    return Err(DatastoreError::EventAlreadyExists(event_id.to_string()));
+                }
+            }
+        } else {
+            for event in events.iter_mut() {
+                event.id = None;
+            }
+        }
+        bucket.events = Some(TryVec::new(events));
+        self.create_bucket(conn, bucket)
+    }
+
+    /// Creates the bucket `dst_id` as a copy of `src_id` with all its events, for experimenting
+    /// on the events without touching the original
+    ///
+    /// The copy has the same type, client, hostname and data, it is created now and its events
+    /// get new ids. Returns `BucketAlreadyExists` if `dst_id` is already used.
+    pub fn clone_bucket(
+        &mut self,
+        conn: &Connection,
+        src_id: &str,
+        dst_id: &str,
+    ) -> Result<(), DatastoreError> {
+        let src = self.get_bucket(src_id)?;
+        if self.buckets_cache.contains_key(dst_id) {
+            return Err(DatastoreError::BucketAlreadyExists(dst_id.to_string()));
+        }
+        let mut events =
+            self.get_events(conn, src_id, None, None, None, GetEventsOptions::default())?;
+        for event in events.iter_mut() {
+            event.id = None;
+        }
+        let bucket = Bucket {
+            bid: None,
+            id: dst_id.to_string(),
+            _type: src._type,
+            client: src.client,
+            hostname: src.hostname,
+            created: None,
+            data: src.data,
+            metadata: Default::default(),
+            events: Some(TryVec::new(events)),
+            last_updated: None,
+        };
+        self.create_bucket(conn, bucket)
+    }
+
     pub fn delete_bucket(
         &mut self,
         conn: &Connection,
         bucket_id: &str,
     ) -> Result<(), DatastoreError> {
         let bucket = (self.get_bucket(bucket_id))?;
+        if self.daily_aggregates.is_some() {
+            daily_aggregates::remove_bucket(conn, bucket.bid.unwrap())?;
+        }
         // Delete all events in bucket
         match conn.execute("DELETE FROM events WHERE bucketrow = ?1", [&bucket.bid]) {
-            Ok(_) => (),
+            Ok(_) => {
+                self.event_counts.remove(bucket_id);
+                self.forget_heartbeat(bucket_id);
+            }
             Err(err) => return Err(DatastoreError::InternalError(err.to_string())),
         }
         // Delete bucket itself
         match conn.execute("DELETE FROM buckets WHERE id = ?1", [&bucket.bid]) {
             Ok(_) => {
                 self.buckets_cache.remove(bucket_id);
+                self.event_counts.remove(bucket_id);
                 Ok(())
             }
             Err(err) => match err {
@@ -410,6 +615,34 @@ impl DatastoreInstance {
         }
     }
 
+    /// Applies a JSON merge patch to the data of a bucket, the events are left as they are
+    pub fn update_bucket_data(
+        &mut self,
+        conn: &Connection,
+        bucket_id: &str,
+        patch: &serde_json::map::Map<String, Value>,
+    ) -> Result<Bucket, DatastoreError> {
+        let mut bucket = self.get_bucket(bucket_id)?;
+        let mut data = Value::Object(bucket.data);
+        merge_patch(&mut data, &Value::Object(patch.clone()));
+        bucket.data = match data {
+            Value::Object(data) => data,
+            _ => unreachable!(),
+        };
+
+        let data = serde_json::to_string(&bucket.data).unwrap();
+        if let Err(err) = conn.execute(
+            "UPDATE buckets SET data = ?1 WHERE id = ?2",
+            [&data as &dyn ToSql, &bucket.bid],
+        ) {
+            return Err(DatastoreError::InternalError(format!(
+                "Failed to execute update_bucket_data SQL statement: {err}"
+            )));
+        }
+        self.buckets_cache.insert(bucket.id.clone(), bucket.clone());
+        Ok(bucket)
+    }
+
     pub fn get_bucket(&self, bucket_id: &str) -> Result<Bucket, DatastoreError> {
         let cached_bucket = self.buckets_cache.get(bucket_id);
         match cached_bucket {
@@ -422,6 +655,47 @@ impl DatastoreInstance {
         self.buckets_cache.clone()
     }
 
+    /// Gets the last_updated time and event count of all buckets with a single query
+    pub fn get_bucket_states(
+        &self,
+        conn: &Connection,
+    ) -> Result<HashMap<String, BucketState>, DatastoreError> {
+        let mut stmt =
+            match conn.prepare("SELECT bucketrow, count(*) FROM events GROUP BY bucketrow") {
+                Ok(stmt) => stmt,
+                Err(err) => {
+                    return Err(DatastoreError::InternalError(format!(
+                        "Failed to prepare get_bucket_states SQL statement: {err}"
+                    )))
+                }
+            };
+        let counts: HashMap<i64, i64> = match stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .and_then(|rows| rows.collect())
+        {
+            Ok(counts) => counts,
+            Err(err) => {
+                return Err(DatastoreError::InternalError(format!(
+                    "Failed to query get_bucket_states SQL statement: {err}"
+                )))
+            }
+        };
+        Ok(self
+            .buckets_cache
+            .values()
+            .map(|bucket| {
+                let state = BucketState {
+                    last_updated: bucket.last_updated,
+                    event_count: bucket
+                        .bid
+                        .and_then(|bid| counts.get(&bid).copied())
+                        .unwrap_or(0),
+                };
+                (bucket.id.clone(), state)
+            })
+            .collect())
+    }
+
     pub fn insert_events(
         &mut self,
         conn: &Connection,
@@ -429,11 +703,16 @@ impl DatastoreInstance {
         mut events: Vec<Event>,
     ) -> Result<Vec<Event>, DatastoreError> {
         let mut bucket = self.get_bucket(bucket_id)?;
+        if bucket.data.get(MONOTONIC_TIMESTAMPS_KEY) == Some(&Value::Bool(true)) {
+            self.check_monotonic(conn, &bucket, &events)?;
+        }
+        // Inserts with an existing id replace the event, so the new count isn't known up front
+        self.event_counts.remove(bucket_id);
 
         let mut stmt = match conn.prepare(
             "
-                INSERT OR REPLACE INTO events(bucketrow, id, starttime, endtime, data)
-                VALUES (?1, ?2, ?3, ?4, ?5)",
+                INSERT OR REPLACE INTO events(bucketrow, id, uuid, starttime, endtime, data)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
         ) {
             Ok(stmt) => stmt,
             Err(err) => {
@@ -453,10 +732,27 @@ impl DatastoreInstance {
                 }
             };
             let endtime_nanos = starttime_nanos + duration_nanos;
-            let data = serde_json::to_string(&event.data).unwrap();
+            let data = encode_event_data(&event.data, self.compress_event_data)?;
+            let (id, uuid) = match &event.id {
+                Some(EventId::Int(id)) => (Some(*id), None),
+                Some(EventId::Uuid(uuid)) => (None, Some(uuid.clone())),
+                None if self.uuid_event_ids => (None, Some(uuid::Uuid::new_v4().to_string())),
+                None => (None, None),
+            };
+            if let (Some(settings), Some(event_id)) = (&self.daily_aggregates, &event.id) {
+                // The event replaces the one with the same id, if any
+                let (rowid, uuid) = event_id_params(event_id);
+                daily_aggregates::remove_events(
+                    conn,
+                    settings,
+                    "id = ?1 OR uuid = ?2",
+                    &[&rowid, &uuid],
+                )?;
+            }
             let res = stmt.execute([
                 &bucket.bid.unwrap(),
-                &event.id as &dyn ToSql,
+                &id as &dyn ToSql,
+                &uuid as &dyn ToSql,
                 &starttime_nanos,
                 &endtime_nanos,
                 &data as &dyn ToSql,
@@ -465,7 +761,16 @@ impl DatastoreInstance {
                 Ok(_) => {
                     self.update_endtime(&mut bucket, event);
                     let rowid = conn.last_insert_rowid();
-                    event.id = Some(rowid);
+                    event.id = Some(event_id_from_row(rowid, uuid));
+                    self.stats.add_events_inserted(1);
+                    if let Some(settings) = &self.daily_aggregates {
+                        daily_aggregates::add_events(
+                            conn,
+                            settings,
+                            bucket.bid.unwrap(),
+                            std::slice::from_ref(event),
+                        )?;
+                    }
                 }
                 Err(err) => {
                     return Err(DatastoreError::InternalError(format!(
@@ -474,20 +779,22 @@ impl DatastoreInstance {
                 }
             };
         }
+        self.touch_bucket(bucket_id);
         Ok(events)
     }
 
     pub fn delete_events_by_id(
-        &self,
+        &mut self,
         conn: &Connection,
         bucket_id: &str,
-        event_ids: Vec<i64>,
+        event_ids: Vec<EventId>,
     ) -> Result<(), DatastoreError> {
         let bucket = self.get_bucket(bucket_id)?;
+        self.event_counts.remove(bucket_id);
         let mut stmt = match conn.prepare(
             "
                 DELETE FROM events
-                WHERE bucketrow = ?1 AND id = ?2",
+                WHERE bucketrow = ?1 AND (id = ?2 OR uuid = ?3)",
         ) {
             Ok(stmt) => stmt,
             Err(err) => {
@@ -497,7 +804,16 @@ impl DatastoreInstance {
             }
         };
         for id in event_ids {
-            let res = stmt.execute([&bucket.bid.unwrap(), &id as &dyn ToSql]);
+            let (rowid, uuid) = event_id_params(&id);
+            if let Some(settings) = &self.daily_aggregates {
+                daily_aggregates::remove_events(
+                    conn,
+                    settings,
+                    "bucketrow = ?1 AND (id = ?2 OR uuid = ?3)",
+                    &[&bucket.bid.unwrap(), &rowid, &uuid],
+                )?;
+            }
+            let res = stmt.execute([&bucket.bid.unwrap(), &rowid as &dyn ToSql, &uuid]);
             match res {
                 Ok(_) => {}
                 Err(err) => {
@@ -507,11 +823,102 @@ impl DatastoreInstance {
                 }
             };
         }
+        self.touch_bucket(bucket_id);
         Ok(())
     }
 
     // TODO: Function for deleting events by timerange with limit
 
+    /// Checks that none of `events` starts before the latest event in the bucket or an earlier
+    /// event in `events`, before anything is inserted
+    fn check_monotonic(
+        &self,
+        conn: &Connection,
+        bucket: &Bucket,
+        events: &[Event],
+    ) -> Result<(), DatastoreError> {
+        let latest_nanos: Option<i64> = match conn.query_row(
+            "SELECT max(starttime) FROM events WHERE bucketrow = ?1",
+            [&bucket.bid.unwrap()],
+            |row| row.get(0),
+        ) {
+            Ok(latest_nanos) => latest_nanos,
+            Err(err) => {
+                return Err(DatastoreError::InternalError(format!(
+                    "Failed to get the latest event of bucket {}: {err}",
+                    bucket.id
+                )))
+            }
+        };
+        let mut latest = latest_nanos.map(|nanos| {
+            DateTime::from_timestamp(nanos / 1_000_000_000, (nanos % 1_000_000_000) as u32).unwrap()
+        });
+        for event in events {
+            if let Some(latest) = latest.filter(|latest| event.timestamp < *latest) {
+                return Err(DatastoreError::EventOutOfOrder(format!(
+                    "Event at {} starts before the latest event in bucket '{}' at {}",
+                    event.timestamp.to_rfc3339(),
+                    bucket.id,
+                    latest.to_rfc3339()
+                )));
+            }
+            latest = Some(event.timestamp);
+        }
+        Ok(())
+    }
+
+    /// Checks that moving an event to `timestamp` keeps it between the events inserted before
+    /// and after it
+    fn check_monotonic_update(
+        &self,
+        conn: &Connection,
+        bucket: &Bucket,
+        event_id: &EventId,
+        timestamp: DateTime<Utc>,
+    ) -> Result<(), DatastoreError> {
+        let (rowid, uuid) = event_id_params(event_id);
+        let neighbours: (Option<i64>, Option<i64>) = match conn.query_row(
+            "
+                SELECT
+                    (SELECT max(starttime) FROM events WHERE bucketrow = ?1 AND id < e.id),
+                    (SELECT min(starttime) FROM events WHERE bucketrow = ?1 AND id > e.id)
+                FROM events e
+                WHERE e.bucketrow = ?1 AND (e.id = ?2 OR e.uuid = ?3)",
+            [&bucket.bid.unwrap(), &rowid as &dyn ToSql, &uuid],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        ) {
+            Ok(neighbours) => neighbours,
+            Err(err) => {
+                return Err(DatastoreError::InternalError(format!(
+                    "Failed to get the neighbours of event {event_id} in bucket {}: {err}",
+                    bucket.id
+                )))
+            }
+        };
+        let from_nanos = |nanos: i64| {
+            DateTime::from_timestamp(nanos / 1_000_000_000, (nanos % 1_000_000_000) as u32).unwrap()
+        };
+        match neighbours {
+            (Some(before), _) if timestamp < from_nanos(before) => {
+                Err(DatastoreError::EventOutOfOrder(format!(
+                    "Event at {} would start before the event inserted before it in bucket '{}' at {}",
+                    timestamp.to_rfc3339(),
+                    bucket.id,
+                    from_nanos(before).to_rfc3339()
+                )))
+            }
+            (_, Some(after)) if timestamp > from_nanos(after) => {
+                Err(DatastoreError::EventOutOfOrder(format!(
+                    "Event at {} would start after the event inserted after it in bucket '{}' at {}",
+                    timestamp.to_rfc3339(),
+                    bucket.id,
+                    from_nanos(after).to_rfc3339()
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+
     fn update_endtime(&mut self, bucket: &mut Bucket, event: &Event) {
         let mut update = false;
         /* Potentially update start */
@@ -547,6 +954,32 @@ impl DatastoreInstance {
         }
     }
 
+    /// Marks the cached bucket as modified, used as a validator for conditional requests
+    fn touch_bucket(&mut self, bucket_id: &str) {
+        if let Some(bucket) = self.buckets_cache.get_mut(bucket_id) {
+            bucket.last_updated = Some(Utc::now());
+        }
+        // The last heartbeat is no longer what the last event was made of
+        self.forget_heartbeat(bucket_id);
+    }
+
+    /// Counts the events inserted from now on in `stats`, instead of counters of its own
+    pub(crate) fn set_stats(&mut self, stats: Arc<DatastoreStats>) {
+        self.stats = stats;
+    }
+
+    fn forget_heartbeat(&mut self, bucket_id: &str) {
+        self.last_heartbeats.remove(bucket_id);
+        self.uncommitted_heartbeats.remove(bucket_id);
+    }
+
+    /// Marks the heartbeats of the current transaction as committed, after which retries of
+    /// them are recognized by `heartbeat`. Called by the worker once the commit succeeded.
+    pub fn commit_heartbeats(&mut self) {
+        self.last_heartbeats
+            .extend(self.uncommitted_heartbeats.drain());
+    }
+
     pub fn replace_last_event(
         &mut self,
         conn: &Connection,
@@ -580,23 +1013,58 @@ impl DatastoreInstance {
             }
         };
         let endtime_nanos = starttime_nanos + duration_nanos;
-        let data = serde_json::to_string(&event.data).unwrap();
+        let data = encode_event_data(&event.data, self.compress_event_data)?;
+        if let Some(settings) = &self.daily_aggregates {
+            daily_aggregates::remove_events(
+                conn,
+                settings,
+                "bucketrow = ?1
+                    AND endtime = (SELECT max(endtime) FROM events WHERE bucketrow = ?1)",
+                &[&bucket.bid.unwrap()],
+            )?;
+        }
         match stmt.execute([
             &bucket.bid.unwrap(),
             &starttime_nanos,
             &endtime_nanos,
             &data as &dyn ToSql,
         ]) {
-            Ok(_) => self.update_endtime(&mut bucket, event),
+            Ok(replaced) => {
+                self.update_endtime(&mut bucket, event);
+                if let Some(settings) = &self.daily_aggregates {
+                    daily_aggregates::add_events(
+                        conn,
+                        settings,
+                        bucket.bid.unwrap(),
+                        &vec![event.clone(); replaced],
+                    )?;
+                }
+            }
             Err(err) => {
                 return Err(DatastoreError::InternalError(format!(
                     "Failed to execute replace_last_event SQL statement: {err}"
                 )))
             }
         };
+        self.touch_bucket(bucket_id);
         Ok(())
     }
 
+    /// Checks if a heartbeat is identical to the last one of the bucket, which happens when a
+    /// watcher retries a heartbeat it didn't get a response for even though the server handled it.
+    ///
+    /// Only heartbeats whose write has been committed count, a heartbeat which failed to be
+    /// written or was lost with its transaction is handled again when retried.
+    fn is_replayed_heartbeat(&self, bucket_id: &str, heartbeat: &Event) -> bool {
+        match self.last_heartbeats.get(bucket_id) {
+            Some((event, received)) => {
+                event == heartbeat
+                    && Utc::now() - *received <= Duration::seconds(HEARTBEAT_REPLAY_WINDOW_SECS)
+            }
+            None => false,
+        }
+    }
+
     pub fn heartbeat(
         &mut self,
         conn: &Connection,
@@ -606,6 +1074,23 @@ impl DatastoreInstance {
         last_heartbeat: &mut HashMap<String, Option<Event>>,
     ) -> Result<Event, DatastoreError> {
         self.get_bucket(bucket_id)?;
+        if self.is_replayed_heartbeat(bucket_id, &heartbeat) {
+            debug!("Ignoring replayed heartbeat");
+            // Respond with the event the heartbeat was stored as, as the first time
+            if let Some(Some(last_event)) = last_heartbeat.get(bucket_id) {
+                return Ok(last_event.clone());
+            }
+            let mut last_event_vec = self.get_events(
+                conn,
+                bucket_id,
+                None,
+                None,
+                Some(1),
+                GetEventsOptions::default(),
+            )?;
+            return Ok(last_event_vec.pop().unwrap_or(heartbeat));
+        }
+        let received = Utc::now();
         if !last_heartbeat.contains_key(bucket_id) {
             last_heartbeat.insert(bucket_id.to_string(), None);
         }
@@ -614,12 +1099,21 @@ impl DatastoreInstance {
             Some(last_event) => last_event,
             None => {
                 // last heartbeat was not in cache, fetch from DB
-                let mut last_event_vec = self.get_events(conn, bucket_id, None, None, Some(1))?;
+                let mut last_event_vec = self.get_events(
+                    conn,
+                    bucket_id,
+                    None,
+                    None,
+                    Some(1),
+                    GetEventsOptions::default(),
+                )?;
                 match last_event_vec.pop() {
                     Some(last_event) => last_event,
                     None => {
                         // There was no last event, insert and return
                         self.insert_events(conn, bucket_id, vec![heartbeat.clone()])?;
+                        self.uncommitted_heartbeats
+                            .insert(bucket_id.to_string(), (heartbeat.clone(), received));
                         return Ok(heartbeat);
                     }
                 }
@@ -634,9 +1128,11 @@ impl DatastoreInstance {
             None => {
                 debug!("Failed to merge heartbeat");
                 self.insert_events(conn, bucket_id, vec![heartbeat.clone()])?;
-                heartbeat
+                heartbeat.clone()
             }
         };
+        self.uncommitted_heartbeats
+            .insert(bucket_id.to_string(), (heartbeat, received));
         last_heartbeat.insert(bucket_id.to_string(), Some(inserted_heartbeat.clone()));
         Ok(inserted_heartbeat)
     }
@@ -645,16 +1141,16 @@ impl DatastoreInstance {
         &mut self,
         conn: &Connection,
         bucket_id: &str,
-        event_id: i64,
+        event_id: &EventId,
     ) -> Result<Event, DatastoreError> {
         let bucket = self.get_bucket(bucket_id)?;
 
         let mut stmt = match conn.prepare(
             "
-                SELECT id, starttime, endtime, data
+                SELECT id, uuid, starttime, endtime, data
                 FROM events
                 WHERE bucketrow = ?1
-                    AND id = ?2
+                    AND (id = ?2 OR uuid = ?3)
                 LIMIT 1
             ;",
         ) {
@@ -667,26 +1163,15 @@ impl DatastoreInstance {
         };
 
         // TODO: Refactor to share row-parsing logic with get_events
-        let row = match stmt.query_row([&bucket.bid.unwrap(), &event_id], |row| {
-            let id = row.get(0)?;
-            let starttime_ns: i64 = row.get(1)?;
-            let endtime_ns: i64 = row.get(2)?;
-            let data_str: String = row.get(3)?;
-
-            let time_seconds: i64 = starttime_ns / 1_000_000_000;
-            let time_subnanos: u32 = (starttime_ns % 1_000_000_000) as u32;
-            let duration_ns = endtime_ns - starttime_ns;
-            let data: serde_json::map::Map<String, Value> =
-                serde_json::from_str(&data_str).unwrap();
-
-            Ok(Event {
-                id: Some(id),
-                timestamp: DateTime::from_timestamp(time_seconds, time_subnanos).unwrap(),
-                duration: Duration::nanoseconds(duration_ns),
-                data,
-            })
-        }) {
+        let (rowid, uuid) = event_id_params(event_id);
+        let row = match stmt.query_row(
+            [&bucket.bid.unwrap(), &rowid as &dyn ToSql, &uuid],
+            event_from_row,
+        ) {
             Ok(rows) => rows,
+            Err(rusqlite::Error::QueryReturnedNoRows) => {
+                return Err(DatastoreError::NoSuchEvent(event_id.to_string()))
+            }
             Err(err) => {
                 return Err(DatastoreError::InternalError(format!(
                     "Failed to map get_event SQL statement: {err}"
@@ -697,6 +1182,121 @@ impl DatastoreInstance {
         Ok(row)
     }
 
+    /// Applies a JSON merge patch to the data of an event, optionally also changing its
+    /// timestamp and duration
+    ///
+    /// In a bucket with monotonic timestamps the event can only be moved as far as the events
+    /// inserted before and after it, see `check_monotonic_update`.
+    pub fn update_event_data(
+        &mut self,
+        conn: &Connection,
+        bucket_id: &str,
+        event_id: &EventId,
+        patch: &serde_json::map::Map<String, Value>,
+        timestamp: Option<DateTime<Utc>>,
+        duration: Option<Duration>,
+    ) -> Result<Event, DatastoreError> {
+        let mut bucket = self.get_bucket(bucket_id)?;
+        let mut event = self.get_event(conn, bucket_id, event_id)?;
+
+        let mut data = Value::Object(event.data);
+        merge_patch(&mut data, &Value::Object(patch.clone()));
+        event.data = match data {
+            Value::Object(data) => data,
+            _ => unreachable!(),
+        };
+        if let Some(timestamp) = timestamp {
+            if bucket.data.get(MONOTONIC_TIMESTAMPS_KEY) == Some(&Value::Bool(true)) {
+                self.check_monotonic_update(conn, &bucket, event_id, timestamp)?;
+            }
+            event.timestamp = timestamp;
+        }
+        if let Some(duration) = duration {
+            event.duration = duration;
+        }
+
+        let mut stmt = match conn.prepare(
+            "
+                UPDATE events
+                SET starttime = ?4, endtime = ?5, data = ?6
+                WHERE bucketrow = ?1 AND (id = ?2 OR uuid = ?3)
+            ",
+        ) {
+            Ok(stmt) => stmt,
+            Err(err) => {
+                return Err(DatastoreError::InternalError(format!(
+                    "Failed to prepare update_event_data SQL statement: {err}"
+                )))
+            }
+        };
+        // The times come from the request, so unlike stored events they may be out of range
+        let endtime_nanos = event
+            .timestamp
+            .timestamp_nanos_opt()
+            .and_then(|starttime_nanos| {
+                let duration_nanos = event.duration.num_nanoseconds()?;
+                Some((
+                    starttime_nanos,
+                    starttime_nanos.checked_add(duration_nanos)?,
+                ))
+            });
+        let (starttime_nanos, endtime_nanos) = match endtime_nanos {
+            Some(nanos) => nanos,
+            None => {
+                return Err(DatastoreError::InvalidEvent(format!(
+                    "Event at {} with a duration of {}s is outside of the range of storable times",
+                    event.timestamp.to_rfc3339(),
+                    event.duration.num_milliseconds() as f64 / 1000.0
+                )))
+            }
+        };
+        let data = encode_event_data(&event.data, self.compress_event_data)?;
+        let (rowid, uuid) = event_id_params(event_id);
+        if let Some(settings) = &self.daily_aggregates {
+            daily_aggregates::remove_events(
+                conn,
+                settings,
+                "bucketrow = ?1 AND (id = ?2 OR uuid = ?3)",
+                &[&bucket.bid.unwrap(), &rowid, &uuid],
+            )?;
+        }
+        match stmt.execute([
+            &bucket.bid.unwrap(),
+            &rowid as &dyn ToSql,
+            &uuid,
+            &starttime_nanos,
+            &endtime_nanos,
+            &data as &dyn ToSql,
+        ]) {
+            Ok(_) => {
+                self.update_endtime(&mut bucket, &event);
+                if let Some(settings) = &self.daily_aggregates {
+                    daily_aggregates::add_events(
+                        conn,
+                        settings,
+                        bucket.bid.unwrap(),
+                        std::slice::from_ref(&event),
+                    )?;
+                }
+            }
+            Err(err) => {
+                return Err(DatastoreError::InternalError(format!(
+                    "Failed to execute update_event_data SQL statement: {err}"
+                )))
+            }
+        };
+        self.touch_bucket(bucket_id);
+        Ok(event)
+    }
+
+    /// Events overlapping the closed interval `[starttime, endtime]` are returned, events which
+    /// only touch an end of the interval are included with their duration cut to zero. If
+    /// `inclusive_end` is false the interval is half-open instead, `[starttime, endtime)`, so
+    /// events starting at `endtime` or ending at `starttime` are left out and consecutive
+    /// intervals never both return the same boundary event.
+    ///
+    /// Events are ordered by their start with the newest first unless `ascending` is set, the
+    /// limit keeps the first events in that order.
     pub fn get_events(
         &mut self,
         conn: &Connection,
@@ -704,19 +1304,14 @@ impl DatastoreInstance {
         starttime_opt: Option<DateTime<Utc>>,
         endtime_opt: Option<DateTime<Utc>>,
         limit_opt: Option<u64>,
+        options: GetEventsOptions,
     ) -> Result<Vec<Event>, DatastoreError> {
         let bucket = self.get_bucket(bucket_id)?;
 
         let mut list = Vec::new();
 
-        let starttime_filter_ns: i64 = match starttime_opt {
-            Some(dt) => dt.timestamp_nanos_opt().unwrap(),
-            None => 0,
-        };
-        let endtime_filter_ns: i64 = match endtime_opt {
-            Some(dt) => dt.timestamp_nanos_opt().unwrap(),
-            None => std::i64::MAX,
-        };
+        let (starttime_filter_ns, endtime_filter_ns) =
+            interval_filter_ns(starttime_opt, endtime_opt);
         if starttime_filter_ns > endtime_filter_ns {
             warn!("Starttime in event query was lower than endtime!");
             return Ok(list);
@@ -726,17 +1321,22 @@ impl DatastoreInstance {
             None => -1,
         };
 
-        let mut stmt = match conn.prepare(
+        let interval_filter = if options.inclusive_end {
+            OVERLAPS_INTERVAL
+        } else {
+            "(endtime > ?2 OR starttime >= ?2) AND starttime < ?3"
+        };
+        let order = if options.ascending { "ASC" } else { "DESC" };
+        let mut stmt = match conn.prepare(&format!(
             "
-                SELECT id, starttime, endtime, data
+                SELECT id, uuid, starttime, endtime, data
                 FROM events
                 WHERE bucketrow = ?1
-                    AND endtime >= ?2
-                    AND starttime <= ?3
-                ORDER BY starttime DESC
+                    AND {interval_filter}
+                ORDER BY starttime {order}
                 LIMIT ?4
-            ;",
-        ) {
+            ;"
+        )) {
             Ok(stmt) => stmt,
             Err(err) => {
                 return Err(DatastoreError::InternalError(format!(
@@ -753,10 +1353,10 @@ impl DatastoreInstance {
                 &limit,
             ],
             |row| {
-                let id = row.get(0)?;
-                let mut starttime_ns: i64 = row.get(1)?;
-                let mut endtime_ns: i64 = row.get(2)?;
-                let data_str: String = row.get(3)?;
+                let id = event_id_from_row(row.get(0)?, row.get(1)?);
+                let mut starttime_ns: i64 = row.get(2)?;
+                let mut endtime_ns: i64 = row.get(3)?;
+                let data = decode_event_data(row.get_ref(4)?)?;
 
                 if starttime_ns < starttime_filter_ns {
                     starttime_ns = starttime_filter_ns
@@ -768,8 +1368,6 @@ impl DatastoreInstance {
 
                 let time_seconds: i64 = starttime_ns / 1_000_000_000;
                 let time_subnanos: u32 = (starttime_ns % 1_000_000_000) as u32;
-                let data: serde_json::map::Map<String, Value> =
-                    serde_json::from_str(&data_str).unwrap();
 
                 Ok(Event {
                     id: Some(id),
@@ -796,6 +1394,112 @@ impl DatastoreInstance {
         Ok(list)
     }
 
+    /// Events with an integer id greater than `after_id` in the order of their ids, oldest insert
+    /// first, as a cursor for incremental sync
+    ///
+    /// Unlike a timestamp an id is unique and never reused, so resuming after the last id seen
+    /// neither returns an event twice nor skips events which share a timestamp, and ids missing
+    /// because their events were deleted don't matter. Events are returned whole, not cut to an
+    /// interval. Events with UUID ids are ordered among the others by when they were inserted,
+    /// but as their id can't be used as `after_id` a bucket with them can't be resumed.
+    pub fn get_events_after_id(
+        &mut self,
+        conn: &Connection,
+        bucket_id: &str,
+        after_id: i64,
+        limit_opt: Option<u64>,
+    ) -> Result<Vec<Event>, DatastoreError> {
+        let bucket = self.get_bucket(bucket_id)?;
+        let limit = match limit_opt {
+            Some(l) => l as i64,
+            None => -1,
+        };
+        // Uses events_bucketrow_index, which is ordered by id within a bucket
+        let mut stmt = match conn.prepare(
+            "
+                SELECT id, uuid, starttime, endtime, data
+                FROM events
+                WHERE bucketrow = ?1
+                    AND id > ?2
+                ORDER BY id ASC
+                LIMIT ?3
+            ;",
+        ) {
+            Ok(stmt) => stmt,
+            Err(err) => {
+                return Err(DatastoreError::InternalError(format!(
+                    "Failed to prepare get_events_after_id SQL statement: {err}"
+                )))
+            }
+        };
+        let rows = match stmt.query_map([&bucket.bid.unwrap(), &after_id, &limit], event_from_row) {
+            Ok(rows) => rows,
+            Err(err) => {
+                return Err(DatastoreError::InternalError(format!(
+                    "Failed to map get_events_after_id SQL statement: {err}"
+                )))
+            }
+        };
+        let mut list = Vec::new();
+        for row in rows {
+            match row {
+                Ok(event) => list.push(event),
+                Err(err) => warn!("Corrupt event in bucket {}: {}", bucket_id, err),
+            };
+        }
+        Ok(list)
+    }
+
+    /// Enables the daily aggregates with `settings` or disables them if `None`, rebuilding them
+    /// unless they already are in the requested state, see the `daily_aggregates` module
+    pub fn set_daily_aggregates(
+        &mut self,
+        conn: &Connection,
+        settings: Option<DailyAggregates>,
+    ) -> Result<(), DatastoreError> {
+        if settings == self.daily_aggregates {
+            return Ok(());
+        }
+        daily_aggregates::configure(conn, settings.as_ref())?;
+        self.daily_aggregates = settings;
+        Ok(())
+    }
+
+    fn enabled_daily_aggregates(&self) -> Result<&DailyAggregates, DatastoreError> {
+        match &self.daily_aggregates {
+            Some(settings) => Ok(settings),
+            None => Err(DatastoreError::Disabled(
+                "Daily aggregates are not enabled".to_string(),
+            )),
+        }
+    }
+
+    /// Recomputes all daily aggregates from the events, they are kept up to date without it
+    /// unless the database was changed by something else than this datastore
+    pub fn rebuild_daily_aggregates(&mut self, conn: &Connection) -> Result<(), DatastoreError> {
+        daily_aggregates::rebuild(conn, self.enabled_daily_aggregates()?)
+    }
+
+    /// The time per local day and category of the events in the bucket as events, see
+    /// `daily_aggregates::get`
+    pub fn get_daily_aggregates(
+        &self,
+        conn: &Connection,
+        bucket_id: &str,
+        starttime_opt: Option<DateTime<Utc>>,
+        endtime_opt: Option<DateTime<Utc>>,
+    ) -> Result<Vec<Event>, DatastoreError> {
+        let settings = self.enabled_daily_aggregates()?;
+        let bucket = self.get_bucket(bucket_id)?;
+        daily_aggregates::get(
+            conn,
+            settings,
+            bucket.bid.unwrap(),
+            starttime_opt,
+            endtime_opt,
+        )
+    }
+
     pub fn get_event_count(
         &self,
         conn: &Connection,
@@ -805,26 +1509,19 @@ impl DatastoreInstance {
     ) -> Result<i64, DatastoreError> {
         let bucket = self.get_bucket(bucket_id)?;
 
-        let starttime_filter_ns: i64 = match starttime_opt {
-            Some(dt) => dt.timestamp_nanos_opt().unwrap(),
-            None => 0,
-        };
-        let endtime_filter_ns: i64 = match endtime_opt {
-            Some(dt) => dt.timestamp_nanos_opt().unwrap(),
-            None => std::i64::MAX,
-        };
+        let (starttime_filter_ns, endtime_filter_ns) =
+            interval_filter_ns(starttime_opt, endtime_opt);
         if starttime_filter_ns >= endtime_filter_ns {
             warn!("Endtime in event query was same or lower than starttime!");
             return Ok(0);
         }
 
-        let mut stmt = match conn.prepare(
+        let mut stmt = match conn.prepare(&format!(
             "
             SELECT count(*) FROM events
             WHERE bucketrow = ?1
-                AND endtime >= ?2
-                AND starttime <= ?3",
-        ) {
+                AND {OVERLAPS_INTERVAL}"
+        )) {
             Ok(stmt) => stmt,
             Err(err) => {
                 return Err(DatastoreError::InternalError(format!(
@@ -852,6 +1549,149 @@ impl DatastoreInstance {
         Ok(count)
     }
 
+    /// The values of the top-level data key `key` in the events overlapping the interval, with
+    /// the number of events having each value
+    ///
+    /// Events without the key or where it is null are left out. The most common values come
+    /// first. Keys containing a double quote can't be expressed as a JSON path and are rejected.
+    pub fn get_distinct_values(
+        &self,
+        conn: &Connection,
+        bucket_id: &str,
+        key: &str,
+        starttime_opt: Option<DateTime<Utc>>,
+        endtime_opt: Option<DateTime<Utc>>,
+    ) -> Result<Vec<DistinctValue>, DatastoreError> {
+        let bucket = self.get_bucket(bucket_id)?;
+        if key.contains('"') {
+            return Err(DatastoreError::InternalError(format!(
+                "Data key {key} can't contain a double quote"
+            )));
+        }
+
+        let (starttime_filter_ns, endtime_filter_ns) =
+            interval_filter_ns(starttime_opt, endtime_opt);
+        let path = format!("$.\"{key}\"");
+
+        // Values as JSON text, serialized again to get the same text for equal values
+        let mut counts: HashMap<String, i64> = HashMap::new();
+        let mut add_value = |value: Value, count: i64| {
+            if !value.is_null() {
+                *counts.entry(value.to_string()).or_insert(0) += count;
+            }
+        };
+
+        // Uncompressed data is grouped by SQLite, compressed data has to be decoded here
+        let mut stmt = match conn.prepare(&format!(
+            "
+            SELECT data -> ?4 AS value, count(*) FROM events
+            WHERE bucketrow = ?1
+                AND {OVERLAPS_INTERVAL}
+                AND typeof(data) = 'text'
+                AND json_extract(data, ?4) IS NOT NULL
+            GROUP BY value"
+        )) {
+            Ok(stmt) => stmt,
+            Err(err) => {
+                return Err(DatastoreError::InternalError(format!(
+                    "Failed to prepare get_distinct_values SQL statement: {err}"
+                )))
+            }
+        };
+        let rows = stmt.query_map(
+            params![
+                bucket.bid.unwrap(),
+                starttime_filter_ns,
+                endtime_filter_ns,
+                path
+            ],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        );
+        let rows: Vec<(String, i64)> = match rows.and_then(|rows| rows.collect()) {
+            Ok(rows) => rows,
+            Err(err) => {
+                return Err(DatastoreError::InternalError(format!(
+                    "Failed to query get_distinct_values SQL statement: {err}"
+                )))
+            }
+        };
+        for (value, count) in rows {
+            match serde_json::from_str(&value) {
+                Ok(value) => add_value(value, count),
+                Err(err) => {
+                    return Err(DatastoreError::InternalError(format!(
+                        "Failed to parse data value {value}: {err}"
+                    )))
+                }
+            }
+        }
+
+        let mut stmt = match conn.prepare(&format!(
+            "
+            SELECT data FROM events
+            WHERE bucketrow = ?1
+                AND {OVERLAPS_INTERVAL}
+                AND typeof(data) = 'blob'"
+        )) {
+            Ok(stmt) => stmt,
+            Err(err) => {
+                return Err(DatastoreError::InternalError(format!(
+                    "Failed to prepare get_distinct_values SQL statement: {err}"
+                )))
+            }
+        };
+        let rows = stmt.query_map(
+            [
+                &bucket.bid.unwrap(),
+                &starttime_filter_ns,
+                &endtime_filter_ns,
+            ],
+            |row| decode_event_data(row.get_ref(0)?),
+        );
+        let rows: Vec<serde_json::map::Map<String, Value>> =
+            match rows.and_then(|rows| rows.collect()) {
+                Ok(rows) => rows,
+                Err(err) => {
+                    return Err(DatastoreError::InternalError(format!(
+                        "Failed to query get_distinct_values SQL statement: {err}"
+                    )))
+                }
+            };
+        for mut data in rows {
+            if let Some(value) = data.remove(key) {
+                add_value(value, 1);
+            }
+        }
+
+        let mut values: Vec<DistinctValue> = counts
+            .into_iter()
+            .map(|(value, count)| DistinctValue {
+                value: serde_json::from_str(&value).unwrap(),
+                count,
+            })
+            .collect();
+        values.sort_by(|a, b| {
+            b.count
+                .cmp(&a.count)
+                .then_with(|| a.value.to_string().cmp(&b.value.to_string()))
+        });
+        Ok(values)
+    }
+
+    /// Total number of events in the bucket, only counted again after the events have changed
+    pub fn get_cached_event_count(
+        &mut self,
+        conn: &Connection,
+        bucket_id: &str,
+    ) -> Result<i64, DatastoreError> {
+        if let Some(count) = self.event_counts.get(bucket_id) {
+            return Ok(*count);
+        }
+        let count = self.get_event_count(conn, bucket_id, None, None)?;
+        self.event_counts.insert(bucket_id.to_string(), count);
+        Ok(count)
+    }
+
     pub fn insert_key_value(
         &self,
         conn: &Connection,
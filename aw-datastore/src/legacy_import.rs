@@ -205,7 +205,14 @@ mod import {
         let mut num_events = 0;
         for (bucket_id, _bucket) in buckets {
             let events = ds
-                .get_events(&new_conn, &bucket_id, None, None, Some(1000))
+                .get_events(
+                    &new_conn,
+                    &bucket_id,
+                    None,
+                    None,
+                    Some(1000),
+                    crate::datastore::GetEventsOptions::default(),
+                )
                 .unwrap();
             num_events += events.len();
         }
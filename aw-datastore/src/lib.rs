@@ -15,12 +15,22 @@ macro_rules! json_map {
     }};
 }
 
+mod daily_aggregates;
 mod datastore;
 mod legacy_import;
+mod migrations;
+mod mirror;
+mod stats;
 mod worker;
 
+pub use self::daily_aggregates::DailyAggregates;
 pub use self::datastore::DatastoreInstance;
+pub use self::datastore::GetEventsOptions;
+pub use self::datastore::MONOTONIC_TIMESTAMPS_KEY;
+pub use self::migrations::newest_schema_version;
+pub use self::stats::DatastoreStats;
 pub use self::worker::Datastore;
+pub use self::worker::DEFAULT_BUSY_TIMEOUT;
 
 #[derive(Debug, Clone)]
 pub enum DatastoreMethod {
@@ -33,6 +43,14 @@ pub enum DatastoreMethod {
 pub enum DatastoreError {
     NoSuchBucket(String),
     BucketAlreadyExists(String),
+    EventAlreadyExists(String),
+    NoSuchEvent(String),
+    /// An event was inserted before the latest event of a bucket with monotonic timestamps
+    EventOutOfOrder(String),
+    /// An event whose start or end can't be stored, such as one ending after the year 2262
+    InvalidEvent(String),
+    /// The request needs a feature which is not enabled
+    Disabled(String),
     NoSuchKey(String),
     MpscError,
     InternalError(String),
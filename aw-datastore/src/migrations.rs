@@ -0,0 +1,236 @@
+//! Ordered migrations of the database schema
+//!
+//! The version of the schema is the SQLite `user_version` of the database, 0 being an
+//! uninitialized database. Each migration brings the schema from the version before it to its
+//! own version, to change the schema add a new migration to the end of `MIGRATIONS`.
+//!
+//! Every migration is applied in a transaction of its own, together with the update of the
+//! version and a row in the `schema_migrations` table which records when it was applied, so a
+//! failed migration leaves the database at the previous version. Databases which were migrated
+//! before the table existed get rows without a time for the migrations they already had.
+use chrono::Utc;
+use rusqlite::Connection;
+
+use crate::DatastoreError;
+
+/// A change of the database schema, see the module docs
+pub(crate) struct Migration {
+    version: i32,
+    description: &'static str,
+    apply: fn(&Connection) -> rusqlite::Result<()>,
+}
+
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "create buckets and events tables",
+        apply: migrate_v0_to_v1,
+    },
+    Migration {
+        version: 2,
+        description: "add data field to buckets",
+        apply: migrate_v1_to_v2,
+    },
+    Migration {
+        version: 3,
+        description: "replace the broken data field of buckets",
+        apply: migrate_v2_to_v3,
+    },
+    Migration {
+        version: 4,
+        description: "add table for key-value storage",
+        apply: migrate_v3_to_v4,
+    },
+    Migration {
+        version: 5,
+        description: "add uuid field to events",
+        apply: migrate_v4_to_v5,
+    },
+    Migration {
+        version: 6,
+        description: "add tables for daily aggregates",
+        apply: migrate_v5_to_v6,
+    },
+];
+
+/// The version of the schema after all migrations
+pub fn newest_schema_version() -> i32 {
+    MIGRATIONS.last().unwrap().version
+}
+
+pub(crate) fn get_version(conn: &Connection) -> i32 {
+    conn.pragma_query_value(None, "user_version", |row| row.get(0))
+        .unwrap()
+}
+
+/// Applies the migrations the database doesn't have yet, returning the versions which were
+/// applied
+pub(crate) fn migrate(conn: &Connection) -> Result<Vec<i32>, DatastoreError> {
+    let version = get_version(conn);
+    let migration_err = |version: i32, err: rusqlite::Error| {
+        DatastoreError::InternalError(format!(
+            "Failed to migrate database to version {version}: {err}"
+        ))
+    };
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS schema_migrations (
+            version INTEGER PRIMARY KEY,
+            description TEXT NOT NULL,
+            applied_at TEXT
+        )",
+        [],
+    )
+    .map_err(|err| migration_err(version, err))?;
+    for migration in MIGRATIONS.iter().filter(|m| m.version <= version) {
+        conn.execute(
+            "INSERT OR IGNORE INTO schema_migrations (version, description) VALUES (?1, ?2)",
+            (migration.version, migration.description),
+        )
+        .map_err(|err| migration_err(migration.version, err))?;
+    }
+
+    let mut applied = Vec::new();
+    for migration in MIGRATIONS.iter().filter(|m| m.version > version) {
+        info!(
+            "Migrating database to version {}: {}",
+            migration.version, migration.description
+        );
+        apply_migration(conn, migration).map_err(|err| migration_err(migration.version, err))?;
+        applied.push(migration.version);
+    }
+    Ok(applied)
+}
+
+fn apply_migration(conn: &Connection, migration: &Migration) -> rusqlite::Result<()> {
+    let tx = conn.unchecked_transaction()?;
+    (migration.apply)(&tx)?;
+    tx.execute(
+        "INSERT OR REPLACE INTO schema_migrations (version, description, applied_at)
+            VALUES (?1, ?2, ?3)",
+        (migration.version, migration.description, Utc::now()),
+    )?;
+    tx.pragma_update(None, "user_version", migration.version)?;
+    tx.commit()
+}
+
+fn migrate_v0_to_v1(conn: &Connection) -> rusqlite::Result<()> {
+    /* Set up bucket table */
+    conn.execute(
+        "
+        CREATE TABLE IF NOT EXISTS buckets (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT UNIQUE NOT NULL,
+            type TEXT NOT NULL,
+            client TEXT NOT NULL,
+            hostname TEXT NOT NULL,
+            created TEXT NOT NULL
+        )",
+        [],
+    )?;
+
+    /* Set up index for bucket table */
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS bucket_id_index ON buckets(id)",
+        [],
+    )?;
+
+    /* Set up events table */
+    conn.execute(
+        "
+        CREATE TABLE IF NOT EXISTS events (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            bucketrow INTEGER NOT NULL,
+            starttime INTEGER NOT NULL,
+            endtime INTEGER NOT NULL,
+            data TEXT NOT NULL,
+            FOREIGN KEY (bucketrow) REFERENCES buckets(id)
+        )",
+        [],
+    )?;
+
+    /* Set up index for events table */
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS events_bucketrow_index ON events(bucketrow)",
+        [],
+    )?;
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS events_starttime_index ON events(starttime)",
+        [],
+    )?;
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS events_endtime_index ON events(endtime)",
+        [],
+    )?;
+    Ok(())
+}
+
+fn migrate_v1_to_v2(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute("ALTER TABLE buckets ADD COLUMN data TEXT DEFAULT '{}';", [])?;
+    Ok(())
+}
+
+fn migrate_v2_to_v3(conn: &Connection) -> rusqlite::Result<()> {
+    // For details about why this migration was necessary, see: https://github.com/ActivityWatch/aw-server-rust/pull/52
+
+    // Rename column, marking it as deprecated
+    match conn.execute(
+        "ALTER TABLE buckets RENAME COLUMN data TO data_deprecated;",
+        [],
+    ) {
+        Ok(_) => (),
+        // This error is okay, it still has the intended effects
+        Err(rusqlite::Error::ExecuteReturnedResults) => (),
+        Err(e) => return Err(e),
+    };
+
+    // Create new correct column
+    conn.execute(
+        "ALTER TABLE buckets ADD COLUMN data TEXT NOT NULL DEFAULT '{}';",
+        [],
+    )?;
+    Ok(())
+}
+
+fn migrate_v3_to_v4(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute(
+        "CREATE TABLE key_value (
+        key TEXT PRIMARY KEY,
+        value TEXT,
+        last_modified NUMBER NOT NULL
+    );",
+        [],
+    )?;
+    Ok(())
+}
+
+fn migrate_v4_to_v5(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute("ALTER TABLE events ADD COLUMN uuid TEXT;", [])?;
+    conn.execute(
+        "CREATE UNIQUE INDEX IF NOT EXISTS events_uuid_index ON events(uuid)",
+        [],
+    )?;
+    Ok(())
+}
+
+fn migrate_v5_to_v6(conn: &Connection) -> rusqlite::Result<()> {
+    // See daily_aggregates.rs, the tables stay empty unless daily aggregates are enabled
+    conn.execute(
+        "CREATE TABLE daily_aggregates (
+            bucketrow INTEGER NOT NULL,
+            day TEXT NOT NULL,
+            category TEXT NOT NULL,
+            duration INTEGER NOT NULL,
+            PRIMARY KEY (bucketrow, day, category)
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE TABLE daily_aggregates_settings (
+            key TEXT NOT NULL,
+            timezone TEXT
+        )",
+        [],
+    )?;
+    Ok(())
+}
@@ -0,0 +1,78 @@
+//! Replication of the writes to a datastore to a second datastore, see `Datastore::set_mirror`
+//!
+//! The worker of the primary datastore collects the writes which succeeded in its current
+//! transaction and hands them to the mirror once the transaction is committed. A thread of the
+//! mirror applies them to the secondary datastore in the same order, so the primary never waits
+//! for the secondary and failures there are only logged.
+//!
+//! The mirror is asynchronous and best-effort:
+//!
+//! - It lags behind the primary by at least the time until the primary commits, and the
+//!   secondary commits the writes on its own schedule
+//! - Writes which fail on the secondary are logged and dropped, they are not retried
+//! - Writes still queued when the process exits are lost
+//! - Inserted events are replicated with the ids they were given by the primary, so the ids
+//!   match as long as the secondary started out as a copy of the primary. Heartbeats are
+//!   repeated as heartbeats, events they create get ids from the secondary, which are the same
+//!   integer ids unless the primary uses UUIDs.
+//! - Writes made while no mirror was set are never replicated
+use std::sync::mpsc;
+use std::thread;
+
+use crate::worker::{Command, Response};
+use crate::Datastore;
+
+pub(crate) struct Mirror {
+    sender: mpsc::Sender<Vec<Command>>,
+}
+
+impl Mirror {
+    pub(crate) fn new(secondary: Datastore) -> Mirror {
+        let (sender, receiver) = mpsc::channel::<Vec<Command>>();
+        thread::spawn(move || {
+            for commands in receiver {
+                for command in commands {
+                    if let Err(err) = secondary.request(command) {
+                        warn!("Failed to mirror write to secondary datastore: {:?}", err);
+                    }
+                }
+            }
+            // The primary is gone, make sure the secondary has everything it was sent
+            if let Err(err) = secondary.force_commit() {
+                warn!("Failed to commit secondary datastore: {:?}", err);
+            }
+        });
+        Mirror { sender }
+    }
+
+    /// Queues committed writes to be applied to the secondary datastore
+    pub(crate) fn replicate(&self, commands: Vec<Command>) {
+        if commands.is_empty() {
+            return;
+        }
+        if self.sender.send(commands).is_err() {
+            warn!("Mirror thread has stopped, writes are no longer mirrored");
+        }
+    }
+}
+
+/// The command which repeats a successful write on the secondary, `None` for reads
+pub(crate) fn mirrored_command(command: &Command, response: &Response) -> Option<Command> {
+    match (command, response) {
+        (Command::InsertEvents(bucket_id, _), Response::EventList(events)) => {
+            Some(Command::InsertEvents(bucket_id.clone(), events.clone()))
+        }
+        (Command::Heartbeat(_, _, _), _)
+        | (Command::CreateBucket(_), _)
+        | (Command::CreateBuckets(_, _), _)
+        | (Command::ImportBucket(_, _), _)
+        | (Command::CloneBucket(_, _), _)
+        | (Command::DeleteBucket(_), _)
+        | (Command::UpdateBucketData(_, _), _)
+        | (Command::DeleteEventsById(_, _), _)
+        | (Command::UpdateEventData(_, _, _, _, _), _)
+        | (Command::SetKeyValue(_, _), _)
+        | (Command::DeleteKeyValue(_), _) => Some(command.clone()),
+        _ => None,
+    }
+}
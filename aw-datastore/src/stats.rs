@@ -0,0 +1,54 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use aw_models::ServerStats;
+
+/// Cumulative counters of a `Datastore`, shared by all its clones
+///
+/// The counters start at zero when the datastore is created and are never reset. Deleted events
+/// are counted by the ids asked to be deleted, whether or not they existed.
+#[derive(Debug, Default)]
+pub struct DatastoreStats {
+    events_inserted: AtomicU64,
+    events_deleted: AtomicU64,
+    queries_run: AtomicU64,
+    query_errors: AtomicU64,
+    commits: AtomicU64,
+}
+
+impl DatastoreStats {
+    pub(crate) fn add_events_inserted(&self, count: usize) {
+        self.events_inserted
+            .fetch_add(count as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn add_events_deleted(&self, count: usize) {
+        self.events_deleted
+            .fetch_add(count as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn add_commit(&self) {
+        self.commits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// The number of transactions committed to the database, each of which syncs it to disk
+    pub fn commits(&self) -> u64 {
+        self.commits.load(Ordering::Relaxed)
+    }
+
+    /// Counts a query which was run against the datastore, and whether it failed
+    pub fn record_query(&self, succeeded: bool) {
+        self.queries_run.fetch_add(1, Ordering::Relaxed);
+        if !succeeded {
+            self.query_errors.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn snapshot(&self) -> ServerStats {
+        ServerStats {
+            events_inserted: self.events_inserted.load(Ordering::Relaxed),
+            events_deleted: self.events_deleted.load(Ordering::Relaxed),
+            queries_run: self.queries_run.load(Ordering::Relaxed),
+            query_errors: self.query_errors.load(Ordering::Relaxed),
+        }
+    }
+}
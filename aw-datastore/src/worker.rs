@@ -1,5 +1,6 @@
 use std::collections::HashMap;
 use std::fmt;
+use std::sync::Arc;
 use std::thread;
 
 use chrono::DateTime;
@@ -12,20 +13,38 @@ use rusqlite::Transaction;
 use rusqlite::TransactionBehavior;
 
 use aw_models::Bucket;
+use aw_models::BucketCreationResult;
+use aw_models::BucketState;
+use aw_models::DistinctValue;
 use aw_models::Event;
+use aw_models::EventId;
+use aw_models::VacuumResult;
 
+use crate::mirror::{self, Mirror};
+use crate::DailyAggregates;
 use crate::DatastoreError;
 use crate::DatastoreInstance;
 use crate::DatastoreMethod;
+use crate::DatastoreStats;
+use crate::GetEventsOptions;
 
 use mpsc_requests::ResponseReceiver;
 
 type RequestSender = mpsc_requests::RequestSender<Command, Result<Response, DatastoreError>>;
 type RequestReceiver = mpsc_requests::RequestReceiver<Command, Result<Response, DatastoreError>>;
 
+/// How long an operation waits for a lock held by another connection before failing with
+/// SQLITE_BUSY, see `Datastore::set_busy_timeout`
+pub const DEFAULT_BUSY_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// How many events are inserted in a transaction before it is committed, unless insert
+/// coalescing holds the commit back, see `Datastore::set_insert_coalescing`
+const COMMIT_EVENT_THRESHOLD: usize = 100;
+
 #[derive(Clone)]
 pub struct Datastore {
     requester: RequestSender,
+    stats: Arc<DatastoreStats>,
 }
 
 impl fmt::Debug for Datastore {
@@ -52,31 +71,60 @@ pub enum Response {
     Count(i64),
     KeyValue(String),
     KeyValues(HashMap<String, String>),
+    Vacuum(VacuumResult),
+    BucketCreationResults(HashMap<String, BucketCreationResult>),
+    BucketStates(HashMap<String, BucketState>),
+    DistinctValues(Vec<DistinctValue>),
 }
 
 #[allow(clippy::large_enum_variant)]
 #[derive(Debug, Clone)]
 pub enum Command {
     CreateBucket(Bucket),
+    CreateBuckets(Vec<Bucket>, bool),
+    ImportBucket(Bucket, bool),
+    CloneBucket(String, String),
     DeleteBucket(String),
+    UpdateBucketData(String, serde_json::Map<String, serde_json::Value>),
     GetBucket(String),
     GetBuckets(),
+    GetBucketStates(),
     InsertEvents(String, Vec<Event>),
     Heartbeat(String, Event, f64),
-    GetEvent(String, i64),
+    GetEvent(String, EventId),
     GetEvents(
         String,
         Option<DateTime<Utc>>,
         Option<DateTime<Utc>>,
         Option<u64>,
+        GetEventsOptions,
     ),
+    GetEventsAfterId(String, i64, Option<u64>),
     GetEventCount(String, Option<DateTime<Utc>>, Option<DateTime<Utc>>),
-    DeleteEventsById(String, Vec<i64>),
+    GetDailyAggregates(String, Option<DateTime<Utc>>, Option<DateTime<Utc>>),
+    GetCachedEventCount(String),
+    GetDistinctValues(String, String, Option<DateTime<Utc>>, Option<DateTime<Utc>>),
+    DeleteEventsById(String, Vec<EventId>),
+    UpdateEventData(
+        String,
+        EventId,
+        serde_json::Map<String, serde_json::Value>,
+        Option<DateTime<Utc>>,
+        Option<Duration>,
+    ),
     ForceCommit(),
+    SetCompressEventData(bool),
+    SetUuidEventIds(bool),
+    SetBusyTimeout(std::time::Duration),
+    SetInsertCoalescing(Option<std::time::Duration>),
+    SetDailyAggregates(Option<DailyAggregates>),
+    RebuildDailyAggregates(),
+    SetMirror(Option<Datastore>),
     GetKeyValues(String),
     GetKeyValue(String),
     SetKeyValue(String, String),
     DeleteKeyValue(String),
+    Vacuum(),
     Close(),
 }
 
@@ -99,12 +147,19 @@ struct DatastoreWorker {
     uncommitted_events: usize,
     commit: bool,
     last_heartbeat: HashMap<String, Option<Event>>,
+    mirror: Option<Mirror>,
+    /// Writes of the current transaction which are mirrored once it is committed
+    mirror_pending: Vec<Command>,
+    /// Minimum time a transaction stays open before inserted events commit it
+    insert_coalescing: Option<Duration>,
+    stats: Arc<DatastoreStats>,
 }
 
 impl DatastoreWorker {
     pub fn new(
         responder: mpsc_requests::RequestReceiver<Command, Result<Response, DatastoreError>>,
         legacy_import: bool,
+        stats: Arc<DatastoreStats>,
     ) -> Self {
         DatastoreWorker {
             responder,
@@ -113,6 +168,10 @@ impl DatastoreWorker {
             uncommitted_events: 0,
             commit: false,
             last_heartbeat: HashMap::new(),
+            mirror: None,
+            mirror_pending: Vec::new(),
+            insert_coalescing: None,
+            stats,
         }
     }
 
@@ -126,7 +185,10 @@ impl DatastoreWorker {
                 Connection::open(path).expect("Failed to create datastore")
             }
         };
+        conn.busy_timeout(DEFAULT_BUSY_TIMEOUT)
+            .expect("Failed to set busy timeout");
         let mut ds = DatastoreInstance::new(&conn, true).unwrap();
+        ds.set_stats(self.stats.clone());
 
         // Ensure legacy import
         if self.legacy_import {
@@ -163,6 +225,7 @@ impl DatastoreWorker {
 
             self.uncommitted_events = 0;
             self.commit = false;
+            let mut vacuum_sender = None;
             loop {
                 let (request, response_sender) = match self.responder.poll() {
                     Ok((req, res_sender)) => (req, res_sender),
@@ -173,14 +236,28 @@ impl DatastoreWorker {
                         break;
                     }
                 };
+                if let Command::Vacuum() = request {
+                    // VACUUM can't run inside a transaction, run it after the commit
+                    vacuum_sender = Some(response_sender);
+                    break;
+                }
+                let mirrored = self.mirror.as_ref().map(|_| request.clone());
                 let response = self.handle_request(request, &mut ds, &tx);
+                if let (Some(command), Ok(response)) = (mirrored, &response) {
+                    self.mirror_pending
+                        .extend(mirror::mirrored_command(&command, response));
+                }
                 response_sender.respond(response);
 
                 let now: DateTime<Utc> = Utc::now();
                 let commit_interval_passed: bool = (now - last_commit_time) > Duration::seconds(15);
+                let coalescing_window_passed = self
+                    .insert_coalescing
+                    .is_none_or(|window| (now - last_commit_time) >= window);
                 if self.commit
                     || commit_interval_passed
-                    || self.uncommitted_events > 100
+                    || (self.uncommitted_events > COMMIT_EVENT_THRESHOLD
+                        && coalescing_window_passed)
                     || self.quit
                 {
                     break;
@@ -194,6 +271,17 @@ impl DatastoreWorker {
                 Ok(_) => (),
                 Err(err) => panic!("Failed to commit datastore transaction! {err}"),
             }
+            self.stats.add_commit();
+            ds.commit_heartbeats();
+            let pending = std::mem::take(&mut self.mirror_pending);
+            if let Some(mirror) = &self.mirror {
+                mirror.replicate(pending);
+            }
+            if let Some(response_sender) = vacuum_sender {
+                info!("Vacuuming database");
+                let response = ds.vacuum(&conn).map(Response::Vacuum);
+                response_sender.respond(response);
+            }
             if self.quit {
                 break;
             };
@@ -215,6 +303,30 @@ impl DatastoreWorker {
                 }
                 Err(e) => Err(e),
             },
+            Command::ImportBucket(bucket, preserve_ids) => {
+                match ds.import_bucket(tx, bucket, preserve_ids) {
+                    Ok(_) => {
+                        self.commit = true;
+                        Ok(Response::Empty())
+                    }
+                    Err(e) => Err(e),
+                }
+            }
+            Command::CloneBucket(src_id, dst_id) => match ds.clone_bucket(tx, &src_id, &dst_id) {
+                Ok(_) => {
+                    self.commit = true;
+                    Ok(Response::Empty())
+                }
+                Err(e) => Err(e),
+            },
+            Command::CreateBuckets(buckets, strict) => match ds.create_buckets(tx, buckets, strict)
+            {
+                Ok(results) => {
+                    self.commit = true;
+                    Ok(Response::BucketCreationResults(results))
+                }
+                Err(e) => Err(e),
+            },
             Command::DeleteBucket(bucketname) => match ds.delete_bucket(tx, &bucketname) {
                 Ok(_) => {
                     self.commit = true;
@@ -222,10 +334,23 @@ impl DatastoreWorker {
                 }
                 Err(e) => Err(e),
             },
+            Command::UpdateBucketData(bucketname, patch) => {
+                match ds.update_bucket_data(tx, &bucketname, &patch) {
+                    Ok(bucket) => {
+                        self.commit = true;
+                        Ok(Response::Bucket(bucket))
+                    }
+                    Err(e) => Err(e),
+                }
+            }
             Command::GetBucket(bucketname) => match ds.get_bucket(&bucketname) {
                 Ok(b) => Ok(Response::Bucket(b)),
                 Err(e) => Err(e),
             },
+            Command::GetBucketStates() => match ds.get_bucket_states(tx) {
+                Ok(states) => Ok(Response::BucketStates(states)),
+                Err(e) => Err(e),
+            },
             Command::GetBuckets() => Ok(Response::BucketMap(ds.get_buckets())),
             Command::InsertEvents(bucketname, events) => {
                 match ds.insert_events(tx, &bucketname, events) {
@@ -247,13 +372,26 @@ impl DatastoreWorker {
                 }
             }
             Command::GetEvent(bucketname, event_id) => {
-                match ds.get_event(tx, &bucketname, event_id) {
+                match ds.get_event(tx, &bucketname, &event_id) {
                     Ok(el) => Ok(Response::Event(el)),
                     Err(e) => Err(e),
                 }
             }
-            Command::GetEvents(bucketname, starttime_opt, endtime_opt, limit_opt) => {
-                match ds.get_events(tx, &bucketname, starttime_opt, endtime_opt, limit_opt) {
+            Command::GetEvents(bucketname, starttime_opt, endtime_opt, limit_opt, options) => {
+                match ds.get_events(
+                    tx,
+                    &bucketname,
+                    starttime_opt,
+                    endtime_opt,
+                    limit_opt,
+                    options,
+                ) {
+                    Ok(el) => Ok(Response::EventList(el)),
+                    Err(e) => Err(e),
+                }
+            }
+            Command::GetEventsAfterId(bucketname, after_id, limit_opt) => {
+                match ds.get_events_after_id(tx, &bucketname, after_id, limit_opt) {
                     Ok(el) => Ok(Response::EventList(el)),
                     Err(e) => Err(e),
                 }
@@ -264,16 +402,83 @@ impl DatastoreWorker {
                     Err(e) => Err(e),
                 }
             }
+            Command::GetDailyAggregates(bucketname, starttime_opt, endtime_opt) => {
+                match ds.get_daily_aggregates(tx, &bucketname, starttime_opt, endtime_opt) {
+                    Ok(el) => Ok(Response::EventList(el)),
+                    Err(e) => Err(e),
+                }
+            }
+            Command::GetCachedEventCount(bucketname) => {
+                match ds.get_cached_event_count(tx, &bucketname) {
+                    Ok(n) => Ok(Response::Count(n)),
+                    Err(e) => Err(e),
+                }
+            }
+            Command::GetDistinctValues(bucketname, key, starttime_opt, endtime_opt) => {
+                match ds.get_distinct_values(tx, &bucketname, &key, starttime_opt, endtime_opt) {
+                    Ok(values) => Ok(Response::DistinctValues(values)),
+                    Err(e) => Err(e),
+                }
+            }
             Command::DeleteEventsById(bucketname, event_ids) => {
                 match ds.delete_events_by_id(tx, &bucketname, event_ids) {
                     Ok(()) => Ok(Response::Empty()),
                     Err(e) => Err(e),
                 }
             }
+            Command::UpdateEventData(bucketname, event_id, patch, timestamp, duration) => {
+                match ds.update_event_data(tx, &bucketname, &event_id, &patch, timestamp, duration)
+                {
+                    Ok(event) => {
+                        self.commit = true;
+                        self.last_heartbeat.insert(bucketname.to_string(), None); // invalidate last_heartbeat cache
+                        Ok(Response::Event(event))
+                    }
+                    Err(e) => Err(e),
+                }
+            }
             Command::ForceCommit() => {
                 self.commit = true;
                 Ok(Response::Empty())
             }
+            Command::SetCompressEventData(enabled) => {
+                ds.set_compress_event_data(enabled);
+                Ok(Response::Empty())
+            }
+            Command::SetUuidEventIds(enabled) => {
+                ds.set_uuid_event_ids(enabled);
+                Ok(Response::Empty())
+            }
+            Command::SetBusyTimeout(timeout) => match tx.busy_timeout(timeout) {
+                Ok(()) => Ok(Response::Empty()),
+                Err(err) => Err(DatastoreError::InternalError(format!(
+                    "Failed to set busy timeout: {err}"
+                ))),
+            },
+            Command::SetInsertCoalescing(window) => {
+                self.insert_coalescing = window.map(|window| Duration::from_std(window).unwrap());
+                Ok(Response::Empty())
+            }
+            Command::SetDailyAggregates(settings) => match ds.set_daily_aggregates(tx, settings) {
+                Ok(()) => {
+                    self.commit = true;
+                    Ok(Response::Empty())
+                }
+                Err(e) => Err(e),
+            },
+            Command::RebuildDailyAggregates() => match ds.rebuild_daily_aggregates(tx) {
+                Ok(()) => {
+                    self.commit = true;
+                    Ok(Response::Empty())
+                }
+                Err(e) => Err(e),
+            },
+            Command::SetMirror(secondary) => {
+                // Writes made before the mirror was set or replaced aren't mirrored
+                self.mirror_pending.clear();
+                self.mirror = secondary.map(Mirror::new);
+                Ok(Response::Empty())
+            }
             Command::GetKeyValues(pattern) => match ds.get_key_values(tx, pattern.as_str()) {
                 Ok(result) => Ok(Response::KeyValues(result)),
                 Err(e) => Err(e),
@@ -290,6 +495,9 @@ impl DatastoreWorker {
                 Ok(()) => Ok(Response::Empty()),
                 Err(e) => Err(e),
             },
+            Command::Vacuum() => Err(DatastoreError::InternalError(
+                "Vacuum can't be run inside a transaction".to_string(),
+            )),
             Command::Close() => {
                 self.quit = true;
                 Ok(Response::Empty())
@@ -312,11 +520,18 @@ impl Datastore {
     fn _new_internal(method: DatastoreMethod, legacy_import: bool) -> Self {
         let (requester, responder) =
             mpsc_requests::channel::<Command, Result<Response, DatastoreError>>();
+        let stats = Arc::new(DatastoreStats::default());
+        let worker_stats = stats.clone();
         let _thread = thread::spawn(move || {
-            let mut di = DatastoreWorker::new(responder, legacy_import);
+            let mut di = DatastoreWorker::new(responder, legacy_import, worker_stats);
             di.work_loop(method);
         });
-        Datastore { requester }
+        Datastore { requester, stats }
+    }
+
+    /// Counters of the events inserted and deleted through this datastore and its clones
+    pub fn stats(&self) -> &DatastoreStats {
+        &self.stats
     }
 
     pub fn create_bucket(&self, bucket: &Bucket) -> Result<(), DatastoreError> {
@@ -328,6 +543,40 @@ impl Datastore {
         }
     }
 
+    /// Creates a bucket with its events, see `DatastoreInstance::import_bucket`
+    pub fn import_bucket(&self, bucket: &Bucket, preserve_ids: bool) -> Result<(), DatastoreError> {
+        let cmd = Command::ImportBucket(bucket.clone(), preserve_ids);
+        let receiver = self.requester.request(cmd).unwrap();
+        match receiver.collect().unwrap() {
+            Ok(_) => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Copies a bucket and its events to a new bucket, see `DatastoreInstance::clone_bucket`
+    pub fn clone_bucket(&self, src_id: &str, dst_id: &str) -> Result<(), DatastoreError> {
+        let cmd = Command::CloneBucket(src_id.to_string(), dst_id.to_string());
+        let receiver = self.requester.request(cmd).unwrap();
+        _unwrap_response(receiver)
+    }
+
+    /// Creates all buckets in one transaction, see `DatastoreInstance::create_buckets`
+    pub fn create_buckets(
+        &self,
+        buckets: Vec<Bucket>,
+        strict: bool,
+    ) -> Result<HashMap<String, BucketCreationResult>, DatastoreError> {
+        let cmd = Command::CreateBuckets(buckets, strict);
+        let receiver = self.requester.request(cmd).unwrap();
+        match receiver.collect().unwrap() {
+            Ok(r) => match r {
+                Response::BucketCreationResults(results) => Ok(results),
+                _ => panic!("Invalid response"),
+            },
+            Err(e) => Err(e),
+        }
+    }
+
     pub fn delete_bucket(&self, bucket_id: &str) -> Result<(), DatastoreError> {
         let cmd = Command::DeleteBucket(bucket_id.to_string());
         let receiver = self.requester.request(cmd).unwrap();
@@ -340,6 +589,23 @@ impl Datastore {
         }
     }
 
+    /// Applies a JSON merge patch to the data of a bucket, keys set to null are removed
+    pub fn update_bucket_data(
+        &self,
+        bucket_id: &str,
+        patch: serde_json::Map<String, serde_json::Value>,
+    ) -> Result<Bucket, DatastoreError> {
+        let cmd = Command::UpdateBucketData(bucket_id.to_string(), patch);
+        let receiver = self.requester.request(cmd).unwrap();
+        match receiver.collect().unwrap() {
+            Ok(r) => match r {
+                Response::Bucket(b) => Ok(b),
+                _ => panic!("Invalid response"),
+            },
+            Err(e) => Err(e),
+        }
+    }
+
     pub fn get_bucket(&self, bucket_id: &str) -> Result<Bucket, DatastoreError> {
         let cmd = Command::GetBucket(bucket_id.to_string());
         let receiver = self.requester.request(cmd).unwrap();
@@ -352,6 +618,19 @@ impl Datastore {
         }
     }
 
+    /// Last update time and event count of every bucket
+    pub fn get_bucket_states(&self) -> Result<HashMap<String, BucketState>, DatastoreError> {
+        let cmd = Command::GetBucketStates();
+        let receiver = self.requester.request(cmd).unwrap();
+        match receiver.collect().unwrap() {
+            Ok(r) => match r {
+                Response::BucketStates(states) => Ok(states),
+                _ => panic!("Invalid response"),
+            },
+            Err(e) => Err(e),
+        }
+    }
+
     pub fn get_buckets(&self) -> Result<HashMap<String, Bucket>, DatastoreError> {
         let cmd = Command::GetBuckets();
         let receiver = self.requester.request(cmd).unwrap();
@@ -399,7 +678,7 @@ impl Datastore {
         }
     }
 
-    pub fn get_event(&self, bucket_id: &str, event_id: i64) -> Result<Event, DatastoreError> {
+    pub fn get_event(&self, bucket_id: &str, event_id: EventId) -> Result<Event, DatastoreError> {
         let cmd = Command::GetEvent(bucket_id.to_string(), event_id);
         let receiver = self.requester.request(cmd).unwrap();
         match receiver.collect().unwrap() {
@@ -411,6 +690,7 @@ impl Datastore {
         }
     }
 
+    /// Get the events overlapping `[starttime, endtime]`, see `get_events_with_options`
     pub fn get_events(
         &self,
         bucket_id: &str,
@@ -418,7 +698,76 @@ impl Datastore {
         endtime_opt: Option<DateTime<Utc>>,
         limit_opt: Option<u64>,
     ) -> Result<Vec<Event>, DatastoreError> {
-        let cmd = Command::GetEvents(bucket_id.to_string(), starttime_opt, endtime_opt, limit_opt);
+        self.get_events_with_options(
+            bucket_id,
+            starttime_opt,
+            endtime_opt,
+            limit_opt,
+            GetEventsOptions::default(),
+        )
+    }
+
+    /// Get the events overlapping `[starttime, endtime]`, or `[starttime, endtime)` if
+    /// `inclusive_end` is false, see `DatastoreInstance::get_events`
+    ///
+    /// With an inclusive end events which only touch the start or end of the interval are
+    /// returned with zero duration, so an event at a day boundary is returned for both days.
+    pub fn get_events_with_options(
+        &self,
+        bucket_id: &str,
+        starttime_opt: Option<DateTime<Utc>>,
+        endtime_opt: Option<DateTime<Utc>>,
+        limit_opt: Option<u64>,
+        options: GetEventsOptions,
+    ) -> Result<Vec<Event>, DatastoreError> {
+        let cmd = Command::GetEvents(
+            bucket_id.to_string(),
+            starttime_opt,
+            endtime_opt,
+            limit_opt,
+            options,
+        );
+        let receiver = self.requester.request(cmd).unwrap();
+        match receiver.collect().unwrap() {
+            Ok(r) => match r {
+                Response::EventList(el) => Ok(el),
+                _ => panic!("Invalid response"),
+            },
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Events with an id greater than `after_id` in id order, see
+    /// `DatastoreInstance::get_events_after_id`
+    pub fn get_events_after_id(
+        &self,
+        bucket_id: &str,
+        after_id: i64,
+        limit_opt: Option<u64>,
+    ) -> Result<Vec<Event>, DatastoreError> {
+        let cmd = Command::GetEventsAfterId(bucket_id.to_string(), after_id, limit_opt);
+        let receiver = self.requester.request(cmd).unwrap();
+        match receiver.collect().unwrap() {
+            Ok(r) => match r {
+                Response::EventList(el) => Ok(el),
+                _ => panic!("Invalid response"),
+            },
+            Err(e) => Err(e),
+        }
+    }
+
+    /// The time per local day and category of the events in the bucket, as an event per day
+    /// and category starting at the start of the day, for the days overlapping the range
+    ///
+    /// Much cheaper than reading the events of long ranges, but only available with daily
+    /// aggregates enabled, see `set_daily_aggregates`.
+    pub fn get_daily_aggregates(
+        &self,
+        bucket_id: &str,
+        starttime_opt: Option<DateTime<Utc>>,
+        endtime_opt: Option<DateTime<Utc>>,
+    ) -> Result<Vec<Event>, DatastoreError> {
+        let cmd = Command::GetDailyAggregates(bucket_id.to_string(), starttime_opt, endtime_opt);
         let receiver = self.requester.request(cmd).unwrap();
         match receiver.collect().unwrap() {
             Ok(r) => match r {
@@ -446,13 +795,185 @@ impl Datastore {
         }
     }
 
+    /// Values of a top-level data key in the events of an interval, see
+    /// `DatastoreInstance::get_distinct_values`
+    pub fn get_distinct_values(
+        &self,
+        bucket_id: &str,
+        key: &str,
+        starttime_opt: Option<DateTime<Utc>>,
+        endtime_opt: Option<DateTime<Utc>>,
+    ) -> Result<Vec<DistinctValue>, DatastoreError> {
+        let cmd = Command::GetDistinctValues(
+            bucket_id.to_string(),
+            key.to_string(),
+            starttime_opt,
+            endtime_opt,
+        );
+        let receiver = self.requester.request(cmd).unwrap();
+        match receiver.collect().unwrap() {
+            Ok(r) => match r {
+                Response::DistinctValues(values) => Ok(values),
+                _ => panic!("Invalid response"),
+            },
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Total number of events in the bucket, kept cached between inserts and deletes
+    pub fn get_cached_event_count(&self, bucket_id: &str) -> Result<i64, DatastoreError> {
+        let cmd = Command::GetCachedEventCount(bucket_id.to_string());
+        let receiver = self.requester.request(cmd).unwrap();
+        match receiver.collect().unwrap() {
+            Ok(r) => match r {
+                Response::Count(n) => Ok(n),
+                _ => panic!("Invalid response"),
+            },
+            Err(e) => Err(e),
+        }
+    }
+
     pub fn delete_events_by_id(
         &self,
         bucket_id: &str,
-        event_ids: Vec<i64>,
+        event_ids: Vec<EventId>,
     ) -> Result<(), DatastoreError> {
+        let count = event_ids.len();
         let cmd = Command::DeleteEventsById(bucket_id.to_string(), event_ids);
         let receiver = self.requester.request(cmd).unwrap();
+        match receiver.collect().unwrap() {
+            Ok(r) => match r {
+                Response::Empty() => {
+                    self.stats.add_events_deleted(count);
+                    Ok(())
+                }
+                _ => panic!("Invalid response"),
+            },
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn update_event_data(
+        &self,
+        bucket_id: &str,
+        event_id: EventId,
+        patch: serde_json::Map<String, serde_json::Value>,
+        timestamp: Option<DateTime<Utc>>,
+        duration: Option<Duration>,
+    ) -> Result<Event, DatastoreError> {
+        let cmd =
+            Command::UpdateEventData(bucket_id.to_string(), event_id, patch, timestamp, duration);
+        let receiver = self.requester.request(cmd).unwrap();
+        match receiver.collect().unwrap() {
+            Ok(r) => match r {
+                Response::Event(e) => Ok(e),
+                _ => panic!("Invalid response"),
+            },
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Sets how long operations wait for locks held by other connections to the database, such
+    /// as another process reading it, before failing. Defaults to `DEFAULT_BUSY_TIMEOUT`.
+    pub fn set_busy_timeout(&self, timeout: std::time::Duration) -> Result<(), DatastoreError> {
+        let cmd = Command::SetBusyTimeout(timeout);
+        let receiver = self.requester.request(cmd).unwrap();
+        match receiver.collect().unwrap() {
+            Ok(r) => match r {
+                Response::Empty() => Ok(()),
+                _ => panic!("Invalid response"),
+            },
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Holds back the commit of inserted events until the transaction has been open for at least
+    /// `window`, or commits as soon as enough events are inserted if `None`, which is the default
+    ///
+    /// The worker keeps a transaction open across requests and commits it after 100 inserted
+    /// events, on writes to buckets and key-values, on `force_commit` and every 15 seconds. Under
+    /// a steady stream of inserts, such as many watchers sending heartbeats or an import in small
+    /// batches, the event threshold alone can commit, and so fsync, many times a second. With a
+    /// window of e.g. 50ms the inserts within it are coalesced into one commit instead. Every
+    /// request is still handled in order and answered on its own, so a failed insert doesn't
+    /// affect the others, but a successful one is only durable once the transaction is committed.
+    pub fn set_insert_coalescing(
+        &self,
+        window: Option<std::time::Duration>,
+    ) -> Result<(), DatastoreError> {
+        let cmd = Command::SetInsertCoalescing(window);
+        let receiver = self.requester.request(cmd).unwrap();
+        _unwrap_response(receiver)
+    }
+
+    /// Maintains a table of the time per bucket, local day and category of the events, which
+    /// `get_daily_aggregates` reads, or drops it if `None`, which is the default
+    ///
+    /// The settings are stored in the database, so the table stays maintained when the database
+    /// is opened again. Enabling or changing the settings builds the table from all events,
+    /// which takes a while on large databases. While enabled, every write of events updates the
+    /// days it touches in the same transaction, see the `daily_aggregates` module.
+    pub fn set_daily_aggregates(
+        &self,
+        settings: Option<DailyAggregates>,
+    ) -> Result<(), DatastoreError> {
+        let cmd = Command::SetDailyAggregates(settings);
+        let receiver = self.requester.request(cmd).unwrap();
+        _unwrap_response(receiver)
+    }
+
+    /// Recomputes all daily aggregates from the events, see `set_daily_aggregates`
+    pub fn rebuild_daily_aggregates(&self) -> Result<(), DatastoreError> {
+        let cmd = Command::RebuildDailyAggregates();
+        let receiver = self.requester.request(cmd).unwrap();
+        _unwrap_response(receiver)
+    }
+
+    /// Mirrors the writes to this datastore to `secondary`, or stops mirroring if `None`
+    ///
+    /// Writes are applied to the secondary in the background after they have been committed
+    /// here, failures are logged and don't affect this datastore, see the `mirror` module for
+    /// the guarantees. The secondary must not be this datastore or mirror back to it.
+    pub fn set_mirror(&self, secondary: Option<Datastore>) -> Result<(), DatastoreError> {
+        let cmd = Command::SetMirror(secondary);
+        let receiver = self.requester.request(cmd).unwrap();
+        match receiver.collect().unwrap() {
+            Ok(r) => match r {
+                Response::Empty() => Ok(()),
+                _ => panic!("Invalid response"),
+            },
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Sends a command to the worker and waits for its response
+    pub(crate) fn request(&self, cmd: Command) -> Result<Response, DatastoreError> {
+        match self.requester.request(cmd) {
+            Ok(receiver) => receiver.collect().map_err(|_| DatastoreError::MpscError)?,
+            Err(_) => Err(DatastoreError::MpscError),
+        }
+    }
+
+    /// Enables zstd compression of large event data, disabled by default
+    pub fn set_compress_event_data(&self, enabled: bool) -> Result<(), DatastoreError> {
+        let cmd = Command::SetCompressEventData(enabled);
+        let receiver = self.requester.request(cmd).unwrap();
+        match receiver.collect().unwrap() {
+            Ok(r) => match r {
+                Response::Empty() => Ok(()),
+                _ => panic!("Invalid response"),
+            },
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Gives new events UUIDs as ids instead of integers, disabled by default
+    ///
+    /// Events which already have an integer id keep it, so a database should preferably use the
+    /// same setting from the start.
+    pub fn set_uuid_event_ids(&self, enabled: bool) -> Result<(), DatastoreError> {
+        let cmd = Command::SetUuidEventIds(enabled);
+        let receiver = self.requester.request(cmd).unwrap();
         match receiver.collect().unwrap() {
             Ok(r) => match r {
                 Response::Empty() => Ok(()),
@@ -514,6 +1035,21 @@ impl Datastore {
         _unwrap_response(receiver)
     }
 
+    /// Rebuilds the database to shrink it after large deletions
+    ///
+    /// This can take a long time on large databases, no other requests are handled meanwhile.
+    pub fn vacuum(&self) -> Result<VacuumResult, DatastoreError> {
+        let cmd = Command::Vacuum();
+        let receiver = self.requester.request(cmd).unwrap();
+        match receiver.collect().unwrap() {
+            Ok(r) => match r {
+                Response::Vacuum(result) => Ok(result),
+                _ => panic!("Invalid response"),
+            },
+            Err(e) => Err(e),
+        }
+    }
+
     // Should block until worker has stopped
     pub fn close(&self) {
         info!("Sending close request to database");
@@ -13,11 +13,18 @@ mod datastore_tests {
     use chrono::Utc;
     use serde_json::json;
 
+    use aw_datastore::DailyAggregates;
     use aw_datastore::Datastore;
+    use aw_datastore::DatastoreError;
+    use aw_datastore::GetEventsOptions;
+    use aw_datastore::MONOTONIC_TIMESTAMPS_KEY;
 
     use aw_models::Bucket;
+    use aw_models::BucketCreationResult;
     use aw_models::BucketMetadata;
     use aw_models::Event;
+    use aw_models::EventId;
+    use aw_models::TryVec;
 
     fn test_bucket() -> Bucket {
         Bucket {
@@ -119,6 +126,182 @@ mod datastore_tests {
         }
     }
 
+    #[test]
+    fn test_buckets_create() {
+        // Setup datastore
+        let ds = Datastore::new_in_memory(false);
+        let existing = create_test_bucket(&ds);
+        let bucket = |id: &str| Bucket {
+            id: id.to_string(),
+            ..test_bucket()
+        };
+
+        // Strict creation fails as a whole if one of the buckets exists
+        let res = ds.create_buckets(vec![bucket("new1"), existing.clone()], true);
+        assert!(matches!(res, Err(DatastoreError::BucketAlreadyExists(_))));
+        assert!(ds.get_bucket("new1").is_err());
+        let res = ds.create_buckets(vec![bucket("new1"), bucket("new1")], true);
+        assert!(matches!(res, Err(DatastoreError::BucketAlreadyExists(_))));
+
+        // Otherwise existing ones are skipped
+        let results = ds
+            .create_buckets(
+                vec![
+                    bucket("new1"),
+                    existing.clone(),
+                    bucket("new2"),
+                    bucket("new1"),
+                ],
+                false,
+            )
+            .unwrap();
+        assert_eq!(results.len(), 3);
+        assert_eq!(results["new1"], BucketCreationResult::Created);
+        assert_eq!(results["new2"], BucketCreationResult::Created);
+        assert_eq!(results[&existing.id], BucketCreationResult::Skipped);
+        assert_eq!(ds.get_buckets().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_import_bucket() {
+        // Setup datastore
+        let ds = Datastore::new_in_memory(false);
+        let event = |id: i64| Event {
+            id: Some(EventId::Int(id)),
+            timestamp: Utc::now(),
+            duration: Duration::seconds(1),
+            data: json_map! {},
+        };
+        let bucket = |id: &str, event_ids: &[i64]| Bucket {
+            id: id.to_string(),
+            events: Some(TryVec::new(event_ids.iter().map(|id| event(*id)).collect())),
+            ..test_bucket()
+        };
+        let event_ids = |bucket_id: &str| {
+            let mut ids: Vec<EventId> = ds
+                .get_events(bucket_id, None, None, None)
+                .unwrap()
+                .into_iter()
+                .map(|e| e.id.unwrap())
+                .collect();
+            ids.sort_by_key(|id| id.to_string());
+            ids
+        };
+
+        ds.import_bucket(&bucket("b1", &[10, 11]), true).unwrap();
+        assert_eq!(event_ids("b1"), vec![EventId::Int(10), EventId::Int(11)]);
+
+        // Ids used in another bucket or twice in the import fail without creating anything
+        let res = ds.import_bucket(&bucket("b2", &[12, 11]), true);
+        assert!(matches!(res, Err(DatastoreError::EventAlreadyExists(id)) if id == "11"));
+        let res = ds.import_bucket(&bucket("b2", &[12, 12]), true);
+        assert!(matches!(res, Err(DatastoreError::EventAlreadyExists(id)) if id == "12"));
+        assert!(ds.get_bucket("b2").is_err());
+        assert_eq!(event_ids("b1"), vec![EventId::Int(10), EventId::Int(11)]);
+
+        // Without preserving ids new ones are assigned
+        ds.import_bucket(&bucket("b2", &[10, 11]), false).unwrap();
+        let ids = event_ids("b2");
+        assert_eq!(ids.len(), 2);
+        assert!(!ids.contains(&EventId::Int(10)) && !ids.contains(&EventId::Int(11)));
+        assert_eq!(event_ids("b1"), vec![EventId::Int(10), EventId::Int(11)]);
+    }
+
+    #[test]
+    fn test_clone_bucket() {
+        let ds = Datastore::new_in_memory(false);
+        let bucket = create_test_bucket(&ds);
+        ds.update_bucket_data(&bucket.id, json_map! {"name": "Original"})
+            .unwrap();
+        let events: Vec<Event> = (0..3)
+            .map(|i| Event {
+                id: None,
+                timestamp: Utc::now() + Duration::seconds(i),
+                duration: Duration::seconds(1),
+                data: json_map! {"i": i},
+            })
+            .collect();
+        let events = ds.insert_events(&bucket.id, &events).unwrap();
+
+        ds.clone_bucket(&bucket.id, "clone").unwrap();
+        let clone = ds.get_bucket("clone").unwrap();
+        assert_eq!(clone._type, bucket._type);
+        assert_eq!(clone.data, json_map! {"name": "Original"});
+        let cloned = ds.get_events("clone", None, None, None).unwrap();
+        let original = ds.get_events(&bucket.id, None, None, None).unwrap();
+        assert_eq!(cloned.len(), 3);
+        for (cloned, original) in cloned.iter().zip(original.iter()) {
+            assert_eq!(cloned.timestamp, original.timestamp);
+            assert_eq!(cloned.data, original.data);
+            assert!(!events.iter().any(|e| e.id == cloned.id));
+        }
+
+        // The clone is independent of the original
+        ds.delete_events_by_id("clone", vec![cloned[0].id.clone().unwrap()])
+            .unwrap();
+        assert_eq!(ds.get_event_count(&bucket.id, None, None).unwrap(), 3);
+
+        // Existing targets are rejected
+        let res = ds.clone_bucket(&bucket.id, "clone");
+        assert!(matches!(res, Err(DatastoreError::BucketAlreadyExists(id)) if id == "clone"));
+        assert_eq!(ds.get_event_count("clone", None, None).unwrap(), 2);
+        let res = ds.clone_bucket("nonexistent", "clone2");
+        assert!(matches!(res, Err(DatastoreError::NoSuchBucket(_))));
+    }
+
+    #[test]
+    fn test_bucket_states() {
+        // Setup datastore
+        let ds = Datastore::new_in_memory(false);
+        let bucket = create_test_bucket(&ds);
+        let empty = Bucket {
+            id: "empty".to_string(),
+            ..test_bucket()
+        };
+        ds.create_bucket(&empty).unwrap();
+
+        let states = ds.get_bucket_states().unwrap();
+        assert_eq!(states.len(), 2);
+        assert_eq!(states["empty"].event_count, 0);
+        assert!(states["empty"].last_updated.is_some());
+
+        // Every change bumps last_updated
+        let mut last_updated = states[&bucket.id].last_updated;
+        let mut assert_updated = |event_count: i64| {
+            let state = ds.get_bucket_states().unwrap()[&bucket.id].clone();
+            assert_eq!(state.event_count, event_count);
+            assert!(state.last_updated > last_updated);
+            last_updated = state.last_updated;
+        };
+        let event = Event {
+            id: None,
+            timestamp: Utc::now(),
+            duration: Duration::seconds(1),
+            data: json_map! {"key": json!("value")},
+        };
+        std::thread::sleep(std::time::Duration::from_millis(1));
+        let inserted = ds
+            .insert_events(&bucket.id, std::slice::from_ref(&event))
+            .unwrap();
+        assert_updated(1);
+
+        std::thread::sleep(std::time::Duration::from_millis(1));
+        let mut heartbeat = event.clone();
+        heartbeat.timestamp += Duration::seconds(1);
+        ds.heartbeat(&bucket.id, heartbeat, 10.0).unwrap();
+        assert_updated(1);
+
+        std::thread::sleep(std::time::Duration::from_millis(1));
+        let event_id = inserted[0].id.clone().unwrap();
+        ds.update_event_data(&bucket.id, event_id.clone(), json_map! {}, None, None)
+            .unwrap();
+        assert_updated(1);
+
+        std::thread::sleep(std::time::Duration::from_millis(1));
+        ds.delete_events_by_id(&bucket.id, vec![event_id]).unwrap();
+        assert_updated(0);
+    }
+
     #[test]
     fn test_events_get_single() {
         // Setup datastore
@@ -140,9 +323,9 @@ mod datastore_tests {
 
         let events = ds.get_events(&bucket.id, None, None, None).unwrap();
         let first_event = events.first().unwrap();
-        let first_event_id = first_event.id.unwrap();
+        let first_event_id = first_event.id.clone().unwrap();
 
-        let fetched_event = ds.get_event(&bucket.id, first_event_id).unwrap();
+        let fetched_event = ds.get_event(&bucket.id, first_event_id.clone()).unwrap();
         // TODO: Check entire events to ensure integrity
         assert_eq!(fetched_event.id.unwrap(), first_event_id);
     }
@@ -209,6 +392,168 @@ mod datastore_tests {
         assert_eq!(event_count, 2);
     }
 
+    #[test]
+    fn test_monotonic_timestamps() {
+        let ds = Datastore::new_in_memory(false);
+        let mut bucket = test_bucket();
+        bucket
+            .data
+            .insert(MONOTONIC_TIMESTAMPS_KEY.to_string(), json!(true));
+        ds.create_bucket(&bucket).unwrap();
+        let plain_bucket = Bucket {
+            id: "testid-plain".to_string(),
+            ..test_bucket()
+        };
+        ds.create_bucket(&plain_bucket).unwrap();
+
+        let event = |sec: i64| Event {
+            id: None,
+            timestamp: chrono::DateTime::from_timestamp(sec, 0).unwrap(),
+            duration: Duration::seconds(1),
+            data: json_map! {},
+        };
+        ds.insert_events(&bucket.id, &[event(10), event(20), event(20)])
+            .unwrap();
+
+        // Events before the latest one are rejected, and nothing of the batch is inserted
+        let res = ds.insert_events(&bucket.id, &[event(30), event(15)]);
+        assert!(matches!(res, Err(DatastoreError::EventOutOfOrder(_))));
+        let res = ds.insert_events(&bucket.id, &[event(19)]);
+        assert!(matches!(res, Err(DatastoreError::EventOutOfOrder(_))));
+        assert_eq!(ds.get_event_count(&bucket.id, None, None).unwrap(), 3);
+        ds.insert_events(&bucket.id, &[event(20), event(25)])
+            .unwrap();
+
+        // Events can only be moved as far as the events inserted before and after them
+        let inserted = ds
+            .insert_events(&bucket.id, &[event(30), event(40)])
+            .unwrap();
+        let move_to = |event_id: &Option<EventId>, sec: i64| {
+            ds.update_event_data(
+                &bucket.id,
+                event_id.clone().unwrap(),
+                json_map! {},
+                Some(event(sec).timestamp),
+                None,
+            )
+        };
+        let res = move_to(&inserted[0].id, 24);
+        assert!(matches!(res, Err(DatastoreError::EventOutOfOrder(_))));
+        let res = move_to(&inserted[0].id, 41);
+        assert!(matches!(res, Err(DatastoreError::EventOutOfOrder(_))));
+        move_to(&inserted[0].id, 35).unwrap();
+        move_to(&inserted[1].id, 100).unwrap();
+
+        // Without the flag events may be backfilled
+        ds.insert_events(&plain_bucket.id, &[event(20), event(10)])
+            .unwrap();
+        assert_eq!(ds.get_event_count(&plain_bucket.id, None, None).unwrap(), 2);
+    }
+
+    #[test]
+    fn test_cached_event_count() {
+        // Setup datastore
+        let ds = Datastore::new_in_memory(false);
+        let bucket = create_test_bucket(&ds);
+        assert_eq!(ds.get_cached_event_count(&bucket.id).unwrap(), 0);
+
+        let event = |sec: i64| Event {
+            id: None,
+            timestamp: chrono::DateTime::from_timestamp(sec, 0).unwrap(),
+            duration: Duration::seconds(1),
+            data: json_map! {"key": json!(sec)},
+        };
+        let inserted = ds
+            .insert_events(&bucket.id, &[event(0), event(10), event(20)])
+            .unwrap();
+        assert_eq!(ds.get_cached_event_count(&bucket.id).unwrap(), 3);
+
+        // A heartbeat merged into the last event leaves the count unchanged
+        ds.heartbeat(&bucket.id, event(20), 5.0).unwrap();
+        assert_eq!(ds.get_cached_event_count(&bucket.id).unwrap(), 3);
+        ds.heartbeat(&bucket.id, event(100), 5.0).unwrap();
+        assert_eq!(ds.get_cached_event_count(&bucket.id).unwrap(), 4);
+
+        // Replacing an event by id doesn't add one
+        ds.insert_events(&bucket.id, &inserted[..1]).unwrap();
+        assert_eq!(ds.get_cached_event_count(&bucket.id).unwrap(), 4);
+
+        ds.delete_events_by_id(&bucket.id, vec![inserted[1].id.clone().unwrap()])
+            .unwrap();
+        assert_eq!(ds.get_cached_event_count(&bucket.id).unwrap(), 3);
+        assert_eq!(ds.get_event_count(&bucket.id, None, None).unwrap(), 3);
+
+        // A bucket created again with the same id starts from zero
+        ds.delete_bucket(&bucket.id).unwrap();
+        assert!(ds.get_cached_event_count(&bucket.id).is_err());
+        let bucket = create_test_bucket(&ds);
+        assert_eq!(ds.get_cached_event_count(&bucket.id).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_distinct_values() {
+        let ds = Datastore::new_in_memory(false);
+        let bucket = create_test_bucket(&ds);
+
+        let large_title = "a".repeat(10000);
+        let event = |sec: i64, data| Event {
+            id: None,
+            timestamp: chrono::DateTime::from_timestamp(sec, 0).unwrap(),
+            duration: Duration::seconds(1),
+            data,
+        };
+        ds.insert_events(
+            &bucket.id,
+            &[
+                event(0, json_map! {"app": json!("Editor")}),
+                event(10, json_map! {"app": json!("Browser")}),
+                event(
+                    20,
+                    json_map! {"app": json!("Editor"), "title": json!("notes")},
+                ),
+                event(30, json_map! {"app": json!(null)}),
+                event(40, json_map! {"title": json!("no app")}),
+                event(50, json_map! {"app": json!(1)}),
+            ],
+        )
+        .unwrap();
+        // Large data is stored compressed and can't be read by SQLite
+        ds.set_compress_event_data(true).unwrap();
+        ds.insert_events(
+            &bucket.id,
+            &[event(
+                60,
+                json_map! {"app": json!("Browser"), "title": json!(large_title)},
+            )],
+        )
+        .unwrap();
+
+        let values = ds
+            .get_distinct_values(&bucket.id, "app", None, None)
+            .unwrap();
+        let values: Vec<_> = values.into_iter().map(|v| (v.value, v.count)).collect();
+        assert_eq!(
+            values,
+            vec![(json!("Browser"), 2), (json!("Editor"), 2), (json!(1), 1)]
+        );
+
+        let values = ds
+            .get_distinct_values(
+                &bucket.id,
+                "app",
+                Some(chrono::DateTime::from_timestamp(15, 0).unwrap()),
+                Some(chrono::DateTime::from_timestamp(35, 0).unwrap()),
+            )
+            .unwrap();
+        let values: Vec<_> = values.into_iter().map(|v| (v.value, v.count)).collect();
+        assert_eq!(values, vec![(json!("Editor"), 1)]);
+
+        assert!(ds
+            .get_distinct_values(&bucket.id, "missing", None, None)
+            .unwrap()
+            .is_empty());
+    }
+
     /// Tests that events that cover a timeperiod get included when that timeperiod is queried.
     #[test]
     fn test_get_events_filters_cover() {
@@ -246,6 +591,123 @@ mod datastore_tests {
         assert_eq!(event_count, 1);
     }
 
+    /// Tests events exactly at the start and end of the queried timeperiod
+    #[test]
+    fn test_get_events_boundaries() {
+        // Setup datastore
+        let ds = Datastore::new_in_memory(false);
+        let bucket = create_test_bucket(&ds);
+
+        let event = |sec: i64, duration: i64, name: &str| Event {
+            id: None,
+            timestamp: chrono::DateTime::from_timestamp(sec, 0).unwrap(),
+            duration: Duration::seconds(duration),
+            data: json_map! {"name": json!(name)},
+        };
+        let events = [
+            event(0, 10, "ends_at_start"),
+            event(10, 0, "zero_at_start"),
+            event(10, 5, "starts_at_start"),
+            event(15, 5, "ends_at_end"),
+            event(20, 5, "starts_at_end"),
+        ];
+        ds.insert_events(&bucket.id, &events).unwrap();
+
+        let start = chrono::DateTime::from_timestamp(10, 0);
+        let end = chrono::DateTime::from_timestamp(20, 0);
+        // The order of events with the same starttime is undefined, so compare sorted names
+        let names = |events: Vec<Event>| -> Vec<String> {
+            let mut names: Vec<String> = events
+                .iter()
+                .map(|e| e.data["name"].as_str().unwrap().to_string())
+                .collect();
+            names.sort();
+            names
+        };
+
+        // Closed interval, events touching the boundaries are included with zero duration
+        let fetched = ds.get_events(&bucket.id, start, end, None).unwrap();
+        assert_eq!(
+            names(fetched.clone()),
+            vec![
+                "ends_at_end",
+                "ends_at_start",
+                "starts_at_end",
+                "starts_at_start",
+                "zero_at_start"
+            ]
+        );
+        assert_eq!(fetched[0].duration, Duration::seconds(0));
+        assert_eq!(fetched[4].duration, Duration::seconds(0));
+
+        // Half-open interval, only events within it are included
+        let half_open = GetEventsOptions {
+            inclusive_end: false,
+            ..Default::default()
+        };
+        let fetched = ds
+            .get_events_with_options(&bucket.id, start, end, None, half_open)
+            .unwrap();
+        assert_eq!(
+            names(fetched),
+            vec!["ends_at_end", "starts_at_start", "zero_at_start"]
+        );
+
+        // Consecutive half-open intervals return each event once
+        let next = ds
+            .get_events_with_options(
+                &bucket.id,
+                end,
+                chrono::DateTime::from_timestamp(30, 0),
+                None,
+                half_open,
+            )
+            .unwrap();
+        assert_eq!(names(next), vec!["starts_at_end"]);
+    }
+
+    #[test]
+    fn test_get_events_order() {
+        // Setup datastore
+        let ds = Datastore::new_in_memory(false);
+        let bucket = create_test_bucket(&ds);
+
+        let event = |sec: i64, name: &str| Event {
+            id: None,
+            timestamp: chrono::DateTime::from_timestamp(sec, 0).unwrap(),
+            duration: Duration::seconds(1),
+            data: json_map! {"name": json!(name)},
+        };
+        let events = [event(10, "b"), event(0, "a"), event(20, "c")];
+        ds.insert_events(&bucket.id, &events).unwrap();
+        let names = |events: Vec<Event>| -> Vec<String> {
+            events
+                .iter()
+                .map(|e| e.data["name"].as_str().unwrap().to_string())
+                .collect()
+        };
+        let ascending = GetEventsOptions {
+            ascending: true,
+            ..Default::default()
+        };
+
+        // Newest first by default
+        let fetched = ds.get_events(&bucket.id, None, None, None).unwrap();
+        assert_eq!(names(fetched), vec!["c", "b", "a"]);
+        let fetched = ds.get_events(&bucket.id, None, None, Some(2)).unwrap();
+        assert_eq!(names(fetched), vec!["c", "b"]);
+
+        // The limit keeps the oldest events when ascending
+        let fetched = ds
+            .get_events_with_options(&bucket.id, None, None, None, ascending)
+            .unwrap();
+        assert_eq!(names(fetched), vec!["a", "b", "c"]);
+        let fetched = ds
+            .get_events_with_options(&bucket.id, None, None, Some(2), ascending)
+            .unwrap();
+        assert_eq!(names(fetched), vec!["a", "b"]);
+    }
+
     #[test]
     fn test_events_delete() {
         // Setup datastore
@@ -281,7 +743,7 @@ mod datastore_tests {
         let e2 = &fetched_events_all[1];
 
         // Delete one event
-        ds.delete_events_by_id(&bucket.id, vec![e1.id.unwrap()])
+        ds.delete_events_by_id(&bucket.id, vec![e1.id.clone().unwrap()])
             .unwrap();
 
         // Get all events
@@ -298,6 +760,191 @@ mod datastore_tests {
         }
     }
 
+    #[test]
+    fn test_events_after_id() {
+        let ds = Datastore::new_in_memory(false);
+        let bucket = create_test_bucket(&ds);
+        let event = |n: i64| Event {
+            id: None,
+            // Inserted out of timestamp order, the ids are in insertion order
+            timestamp: chrono::DateTime::from_timestamp(100 - n, 0).unwrap(),
+            duration: Duration::seconds(1),
+            data: json_map! {"n": n},
+        };
+        let events = ds
+            .insert_events(&bucket.id, &[event(0), event(1), event(2)])
+            .unwrap();
+        let ns = |events: Vec<Event>| -> Vec<i64> {
+            events
+                .iter()
+                .map(|e| e.data["n"].as_i64().unwrap())
+                .collect()
+        };
+        assert_eq!(
+            ns(ds.get_events_after_id(&bucket.id, 0, None).unwrap()),
+            vec![0, 1, 2]
+        );
+
+        ds.delete_events_by_id(&bucket.id, vec![events[1].id.clone().unwrap()])
+            .unwrap();
+        let first_id = match events[0].id {
+            Some(EventId::Int(id)) => id,
+            _ => panic!("Expected an integer id"),
+        };
+        assert_eq!(
+            ns(ds
+                .get_events_after_id(&bucket.id, first_id, Some(1))
+                .unwrap()),
+            vec![2]
+        );
+
+        // Events with UUID ids come after the ones inserted before them
+        ds.set_uuid_event_ids(true).unwrap();
+        ds.insert_events(&bucket.id, &[event(3)]).unwrap();
+        assert_eq!(
+            ns(ds.get_events_after_id(&bucket.id, first_id, None).unwrap()),
+            vec![2, 3]
+        );
+        assert!(matches!(
+            ds.get_events_after_id("nonexistent", 0, None),
+            Err(DatastoreError::NoSuchBucket(_))
+        ));
+    }
+
+    #[test]
+    fn test_daily_aggregates() {
+        let ds = Datastore::new_in_memory(false);
+        let bucket = create_test_bucket(&ds);
+        let day = |day: i64| chrono::DateTime::from_timestamp(day * 86_400, 0).unwrap();
+        let event = |secs: i64, duration_secs: i64, app: &str| Event {
+            id: None,
+            timestamp: day(10) + Duration::seconds(secs),
+            duration: Duration::seconds(duration_secs),
+            data: json_map! {"app": json!(app)},
+        };
+        let totals = |start, end| -> Vec<(chrono::DateTime<Utc>, String, i64)> {
+            ds.get_daily_aggregates(&bucket.id, start, end)
+                .unwrap()
+                .into_iter()
+                .map(|e| {
+                    let app = e.data["app"].as_str().unwrap().to_string();
+                    (e.timestamp, app, e.duration.num_seconds())
+                })
+                .collect()
+        };
+
+        // Events from before the aggregates were enabled are included
+        ds.insert_events(&bucket.id, &[event(0, 60, "editor")])
+            .unwrap();
+        assert!(matches!(
+            ds.get_daily_aggregates(&bucket.id, None, None),
+            Err(DatastoreError::Disabled(_))
+        ));
+        ds.set_daily_aggregates(Some(DailyAggregates {
+            key: "app".to_string(),
+            timezone: Some(chrono_tz::UTC),
+        }))
+        .unwrap();
+        assert_eq!(totals(None, None), vec![(day(10), "editor".into(), 60)]);
+
+        // Inserts, crossing midnight
+        let inserted = ds
+            .insert_events(
+                &bucket.id,
+                &[event(100, 30, "editor"), event(86_300, 200, "browser")],
+            )
+            .unwrap();
+        assert_eq!(
+            totals(None, None),
+            vec![
+                (day(10), "browser".into(), 100),
+                (day(10), "editor".into(), 90),
+                (day(11), "browser".into(), 100),
+            ]
+        );
+        // Only the days overlapping the range
+        assert_eq!(
+            totals(Some(day(11)), Some(day(11) + Duration::hours(1))),
+            vec![(day(11), "browser".into(), 100)]
+        );
+
+        // Heartbeats, data updates and deletions
+        ds.heartbeat(&bucket.id, event(86_520, 0, "browser"), 30.0)
+            .unwrap();
+        ds.update_event_data(
+            &bucket.id,
+            inserted[1].id.clone().unwrap(),
+            json_map! {"app": json!("terminal")},
+            None,
+            None,
+        )
+        .unwrap();
+        ds.delete_events_by_id(&bucket.id, vec![inserted[0].id.clone().unwrap()])
+            .unwrap();
+        assert_eq!(
+            totals(None, None),
+            vec![
+                (day(10), "editor".into(), 60),
+                (day(10), "terminal".into(), 100),
+                (day(11), "terminal".into(), 120),
+            ]
+        );
+
+        // Deleting the bucket removes its aggregates
+        ds.rebuild_daily_aggregates().unwrap();
+        assert_eq!(totals(None, None).len(), 3);
+        ds.delete_bucket(&bucket.id).unwrap();
+        create_test_bucket(&ds);
+        assert!(totals(None, None).is_empty());
+
+        // Days of other timezones, the browser event is within a day at UTC+2
+        ds.insert_events(&bucket.id, &[event(86_300, 200, "browser")])
+            .unwrap();
+        ds.set_daily_aggregates(Some(DailyAggregates {
+            key: "app".to_string(),
+            timezone: Some(chrono_tz::Etc::GMTMinus2),
+        }))
+        .unwrap();
+        assert_eq!(
+            totals(None, None),
+            vec![(day(11) - Duration::hours(2), "browser".into(), 200)]
+        );
+
+        // Events are grouped by the value of the key only, events without it count as null
+        ds.insert_events(
+            &bucket.id,
+            &[Event {
+                data: json_map! {"title": json!("other")},
+                ..event(86_300, 60, "")
+            }],
+        )
+        .unwrap();
+        ds.set_daily_aggregates(Some(DailyAggregates {
+            key: "title".to_string(),
+            timezone: Some(chrono_tz::UTC),
+        }))
+        .unwrap();
+        let aggregates = ds.get_daily_aggregates(&bucket.id, None, None).unwrap();
+        let categories: Vec<_> = aggregates
+            .iter()
+            .map(|e| (e.data.clone(), e.duration.num_seconds()))
+            .collect();
+        assert_eq!(
+            categories,
+            vec![
+                (json_map! {"title": json!("other")}, 60),
+                (json_map! {"title": json!(null)}, 100),
+                (json_map! {"title": json!(null)}, 100),
+            ]
+        );
+
+        ds.set_daily_aggregates(None).unwrap();
+        assert!(matches!(
+            ds.rebuild_daily_aggregates(),
+            Err(DatastoreError::Disabled(_))
+        ));
+    }
+
     #[test]
     fn test_bucket_metadata_start_end() {
         // Setup datastore
@@ -373,6 +1020,56 @@ mod datastore_tests {
         assert_ne!(fetched_events[0].id, e2.id);
     }
 
+    #[test]
+    fn test_event_heartbeat_replay() {
+        // Setup datastore
+        let ds = Datastore::new_in_memory(false);
+        let bucket = create_test_bucket(&ds);
+
+        let now = Utc::now();
+        let e1 = Event {
+            id: None,
+            timestamp: now,
+            duration: Duration::seconds(0),
+            data: json_map! {"key": json!("value")},
+        };
+        let mut e2 = e1.clone();
+        e2.timestamp += Duration::seconds(1);
+        e2.data = json_map! {"key": json!("other value")};
+
+        ds.heartbeat(&bucket.id, e1.clone(), 10.0).unwrap();
+        let mut e2_retry = e2.clone();
+        e2_retry.timestamp += Duration::seconds(5);
+        ds.heartbeat(&bucket.id, e2.clone(), 10.0).unwrap();
+        ds.heartbeat(&bucket.id, e2_retry.clone(), 10.0).unwrap();
+        ds.force_commit().unwrap();
+
+        // A retry of the last heartbeat is ignored and answered with the event it was merged into
+        let replayed = ds.heartbeat(&bucket.id, e2_retry.clone(), 10.0).unwrap();
+        assert_eq!(replayed.timestamp, e2.timestamp);
+        assert_eq!(replayed.duration, Duration::seconds(5));
+        let fetched_events = ds.get_events(&bucket.id, None, None, None).unwrap();
+        assert_eq!(fetched_events.len(), 2);
+        assert_eq!(fetched_events[0].duration, Duration::seconds(5));
+
+        // Only the last heartbeat is matched, older ones are handled as usual
+        ds.heartbeat(&bucket.id, e1.clone(), 10.0).unwrap();
+        let fetched_events = ds.get_events(&bucket.id, None, None, None).unwrap();
+        assert_eq!(fetched_events.len(), 3);
+
+        // Heartbeats which only have the same data are still merged
+        let mut e3 = e2.clone();
+        e3.timestamp += Duration::seconds(20);
+        ds.heartbeat(&bucket.id, e3.clone(), 10.0).unwrap();
+        ds.force_commit().unwrap();
+        let mut e4 = e3.clone();
+        e4.timestamp += Duration::seconds(1);
+        ds.heartbeat(&bucket.id, e4, 10.0).unwrap();
+        let fetched_events = ds.get_events(&bucket.id, None, None, None).unwrap();
+        assert_eq!(fetched_events.len(), 4);
+        assert_eq!(fetched_events[0].duration, Duration::seconds(1));
+    }
+
     #[test]
     fn test_event_replace() {
         // Setup datastore
@@ -396,9 +1093,9 @@ mod datastore_tests {
         let events_init = &[e1, e2, e3];
         let events_ret = ds.insert_events(&bucket.id, events_init).unwrap();
         // Validate return from insert
-        assert_eq!(events_ret[0].id, Some(1));
-        assert_eq!(events_ret[1].id, Some(2));
-        assert_eq!(events_ret[2].id, Some(3));
+        assert_eq!(events_ret[0].id, Some(EventId::Int(1)));
+        assert_eq!(events_ret[1].id, Some(EventId::Int(2)));
+        assert_eq!(events_ret[2].id, Some(EventId::Int(3)));
         assert_eq!(events_ret.len(), 3);
         assert_eq!(events_ret[0], events_init[0]);
         assert_eq!(events_ret[1], events_init[1]);
@@ -427,12 +1124,115 @@ mod datastore_tests {
             let fetched_events = ds.get_events(&bucket.id, None, None, None).unwrap();
             assert_eq!(fetched_events.len(), 3);
             assert_eq!(fetched_events[1], e2);
-            assert_eq!(fetched_events[0].id, Some(1));
-            assert_eq!(fetched_events[1].id, Some(2));
-            assert_eq!(fetched_events[2].id, Some(3));
+            assert_eq!(fetched_events[0].id, Some(EventId::Int(1)));
+            assert_eq!(fetched_events[1].id, Some(EventId::Int(2)));
+            assert_eq!(fetched_events[2].id, Some(EventId::Int(3)));
         }
     }
 
+    #[test]
+    fn test_event_update_data() {
+        // Setup datastore
+        let ds = Datastore::new_in_memory(false);
+        let bucket = create_test_bucket(&ds);
+
+        let e = Event {
+            id: None,
+            timestamp: Utc::now(),
+            duration: Duration::seconds(1),
+            data: json_map! {"title": json!("wrong"), "app": json!("firefox"), "nested": json!({"a": 1, "b": 2})},
+        };
+        let event_id = ds
+            .insert_events(&bucket.id, std::slice::from_ref(&e))
+            .unwrap()[0]
+            .id
+            .clone()
+            .unwrap();
+
+        // Merge patch: replace a value, remove a key and merge a nested object
+        let patch = json_map! {"title": json!("right"), "app": json!(null), "nested": json!({"b": null, "c": 3})};
+        let updated = ds
+            .update_event_data(&bucket.id, event_id.clone(), patch, None, None)
+            .unwrap();
+        let expected_data = json_map! {"title": json!("right"), "nested": json!({"a": 1, "c": 3})};
+        assert_eq!(updated.data, expected_data);
+        assert_eq!(updated.timestamp, e.timestamp);
+        assert_eq!(updated.duration, e.duration);
+
+        let fetched = ds.get_event(&bucket.id, event_id.clone()).unwrap();
+        assert_eq!(fetched, updated);
+
+        // Timestamp and duration are only changed if explicitly given
+        let updated = ds
+            .update_event_data(
+                &bucket.id,
+                event_id.clone(),
+                json_map! {},
+                Some(e.timestamp + Duration::seconds(1)),
+                Some(Duration::seconds(5)),
+            )
+            .unwrap();
+        assert_eq!(updated.data, expected_data);
+        assert_eq!(updated.timestamp, e.timestamp + Duration::seconds(1));
+        assert_eq!(updated.duration, Duration::seconds(5));
+        let fetched = ds.get_event(&bucket.id, event_id).unwrap();
+        assert_eq!(fetched, updated);
+    }
+
+    #[test]
+    fn test_event_uuid_ids() {
+        // Setup datastore
+        let ds = Datastore::new_in_memory(false);
+        let bucket = create_test_bucket(&ds);
+        let event = |sec: i64| Event {
+            id: None,
+            timestamp: chrono::DateTime::from_timestamp(sec, 0).unwrap(),
+            duration: Duration::seconds(1),
+            data: json_map! {"key": json!("value")},
+        };
+
+        // Events inserted before switching strategy keep their integer ids
+        let int_id = ds.insert_events(&bucket.id, &[event(0)]).unwrap()[0]
+            .id
+            .clone()
+            .unwrap();
+        assert_eq!(int_id, EventId::Int(1));
+
+        ds.set_uuid_event_ids(true).unwrap();
+        let inserted = ds.insert_events(&bucket.id, &[event(1), event(2)]).unwrap();
+        let uuid_id = inserted[0].id.clone().unwrap();
+        assert!(matches!(uuid_id, EventId::Uuid(_)));
+        assert_ne!(inserted[0].id, inserted[1].id);
+
+        // Both kinds of ids can be fetched, patched and deleted
+        assert_eq!(
+            ds.get_event(&bucket.id, uuid_id.clone()).unwrap().id,
+            Some(uuid_id.clone())
+        );
+        assert_eq!(
+            ds.get_event(&bucket.id, int_id.clone()).unwrap().id,
+            Some(int_id.clone())
+        );
+        let patched = ds
+            .update_event_data(
+                &bucket.id,
+                uuid_id.clone(),
+                json_map! {"key": json!("patched")},
+                None,
+                None,
+            )
+            .unwrap();
+        assert_eq!(patched.id, Some(uuid_id.clone()));
+        assert_eq!(patched.data, json_map! {"key": json!("patched")});
+
+        ds.delete_events_by_id(&bucket.id, vec![uuid_id.clone(), int_id])
+            .unwrap();
+        let events = ds.get_events(&bucket.id, None, None, None).unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].id, inserted[1].id);
+        assert!(ds.get_event(&bucket.id, uuid_id).is_err());
+    }
+
     #[test]
     fn test_datastore_reload() {
         // Create tmp datastore path
@@ -494,4 +1294,352 @@ mod datastore_tests {
             );
         }
     }
+
+    #[test]
+    fn test_migrate_old_schema() {
+        let mut db_path = get_cache_dir().unwrap();
+        db_path.push("datastore-migration-unittest.db");
+        let db_path_str = db_path.to_str().unwrap().to_string();
+
+        if db_path.exists() {
+            std::fs::remove_file(db_path.clone())
+                .expect("Failed to remove datastore-migration-unittest.db file");
+        }
+        {
+            let conn = rusqlite::Connection::open(&db_path).unwrap();
+            conn.execute_batch(include_str!("fixtures/datastore_v2.sql"))
+                .unwrap();
+        }
+
+        let ds = Datastore::new(db_path_str, false);
+        let bucket = ds.get_bucket("aw-watcher-afk_host").unwrap();
+        assert_eq!(bucket._type, "afkstatus");
+        assert!(bucket.data.is_empty());
+        let events = ds
+            .get_events("aw-watcher-afk_host", None, None, None)
+            .unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].data, json_map! {"status": json!("afk")});
+        assert_eq!(events[1].duration, Duration::seconds(60));
+
+        // Tables and columns of later versions work
+        ds.set_key_value("key", "value").unwrap();
+        ds.set_uuid_event_ids(true).unwrap();
+        let event = Event {
+            id: None,
+            timestamp: Utc::now(),
+            duration: Duration::seconds(1),
+            data: json_map! {"status": json!("not-afk")},
+        };
+        let inserted = ds.insert_events("aw-watcher-afk_host", &[event]).unwrap();
+        assert!(matches!(inserted[0].id, Some(EventId::Uuid(_))));
+        ds.force_commit().unwrap();
+        ds.close();
+
+        let conn = rusqlite::Connection::open(&db_path).unwrap();
+        let version: i32 = conn
+            .pragma_query_value(None, "user_version", |row| row.get(0))
+            .unwrap();
+        assert_eq!(version, aw_datastore::newest_schema_version());
+        let mut stmt = conn
+            .prepare(
+                "SELECT version, applied_at IS NOT NULL FROM schema_migrations ORDER BY version",
+            )
+            .unwrap();
+        let migrations: Vec<(i32, bool)> = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .map(|m| m.unwrap())
+            .collect();
+        // The migrations the fixture already had are recorded without a time
+        assert_eq!(
+            migrations,
+            vec![
+                (1, false),
+                (2, false),
+                (3, true),
+                (4, true),
+                (5, true),
+                (6, true)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_event_data_compression() {
+        let mut db_path = get_cache_dir().unwrap();
+        db_path.push("datastore-compression-unittest.db");
+        let db_path_str = db_path.to_str().unwrap().to_string();
+
+        if db_path.exists() {
+            std::fs::remove_file(db_path.clone())
+                .expect("Failed to remove datastore-compression-unittest.db file");
+        }
+
+        let large_data = "a".repeat(10000);
+        let event = |sec: i64, value: &str| Event {
+            id: None,
+            timestamp: chrono::DateTime::from_timestamp(sec, 0).unwrap(),
+            duration: Duration::seconds(1),
+            data: json_map! {"key": json!(value)},
+        };
+        let old_events = vec![event(0, "small"), event(1, &large_data)];
+        let new_events = vec![event(2, "small"), event(3, &large_data)];
+        {
+            let ds = Datastore::new(db_path_str.clone(), false);
+            let bucket = create_test_bucket(&ds);
+            ds.insert_events(&bucket.id, &old_events).unwrap();
+            ds.set_compress_event_data(true).unwrap();
+            ds.insert_events(&bucket.id, &new_events).unwrap();
+
+            // Both old uncompressed and new compressed events are readable
+            let mut fetched_events = ds.get_events(&bucket.id, None, None, None).unwrap();
+            fetched_events.reverse();
+            let expected: Vec<Event> = old_events
+                .iter()
+                .chain(new_events.iter())
+                .cloned()
+                .collect();
+            assert_eq!(fetched_events, expected);
+            assert_eq!(ds.get_event(&bucket.id, 4.into()).unwrap(), new_events[1]);
+            ds.force_commit().unwrap();
+            ds.close();
+        }
+
+        // Only the large event written with compression enabled is stored as a blob
+        let conn = rusqlite::Connection::open(&db_path).unwrap();
+        let mut stmt = conn
+            .prepare("SELECT typeof(data) FROM events ORDER BY starttime")
+            .unwrap();
+        let types: Vec<String> = stmt
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .map(|t| t.unwrap())
+            .collect();
+        assert_eq!(types, vec!["text", "text", "text", "blob"]);
+    }
+
+    #[test]
+    fn test_busy_timeout() {
+        let mut db_path = get_cache_dir().unwrap();
+        db_path.push("datastore-busy-unittest.db");
+        let db_path_str = db_path.to_str().unwrap().to_string();
+
+        if db_path.exists() {
+            std::fs::remove_file(db_path.clone())
+                .expect("Failed to remove datastore-busy-unittest.db file");
+        }
+
+        let ds = Datastore::new(db_path_str, false);
+        ds.set_busy_timeout(std::time::Duration::from_secs(10))
+            .unwrap();
+        let bucket = create_test_bucket(&ds);
+        ds.force_commit().unwrap();
+
+        // Another connection keeps reading while the datastore commits its write
+        let (locked_tx, locked_rx) = std::sync::mpsc::channel();
+        let reader_path = db_path.clone();
+        let reader = std::thread::spawn(move || {
+            let conn = rusqlite::Connection::open(reader_path).unwrap();
+            conn.execute_batch("BEGIN").unwrap();
+            let count: i64 = conn
+                .query_row("SELECT count(*) FROM events", [], |row| row.get(0))
+                .unwrap();
+            locked_tx.send(()).unwrap();
+            std::thread::sleep(std::time::Duration::from_millis(500));
+            conn.execute_batch("COMMIT").unwrap();
+            count
+        });
+        locked_rx.recv().unwrap();
+
+        let start = std::time::Instant::now();
+        let event = Event {
+            id: None,
+            timestamp: Utc::now(),
+            duration: Duration::seconds(1),
+            data: json_map! {"key": json!("value")},
+        };
+        ds.insert_events(&bucket.id, &[event]).unwrap();
+        // The commit, which happens after force_commit has returned, waits for the reader
+        // instead of failing with SQLITE_BUSY, the next request is handled after it
+        ds.force_commit().unwrap();
+        assert_eq!(ds.get_event_count(&bucket.id, None, None).unwrap(), 1);
+        assert!(start.elapsed() >= std::time::Duration::from_millis(300));
+        assert_eq!(reader.join().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_vacuum() {
+        let mut db_path = get_cache_dir().unwrap();
+        db_path.push("datastore-vacuum-unittest.db");
+        let db_path_str = db_path.to_str().unwrap().to_string();
+
+        if db_path.exists() {
+            std::fs::remove_file(db_path.clone())
+                .expect("Failed to remove datastore-vacuum-unittest.db file");
+        }
+
+        let ds = Datastore::new(db_path_str, false);
+        let bucket = create_test_bucket(&ds);
+        let events: Vec<Event> = (0..1000)
+            .map(|i| Event {
+                id: None,
+                timestamp: chrono::DateTime::from_timestamp(i, 0).unwrap(),
+                duration: Duration::seconds(1),
+                data: json_map! {"key": json!("a".repeat(100))},
+            })
+            .collect();
+        ds.insert_events(&bucket.id, &events).unwrap();
+        ds.force_commit().unwrap();
+        let ids = ds
+            .get_events(&bucket.id, None, None, None)
+            .unwrap()
+            .iter()
+            .map(|e| e.id.clone().unwrap())
+            .collect();
+        ds.delete_events_by_id(&bucket.id, ids).unwrap();
+
+        // Deleted data is given back, the size is the same as the file size
+        let result = ds.vacuum().unwrap();
+        assert!(result.size_after < result.size_before);
+        assert_eq!(
+            result.size_after,
+            std::fs::metadata(&db_path).unwrap().len()
+        );
+
+        // Datastore still works afterwards
+        ds.insert_events(&bucket.id, &events[..1]).unwrap();
+        assert_eq!(ds.get_event_count(&bucket.id, None, None).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_mirror() {
+        let ds = Datastore::new_in_memory(false);
+        let mirror = Datastore::new_in_memory(false);
+        ds.set_mirror(Some(mirror.clone())).unwrap();
+        let bucket = create_test_bucket(&ds);
+        let event = |secs: i64, app: &str| Event {
+            id: None,
+            timestamp: chrono::DateTime::from_timestamp(secs, 0).unwrap(),
+            duration: Duration::seconds(1),
+            data: json_map! {"app": json!(app)},
+        };
+        ds.insert_events(&bucket.id, &[event(0, "a"), event(10, "b")])
+            .unwrap();
+        ds.heartbeat(&bucket.id, event(12, "b"), 5.0).unwrap();
+        ds.set_key_value("key", "value").unwrap();
+
+        // Writes are only mirrored once they are committed, and then in the background
+        let wait_for_mirror = |expected: usize| {
+            for _ in 0..50 {
+                if mirror.get_event_count(&bucket.id, None, None).ok() == Some(expected as i64) {
+                    return;
+                }
+                std::thread::sleep(std::time::Duration::from_millis(100));
+            }
+            panic!("Mirror never got {expected} events");
+        };
+        ds.force_commit().unwrap();
+        wait_for_mirror(2);
+        let events = ds.get_events(&bucket.id, None, None, None).unwrap();
+        let mirrored = mirror.get_events(&bucket.id, None, None, None).unwrap();
+        assert_eq!(mirrored, events);
+        assert_eq!(mirrored[0].duration, Duration::seconds(3));
+        assert_eq!(
+            mirrored.iter().map(|e| e.id.clone()).collect::<Vec<_>>(),
+            events.iter().map(|e| e.id.clone()).collect::<Vec<_>>()
+        );
+        assert_eq!(mirror.get_key_value("key").unwrap(), "value");
+
+        ds.delete_events_by_id(&bucket.id, vec![events[0].id.clone().unwrap()])
+            .unwrap();
+        ds.force_commit().unwrap();
+        wait_for_mirror(1);
+
+        // Failures on the mirror don't affect the primary
+        mirror.delete_bucket(&bucket.id).unwrap();
+        ds.insert_events(&bucket.id, &[event(20, "c")]).unwrap();
+        ds.force_commit().unwrap();
+        assert_eq!(ds.get_event_count(&bucket.id, None, None).unwrap(), 2);
+
+        ds.set_mirror(None).unwrap();
+        mirror.create_bucket(&bucket).unwrap();
+        ds.insert_events(&bucket.id, &[event(30, "d")]).unwrap();
+        ds.force_commit().unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(200));
+        assert_eq!(mirror.get_event_count(&bucket.id, None, None).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_insert_coalescing_concurrent() {
+        let ds = Datastore::new_in_memory(false);
+        let bucket = create_test_bucket(&ds);
+        ds.force_commit().unwrap();
+
+        let concurrent_inserts = |ds: &Datastore| {
+            let threads: Vec<_> = (0..8)
+                .map(|thread| {
+                    let ds = ds.clone();
+                    let bucket_id = bucket.id.clone();
+                    std::thread::spawn(move || {
+                        let mut ids = Vec::new();
+                        for i in 0..50 {
+                            let event = Event {
+                                id: None,
+                                timestamp: Utc::now(),
+                                duration: Duration::seconds(1),
+                                data: json_map! {"thread": thread, "i": i},
+                            };
+                            let inserted = ds.insert_events(&bucket_id, &[event]).unwrap();
+                            ids.push(inserted[0].id.clone().unwrap());
+                            // Failures are reported to the request which caused them only
+                            if i == 25 {
+                                assert!(ds.insert_events("nonexistent", &[]).is_err());
+                            }
+                        }
+                        ids
+                    })
+                })
+                .collect();
+            threads
+                .into_iter()
+                .map(|t| t.join().unwrap())
+                .collect::<Vec<_>>()
+        };
+
+        // Without coalescing every 100 events are committed
+        let commits_before = ds.stats().commits();
+        concurrent_inserts(&ds);
+        assert!(ds.stats().commits() - commits_before >= 3);
+
+        ds.set_insert_coalescing(Some(std::time::Duration::from_secs(10)))
+            .unwrap();
+        ds.force_commit().unwrap();
+        let commits_before = ds.stats().commits();
+        let ids = concurrent_inserts(&ds);
+        assert_eq!(ds.stats().commits(), commits_before);
+        ds.force_commit().unwrap();
+        assert_eq!(ds.stats().commits(), commits_before + 1);
+
+        // Each thread got the ids of its events in the order it inserted them
+        for thread_ids in &ids {
+            let numbers: Vec<i64> = thread_ids
+                .iter()
+                .map(|id| match id {
+                    EventId::Int(n) => *n,
+                    _ => panic!("Expected an integer id"),
+                })
+                .collect();
+            assert!(numbers.windows(2).all(|w| w[0] < w[1]));
+        }
+        let events = ds.get_events(&bucket.id, None, None, None).unwrap();
+        assert_eq!(events.len(), 800);
+        for (thread, thread_ids) in ids.iter().enumerate() {
+            for (i, id) in thread_ids.iter().enumerate() {
+                let event = events.iter().find(|e| e.id.as_ref() == Some(id)).unwrap();
+                assert_eq!(event.data["thread"], json!(thread));
+                assert_eq!(event.data["i"], json!(i));
+            }
+        }
+    }
 }
@@ -0,0 +1,31 @@
+-- A database at schema version 2, as written by the first releases of aw-server-rust
+CREATE TABLE buckets (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT UNIQUE NOT NULL,
+    type TEXT NOT NULL,
+    client TEXT NOT NULL,
+    hostname TEXT NOT NULL,
+    created TEXT NOT NULL,
+    data TEXT DEFAULT '{}'
+);
+CREATE INDEX bucket_id_index ON buckets(id);
+CREATE TABLE events (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    bucketrow INTEGER NOT NULL,
+    starttime INTEGER NOT NULL,
+    endtime INTEGER NOT NULL,
+    data TEXT NOT NULL,
+    FOREIGN KEY (bucketrow) REFERENCES buckets(id)
+);
+CREATE INDEX events_bucketrow_index ON events(bucketrow);
+CREATE INDEX events_starttime_index ON events(starttime);
+CREATE INDEX events_endtime_index ON events(endtime);
+
+INSERT INTO buckets (name, type, client, hostname, created)
+    VALUES ('aw-watcher-afk_host', 'afkstatus', 'aw-watcher-afk', 'host',
+            '2019-01-01 00:00:00+00:00');
+INSERT INTO events (bucketrow, starttime, endtime, data)
+    VALUES (1, 1546300800000000000, 1546300860000000000, '{"status":"not-afk"}'),
+           (1, 1546300860000000000, 1546300920000000000, '{"status":"afk"}');
+
+PRAGMA user_version = 2;
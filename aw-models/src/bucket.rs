@@ -30,6 +30,17 @@ pub struct Bucket {
     pub last_updated: Option<DateTime<Utc>>, // TODO: Should probably be moved into metadata field
 }
 
+/// Key in the data of a bucket holding the version of the schema of its events, a number which
+/// watchers increase when the data they store changes
+pub const SCHEMA_VERSION_KEY: &str = "schema_version";
+
+impl Bucket {
+    /// The schema version recorded in the bucket data, if any
+    pub fn schema_version(&self) -> Option<u64> {
+        self.data.get(SCHEMA_VERSION_KEY).and_then(Value::as_u64)
+    }
+}
+
 #[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, Default)]
 pub struct BucketMetadata {
     #[serde(default)]
@@ -42,6 +53,27 @@ pub struct BucketsExport {
     pub buckets: HashMap<String, Bucket>,
 }
 
+/// How far a bucket has changed, used to find the buckets which need to be synced
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, PartialEq, Eq)]
+pub struct BucketState {
+    /// Last time events in the bucket were inserted, changed or deleted, or when the server
+    /// started if it hasn't changed since
+    pub last_updated: Option<DateTime<Utc>>,
+    pub event_count: i64,
+}
+
+/// Outcome of creating one of the buckets in a bulk creation
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, PartialEq, Eq)]
+#[serde(tag = "status", rename_all = "lowercase")]
+pub enum BucketCreationResult {
+    Created,
+    /// The bucket already existed and was left as is
+    Skipped,
+    Failed {
+        error: String,
+    },
+}
+
 #[test]
 fn test_bucket() {
     let b = Bucket {
@@ -0,0 +1,49 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::EventId;
+
+/// How many events have a problem and the ids of the first ones, oldest first
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, Default, PartialEq)]
+pub struct QualityIssue {
+    pub count: u64,
+    /// At most `BucketQuality::SAMPLE_LEN` ids
+    pub sample_ids: Vec<EventId>,
+}
+
+/// The time which no event covers, in seconds
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, Default, PartialEq)]
+pub struct QualityGaps {
+    pub count: u64,
+    /// Total time of all gaps
+    pub duration: f64,
+    /// Time of the longest gap
+    pub longest: f64,
+}
+
+/// A health report of the events of a bucket, for spotting a misbehaving watcher
+///
+/// Only the events overlapping the checked range are included, which is the time from the first
+/// to the end of the last event unless a range is given.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, Default, PartialEq)]
+pub struct BucketQuality {
+    /// Number of events checked
+    pub event_count: u64,
+    /// Events overlapping another event, which a single watcher should never create
+    pub overlaps: QualityIssue,
+    /// Gaps longer than the requested minimum, such as when the watcher wasn't running
+    pub gaps: QualityGaps,
+    /// Events starting before an event which was inserted before them, from a watcher with a
+    /// wrong clock or sending late events
+    ///
+    /// The insertion order is only known for integer ids, so this is `None` if any of the events
+    /// has a UUID.
+    pub out_of_order: Option<QualityIssue>,
+    /// Events without a duration, such as heartbeats which were never merged into an event
+    pub zero_duration: QualityIssue,
+}
+
+impl BucketQuality {
+    /// Number of ids kept as samples of each issue
+    pub const SAMPLE_LEN: usize = 10;
+}
@@ -0,0 +1,34 @@
+use std::collections::HashMap;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// A set of category rules to try out on the events of a bucket, see `CategorizePreview`
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+pub struct CategorizePreviewRequest {
+    /// Rules in the format of the `categorize` query function, a list of `[category, rule]`
+    /// pairs such as `[["Work", "Code"], {"type": "regex", "regex": "vim|code", "ignore_case": true}]`
+    pub rules: Value,
+    pub bucket: String,
+    /// Timeperiod in "start/end" format, or a shortcut such as "today", as in a `Query`
+    pub timeperiod: String,
+}
+
+/// How many events ended up in a category and how long they are in total, in seconds
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, Default, PartialEq)]
+pub struct CategoryPreview {
+    pub event_count: u64,
+    pub duration: f64,
+}
+
+/// The events of each category of a rule set, by the category joined with " > "
+///
+/// Every event is counted once, in the category it is given by the rules, so events matched by
+/// several rules only count towards the deepest category. Categories of rules which match no
+/// events are included with zero events. Events which no rule matches are `uncategorized`.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, Default, PartialEq)]
+pub struct CategorizePreview {
+    pub categories: HashMap<String, CategoryPreview>,
+    pub uncategorized: CategoryPreview,
+}
@@ -0,0 +1,12 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Settings which differed after the server config was read again
+///
+/// Settings in `changed` were applied to the running server, the ones in `requires_restart`
+/// only take effect after a restart.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ConfigReloadResult {
+    pub changed: Vec<String>,
+    pub requires_restart: Vec<String>,
+}
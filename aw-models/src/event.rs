@@ -7,6 +7,7 @@ use serde_json::Map;
 use serde_json::Value;
 
 use crate::duration::DurationSerialization;
+use crate::EventId;
 use crate::TimeInterval;
 
 #[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
@@ -16,7 +17,7 @@ pub struct Event {
     ///
     /// **WARNING:** If you set the ID and insert the event to the server it will replace the previous
     /// event with that ID. Only do this if you are completely sure what you are doing.
-    pub id: Option<i64>,
+    pub id: Option<EventId>,
     /// An rfc3339 timestamp which represents the start of the event
     pub timestamp: DateTime<Utc>,
     /// Duration of the event as a floating point number in seconds.
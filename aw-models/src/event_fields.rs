@@ -0,0 +1,113 @@
+use serde_json::{json, Map, Value};
+
+use crate::duration::DurationSerialization;
+use crate::Event;
+
+/// A selection of the fields of events, for fetching only the parts of them which are used
+///
+/// Parsed from a comma-separated list of field names. `id`, `timestamp`, `duration` and `data`
+/// select the fields of the event, `data.<key>` selects a single key of its data. Requested data
+/// keys which an event doesn't have are omitted from its data rather than set to null, so that
+/// they can be told apart from keys whose value is null.
+///
+/// # Example
+/// ```ignore
+/// fields: "timestamp,duration,data.app"
+/// input:  {"id": 1, "timestamp": "...", "duration": 1.0, "data": {"app": "Firefox", "title": "..."}}
+/// output: {"timestamp": "...", "duration": 1.0, "data": {"app": "Firefox"}}
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EventFields {
+    id: bool,
+    timestamp: bool,
+    duration: bool,
+    all_data: bool,
+    data_keys: Vec<String>,
+}
+
+impl EventFields {
+    pub fn parse(fields: &str) -> Result<EventFields, String> {
+        let mut selection = EventFields::default();
+        for field in fields.split(',').map(str::trim) {
+            match field {
+                "id" => selection.id = true,
+                "timestamp" => selection.timestamp = true,
+                "duration" => selection.duration = true,
+                "data" => selection.all_data = true,
+                field => match field.strip_prefix("data.") {
+                    Some(key) if !key.is_empty() => selection.data_keys.push(key.to_string()),
+                    _ => return Err(format!("Unknown event field '{field}'")),
+                },
+            }
+        }
+        Ok(selection)
+    }
+
+    /// The JSON object of the selected fields of the event
+    pub fn project(&self, event: &Event) -> Map<String, Value> {
+        let mut projected = Map::new();
+        if self.id {
+            projected.insert("id".to_string(), json!(event.id));
+        }
+        if self.timestamp {
+            projected.insert("timestamp".to_string(), json!(event.timestamp));
+        }
+        if self.duration {
+            let duration =
+                DurationSerialization::serialize(&event.duration, serde_json::value::Serializer)
+                    .unwrap();
+            projected.insert("duration".to_string(), duration);
+        }
+        if self.all_data {
+            projected.insert("data".to_string(), Value::Object(event.data.clone()));
+        } else if !self.data_keys.is_empty() {
+            let data: Map<String, Value> = self
+                .data_keys
+                .iter()
+                .filter_map(|key| event.data.get_key_value(key))
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect();
+            projected.insert("data".to_string(), Value::Object(data));
+        }
+        projected
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, Duration};
+    use serde_json::{json, Value};
+
+    use super::EventFields;
+    use crate::{Event, EventId};
+
+    #[test]
+    fn test_event_fields() {
+        let event = Event {
+            id: Some(EventId::Int(1)),
+            timestamp: DateTime::from_timestamp(1_000_000_000, 0).unwrap(),
+            duration: Duration::milliseconds(1500),
+            data: json_map! {"app": "Firefox", "title": "Inbox", "url": Value::Null},
+        };
+
+        let fields = EventFields::parse("timestamp,duration").unwrap();
+        assert_eq!(
+            json!(fields.project(&event)),
+            json!({"timestamp": "2001-09-09T01:46:40Z", "duration": 1.5})
+        );
+
+        // Missing keys are left out, null values are kept
+        let fields = EventFields::parse("id, data.app, data.url, data.missing").unwrap();
+        assert_eq!(
+            json!(fields.project(&event)),
+            json!({"id": 1, "data": {"app": "Firefox", "url": Value::Null}})
+        );
+
+        let fields = EventFields::parse("data,data.app").unwrap();
+        assert_eq!(json!(fields.project(&event)), json!({"data": event.data}));
+
+        assert!(EventFields::parse("timestamp,title").is_err());
+        assert!(EventFields::parse("data.").is_err());
+        assert!(EventFields::parse("").is_err());
+    }
+}
@@ -0,0 +1,65 @@
+use std::convert::Infallible;
+use std::fmt;
+use std::str::FromStr;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Id of an event, an integer by default or a UUID if the server is configured to generate UUIDs
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, PartialEq, Eq, Hash)]
+#[serde(untagged)]
+pub enum EventId {
+    Int(i64),
+    Uuid(String),
+}
+
+impl From<i64> for EventId {
+    fn from(id: i64) -> Self {
+        EventId::Int(id)
+    }
+}
+
+impl fmt::Display for EventId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EventId::Int(id) => write!(f, "{id}"),
+            EventId::Uuid(uuid) => write!(f, "{uuid}"),
+        }
+    }
+}
+
+/// Parses integers as `EventId::Int` and anything else as `EventId::Uuid`
+impl From<&str> for EventId {
+    fn from(s: &str) -> Self {
+        match s.parse() {
+            Ok(id) => EventId::Int(id),
+            Err(_) => EventId::Uuid(s.to_string()),
+        }
+    }
+}
+
+impl FromStr for EventId {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(EventId::from(s))
+    }
+}
+
+#[test]
+fn test_event_id() {
+    let id: EventId = serde_json::from_str("1").unwrap();
+    assert_eq!(id, EventId::Int(1));
+    let id: EventId = serde_json::from_str("\"2b1a1e52-4a8b-4b7e-9a3c-9b6b8b0e5f3d\"").unwrap();
+    assert_eq!(
+        id,
+        EventId::Uuid("2b1a1e52-4a8b-4b7e-9a3c-9b6b8b0e5f3d".to_string())
+    );
+    assert_eq!(serde_json::to_string(&id).unwrap(), format!("\"{id}\""));
+
+    assert_eq!("3".parse::<EventId>().unwrap(), EventId::Int(3));
+    assert_eq!(
+        "abc".parse::<EventId>().unwrap(),
+        EventId::Uuid("abc".to_string())
+    );
+}
@@ -1,10 +1,40 @@
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
+use crate::ServerStats;
+
 #[derive(Serialize, Deserialize, JsonSchema)]
 pub struct Info {
     pub hostname: String,
     pub version: String,
     pub testing: bool,
     pub device_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stats: Option<ServerStats>,
+    /// Missing on servers from before capabilities were reported
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub capabilities: Option<Capabilities>,
+}
+
+/// The optional endpoints and settings of a server, so that clients can avoid what it doesn't
+/// support
+///
+/// Keys which a server doesn't report, because it is older than them, are read as unsupported.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct Capabilities {
+    /// `POST /api/0/query/stream` streams query results as newline-delimited JSON
+    pub query_streaming: bool,
+    /// `POST /api/0/query/<id>/cancel` cancels running queries
+    pub query_cancel: bool,
+    /// `POST /api/0/buckets/<id>/events/stream` inserts newline-delimited events
+    pub event_streaming: bool,
+    /// `GET /api/0/metrics` serves the counters in the Prometheus text format
+    pub metrics: bool,
+    /// New events get UUIDs as ids instead of integers
+    pub uuid_event_ids: bool,
+    /// Daily aggregates are maintained, so the query function `query_bucket_daily` works
+    pub daily_aggregates: bool,
+    /// Names of the functions which can be called in queries
+    pub query_functions: Vec<String>,
 }
@@ -17,18 +17,39 @@ macro_rules! json_map {
 }
 
 mod bucket;
+mod bucket_quality;
+mod categorize_preview;
+mod config_reload;
+mod distinct_value;
 mod duration;
 mod event;
+mod event_fields;
+mod event_id;
 mod info;
+mod python_export;
 mod query;
+mod stats;
 mod timeinterval;
 mod tryvec;
+mod vacuum;
 
 pub use self::bucket::Bucket;
+pub use self::bucket::BucketCreationResult;
 pub use self::bucket::BucketMetadata;
+pub use self::bucket::BucketState;
 pub use self::bucket::BucketsExport;
+pub use self::bucket::SCHEMA_VERSION_KEY;
+pub use self::bucket_quality::{BucketQuality, QualityGaps, QualityIssue};
+pub use self::categorize_preview::{CategorizePreview, CategorizePreviewRequest, CategoryPreview};
+pub use self::config_reload::ConfigReloadResult;
+pub use self::distinct_value::DistinctValue;
 pub use self::event::Event;
-pub use self::info::Info;
-pub use self::query::Query;
+pub use self::event_fields::EventFields;
+pub use self::event_id::EventId;
+pub use self::info::{Capabilities, Info};
+pub use self::python_export::{PythonBucket, PythonBucketsExport, PythonEvent};
+pub use self::query::{Query, QueryStreamFrame};
+pub use self::stats::ServerStats;
 pub use self::timeinterval::TimeInterval;
 pub use self::tryvec::TryVec;
+pub use self::vacuum::VacuumResult;
@@ -0,0 +1,136 @@
+//! Exports in the format of the Python aw-server, so that data can be moved between the servers
+//!
+//! Both servers can import the exports of the other one, but the Python server is stricter about
+//! what it reads. The differences from the `BucketsExport` of this server are:
+//!
+//! - Buckets have a `name`, which is always null as buckets here have no name
+//! - Buckets have no `metadata` and `last_updated`, nor events an `id`
+//! - Timestamps are in the format of Python's `datetime.isoformat()`, with a `+00:00` offset
+//!   instead of `Z` and microseconds instead of nanoseconds, which are left out when zero
+//! - Durations are rounded to microseconds, the precision of Python's `timedelta`
+//! - Fields are in the order the Python server writes them, and buckets are sorted by id
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crate::{Bucket, BucketsExport, Event};
+
+#[derive(Serialize, Debug, Clone)]
+pub struct PythonBucketsExport {
+    pub buckets: BTreeMap<String, PythonBucket>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct PythonBucket {
+    pub id: String,
+    pub created: Option<String>,
+    pub name: Option<String>,
+    #[serde(rename = "type")]
+    pub _type: String,
+    pub client: String,
+    pub hostname: String,
+    pub data: Map<String, Value>,
+    pub events: Vec<PythonEvent>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct PythonEvent {
+    pub timestamp: String,
+    pub duration: f64,
+    pub data: Map<String, Value>,
+}
+
+impl From<BucketsExport> for PythonBucketsExport {
+    fn from(export: BucketsExport) -> Self {
+        PythonBucketsExport {
+            buckets: export
+                .buckets
+                .into_iter()
+                .map(|(id, bucket)| (id, bucket.into()))
+                .collect(),
+        }
+    }
+}
+
+impl From<Bucket> for PythonBucket {
+    fn from(bucket: Bucket) -> Self {
+        let events = match bucket.events {
+            Some(events) => events.take_inner().into_iter().map(Into::into).collect(),
+            None => Vec::new(),
+        };
+        PythonBucket {
+            id: bucket.id,
+            created: bucket.created.as_ref().map(python_isoformat),
+            name: None,
+            _type: bucket._type,
+            client: bucket.client,
+            hostname: bucket.hostname,
+            data: bucket.data,
+            events,
+        }
+    }
+}
+
+impl From<Event> for PythonEvent {
+    fn from(event: Event) -> Self {
+        PythonEvent {
+            timestamp: python_isoformat(&event.timestamp),
+            duration: python_total_seconds(&event.duration),
+            data: event.data,
+        }
+    }
+}
+
+fn python_isoformat(time: &DateTime<Utc>) -> String {
+    if time.timestamp_subsec_micros() == 0 {
+        time.format("%Y-%m-%dT%H:%M:%S+00:00").to_string()
+    } else {
+        time.format("%Y-%m-%dT%H:%M:%S%.6f+00:00").to_string()
+    }
+}
+
+fn python_total_seconds(duration: &Duration) -> f64 {
+    match duration.num_microseconds() {
+        Some(micros) => micros as f64 / 1_000_000.0,
+        None => duration.num_milliseconds() as f64 / 1000.0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::BucketsExport;
+
+    use super::PythonBucketsExport;
+
+    // An export as written by the Python aw-server, compacted
+    const PYTHON_EXPORT: &str = concat!(
+        r#"{"buckets":{"aw-watcher-afk_host":{"id":"aw-watcher-afk_host","#,
+        r#""created":"2023-01-02T10:00:00.123456+00:00","name":null,"type":"afkstatus","#,
+        r#""client":"aw-watcher-afk","hostname":"host","data":{},"events":["#,
+        r#"{"timestamp":"2023-01-02T10:05:00.250000+00:00","duration":30.5,"#,
+        r#""data":{"status":"afk"}},"#,
+        r#"{"timestamp":"2023-01-02T10:00:00+00:00","duration":300.0,"#,
+        r#""data":{"status":"not-afk"}}]},"#,
+        r#""aw-watcher-window_host":{"id":"aw-watcher-window_host","#,
+        r#""created":"2023-01-02T10:00:01+00:00","name":null,"type":"currentwindow","#,
+        r#""client":"aw-watcher-window","hostname":"host","data":{},"events":["#,
+        r#"{"timestamp":"2023-01-02T10:00:00+00:00","duration":1.5,"#,
+        r#""data":{"app":"Firefox","title":"Inbox"}}]}}}"#
+    );
+
+    #[test]
+    fn test_python_export_round_trip() {
+        let export: BucketsExport = serde_json::from_str(PYTHON_EXPORT).unwrap();
+        let afk = &export.buckets["aw-watcher-afk_host"];
+        assert_eq!(afk._type, "afkstatus");
+        assert!(afk.created.is_some());
+
+        let python_export = PythonBucketsExport::from(export);
+        assert_eq!(
+            serde_json::to_string(&python_export).unwrap(),
+            PYTHON_EXPORT
+        );
+    }
+}
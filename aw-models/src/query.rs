@@ -1,11 +1,34 @@
-use serde::Deserialize;
-
-use crate::TimeInterval;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
 
 // TODO Implement serialize once TimeInterval has implemented it
 #[derive(Deserialize, Clone, Debug)]
 pub struct Query {
-    //#[serde(with = "DurationSerialization")]
-    pub timeperiods: Vec<TimeInterval>,
+    /// Timeperiods in "start/end" format, the start or end can be omitted and is then set by
+    /// the server, see `TimeInterval::new_from_string_with_default`
+    pub timeperiods: Vec<String>,
     pub query: Vec<String>,
+    /// Values of the `$name` placeholders in the query, see `aw_query::query_with_params`
+    #[serde(default)]
+    pub params: Map<String, Value>,
+}
+
+/// A line of the newline-delimited JSON returned by `/api/0/query/stream`
+///
+/// Each timeperiod is evaluated as a whole, after which its result is written: one `Item` per
+/// element if it is a list (such as a list of events), otherwise a single `Value`. The frames of
+/// a timeperiod all come before those of the next one. The stream ends with `Done` once all
+/// timeperiods are evaluated, or with `Error` if one of them fails, so a stream ending with
+/// neither was cut off.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum QueryStreamFrame {
+    /// An element of the list a timeperiod evaluated to
+    Item { timeperiod: usize, item: Value },
+    /// The result of a timeperiod which didn't evaluate to a list
+    Value { timeperiod: usize, value: Value },
+    /// Evaluating the timeperiod failed, nothing follows
+    Error { timeperiod: usize, message: String },
+    /// All timeperiods were evaluated
+    Done,
 }
@@ -0,0 +1,11 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Counters of the work done by the server since it was started
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ServerStats {
+    pub events_inserted: u64,
+    pub events_deleted: u64,
+    pub queries_run: u64,
+    pub query_errors: u64,
+}
@@ -10,7 +10,7 @@ use chrono::Utc;
 
 // TODO: Implement serialize
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct TimeInterval {
     start: DateTime<Utc>,
     end: DateTime<Utc>,
@@ -44,6 +44,39 @@ impl TimeInterval {
         Ok(TimeInterval::new(start, end))
     }
 
+    /// Same as `new_from_string`, but the start or end may be left out, in which case it's
+    /// filled in so that the interval is `default_duration` long.
+    /// If both are left out the interval ends at the current time.
+    pub fn new_from_string_with_default(
+        period: &str,
+        default_duration: Duration,
+    ) -> Result<TimeInterval, TimeIntervalError> {
+        let splits = period.split('/').collect::<Vec<&str>>();
+        if splits.len() != 2 {
+            return Err(TimeIntervalError::ParseError());
+        }
+        let parse = |s: &str| -> Result<Option<DateTime<Utc>>, TimeIntervalError> {
+            if s.is_empty() {
+                return Ok(None);
+            }
+            match DateTime::parse_from_rfc3339(s) {
+                Ok(dt) => Ok(Some(dt.with_timezone(&Utc))),
+                Err(_e) => Err(TimeIntervalError::ParseError()),
+            }
+        };
+        let (start, end) = match (parse(splits[0])?, parse(splits[1])?) {
+            (Some(start), Some(end)) => (start, end),
+            (Some(start), None) => (start, start + default_duration),
+            (None, Some(end)) => (end - default_duration, end),
+            (None, None) => {
+                let now = Utc::now();
+                (now - default_duration, now)
+            }
+        };
+
+        Ok(TimeInterval::new(start, end))
+    }
+
     pub fn start(&self) -> &DateTime<Utc> {
         &self.start
     }
@@ -176,3 +209,36 @@ fn test_timeinterval_intersection() {
     );
     assert!(!tp1.intersects(&tp2));
 }
+
+#[test]
+fn test_timeinterval_with_default() {
+    use std::str::FromStr;
+
+    let start: DateTime<Utc> = DateTime::from_str("2000-01-01T00:00:00Z").unwrap();
+    let end: DateTime<Utc> = DateTime::from_str("2000-01-02T00:00:00Z").unwrap();
+    let default = Duration::days(1);
+
+    let tp = TimeInterval::new_from_string_with_default(
+        "2000-01-01T00:00:00+00:00/2000-01-02T00:00:00+00:00",
+        Duration::days(7),
+    )
+    .unwrap();
+    assert_eq!(tp.start(), &start);
+    assert_eq!(tp.end(), &end);
+
+    let tp =
+        TimeInterval::new_from_string_with_default("2000-01-01T00:00:00+00:00/", default).unwrap();
+    assert_eq!(tp.start(), &start);
+    assert_eq!(tp.end(), &end);
+
+    let tp =
+        TimeInterval::new_from_string_with_default("/2000-01-02T00:00:00+00:00", default).unwrap();
+    assert_eq!(tp.start(), &start);
+    assert_eq!(tp.end(), &end);
+
+    let tp = TimeInterval::new_from_string_with_default("/", default).unwrap();
+    assert_eq!(tp.duration(), default);
+
+    assert!(TimeInterval::new_from_string_with_default("2000-01-01", default).is_err());
+    assert!(TimeInterval::new_from_string_with_default("invalid/", default).is_err());
+}
@@ -0,0 +1,9 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Size of the database in bytes before and after it was vacuumed
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+pub struct VacuumResult {
+    pub size_before: u64,
+    pub size_after: u64,
+}
@@ -15,6 +15,7 @@ use serde_json::Number;
 #[derive(Clone, Serialize)]
 #[serde(untagged)]
 pub enum DataType {
+    #[serde(serialize_with = "serialize_none")]
     None(),
     Bool(bool),
     Number(f64),
@@ -26,6 +27,14 @@ pub enum DataType {
     Function(String, functions::QueryFn),
 }
 
+// Would be serialized as an empty list otherwise
+fn serialize_none<S>(serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.serialize_none()
+}
+
 #[allow(clippy::trivially_copy_pass_by_ref)]
 fn serialize_function<S>(
     _element: &str,
@@ -226,6 +235,37 @@ impl TryFrom<&DataType> for f64 {
     }
 }
 
+impl TryFrom<&DataType> for bool {
+    type Error = QueryError;
+    fn try_from(value: &DataType) -> Result<Self, Self::Error> {
+        match value {
+            DataType::Bool(b) => Ok(*b),
+            ref invalid_type => Err(QueryError::InvalidFunctionParameters(format!(
+                "Expected function parameter of type Bool, got {invalid_type:?}"
+            ))),
+        }
+    }
+}
+
+impl TryFrom<&DataType> for Vec<f64> {
+    type Error = QueryError;
+    fn try_from(value: &DataType) -> Result<Self, Self::Error> {
+        let mut tagged_numbers: Vec<DataType> = value.try_into()?;
+        let mut numbers = Vec::new();
+        for number in tagged_numbers.drain(..) {
+            match number {
+                DataType::Number(n) => numbers.push(n),
+                ref invalid_type => {
+                    return Err(QueryError::InvalidFunctionParameters(format!(
+                        "Expected function parameter of type List of Numbers, list contains {invalid_type:?}"
+                    )))
+                }
+            }
+        }
+        Ok(numbers)
+    }
+}
+
 impl TryFrom<&DataType> for usize {
     type Error = QueryError;
     fn try_from(value: &DataType) -> Result<Self, Self::Error> {
@@ -234,6 +274,25 @@ impl TryFrom<&DataType> for usize {
     }
 }
 
+/// Converts JSON, such as query parameters, to the values of the query language where numbers
+/// are always floats
+impl From<&Value> for DataType {
+    fn from(value: &Value) -> Self {
+        match value {
+            Value::Null => DataType::None(),
+            Value::Bool(b) => DataType::Bool(*b),
+            Value::Number(n) => DataType::Number(n.as_f64().unwrap()),
+            Value::String(s) => DataType::String(s.clone()),
+            Value::Array(values) => DataType::List(values.iter().map(DataType::from).collect()),
+            Value::Object(map) => DataType::Dict(
+                map.iter()
+                    .map(|(key, value)| (key.clone(), value.into()))
+                    .collect(),
+            ),
+        }
+    }
+}
+
 impl TryFrom<&DataType> for Value {
     type Error = QueryError;
     fn try_from(value: &DataType) -> Result<Self, Self::Error> {
@@ -26,6 +26,14 @@ pub fn fill_env(env: &mut VarEnv) {
         "sort_by_duration".to_string(),
         DataType::Function("sort_by_duration".to_string(), qfunctions::sort_by_duration),
     );
+    env.insert(
+        "rank_by_duration".to_string(),
+        DataType::Function("rank_by_duration".into(), qfunctions::rank_by_duration),
+    );
+    env.insert(
+        "rank_by_key".to_string(),
+        DataType::Function("rank_by_key".into(), qfunctions::rank_by_key),
+    );
     env.insert(
         "sort_by_timestamp".to_string(),
         DataType::Function(
@@ -60,6 +68,13 @@ pub fn fill_env(env: &mut VarEnv) {
             qfunctions::merge_events_by_keys,
         ),
     );
+    env.insert(
+        "merge_consecutive".to_string(),
+        DataType::Function(
+            "merge_consecutive".to_string(),
+            qfunctions::merge_consecutive,
+        ),
+    );
     env.insert(
         "chunk_events_by_key".to_string(),
         DataType::Function(
@@ -89,6 +104,18 @@ pub fn fill_env(env: &mut VarEnv) {
             qfunctions::filter_period_intersect,
         ),
     );
+    env.insert(
+        "filter_duration".to_string(),
+        DataType::Function("filter_duration".to_string(), qfunctions::filter_duration),
+    );
+    env.insert(
+        "rename_keys".to_string(),
+        DataType::Function("rename_keys".into(), qfunctions::rename_keys),
+    );
+    env.insert(
+        "budget".to_string(),
+        DataType::Function("budget".into(), qfunctions::budget),
+    );
     env.insert(
         "split_url_events".to_string(),
         DataType::Function("split_url_events".to_string(), qfunctions::split_url_events),
@@ -109,13 +136,200 @@ pub fn fill_env(env: &mut VarEnv) {
         "period_union".to_string(),
         DataType::Function("period_union".into(), qfunctions::period_union),
     );
+    env.insert(
+        "period_intersect".to_string(),
+        DataType::Function("period_intersect".into(), qfunctions::period_intersect),
+    );
+    env.insert(
+        "overlap_duration".to_string(),
+        DataType::Function("overlap_duration".into(), qfunctions::overlap_duration),
+    );
     env.insert(
         "union_no_overlap".to_string(),
         DataType::Function("union_no_overlap".into(), qfunctions::union_no_overlap),
     );
+    env.insert(
+        "find_overlaps".to_string(),
+        DataType::Function("find_overlaps".into(), qfunctions::find_overlaps),
+    );
+    env.insert(
+        "join_overlapping".to_string(),
+        DataType::Function("join_overlapping".into(), qfunctions::join_overlapping),
+    );
+    env.insert(
+        "activity_bounds".to_string(),
+        DataType::Function("activity_bounds".into(), qfunctions::activity_bounds),
+    );
+    env.insert(
+        "transition_counts".to_string(),
+        DataType::Function("transition_counts".into(), qfunctions::transition_counts),
+    );
+    env.insert(
+        "by_hour_of_day".to_string(),
+        DataType::Function("by_hour_of_day".into(), qfunctions::by_hour_of_day),
+    );
+    env.insert(
+        "split_weekday_weekend".to_string(),
+        DataType::Function(
+            "split_weekday_weekend".into(),
+            qfunctions::split_weekday_weekend,
+        ),
+    );
+    env.insert(
+        "daily_streak".to_string(),
+        DataType::Function("daily_streak".into(), qfunctions::daily_streak),
+    );
+    env.insert(
+        "close_gaps".to_string(),
+        DataType::Function("close_gaps".into(), qfunctions::close_gaps),
+    );
+    env.insert(
+        "debounce_status".to_string(),
+        DataType::Function("debounce_status".into(), qfunctions::debounce_status),
+    );
+    env.insert(
+        "duration_histogram".to_string(),
+        DataType::Function("duration_histogram".into(), qfunctions::duration_histogram),
+    );
+    env.insert(
+        "normalize_durations".to_string(),
+        DataType::Function(
+            "normalize_durations".into(),
+            qfunctions::normalize_durations,
+        ),
+    );
+    env.insert(
+        "concentration".to_string(),
+        DataType::Function("concentration".into(), qfunctions::concentration),
+    );
+    env.insert(
+        "activity_entropy".to_string(),
+        DataType::Function("activity_entropy".into(), qfunctions::activity_entropy),
+    );
+    env.insert(
+        "split_at".to_string(),
+        DataType::Function("split_at".into(), qfunctions::split_at),
+    );
+    env.insert(
+        "redact".to_string(),
+        DataType::Function("redact".into(), qfunctions::redact),
+    );
+    env.insert(
+        "redact_hash".to_string(),
+        DataType::Function("redact_hash".into(), qfunctions::redact_hash),
+    );
+    env.insert(
+        "longest_session".to_string(),
+        DataType::Function("longest_session".into(), qfunctions::longest_session),
+    );
+    env.insert(
+        "active_ratio".to_string(),
+        DataType::Function("active_ratio".into(), qfunctions::active_ratio),
+    );
+    env.insert(
+        "time_between".to_string(),
+        DataType::Function("time_between".into(), qfunctions::time_between),
+    );
+    env.insert(
+        "flag_distractions".to_string(),
+        DataType::Function("flag_distractions".into(), qfunctions::flag_distractions),
+    );
+    env.insert(
+        "group_by_numeric".to_string(),
+        DataType::Function("group_by_numeric".into(), qfunctions::group_by_numeric),
+    );
+    env.insert(
+        "find_gaps".to_string(),
+        DataType::Function("find_gaps".into(), qfunctions::find_gaps),
+    );
+    env.insert(
+        "rollup_categories".to_string(),
+        DataType::Function("rollup_categories".into(), qfunctions::rollup_categories),
+    );
+    env.insert(
+        "enrich_browser_events".to_string(),
+        DataType::Function(
+            "enrich_browser_events".into(),
+            qfunctions::enrich_browser_events,
+        ),
+    );
+    env.insert(
+        "dominant_per_slot".to_string(),
+        DataType::Function("dominant_per_slot".into(), qfunctions::dominant_per_slot),
+    );
+    env.insert(
+        "switch_rate".to_string(),
+        DataType::Function("switch_rate".into(), qfunctions::switch_rate),
+    );
+    env.insert(
+        "time_matching".to_string(),
+        DataType::Function("time_matching".into(), qfunctions::time_matching),
+    );
+    env.insert(
+        "first_activity_times".to_string(),
+        DataType::Function(
+            "first_activity_times".into(),
+            qfunctions::first_activity_times,
+        ),
+    );
+    env.insert(
+        "daily_category_trend".to_string(),
+        DataType::Function(
+            "daily_category_trend".into(),
+            qfunctions::daily_category_trend,
+        ),
+    );
+    env.insert(
+        "productivity_score".to_string(),
+        DataType::Function("productivity_score".into(), qfunctions::productivity_score),
+    );
+    env.insert(
+        "switching_cost".to_string(),
+        DataType::Function("switching_cost".into(), qfunctions::switching_cost),
+    );
+    env.insert(
+        "query_bucket_daily".to_string(),
+        DataType::Function("query_bucket_daily".into(), qfunctions::query_bucket_daily),
+    );
+    env.insert(
+        "flow_time".to_string(),
+        DataType::Function("flow_time".into(), qfunctions::flow_time),
+    );
+    env.insert(
+        "rolling_average".to_string(),
+        DataType::Function("rolling_average".into(), qfunctions::rolling_average),
+    );
+    env.insert(
+        "sum".to_string(),
+        DataType::Function("sum".into(), qfunctions::sum),
+    );
+    env.insert(
+        "max".to_string(),
+        DataType::Function("max".into(), qfunctions::max),
+    );
+    env.insert(
+        "min".to_string(),
+        DataType::Function("min".into(), qfunctions::min),
+    );
+    env.insert(
+        "len".to_string(),
+        DataType::Function("len".into(), qfunctions::len),
+    );
+    env.insert(
+        "keys".to_string(),
+        DataType::Function("keys".into(), qfunctions::keys),
+    );
+    env.insert(
+        "values".to_string(),
+        DataType::Function("values".into(), qfunctions::values),
+    );
 }
 
 mod qfunctions {
+    use std::collections::{BTreeMap, HashMap};
+
+    use chrono::NaiveDate;
+
     use aw_datastore::Datastore;
     use aw_models::Event;
     use aw_transform::classify::Rule;
@@ -167,6 +381,39 @@ mod qfunctions {
         Ok(DataType::List(ret))
     }
 
+    /// An event per local day and category of the bucket with the time of the events of that
+    /// category in the day as duration, read from the daily aggregates of the datastore
+    ///
+    /// Days overlapping the timeperiod are included as a whole. Fails if the daily aggregates
+    /// are not enabled, see `Datastore::set_daily_aggregates`.
+    pub fn query_bucket_daily(
+        args: Vec<DataType>,
+        env: &VarEnv,
+        ds: &Datastore,
+    ) -> Result<DataType, QueryError> {
+        // Typecheck
+        validate::args_length(&args, 1)?;
+
+        let bucket_id: String = (&args[0]).try_into()?;
+        let interval = validate::get_timeinterval(env)?;
+
+        let events = match ds.get_daily_aggregates(
+            bucket_id.as_str(),
+            Some(*interval.start()),
+            Some(*interval.end()),
+        ) {
+            Ok(events) => events,
+            Err(e) => {
+                return Err(QueryError::BucketQueryError(format!(
+                    "Failed to query daily aggregates of bucket: {e:?}"
+                )))
+            }
+        };
+        Ok(DataType::List(
+            events.into_iter().map(DataType::Event).collect(),
+        ))
+    }
+
     pub fn query_bucket_names(
         args: Vec<DataType>,
         _env: &VarEnv,
@@ -332,6 +579,41 @@ mod qfunctions {
         Ok(DataType::List(tagged_sorted_events))
     }
 
+    pub fn rank_by_duration(
+        args: Vec<DataType>,
+        _env: &VarEnv,
+        _ds: &Datastore,
+    ) -> Result<DataType, QueryError> {
+        // typecheck
+        validate::args_length(&args, 1)?;
+        let events: Vec<Event> = (&args[0]).try_into()?;
+
+        let mut ranked_events = aw_transform::rank_by_duration(events);
+        let mut tagged_ranked_events = Vec::new();
+        for event in ranked_events.drain(..) {
+            tagged_ranked_events.push(DataType::Event(event));
+        }
+        Ok(DataType::List(tagged_ranked_events))
+    }
+
+    pub fn rank_by_key(
+        args: Vec<DataType>,
+        _env: &VarEnv,
+        _ds: &Datastore,
+    ) -> Result<DataType, QueryError> {
+        // typecheck
+        validate::args_length(&args, 2)?;
+        let events: Vec<Event> = (&args[0]).try_into()?;
+        let key: String = (&args[1]).try_into()?;
+
+        let mut ranked_events = aw_transform::rank_by_key(events, &key);
+        let mut tagged_ranked_events = Vec::new();
+        for event in ranked_events.drain(..) {
+            tagged_ranked_events.push(DataType::Event(event));
+        }
+        Ok(DataType::List(tagged_ranked_events))
+    }
+
     pub fn limit_events(
         args: Vec<DataType>,
         _env: &VarEnv,
@@ -408,6 +690,25 @@ mod qfunctions {
         Ok(DataType::List(merged_tagged_events))
     }
 
+    pub fn merge_consecutive(
+        args: Vec<DataType>,
+        _env: &VarEnv,
+        _ds: &Datastore,
+    ) -> Result<DataType, QueryError> {
+        // typecheck
+        validate::args_length(&args, 2)?;
+        let events: Vec<Event> = (&args[0]).try_into()?;
+        let max_gap: f64 = (&args[1]).try_into()?;
+        let max_gap = chrono::Duration::milliseconds((max_gap * 1000.0) as i64);
+
+        let mut merged_events = aw_transform::merge_consecutive(events, max_gap);
+        let mut merged_tagged_events = Vec::new();
+        for event in merged_events.drain(..) {
+            merged_tagged_events.push(DataType::Event(event));
+        }
+        Ok(DataType::List(merged_tagged_events))
+    }
+
     pub fn chunk_events_by_key(
         args: Vec<DataType>,
         _env: &VarEnv,
@@ -511,6 +812,156 @@ mod qfunctions {
         Ok(DataType::List(filtered_tagged_events))
     }
 
+    pub fn filter_duration(
+        args: Vec<DataType>,
+        _env: &VarEnv,
+        _ds: &Datastore,
+    ) -> Result<DataType, QueryError> {
+        // typecheck
+        validate::args_length(&args, 2).or_else(|_| validate::args_length(&args, 3))?;
+        let events: Vec<Event> = (&args[0]).try_into()?;
+        let min: f64 = (&args[1]).try_into()?;
+        let min = chrono::Duration::milliseconds((min * 1000.0) as i64);
+        let max = match args.len() {
+            3 => {
+                let max: f64 = (&args[2]).try_into()?;
+                Some(chrono::Duration::milliseconds((max * 1000.0) as i64))
+            }
+            _ => None,
+        };
+
+        let mut filtered_events = aw_transform::filter_duration(events, Some(min), max);
+        let mut filtered_tagged_events = Vec::new();
+        for event in filtered_events.drain(..) {
+            filtered_tagged_events.push(DataType::Event(event));
+        }
+        Ok(DataType::List(filtered_tagged_events))
+    }
+
+    pub fn rename_keys(
+        args: Vec<DataType>,
+        _env: &VarEnv,
+        _ds: &Datastore,
+    ) -> Result<DataType, QueryError> {
+        // typecheck
+        validate::args_length(&args, 2)?;
+        let events: Vec<Event> = (&args[0]).try_into()?;
+        let mut mapping = HashMap::new();
+        for (from, to) in validate::get_dict(&args[1], "rename_keys")? {
+            let to: String = to.try_into()?;
+            mapping.insert(from.to_string(), to);
+        }
+
+        let mut renamed_events = aw_transform::rename_keys(events, &mapping);
+        let mut renamed_tagged_events = Vec::new();
+        for event in renamed_events.drain(..) {
+            renamed_tagged_events.push(DataType::Event(event));
+        }
+        Ok(DataType::List(renamed_tagged_events))
+    }
+
+    pub fn redact(
+        args: Vec<DataType>,
+        _env: &VarEnv,
+        _ds: &Datastore,
+    ) -> Result<DataType, QueryError> {
+        // typecheck
+        validate::args_length(&args, 3)?;
+        let events: Vec<Event> = (&args[0]).try_into()?;
+        let keys: Vec<String> = (&args[1]).try_into()?;
+        let replacement: serde_json::Value = (&args[2]).try_into()?;
+
+        let redaction = aw_transform::Redaction::Replace(replacement);
+        let mut redacted_events = aw_transform::redact(events, &keys, &redaction);
+        let mut redacted_tagged_events = Vec::new();
+        for event in redacted_events.drain(..) {
+            redacted_tagged_events.push(DataType::Event(event));
+        }
+        Ok(DataType::List(redacted_tagged_events))
+    }
+
+    pub fn redact_hash(
+        args: Vec<DataType>,
+        _env: &VarEnv,
+        _ds: &Datastore,
+    ) -> Result<DataType, QueryError> {
+        // typecheck
+        validate::args_length(&args, 2)?;
+        let events: Vec<Event> = (&args[0]).try_into()?;
+        let keys: Vec<String> = (&args[1]).try_into()?;
+
+        let mut redacted_events =
+            aw_transform::redact(events, &keys, &aw_transform::Redaction::Hash);
+        let mut redacted_tagged_events = Vec::new();
+        for event in redacted_events.drain(..) {
+            redacted_tagged_events.push(DataType::Event(event));
+        }
+        Ok(DataType::List(redacted_tagged_events))
+    }
+
+    /// Compares the time spent per category with a target number of seconds per category
+    ///
+    /// Categories are named by joining the category path with " > ", such as "Work > Email".
+    /// Time in subcategories doesn't count towards the parent category.
+    pub fn budget(
+        args: Vec<DataType>,
+        _env: &VarEnv,
+        _ds: &Datastore,
+    ) -> Result<DataType, QueryError> {
+        // typecheck
+        validate::args_length(&args, 2)?;
+        let events: Vec<Event> = (&args[0]).try_into()?;
+        let mut targets = HashMap::new();
+        for (category, target) in validate::get_dict(&args[1], "budget")? {
+            let target: f64 = target.try_into()?;
+            targets.insert(category.to_string(), target);
+        }
+
+        let budget_entry = |actual: f64, target: Option<f64>| {
+            let mut entry = HashMap::new();
+            entry.insert("actual".to_string(), DataType::Number(actual));
+            match target {
+                Some(target) => {
+                    entry.insert("target".to_string(), DataType::Number(target));
+                    entry.insert("delta".to_string(), DataType::Number(actual - target));
+                }
+                None => {
+                    entry.insert("target".to_string(), DataType::None());
+                    entry.insert("delta".to_string(), DataType::None());
+                }
+            }
+            DataType::Dict(entry)
+        };
+
+        let mut result = HashMap::new();
+        let merged_events =
+            aw_transform::merge_events_by_keys(events, vec!["$category".to_string()]);
+        for event in merged_events {
+            let category = match event.data["$category"].as_array().and_then(|path| {
+                path.iter()
+                    .map(|name| name.as_str())
+                    .collect::<Option<Vec<&str>>>()
+            }) {
+                Some(path) => path.join(" > "),
+                None => {
+                    return Err(QueryError::InvalidFunctionParameters(format!(
+                        "Expected $category to be a list of strings, got {}",
+                        event.data["$category"]
+                    )))
+                }
+            };
+            let actual = (event.duration.num_milliseconds() as f64) / 1000.0;
+            let entry = budget_entry(actual, targets.get(&category).copied());
+            result.insert(category, entry);
+        }
+        for (category, target) in targets {
+            result
+                .entry(category)
+                .or_insert_with(|| budget_entry(0.0, Some(target)));
+        }
+        Ok(DataType::Dict(result))
+    }
+
     pub fn split_url_events(
         args: Vec<DataType>,
         _env: &VarEnv,
@@ -561,7 +1012,7 @@ mod qfunctions {
         Ok(DataType::List(result_tagged))
     }
 
-    pub fn union_no_overlap(
+    pub fn period_intersect(
         args: Vec<DataType>,
         _env: &VarEnv,
         _ds: &Datastore,
@@ -571,18 +1022,1133 @@ mod qfunctions {
         let events1: Vec<Event> = (&args[0]).try_into()?;
         let events2: Vec<Event> = (&args[1]).try_into()?;
 
-        let mut result = aw_transform::union_no_overlap(events1, events2);
+        let mut result = aw_transform::period_intersect(&events1, &events2);
         let mut result_tagged = Vec::new();
         for event in result.drain(..) {
             result_tagged.push(DataType::Event(event));
         }
         Ok(DataType::List(result_tagged))
     }
-}
 
-mod validate {
-    use crate::{DataType, QueryError, VarEnv};
-    use aw_models::TimeInterval;
+    pub fn overlap_duration(
+        args: Vec<DataType>,
+        _env: &VarEnv,
+        _ds: &Datastore,
+    ) -> Result<DataType, QueryError> {
+        // typecheck
+        validate::args_length(&args, 2)?;
+        let events1: Vec<Event> = (&args[0]).try_into()?;
+        let events2: Vec<Event> = (&args[1]).try_into()?;
+
+        let overlap = aw_transform::overlap_duration(&events1, &events2);
+        Ok(DataType::Number(overlap.num_milliseconds() as f64 / 1000.0))
+    }
+
+    pub fn union_no_overlap(
+        args: Vec<DataType>,
+        _env: &VarEnv,
+        _ds: &Datastore,
+    ) -> Result<DataType, QueryError> {
+        // typecheck
+        validate::args_length(&args, 2)?;
+        let events1: Vec<Event> = (&args[0]).try_into()?;
+        let events2: Vec<Event> = (&args[1]).try_into()?;
+
+        let mut result = aw_transform::union_no_overlap(events1, events2);
+        let mut result_tagged = Vec::new();
+        for event in result.drain(..) {
+            result_tagged.push(DataType::Event(event));
+        }
+        Ok(DataType::List(result_tagged))
+    }
+
+    pub fn find_overlaps(
+        args: Vec<DataType>,
+        _env: &VarEnv,
+        _ds: &Datastore,
+    ) -> Result<DataType, QueryError> {
+        // typecheck
+        validate::args_length(&args, 1)?;
+        let events: Vec<Event> = (&args[0]).try_into()?;
+
+        let mut result = aw_transform::find_overlaps(&events);
+        let mut result_tagged = Vec::new();
+        for event in result.drain(..) {
+            result_tagged.push(DataType::Event(event));
+        }
+        Ok(DataType::List(result_tagged))
+    }
+
+    pub fn join_overlapping(
+        args: Vec<DataType>,
+        _env: &VarEnv,
+        _ds: &Datastore,
+    ) -> Result<DataType, QueryError> {
+        // typecheck
+        validate::args_length(&args, 3)?;
+        let left_events: Vec<Event> = (&args[0]).try_into()?;
+        let right_events: Vec<Event> = (&args[1]).try_into()?;
+        let key: String = (&args[2]).try_into()?;
+
+        let mut result = aw_transform::join_overlapping(left_events, right_events, &key);
+        let mut result_tagged = Vec::new();
+        for event in result.drain(..) {
+            result_tagged.push(DataType::Event(event));
+        }
+        Ok(DataType::List(result_tagged))
+    }
+
+    pub fn activity_bounds(
+        args: Vec<DataType>,
+        _env: &VarEnv,
+        _ds: &Datastore,
+    ) -> Result<DataType, QueryError> {
+        // typecheck
+        validate::args_length(&args, 2)?;
+        let events: Vec<Event> = (&args[0]).try_into()?;
+        let group_key: String = (&args[1]).try_into()?;
+
+        let mut result = HashMap::new();
+        for (group, bounds) in aw_transform::activity_bounds(&events, &group_key) {
+            let mut entry = HashMap::new();
+            entry.insert(
+                "first".to_string(),
+                DataType::String(bounds.first.to_rfc3339()),
+            );
+            entry.insert(
+                "last".to_string(),
+                DataType::String(bounds.last.to_rfc3339()),
+            );
+            result.insert(group, DataType::Dict(entry));
+        }
+        Ok(DataType::Dict(result))
+    }
+
+    pub fn longest_session(
+        args: Vec<DataType>,
+        _env: &VarEnv,
+        _ds: &Datastore,
+    ) -> Result<DataType, QueryError> {
+        // typecheck
+        validate::args_length(&args, 4)?;
+        let events: Vec<Event> = (&args[0]).try_into()?;
+        let max_gap: f64 = (&args[1]).try_into()?;
+        let max_gap = chrono::Duration::milliseconds((max_gap * 1000.0) as i64);
+        let filter_key: String = (&args[2]).try_into()?;
+        let filter_value: serde_json::Value = (&args[3]).try_into()?;
+
+        let events = aw_transform::filter_keyvals(events, &filter_key, &[filter_value]);
+        match aw_transform::longest_session(&events, max_gap) {
+            Some(session) => {
+                let mut result = HashMap::new();
+                result.insert(
+                    "start".to_string(),
+                    DataType::String(session.start.to_rfc3339()),
+                );
+                result.insert(
+                    "end".to_string(),
+                    DataType::String(session.end.to_rfc3339()),
+                );
+                result.insert(
+                    "duration".to_string(),
+                    DataType::Number((session.duration().num_milliseconds() as f64) / 1000.0),
+                );
+                Ok(DataType::Dict(result))
+            }
+            None => Ok(DataType::None()),
+        }
+    }
+
+    /// The number of sessions of the events with `filter_key` set to `filter_value` which are at
+    /// least `min_session` seconds long as `sessions`, and their total length in seconds as
+    /// `duration`
+    pub fn flow_time(
+        args: Vec<DataType>,
+        _env: &VarEnv,
+        _ds: &Datastore,
+    ) -> Result<DataType, QueryError> {
+        // typecheck
+        validate::args_length(&args, 5)?;
+        let events: Vec<Event> = (&args[0]).try_into()?;
+        let min_session: f64 = (&args[1]).try_into()?;
+        let min_session = chrono::Duration::milliseconds((min_session * 1000.0) as i64);
+        let max_gap: f64 = (&args[2]).try_into()?;
+        let max_gap = chrono::Duration::milliseconds((max_gap * 1000.0) as i64);
+        let filter_key: String = (&args[3]).try_into()?;
+        let filter_value: serde_json::Value = (&args[4]).try_into()?;
+
+        let events = aw_transform::filter_keyvals(events, &filter_key, &[filter_value]);
+        let flow = aw_transform::flow_time(&events, min_session, max_gap);
+        let mut result = HashMap::new();
+        result.insert(
+            "sessions".to_string(),
+            DataType::Number(flow.sessions as f64),
+        );
+        result.insert(
+            "duration".to_string(),
+            DataType::Number((flow.duration.num_milliseconds() as f64) / 1000.0),
+        );
+        Ok(DataType::Dict(result))
+    }
+
+    pub fn time_between(
+        args: Vec<DataType>,
+        _env: &VarEnv,
+        _ds: &Datastore,
+    ) -> Result<DataType, QueryError> {
+        // typecheck
+        validate::args_length(&args, 4)?;
+        let events: Vec<Event> = (&args[0]).try_into()?;
+        let key: String = (&args[1]).try_into()?;
+        let value_a: serde_json::Value = (&args[2]).try_into()?;
+        let value_b: serde_json::Value = (&args[3]).try_into()?;
+
+        let times = aw_transform::time_between(&events, &key, &value_a, &value_b)
+            .into_iter()
+            .map(|time| match time {
+                Some(time) => DataType::Number((time.num_milliseconds() as f64) / 1000.0),
+                None => DataType::None(),
+            })
+            .collect();
+        Ok(DataType::List(times))
+    }
+
+    pub fn flag_distractions(
+        args: Vec<DataType>,
+        _env: &VarEnv,
+        _ds: &Datastore,
+    ) -> Result<DataType, QueryError> {
+        // typecheck
+        validate::args_length(&args, 3)?;
+        let events: Vec<Event> = (&args[0]).try_into()?;
+        let min_focus: f64 = (&args[1]).try_into()?;
+        let min_focus = chrono::Duration::milliseconds((min_focus * 1000.0) as i64);
+        let key: String = (&args[2]).try_into()?;
+
+        let mut flagged_events = aw_transform::flag_distractions(events, min_focus, &key);
+        let mut flagged_tagged_events = Vec::new();
+        for event in flagged_events.drain(..) {
+            flagged_tagged_events.push(DataType::Event(event));
+        }
+        Ok(DataType::List(flagged_tagged_events))
+    }
+
+    pub fn transition_counts(
+        args: Vec<DataType>,
+        _env: &VarEnv,
+        _ds: &Datastore,
+    ) -> Result<DataType, QueryError> {
+        // typecheck
+        validate::args_length(&args, 2)?;
+        let events: Vec<Event> = (&args[0]).try_into()?;
+        let key: String = (&args[1]).try_into()?;
+
+        let result = aw_transform::transition_counts(events, &key)
+            .into_iter()
+            .map(|(transition, count)| (transition, DataType::Number(count as f64)))
+            .collect();
+        Ok(DataType::Dict(result))
+    }
+
+    pub fn by_hour_of_day(
+        args: Vec<DataType>,
+        _env: &VarEnv,
+        _ds: &Datastore,
+    ) -> Result<DataType, QueryError> {
+        // typecheck
+        validate::args_length(&args, 2)?;
+        let events: Vec<Event> = (&args[0]).try_into()?;
+        let tz_name: String = (&args[1]).try_into()?;
+        let tz: chrono_tz::Tz = match tz_name.parse() {
+            Ok(tz) => tz,
+            Err(_) => {
+                return Err(QueryError::InvalidFunctionParameters(format!(
+                    "function by_hour_of_day got an unknown timezone '{tz_name}'"
+                )))
+            }
+        };
+
+        let hours = aw_transform::by_hour_of_day(&events, &tz);
+        let mut result = Vec::new();
+        for duration in hours {
+            result.push(DataType::Number(
+                (duration.num_milliseconds() as f64) / 1000.0,
+            ));
+        }
+        Ok(DataType::List(result))
+    }
+
+    pub fn split_weekday_weekend(
+        args: Vec<DataType>,
+        _env: &VarEnv,
+        _ds: &Datastore,
+    ) -> Result<DataType, QueryError> {
+        // typecheck
+        validate::args_length(&args, 2)?;
+        let events: Vec<Event> = (&args[0]).try_into()?;
+        let tz_name: String = (&args[1]).try_into()?;
+        let tz: chrono_tz::Tz = match tz_name.parse() {
+            Ok(tz) => tz,
+            Err(_) => {
+                return Err(QueryError::InvalidFunctionParameters(format!(
+                    "function split_weekday_weekend got an unknown timezone '{tz_name}'"
+                )))
+            }
+        };
+
+        let (weekday, weekend) = aw_transform::split_weekday_weekend(&events, &tz);
+        let mut result = HashMap::new();
+        result.insert(
+            "weekday".to_string(),
+            DataType::List(weekday.into_iter().map(DataType::Event).collect()),
+        );
+        result.insert(
+            "weekend".to_string(),
+            DataType::List(weekend.into_iter().map(DataType::Event).collect()),
+        );
+        Ok(DataType::Dict(result))
+    }
+
+    pub fn active_ratio(
+        args: Vec<DataType>,
+        _env: &VarEnv,
+        _ds: &Datastore,
+    ) -> Result<DataType, QueryError> {
+        // typecheck
+        validate::args_length(&args, 4)?;
+        let events: Vec<Event> = (&args[0]).try_into()?;
+        let mut range = Vec::new();
+        for arg in &args[1..3] {
+            let time_str: String = arg.try_into()?;
+            match chrono::DateTime::parse_from_rfc3339(&time_str) {
+                Ok(time) => range.push(time.with_timezone(&chrono::Utc)),
+                Err(_) => {
+                    return Err(QueryError::InvalidFunctionParameters(format!(
+                        "function active_ratio got an invalid timestamp '{time_str}'"
+                    )))
+                }
+            }
+        }
+        let tz_name: String = (&args[3]).try_into()?;
+        let tz: chrono_tz::Tz = match tz_name.parse() {
+            Ok(tz) => tz,
+            Err(_) => {
+                return Err(QueryError::InvalidFunctionParameters(format!(
+                    "function active_ratio got an unknown timezone '{tz_name}'"
+                )))
+            }
+        };
+
+        let result = aw_transform::active_ratio(&events, range[0], range[1], &tz)
+            .into_iter()
+            .map(|(day, ratio)| {
+                let ratio = match ratio {
+                    Some(ratio) => DataType::Number(ratio),
+                    None => DataType::None(),
+                };
+                (day.format("%Y-%m-%d").to_string(), ratio)
+            })
+            .collect();
+        Ok(DataType::Dict(result))
+    }
+
+    /// The summed duration in seconds of the events in each band, keyed by the band as an
+    /// interval such as `[10, 50)`, and the number of skipped events
+    pub fn group_by_numeric(
+        args: Vec<DataType>,
+        _env: &VarEnv,
+        _ds: &Datastore,
+    ) -> Result<DataType, QueryError> {
+        // typecheck
+        validate::args_length(&args, 3)?;
+        let events: Vec<Event> = (&args[0]).try_into()?;
+        let key: String = (&args[1]).try_into()?;
+        let edges: Vec<f64> = (&args[2]).try_into()?;
+        if edges.windows(2).any(|pair| pair[0] >= pair[1]) {
+            return Err(QueryError::InvalidFunctionParameters(
+                "function group_by_numeric got edges which are not in ascending order".to_string(),
+            ));
+        }
+
+        let bands = aw_transform::group_by_numeric(&events, &key, &edges);
+        let lower_edges = std::iter::once(None).chain(edges.iter().map(Some));
+        let upper_edges = edges.iter().map(Some).chain(std::iter::once(None));
+        let durations = lower_edges
+            .zip(upper_edges)
+            .zip(bands.durations)
+            .map(|((lower, upper), duration)| {
+                let label = match (lower, upper) {
+                    (Some(lower), Some(upper)) => format!("[{lower}, {upper})"),
+                    (Some(lower), None) => format!("[{lower}, inf)"),
+                    (None, Some(upper)) => format!("(-inf, {upper})"),
+                    (None, None) => "(-inf, inf)".to_string(),
+                };
+                (
+                    label,
+                    DataType::Number((duration.num_milliseconds() as f64) / 1000.0),
+                )
+            })
+            .collect();
+
+        let mut result = HashMap::new();
+        result.insert("bands".to_string(), DataType::Dict(durations));
+        result.insert(
+            "skipped".to_string(),
+            DataType::Number(bands.skipped as f64),
+        );
+        Ok(DataType::Dict(result))
+    }
+
+    pub fn find_gaps(
+        args: Vec<DataType>,
+        _env: &VarEnv,
+        _ds: &Datastore,
+    ) -> Result<DataType, QueryError> {
+        // typecheck
+        validate::args_length(&args, 4)?;
+        let events: Vec<Event> = (&args[0]).try_into()?;
+        let mut range = Vec::new();
+        for arg in &args[1..3] {
+            let time_str: String = arg.try_into()?;
+            match chrono::DateTime::parse_from_rfc3339(&time_str) {
+                Ok(time) => range.push(time.with_timezone(&chrono::Utc)),
+                Err(_) => {
+                    return Err(QueryError::InvalidFunctionParameters(format!(
+                        "function find_gaps got an invalid timestamp '{time_str}'"
+                    )))
+                }
+            }
+        }
+        let min_gap: f64 = (&args[3]).try_into()?;
+        let min_gap = chrono::Duration::milliseconds((min_gap * 1000.0) as i64);
+
+        let gaps = aw_transform::find_gaps(&events, range[0], range[1], min_gap)
+            .into_iter()
+            .map(|gap| {
+                let mut result = HashMap::new();
+                result.insert(
+                    "start".to_string(),
+                    DataType::String(gap.start().to_rfc3339()),
+                );
+                result.insert("end".to_string(), DataType::String(gap.end().to_rfc3339()));
+                result.insert(
+                    "duration".to_string(),
+                    DataType::Number((gap.duration().num_milliseconds() as f64) / 1000.0),
+                );
+                DataType::Dict(result)
+            })
+            .collect();
+        Ok(DataType::List(gaps))
+    }
+
+    /// The category tree of the events as nested dicts, each category with its `self_duration`
+    /// and `total_duration` in seconds and its subcategories as `children`
+    pub fn rollup_categories(
+        args: Vec<DataType>,
+        _env: &VarEnv,
+        _ds: &Datastore,
+    ) -> Result<DataType, QueryError> {
+        // typecheck
+        validate::args_length(&args, 1)?;
+        let events: Vec<Event> = (&args[0]).try_into()?;
+
+        fn to_dict(nodes: BTreeMap<String, aw_transform::CategoryNode>) -> DataType {
+            let seconds =
+                |d: chrono::Duration| DataType::Number((d.num_milliseconds() as f64) / 1000.0);
+            let dict = nodes
+                .into_iter()
+                .map(|(name, node)| {
+                    let mut entry = HashMap::new();
+                    entry.insert("self_duration".to_string(), seconds(node.self_duration));
+                    entry.insert("total_duration".to_string(), seconds(node.total_duration));
+                    entry.insert("children".to_string(), to_dict(node.children));
+                    (name, DataType::Dict(entry))
+                })
+                .collect();
+            DataType::Dict(dict)
+        }
+        Ok(to_dict(aw_transform::rollup_categories(&events)))
+    }
+
+    pub fn enrich_browser_events(
+        args: Vec<DataType>,
+        _env: &VarEnv,
+        _ds: &Datastore,
+    ) -> Result<DataType, QueryError> {
+        // typecheck
+        validate::args_length(&args, 3)?;
+        let window_events: Vec<Event> = (&args[0]).try_into()?;
+        let tab_events: Vec<Event> = (&args[1]).try_into()?;
+        let browser_apps: Vec<String> = (&args[2]).try_into()?;
+
+        let mut enriched_events =
+            aw_transform::enrich_browser_events(window_events, &tab_events, &browser_apps);
+        let mut enriched_tagged_events = Vec::new();
+        for event in enriched_events.drain(..) {
+            enriched_tagged_events.push(DataType::Event(event));
+        }
+        Ok(DataType::List(enriched_tagged_events))
+    }
+
+    /// The value of `key` with the most time in each slot, as a list of dicts with the `start`,
+    /// `end`, `value` and `duration` in seconds of the slots
+    pub fn dominant_per_slot(
+        args: Vec<DataType>,
+        _env: &VarEnv,
+        _ds: &Datastore,
+    ) -> Result<DataType, QueryError> {
+        // typecheck
+        validate::args_length(&args, 5)?;
+        let events: Vec<Event> = (&args[0]).try_into()?;
+        let slot: f64 = (&args[1]).try_into()?;
+        let slot = chrono::Duration::milliseconds((slot * 1000.0) as i64);
+        if slot <= chrono::Duration::zero() {
+            return Err(QueryError::InvalidFunctionParameters(
+                "function dominant_per_slot got a slot which isn't positive".to_string(),
+            ));
+        }
+        let key: String = (&args[2]).try_into()?;
+        let mut range = Vec::new();
+        for arg in &args[3..5] {
+            let time_str: String = arg.try_into()?;
+            match chrono::DateTime::parse_from_rfc3339(&time_str) {
+                Ok(time) => range.push(time.with_timezone(&chrono::Utc)),
+                Err(_) => {
+                    return Err(QueryError::InvalidFunctionParameters(format!(
+                        "function dominant_per_slot got an invalid timestamp '{time_str}'"
+                    )))
+                }
+            }
+        }
+
+        let slots = aw_transform::dominant_per_slot(&events, slot, &key, range[0], range[1])
+            .into_iter()
+            .map(|slot| {
+                let mut result = HashMap::new();
+                result.insert(
+                    "start".to_string(),
+                    DataType::String(slot.start.to_rfc3339()),
+                );
+                result.insert("end".to_string(), DataType::String(slot.end.to_rfc3339()));
+                let value = match &slot.value {
+                    Some(value) => DataType::from(value),
+                    None => DataType::None(),
+                };
+                result.insert("value".to_string(), value);
+                result.insert(
+                    "duration".to_string(),
+                    DataType::Number((slot.duration.num_milliseconds() as f64) / 1000.0),
+                );
+                DataType::Dict(result)
+            })
+            .collect();
+        Ok(DataType::List(slots))
+    }
+
+    /// The number of changes of the value of `key` in each slot, by the start of the slot
+    pub fn switch_rate(
+        args: Vec<DataType>,
+        _env: &VarEnv,
+        _ds: &Datastore,
+    ) -> Result<DataType, QueryError> {
+        // typecheck
+        validate::args_length(&args, 3)?;
+        let events: Vec<Event> = (&args[0]).try_into()?;
+        let key: String = (&args[1]).try_into()?;
+        let slot: f64 = (&args[2]).try_into()?;
+        let slot = chrono::Duration::milliseconds((slot * 1000.0) as i64);
+        if slot <= chrono::Duration::zero() {
+            return Err(QueryError::InvalidFunctionParameters(
+                "function switch_rate got a slot which isn't positive".to_string(),
+            ));
+        }
+
+        let result = aw_transform::switch_rate(&events, &key, slot)
+            .into_iter()
+            .map(|(start, count)| (start.to_rfc3339(), DataType::Number(count as f64)))
+            .collect();
+        Ok(DataType::Dict(result))
+    }
+
+    pub fn time_matching(
+        args: Vec<DataType>,
+        _env: &VarEnv,
+        _ds: &Datastore,
+    ) -> Result<DataType, QueryError> {
+        // typecheck
+        validate::args_length(&args, 4)?;
+        let events: Vec<Event> = (&args[0]).try_into()?;
+        let keys: Vec<String> = (&args[1]).try_into()?;
+        let substring: String = (&args[2]).try_into()?;
+        let ignore_case: bool = (&args[3]).try_into()?;
+
+        let total = aw_transform::time_matching(&events, &keys, &substring, ignore_case);
+        Ok(DataType::Number((total.num_milliseconds() as f64) / 1000.0))
+    }
+
+    /// The local time of the first event of each day as `days`, by date, with the `mean` and
+    /// `median` of those times, which are none if there are no events
+    pub fn first_activity_times(
+        args: Vec<DataType>,
+        _env: &VarEnv,
+        _ds: &Datastore,
+    ) -> Result<DataType, QueryError> {
+        // typecheck
+        validate::args_length(&args, 2)?;
+        let events: Vec<Event> = (&args[0]).try_into()?;
+        let tz_name: String = (&args[1]).try_into()?;
+        let tz: chrono_tz::Tz = match tz_name.parse() {
+            Ok(tz) => tz,
+            Err(_) => {
+                return Err(QueryError::InvalidFunctionParameters(format!(
+                    "function first_activity_times got an unknown timezone '{tz_name}'"
+                )))
+            }
+        };
+
+        let days = aw_transform::first_activity_times(&events, &tz);
+        let times: Vec<chrono::NaiveTime> = days.values().cloned().collect();
+        let format_time = |time: Option<chrono::NaiveTime>| match time {
+            Some(time) => DataType::String(time.format("%H:%M:%S").to_string()),
+            None => DataType::None(),
+        };
+        let mut result = HashMap::new();
+        result.insert(
+            "days".to_string(),
+            DataType::Dict(
+                days.into_iter()
+                    .map(|(day, time)| {
+                        (day.format("%Y-%m-%d").to_string(), format_time(Some(time)))
+                    })
+                    .collect(),
+            ),
+        );
+        result.insert(
+            "mean".to_string(),
+            format_time(aw_transform::mean_time_of_day(&times)),
+        );
+        result.insert(
+            "median".to_string(),
+            format_time(aw_transform::median_time_of_day(&times)),
+        );
+        Ok(DataType::Dict(result))
+    }
+
+    pub fn daily_category_trend(
+        args: Vec<DataType>,
+        _env: &VarEnv,
+        _ds: &Datastore,
+    ) -> Result<DataType, QueryError> {
+        // typecheck
+        validate::args_length(&args, 4)?;
+        let events: Vec<Event> = (&args[0]).try_into()?;
+        let tz_name: String = (&args[1]).try_into()?;
+        let tz: chrono_tz::Tz = match tz_name.parse() {
+            Ok(tz) => tz,
+            Err(_) => {
+                return Err(QueryError::InvalidFunctionParameters(format!(
+                    "function daily_category_trend got an unknown timezone '{tz_name}'"
+                )))
+            }
+        };
+        let mut days = Vec::new();
+        for arg in &args[2..4] {
+            let day: String = arg.try_into()?;
+            match NaiveDate::parse_from_str(&day, "%Y-%m-%d") {
+                Ok(day) => days.push(day),
+                Err(_) => {
+                    return Err(QueryError::InvalidFunctionParameters(format!(
+                        "function daily_category_trend got '{day}' as a day, expected a date like 2000-01-31"
+                    )))
+                }
+            }
+        }
+
+        let trends = aw_transform::daily_category_trend(&events, &tz, days[0], days[1]);
+        let mut result = HashMap::new();
+        for (category, totals) in trends {
+            let totals = totals
+                .into_iter()
+                .map(|total| DataType::Number((total.num_milliseconds() as f64) / 1000.0))
+                .collect();
+            result.insert(category, DataType::List(totals));
+        }
+        Ok(DataType::Dict(result))
+    }
+
+    pub fn productivity_score(
+        args: Vec<DataType>,
+        _env: &VarEnv,
+        _ds: &Datastore,
+    ) -> Result<DataType, QueryError> {
+        // typecheck
+        validate::args_length(&args, 3).or_else(|_| validate::args_length(&args, 4))?;
+        let events: Vec<Event> = (&args[0]).try_into()?;
+        let check_weight = |name: &str, weight: f64| {
+            match (-1.0..=1.0).contains(&weight) {
+            true => Ok(weight),
+            false => Err(QueryError::InvalidFunctionParameters(format!(
+                "function productivity_score got the weight {weight} for {name}, expected a weight from -1 to 1"
+            ))),
+        }
+        };
+        let mut weights = HashMap::new();
+        for (category, weight) in validate::get_dict(&args[1], "productivity_score")? {
+            let weight: f64 = weight.try_into()?;
+            weights.insert(category.to_string(), check_weight(category, weight)?);
+        }
+        let tz_name: String = (&args[2]).try_into()?;
+        let tz: chrono_tz::Tz = match tz_name.parse() {
+            Ok(tz) => tz,
+            Err(_) => {
+                return Err(QueryError::InvalidFunctionParameters(format!(
+                    "function productivity_score got an unknown timezone '{tz_name}'"
+                )))
+            }
+        };
+        let uncategorized_weight = match args.len() {
+            4 => {
+                let weight: f64 = (&args[3]).try_into()?;
+                check_weight("uncategorized time", weight)?
+            }
+            _ => 0.0,
+        };
+
+        let scores = aw_transform::productivity_score(&events, &weights, uncategorized_weight, &tz);
+        let mut result = HashMap::new();
+        for (day, score) in scores {
+            result.insert(day.format("%Y-%m-%d").to_string(), DataType::Number(score));
+        }
+        Ok(DataType::Dict(result))
+    }
+
+    /// The number of changes of the value of `key` as `switches`, and the seconds they are
+    /// modeled to cost as `lost_time`
+    pub fn switching_cost(
+        args: Vec<DataType>,
+        _env: &VarEnv,
+        _ds: &Datastore,
+    ) -> Result<DataType, QueryError> {
+        // typecheck
+        validate::args_length(&args, 3)?;
+        let events: Vec<Event> = (&args[0]).try_into()?;
+        let key: String = (&args[1]).try_into()?;
+        let cost_per_switch: f64 = (&args[2]).try_into()?;
+        if cost_per_switch < 0.0 {
+            return Err(QueryError::InvalidFunctionParameters(
+                "function switching_cost got a negative cost per switch".to_string(),
+            ));
+        }
+        let cost_per_switch = chrono::Duration::milliseconds((cost_per_switch * 1000.0) as i64);
+
+        let cost = aw_transform::switching_cost(&events, &key, cost_per_switch);
+        let mut result = HashMap::new();
+        result.insert(
+            "switches".to_string(),
+            DataType::Number(cost.switches as f64),
+        );
+        result.insert(
+            "lost_time".to_string(),
+            DataType::Number((cost.lost_time.num_milliseconds() as f64) / 1000.0),
+        );
+        Ok(DataType::Dict(result))
+    }
+
+    pub fn daily_streak(
+        args: Vec<DataType>,
+        _env: &VarEnv,
+        _ds: &Datastore,
+    ) -> Result<DataType, QueryError> {
+        // typecheck
+        validate::args_length(&args, 2)?;
+        let mut per_day_totals = HashMap::new();
+        for (day, total) in validate::get_dict(&args[0], "daily_streak")? {
+            let day = match NaiveDate::parse_from_str(day, "%Y-%m-%d") {
+                Ok(day) => day,
+                Err(_) => {
+                    return Err(QueryError::InvalidFunctionParameters(format!(
+                    "function daily_streak got '{day}' as a day, expected a date like 2000-01-31"
+                )))
+                }
+            };
+            let total: f64 = total.try_into()?;
+            per_day_totals.insert(day, total);
+        }
+        let threshold: f64 = (&args[1]).try_into()?;
+
+        let streak = aw_transform::daily_streak(&per_day_totals, threshold);
+        let mut result = HashMap::new();
+        result.insert(
+            "current".to_string(),
+            DataType::Number(streak.current.into()),
+        );
+        result.insert(
+            "longest".to_string(),
+            DataType::Number(streak.longest.into()),
+        );
+        Ok(DataType::Dict(result))
+    }
+
+    /// The mean of each day of a dict of daily totals and the `window_days - 1` days before it,
+    /// by day
+    ///
+    /// Days missing from the totals count as zero and get an average too, unless `missing` is
+    /// "skip" in which case only the days with a total are averaged.
+    pub fn rolling_average(
+        args: Vec<DataType>,
+        _env: &VarEnv,
+        _ds: &Datastore,
+    ) -> Result<DataType, QueryError> {
+        // typecheck
+        validate::args_length(&args, 2).or_else(|_| validate::args_length(&args, 3))?;
+        let mut per_day_totals = HashMap::new();
+        for (day, total) in validate::get_dict(&args[0], "rolling_average")? {
+            let day = match NaiveDate::parse_from_str(day, "%Y-%m-%d") {
+                Ok(day) => day,
+                Err(_) => {
+                    return Err(QueryError::InvalidFunctionParameters(format!(
+                    "function rolling_average got '{day}' as a day, expected a date like 2000-01-31"
+                )))
+                }
+            };
+            let total: f64 = total.try_into()?;
+            per_day_totals.insert(day, total);
+        }
+        let window_days: f64 = (&args[1]).try_into()?;
+        if window_days < 1.0 || window_days.fract() != 0.0 {
+            return Err(QueryError::InvalidFunctionParameters(format!(
+                "function rolling_average got a window of {window_days} days, expected a whole number of at least 1"
+            )));
+        }
+        let missing = match args.len() {
+            3 => {
+                let missing: String = (&args[2]).try_into()?;
+                match missing.as_str() {
+                    "zero" => aw_transform::MissingDays::Zero,
+                    "skip" => aw_transform::MissingDays::Skip,
+                    _ => {
+                        return Err(QueryError::InvalidFunctionParameters(format!(
+                            "function rolling_average got '{missing}' for missing days, expected \"zero\" or \"skip\""
+                        )))
+                    }
+                }
+            }
+            _ => aw_transform::MissingDays::Zero,
+        };
+
+        let averages = aw_transform::rolling_average(&per_day_totals, window_days as u32, missing);
+        let mut result = HashMap::new();
+        for (day, average) in averages {
+            result.insert(
+                day.format("%Y-%m-%d").to_string(),
+                DataType::Number(average),
+            );
+        }
+        Ok(DataType::Dict(result))
+    }
+
+    pub fn close_gaps(
+        args: Vec<DataType>,
+        _env: &VarEnv,
+        _ds: &Datastore,
+    ) -> Result<DataType, QueryError> {
+        // typecheck
+        validate::args_length(&args, 2)?;
+        let events: Vec<Event> = (&args[0]).try_into()?;
+        let max_gap: f64 = (&args[1]).try_into()?;
+        let max_gap = chrono::Duration::milliseconds((max_gap * 1000.0) as i64);
+
+        let mut closed_events = aw_transform::close_gaps(events, max_gap);
+        let mut closed_tagged_events = Vec::new();
+        for event in closed_events.drain(..) {
+            closed_tagged_events.push(DataType::Event(event));
+        }
+        Ok(DataType::List(closed_tagged_events))
+    }
+
+    pub fn debounce_status(
+        args: Vec<DataType>,
+        _env: &VarEnv,
+        _ds: &Datastore,
+    ) -> Result<DataType, QueryError> {
+        // typecheck
+        validate::args_length(&args, 3)?;
+        let events: Vec<Event> = (&args[0]).try_into()?;
+        let key: String = (&args[1]).try_into()?;
+        let min_duration: f64 = (&args[2]).try_into()?;
+        let min_duration = chrono::Duration::milliseconds((min_duration * 1000.0) as i64);
+
+        let mut debounced_events = aw_transform::debounce_status(events, &key, min_duration);
+        let mut debounced_tagged_events = Vec::new();
+        for event in debounced_events.drain(..) {
+            debounced_tagged_events.push(DataType::Event(event));
+        }
+        Ok(DataType::List(debounced_tagged_events))
+    }
+
+    pub fn duration_histogram(
+        args: Vec<DataType>,
+        _env: &VarEnv,
+        _ds: &Datastore,
+    ) -> Result<DataType, QueryError> {
+        // typecheck
+        validate::args_length(&args, 2)?;
+        let events: Vec<Event> = (&args[0]).try_into()?;
+        let bin_edges: Vec<f64> = (&args[1]).try_into()?;
+        if !bin_edges.windows(2).all(|pair| pair[0] < pair[1]) {
+            return Err(QueryError::InvalidFunctionParameters(format!(
+                "function duration_histogram expected bin edges in ascending order, got {bin_edges:?}"
+            )));
+        }
+
+        let counts = aw_transform::duration_histogram(&events, &bin_edges)
+            .into_iter()
+            .map(|count| DataType::Number(count as f64))
+            .collect();
+        Ok(DataType::List(counts))
+    }
+
+    pub fn normalize_durations(
+        args: Vec<DataType>,
+        _env: &VarEnv,
+        _ds: &Datastore,
+    ) -> Result<DataType, QueryError> {
+        // typecheck
+        validate::args_length(&args, 1)?;
+        let mut durations = Vec::new();
+        for (key, duration) in validate::get_dict(&args[0], "normalize_durations")? {
+            let duration: f64 = duration.try_into()?;
+            durations.push((key.clone(), duration));
+        }
+
+        // All fractions are 0 if there is nothing to divide by
+        let total: f64 = durations.iter().map(|(_, duration)| duration).sum();
+        let result = durations
+            .into_iter()
+            .map(|(key, duration)| {
+                let fraction = if total > 0.0 { duration / total } else { 0.0 };
+                (key, DataType::Number(fraction))
+            })
+            .collect();
+        Ok(DataType::Dict(result))
+    }
+
+    /// Herfindahl index of a map of durations, the sum of the squared fractions of the total
+    ///
+    /// 1 if all time is in a single category, 1/n if it's spread evenly over n categories, 0 if
+    /// there is no time at all.
+    pub fn concentration(
+        args: Vec<DataType>,
+        _env: &VarEnv,
+        _ds: &Datastore,
+    ) -> Result<DataType, QueryError> {
+        // typecheck
+        validate::args_length(&args, 1)?;
+        let mut durations = Vec::new();
+        for duration in validate::get_dict(&args[0], "concentration")?.values() {
+            let duration: f64 = duration.try_into()?;
+            durations.push(duration);
+        }
+
+        let total: f64 = durations.iter().sum();
+        if total <= 0.0 {
+            return Ok(DataType::Number(0.0));
+        }
+        let index = durations
+            .iter()
+            .map(|duration| (duration / total).powi(2))
+            .sum();
+        Ok(DataType::Number(index))
+    }
+
+    /// Shannon entropy in bits of a map of durations, with the fractions of the total as the
+    /// probabilities
+    ///
+    /// 0 if all time is in a single category, log2(n) if it's spread evenly over n categories,
+    /// none if there is no time at all. Categories without time don't count.
+    pub fn activity_entropy(
+        args: Vec<DataType>,
+        _env: &VarEnv,
+        _ds: &Datastore,
+    ) -> Result<DataType, QueryError> {
+        // typecheck
+        validate::args_length(&args, 1)?;
+        let mut durations = Vec::new();
+        for duration in validate::get_dict(&args[0], "activity_entropy")?.values() {
+            let duration: f64 = duration.try_into()?;
+            durations.push(duration);
+        }
+
+        let total: f64 = durations.iter().filter(|d| **d > 0.0).sum();
+        if total <= 0.0 {
+            return Ok(DataType::None());
+        }
+        let entropy: f64 = durations
+            .iter()
+            .filter(|d| **d > 0.0)
+            .map(|duration| {
+                let p = duration / total;
+                -p * p.log2()
+            })
+            .sum();
+        // A single category gives -0.0
+        Ok(DataType::Number(entropy.abs()))
+    }
+
+    pub fn split_at(
+        args: Vec<DataType>,
+        _env: &VarEnv,
+        _ds: &Datastore,
+    ) -> Result<DataType, QueryError> {
+        // typecheck
+        validate::args_length(&args, 2).or_else(|_| validate::args_length(&args, 3))?;
+        let events: Vec<Event> = (&args[0]).try_into()?;
+        let boundary_strs: Vec<String> = (&args[1]).try_into()?;
+        let segment_key: Option<String> = match args.len() {
+            3 => Some((&args[2]).try_into()?),
+            _ => None,
+        };
+        let mut boundaries = Vec::new();
+        for boundary_str in boundary_strs {
+            match chrono::DateTime::parse_from_rfc3339(&boundary_str) {
+                Ok(boundary) => boundaries.push(boundary.with_timezone(&chrono::Utc)),
+                Err(_) => {
+                    return Err(QueryError::InvalidFunctionParameters(format!(
+                        "function split_at got an invalid timestamp '{boundary_str}'"
+                    )))
+                }
+            }
+        }
+
+        let mut split_events = aw_transform::split_at(events, &boundaries, segment_key.as_deref());
+        let mut split_tagged_events = Vec::new();
+        for event in split_events.drain(..) {
+            split_tagged_events.push(DataType::Event(event));
+        }
+        Ok(DataType::List(split_tagged_events))
+    }
+
+    pub fn sum(
+        args: Vec<DataType>,
+        _env: &VarEnv,
+        _ds: &Datastore,
+    ) -> Result<DataType, QueryError> {
+        // typecheck
+        validate::args_length(&args, 1)?;
+        let numbers: Vec<f64> = (&args[0]).try_into()?;
+
+        Ok(DataType::Number(numbers.iter().sum()))
+    }
+
+    pub fn max(
+        args: Vec<DataType>,
+        _env: &VarEnv,
+        _ds: &Datastore,
+    ) -> Result<DataType, QueryError> {
+        // typecheck
+        validate::args_length(&args, 1)?;
+        let numbers: Vec<f64> = (&args[0]).try_into()?;
+
+        match numbers.into_iter().reduce(f64::max) {
+            Some(n) => Ok(DataType::Number(n)),
+            None => Err(QueryError::InvalidFunctionParameters(
+                "function max got an empty list".to_string(),
+            )),
+        }
+    }
+
+    pub fn min(
+        args: Vec<DataType>,
+        _env: &VarEnv,
+        _ds: &Datastore,
+    ) -> Result<DataType, QueryError> {
+        // typecheck
+        validate::args_length(&args, 1)?;
+        let numbers: Vec<f64> = (&args[0]).try_into()?;
+
+        match numbers.into_iter().reduce(f64::min) {
+            Some(n) => Ok(DataType::Number(n)),
+            None => Err(QueryError::InvalidFunctionParameters(
+                "function min got an empty list".to_string(),
+            )),
+        }
+    }
+
+    pub fn len(
+        args: Vec<DataType>,
+        _env: &VarEnv,
+        _ds: &Datastore,
+    ) -> Result<DataType, QueryError> {
+        // typecheck
+        validate::args_length(&args, 1)?;
+        let len = match &args[0] {
+            DataType::List(list) => list.len(),
+            DataType::Dict(dict) => dict.len(),
+            DataType::String(s) => s.chars().count(),
+            _ => {
+                return Err(QueryError::InvalidFunctionParameters(format!(
+                    "function len got argument {:?}, expected type List, Dict or String",
+                    args[0]
+                )))
+            }
+        };
+        Ok(DataType::Number(len as f64))
+    }
+
+    pub fn keys(
+        args: Vec<DataType>,
+        _env: &VarEnv,
+        _ds: &Datastore,
+    ) -> Result<DataType, QueryError> {
+        // typecheck
+        validate::args_length(&args, 1)?;
+        let dict = validate::get_dict(&args[0], "keys")?;
+
+        // Sort to give a deterministic order, the dict itself is unordered
+        let mut keys: Vec<&String> = dict.keys().collect();
+        keys.sort();
+        Ok(DataType::List(
+            keys.into_iter()
+                .map(|k| DataType::String(k.to_string()))
+                .collect(),
+        ))
+    }
+
+    pub fn values(
+        args: Vec<DataType>,
+        _env: &VarEnv,
+        _ds: &Datastore,
+    ) -> Result<DataType, QueryError> {
+        // typecheck
+        validate::args_length(&args, 1)?;
+        let dict = validate::get_dict(&args[0], "values")?;
+
+        // Values are ordered by their key, to match the order of keys()
+        let mut entries: Vec<(&String, &DataType)> = dict.iter().collect();
+        entries.sort_by_key(|(k, _)| *k);
+        Ok(DataType::List(
+            entries.into_iter().map(|(_, v)| v.clone()).collect(),
+        ))
+    }
+}
+
+mod validate {
+    use std::collections::HashMap;
+
+    use crate::{DataType, QueryError, VarEnv};
+    use aw_models::TimeInterval;
+
+    pub fn get_dict<'a>(
+        arg: &'a DataType,
+        fname: &str,
+    ) -> Result<&'a HashMap<String, DataType>, QueryError> {
+        match arg {
+            DataType::Dict(dict) => Ok(dict),
+            _ => Err(QueryError::InvalidFunctionParameters(format!(
+                "function {fname} got argument {arg:?}, expected type Dict"
+            ))),
+        }
+    }
 
     pub fn args_length(args: &[DataType], len: usize) -> Result<(), QueryError> {
         if args.len() != len {
@@ -1,9 +1,11 @@
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
 
 use crate::functions;
 
 use aw_datastore::Datastore;
 use aw_models::TimeInterval;
+use serde_json::{Map, Value};
 
 use crate::ast::*;
 use crate::DataType;
@@ -11,9 +13,12 @@ use crate::QueryError;
 
 pub type VarEnv = HashMap<String, DataType>;
 
-fn init_env(ti: &TimeInterval) -> VarEnv {
+fn init_env(ti: &TimeInterval, params: &Map<String, Value>) -> VarEnv {
     let mut env = HashMap::new();
     env.insert("TIMEINTERVAL".to_string(), DataType::String(ti.to_string()));
+    for (name, value) in params {
+        env.insert(format!("${name}"), value.into());
+    }
     functions::fill_env(&mut env);
     env
 }
@@ -22,10 +27,12 @@ pub fn interpret_prog(
     p: Program,
     ti: &TimeInterval,
     ds: &Datastore,
+    params: &Map<String, Value>,
+    cancel: &AtomicBool,
 ) -> Result<DataType, QueryError> {
-    let mut env = init_env(ti);
+    let mut env = init_env(ti, params);
     for expr in p.stmts {
-        interpret_expr(&mut env, ds, expr)?;
+        interpret_expr(&mut env, ds, cancel, expr)?;
     }
     match env.remove("RETURN") {
         Some(ret) => Ok(ret),
@@ -36,13 +43,18 @@ pub fn interpret_prog(
 fn interpret_expr(
     env: &mut HashMap<String, DataType>,
     ds: &Datastore,
+    cancel: &AtomicBool,
     expr: Expr,
 ) -> Result<DataType, QueryError> {
     use crate::ast::Expr_::*;
+    // Cooperatively abort if the query has been cancelled
+    if cancel.load(Ordering::Relaxed) {
+        return Err(QueryError::Cancelled());
+    }
     match expr.node {
         Add(a, b) => {
-            let a_res = interpret_expr(env, ds, *a)?;
-            let b_res = interpret_expr(env, ds, *b)?;
+            let a_res = interpret_expr(env, ds, cancel, *a)?;
+            let b_res = interpret_expr(env, ds, cancel, *b)?;
             let res = match a_res {
                 DataType::Number(n1) => match b_res {
                     DataType::Number(n2) => DataType::Number(n1 + n2),
@@ -86,8 +98,8 @@ fn interpret_expr(
             Ok(res)
         }
         Sub(a, b) => {
-            let a_res = interpret_expr(env, ds, *a)?;
-            let b_res = interpret_expr(env, ds, *b)?;
+            let a_res = interpret_expr(env, ds, cancel, *a)?;
+            let b_res = interpret_expr(env, ds, cancel, *b)?;
             let a_num = match a_res {
                 DataType::Number(n) => n,
                 _ => {
@@ -107,8 +119,8 @@ fn interpret_expr(
             Ok(DataType::Number(a_num - b_num))
         }
         Mul(a, b) => {
-            let a_res = interpret_expr(env, ds, *a)?;
-            let b_res = interpret_expr(env, ds, *b)?;
+            let a_res = interpret_expr(env, ds, cancel, *a)?;
+            let b_res = interpret_expr(env, ds, cancel, *b)?;
             let a_num = match a_res {
                 DataType::Number(n) => n,
                 _ => {
@@ -128,8 +140,8 @@ fn interpret_expr(
             Ok(DataType::Number(a_num * b_num))
         }
         Div(a, b) => {
-            let a_res = interpret_expr(env, ds, *a)?;
-            let b_res = interpret_expr(env, ds, *b)?;
+            let a_res = interpret_expr(env, ds, cancel, *a)?;
+            let b_res = interpret_expr(env, ds, cancel, *b)?;
             let a_num = match a_res {
                 DataType::Number(n) => n,
                 _ => {
@@ -154,8 +166,8 @@ fn interpret_expr(
             Ok(DataType::Number(a_num / b_num))
         }
         Mod(a, b) => {
-            let a_res = interpret_expr(env, ds, *a)?;
-            let b_res = interpret_expr(env, ds, *b)?;
+            let a_res = interpret_expr(env, ds, cancel, *a)?;
+            let b_res = interpret_expr(env, ds, cancel, *b)?;
             let a_num = match a_res {
                 DataType::Number(n) => n,
                 _ => {
@@ -175,12 +187,12 @@ fn interpret_expr(
             Ok(DataType::Number(a_num % b_num))
         }
         Equal(lhs, rhs) => {
-            let lhs_res = interpret_expr(env, ds, *lhs)?;
-            let rhs_res = interpret_expr(env, ds, *rhs)?;
+            let lhs_res = interpret_expr(env, ds, cancel, *lhs)?;
+            let rhs_res = interpret_expr(env, ds, cancel, *rhs)?;
             Ok(DataType::Bool(lhs_res.query_eq(&rhs_res)?))
         }
         Assign(var, b) => {
-            let val = interpret_expr(env, ds, *b)?;
+            let val = interpret_expr(env, ds, cancel, *b)?;
             env.insert(var, val);
             Ok(DataType::None())
         }
@@ -193,17 +205,17 @@ fn interpret_expr(
         Number(lit) => Ok(DataType::Number(lit)),
         String(litstr) => Ok(DataType::String(litstr)),
         Return(e) => {
-            let val = interpret_expr(env, ds, *e)?;
+            let val = interpret_expr(env, ds, cancel, *e)?;
             // TODO: Once RETURN is deprecated we can fix this
             env.insert("RETURN".to_string(), val);
             Ok(DataType::None())
         }
         If(ifs) => {
             for (cond, block) in ifs {
-                let c = interpret_expr(env, ds, *cond)?;
+                let c = interpret_expr(env, ds, cancel, *cond)?;
                 if c.query_eq(&DataType::Bool(true))? {
                     for expr in block {
-                        interpret_expr(env, ds, expr)?;
+                        interpret_expr(env, ds, cancel, expr)?;
                     }
                     break;
                 }
@@ -211,7 +223,7 @@ fn interpret_expr(
             Ok(DataType::None())
         }
         Function(fname, e) => {
-            let args = match interpret_expr(env, ds, *e)? {
+            let args = match interpret_expr(env, ds, cancel, *e)? {
                 DataType::List(l) => l,
                 _ => unreachable!(),
             };
@@ -228,7 +240,7 @@ fn interpret_expr(
         List(list) => {
             let mut l = Vec::new();
             for entry in list {
-                let res = interpret_expr(env, ds, entry)?;
+                let res = interpret_expr(env, ds, cancel, entry)?;
                 l.push(res);
             }
             Ok(DataType::List(l))
@@ -236,7 +248,7 @@ fn interpret_expr(
         Dict(d) => {
             let mut dict = HashMap::new();
             for (key, val_uninterpreted) in d {
-                let val = interpret_expr(env, ds, val_uninterpreted)?;
+                let val = interpret_expr(env, ds, cancel, val_uninterpreted)?;
                 dict.insert(key.clone(), val);
             }
             Ok(DataType::Dict(dict))
@@ -41,6 +41,9 @@ lexer! {
     r#"\n"# => (Token::Newline, text),
     // Python-style comments (# ...)
     r#"#[^\n]*"# => (Token::Comment, text),
+    // C-style comments (// ... and /* ... */)
+    r#"//[^\n]*"# => (Token::Comment, text),
+    r#"/\*([^*]|\*+[^*/])*\*+/"# => (Token::Comment, text),
 
     r#"if"# => (Token::If, text),
     r#"elif"# => (Token::ElseIf, text),
@@ -57,6 +60,12 @@ lexer! {
         Token::String(text.to_owned()[1..text.len()-1].replace("\\\"", "\"")),
         text
     ),
+    // Triple-quoted strings can span several lines and are not unescaped, which keeps regexes
+    // readable
+    r#"\"\"\"([^\"]|\"[^\"]|\"\"[^\"])*\"\"\""# => (
+        Token::String(text[3..text.len()-3].to_string()),
+        text
+    ),
     r#"[0-9]+[\.]?[0-9]*"# => {
         let tok = match text.parse() {
             Ok(n) => Token::Number(n),
@@ -66,6 +75,8 @@ lexer! {
     }
 
     r#"[a-zA-Z_][a-zA-Z0-9_]*"# => (Token::Ident(text.to_owned()), text),
+    // Placeholders of query parameters, variables set before the query is run
+    r#"\$[a-zA-Z_][a-zA-Z0-9_]*"# => (Token::Ident(text.to_owned()), text),
 
     r#"=="# => (Token::Equals, text),
     r#"="# => (Token::Assign, text),
@@ -128,7 +139,12 @@ impl<'a> Iterator for Lexer<'a> {
                 return None;
             };
             match tok {
-                (Token::Whitespace, _) | (Token::Comment, _) => {
+                (Token::Whitespace, _) => {
+                    continue;
+                }
+                (Token::Comment, span) => {
+                    // Block comments can span several lines
+                    self.line += span.matches('\n').count();
                     continue;
                 }
                 (Token::Newline, _) => {
@@ -136,7 +152,10 @@ impl<'a> Iterator for Lexer<'a> {
                     continue;
                 }
                 (tok, span) => {
-                    return Some((tok, span_in(span, self.original, self.line)));
+                    let line = self.line;
+                    // Strings can span several lines
+                    self.line += span.matches('\n').count();
+                    return Some((tok, span_in(span, self.original, line)));
                 }
             }
         }
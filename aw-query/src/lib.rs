@@ -4,8 +4,10 @@ extern crate serde;
 extern crate serde_json;
 
 use std::fmt;
+use std::sync::atomic::AtomicBool;
 
 use aw_models::TimeInterval;
+use serde_json::{Map, Value};
 
 use aw_datastore::Datastore;
 
@@ -42,6 +44,7 @@ pub enum QueryError {
     TimeIntervalError(String),
     BucketQueryError(String),
     RegexCompileError(String),
+    Cancelled(),
 }
 
 impl fmt::Display for QueryError {
@@ -50,7 +53,43 @@ impl fmt::Display for QueryError {
     }
 }
 
+/// Names of the functions which can be called in queries, sorted
+pub fn function_names() -> Vec<String> {
+    let mut env = VarEnv::new();
+    functions::fill_env(&mut env);
+    let mut names: Vec<String> = env.into_keys().collect();
+    names.sort();
+    names
+}
+
 pub fn query(code: &str, ti: &TimeInterval, ds: &Datastore) -> Result<DataType, QueryError> {
+    query_cancellable(code, ti, ds, &AtomicBool::new(false))
+}
+
+/// Same as `query`, but aborts with `QueryError::Cancelled` once `cancel` is set
+pub fn query_cancellable(
+    code: &str,
+    ti: &TimeInterval,
+    ds: &Datastore,
+    cancel: &AtomicBool,
+) -> Result<DataType, QueryError> {
+    query_with_params(code, ti, ds, &Map::new(), cancel)
+}
+
+/// Same as `query_cancellable`, with `params` bound to placeholders in the query
+///
+/// A parameter is referred to in the query by its name prefixed with `$`, like a variable, so
+/// `{"categories": [...]}` is used as `categorize(events, $categories)`. Strings, numbers,
+/// booleans, null, lists and dicts are converted to the corresponding query values, and as the
+/// values never pass through the parser they can't change the query itself. Using a placeholder
+/// without a parameter of that name fails with `QueryError::VariableNotDefined`.
+pub fn query_with_params(
+    code: &str,
+    ti: &TimeInterval,
+    ds: &Datastore,
+    params: &Map<String, Value>,
+    cancel: &AtomicBool,
+) -> Result<DataType, QueryError> {
     let lexer = lexer::Lexer::new(code);
     let program = match parser::parse(lexer) {
         Ok(p) => p,
@@ -60,5 +99,5 @@ pub fn query(code: &str, ti: &TimeInterval, ds: &Datastore) -> Result<DataType,
             return Err(QueryError::ParsingError(format!("{e:?}")));
         }
     };
-    interpret::interpret_prog(program, ti, ds)
+    interpret::interpret_prog(program, ti, ds, params, cancel)
 }
@@ -23,7 +23,9 @@ mod query_tests {
 
     use chrono::Duration;
     use serde_json::json;
+    use std::collections::HashMap;
     use std::convert::TryFrom;
+    use std::sync::atomic::{AtomicBool, Ordering};
 
     use aw_query::DataType;
     use aw_query::QueryError;
@@ -362,6 +364,47 @@ mod query_tests {
             filtered_events = filter_keyvals_regex(events, "key", "regex");
             chunked_events = chunk_events_by_key(events, "key");
             merged_events = merge_events_by_keys(events, ["key"]);
+            merged_events = merge_consecutive(events, 5);
+            filtered_events = filter_duration(events, 1);
+            filtered_events = filter_duration(events, 1, 10);
+            overlapping_events = find_overlaps(events);
+            renamed_events = rename_keys(events, {{"key": "renamed"}});
+            redacted_events = redact(events, ["key"], "[redacted]");
+            redacted_events = redact_hash(events, ["key"]);
+            session = longest_session(events, 60, "key", "value");
+            ratios = active_ratio(events, "2000-01-01T00:00:00Z", "2000-01-02T00:00:00Z", "UTC");
+            gaps = time_between(events, "key", "value", "other");
+            flagged = flag_distractions(events, 60, "key");
+            bands = group_by_numeric(events, "key", [1, 2]);
+            gaps = find_gaps(events, "2000-01-01T00:00:00Z", "2000-01-02T00:00:00Z", 60);
+            tree = rollup_categories(events);
+            browsing = enrich_browser_events(events, events, ["Firefox"]);
+            switches = switch_rate(events, "key", 3600);
+            matching = time_matching(events, ["key"], "value", true);
+            wake_times = first_activity_times(events, "Europe/Stockholm");
+            trend = daily_category_trend(categorize(events, []), "UTC", "2000-01-01", "2000-01-07");
+            productivity = productivity_score(categorize(events, []), {{"Work": 1}}, "UTC");
+            switching = switching_cost(events, "key", 300);
+            flow = flow_time(events, 1500, 60, "key", "value");
+            smoothed = rolling_average({{"2000-01-01": 3600}}, 7);
+            timeline = dominant_per_slot(events, 900, "key", "1970-01-01T00:00:00Z", "1970-01-01T01:00:00Z");
+            ranked_events = rank_by_duration(events);
+            ranked_events = rank_by_key(events, "key");
+            joined_events = join_overlapping(events, events, "key");
+            bounds = activity_bounds(events, "key");
+            transitions = transition_counts(events, "key");
+            split = split_weekday_weekend(events, "UTC");
+            streak = daily_streak({{"2000-01-01": 3600}}, 1800);
+            histogram = duration_histogram(events, [60, 300]);
+            debounced = debounce_status(events, "key", 60);
+            closed = close_gaps(events, 1);
+            fractions = normalize_durations({{"Work": 3600, "Other": 1200}});
+            focus = concentration({{"Editor": 3600, "Browser": 1200}});
+            scatter = activity_entropy({{"Editor": 3600, "Browser": 1200}});
+            split_events = split_at(events, ["2000-01-01T00:00:00Z"], "segment");
+            intersected_periods = period_intersect(events, events);
+            both = overlap_duration(events, events);
+            budgets = budget(categorize(events, []), {{"Work": 3600}});
             return  merged_events;"#,
             "testid", "testid"
         );
@@ -506,6 +549,21 @@ mod query_tests {
             aw_query::DataType::String(s) => assert_eq!(s, "test \" with escaped quote"),
             _ => panic!("Wrong datatype"),
         }
+
+        // Triple-quoted strings can span lines and contain quotes, backslashes are kept as is
+        let code = String::from(
+            r#"return """^(Firefox|"Google Chrome")\s
+$""";"#,
+        );
+        let res = aw_query::query(&code, &interval, &ds).unwrap();
+        assert_eq!(
+            res,
+            DataType::String("^(Firefox|\"Google Chrome\")\\s\n$".to_string())
+        );
+
+        let code = String::from(r#"return """""" + "a";"#);
+        let res = aw_query::query(&code, &interval, &ds).unwrap();
+        assert_eq!(res, DataType::String("a".to_string()));
     }
 
     #[test]
@@ -539,6 +597,24 @@ mod query_tests {
 
         let code = String::from("return 1;# testing 123");
         aw_query::query(&code, &interval, &ds).unwrap();
+
+        let code = String::from(
+            r#"
+            # Python-style comment
+            a = 1; // C-style comment
+            // b = 2;
+            /* A comment
+               spanning several lines, with * and / in it */
+            b = a /* inline */ + 2;
+            return b / 3;"#,
+        );
+        let res = aw_query::query(&code, &interval, &ds).unwrap();
+        assert_eq!(res, DataType::Number(1.0));
+
+        // Only the first */ ends the comment
+        let code = String::from("/* a */ return 1; /* b */");
+        let res = aw_query::query(&code, &interval, &ds).unwrap();
+        assert_eq!(res, DataType::Number(1.0));
     }
 
     #[test]
@@ -604,6 +680,1286 @@ mod query_tests {
         assert_eq!(res, DataType::Bool(false));
     }
 
+    #[test]
+    fn test_by_hour_of_day() {
+        let ds = setup_datastore_populated();
+        let interval = TimeInterval::new_from_string(TIME_INTERVAL).unwrap();
+
+        let code =
+            String::from("return by_hour_of_day(query_bucket(\"testid\"), \"Europe/Stockholm\");");
+        let res = aw_query::query(&code, &interval, &ds).unwrap();
+        let hours: Vec<f64> = (&res).try_into().unwrap();
+        assert_eq!(hours, vec![0.0; 24]);
+
+        let code =
+            String::from("return by_hour_of_day(query_bucket(\"testid\"), \"Not/A_Timezone\");");
+        let res = aw_query::query(&code, &interval, &ds);
+        assert_err_type!(res, QueryError::InvalidFunctionParameters(_));
+    }
+
+    #[test]
+    fn test_split_weekday_weekend() {
+        let ds = setup_datastore_populated();
+        let interval = TimeInterval::new_from_string(TIME_INTERVAL).unwrap();
+
+        let code = String::from(
+            "return split_weekday_weekend(query_bucket(\"testid\"), \"Europe/Stockholm\");",
+        );
+        let res = aw_query::query(&code, &interval, &ds).unwrap();
+        let split = match res {
+            DataType::Dict(split) => split,
+            ref data => panic!("Wrong datatype, {data:?}"),
+        };
+        assert_eq!(split.len(), 2);
+        let weekday: Vec<Event> = (&split["weekday"]).try_into().unwrap();
+        let weekend: Vec<Event> = (&split["weekend"]).try_into().unwrap();
+        // Both events are from now, which is either a weekday or a weekend
+        assert_eq!(weekday.len() + weekend.len(), 2);
+        assert!(weekday.is_empty() || weekend.is_empty());
+
+        let code = String::from(
+            "return split_weekday_weekend(query_bucket(\"testid\"), \"Not/A_Timezone\");",
+        );
+        let res = aw_query::query(&code, &interval, &ds);
+        assert_err_type!(res, QueryError::InvalidFunctionParameters(_));
+    }
+
+    #[test]
+    fn test_daily_streak() {
+        let ds = setup_datastore_empty();
+        let interval = TimeInterval::new_from_string(TIME_INTERVAL).unwrap();
+
+        let code = String::from(
+            r#"return daily_streak({"2000-01-01": 4000, "2000-01-02": 3600, "2000-01-04": 100,
+                                  "2000-01-05": 5000}, 3600);"#,
+        );
+        let res = aw_query::query(&code, &interval, &ds).unwrap();
+        let mut expected = HashMap::new();
+        expected.insert("current".to_string(), DataType::Number(1.0));
+        expected.insert("longest".to_string(), DataType::Number(2.0));
+        assert_eq!(res, DataType::Dict(expected));
+
+        let code = String::from(r#"return daily_streak({"yesterday": 4000}, 3600);"#);
+        let res = aw_query::query(&code, &interval, &ds);
+        assert_err_type!(res, QueryError::InvalidFunctionParameters(_));
+    }
+
+    #[test]
+    fn test_rolling_average() {
+        let ds = setup_datastore_empty();
+        let interval = TimeInterval::new_from_string(TIME_INTERVAL).unwrap();
+
+        // 2000-01-03 is missing
+        let totals = r#"{"2000-01-01": 300, "2000-01-02": 600, "2000-01-04": 900}"#;
+        let code = format!("return rolling_average({totals}, 3);");
+        let res = aw_query::query(&code, &interval, &ds).unwrap();
+        assert_eq!(
+            serde_json::to_value(&res).unwrap(),
+            json!({
+                "2000-01-01": 300.0,
+                "2000-01-02": 450.0,
+                "2000-01-03": 300.0,
+                "2000-01-04": 500.0,
+            })
+        );
+
+        let code = format!(r#"return rolling_average({totals}, 3, "skip");"#);
+        let res = aw_query::query(&code, &interval, &ds).unwrap();
+        assert_eq!(
+            serde_json::to_value(&res).unwrap(),
+            json!({ "2000-01-01": 300.0, "2000-01-02": 450.0, "2000-01-04": 750.0 })
+        );
+
+        for code in [
+            format!("return rolling_average({totals}, 0);"),
+            format!("return rolling_average({totals}, 1.5);"),
+            format!(r#"return rolling_average({totals}, 7, "drop");"#),
+            String::from(r#"return rolling_average({"yesterday": 300}, 7);"#),
+        ] {
+            let res = aw_query::query(&code, &interval, &ds);
+            assert_err_type!(res, QueryError::InvalidFunctionParameters(_));
+        }
+    }
+
+    #[test]
+    fn test_active_ratio() {
+        let ds = setup_datastore_empty();
+        let interval = TimeInterval::new_from_string(TIME_INTERVAL).unwrap();
+
+        // Days without any AFK events have no ratio
+        let code = String::from(
+            r#"return active_ratio([], "2000-01-01T12:00:00Z", "2000-01-02T12:00:00Z", "UTC");"#,
+        );
+        let res = aw_query::query(&code, &interval, &ds).unwrap();
+        let mut expected = HashMap::new();
+        expected.insert("2000-01-01".to_string(), DataType::None());
+        expected.insert("2000-01-02".to_string(), DataType::None());
+        assert_eq!(res, DataType::Dict(expected));
+
+        let code =
+            String::from(r#"return active_ratio([], "yesterday", "2000-01-02T12:00:00Z", "UTC");"#);
+        let res = aw_query::query(&code, &interval, &ds);
+        assert_err_type!(res, QueryError::InvalidFunctionParameters(_));
+
+        let code = String::from(
+            r#"return active_ratio([], "2000-01-01T12:00:00Z", "2000-01-02T12:00:00Z", "Not/A_Timezone");"#,
+        );
+        let res = aw_query::query(&code, &interval, &ds);
+        assert_err_type!(res, QueryError::InvalidFunctionParameters(_));
+    }
+
+    #[test]
+    fn test_duration_histogram() {
+        let ds = setup_datastore_populated();
+        let interval = TimeInterval::new_from_string(TIME_INTERVAL).unwrap();
+
+        let code = String::from(
+            r#"events = query_bucket("testid");
+            return duration_histogram(events, [1, 10]);"#,
+        );
+        let res = aw_query::query(&code, &interval, &ds).unwrap();
+        let counts: Vec<f64> = Vec::try_from(&res).unwrap();
+        // Both events are zero length
+        assert_eq!(counts, vec![2.0, 0.0, 0.0]);
+
+        let code = String::from(
+            r#"events = query_bucket("testid");
+            return duration_histogram(events, [10, 1]);"#,
+        );
+        let res = aw_query::query(&code, &interval, &ds);
+        assert_err_type!(res, QueryError::InvalidFunctionParameters(_));
+    }
+
+    #[test]
+    fn test_normalize_durations() {
+        let ds = setup_datastore_empty();
+        let interval = TimeInterval::new_from_string(TIME_INTERVAL).unwrap();
+
+        let code = String::from(r#"return normalize_durations({"Work": 3600, "Other": 1200});"#);
+        let res = aw_query::query(&code, &interval, &ds).unwrap();
+        let mut expected = HashMap::new();
+        expected.insert("Work".to_string(), DataType::Number(0.75));
+        expected.insert("Other".to_string(), DataType::Number(0.25));
+        assert_eq!(res, DataType::Dict(expected));
+
+        let code = String::from(r#"return normalize_durations({"Work": 0, "Other": 0});"#);
+        let res = aw_query::query(&code, &interval, &ds).unwrap();
+        let mut expected = HashMap::new();
+        expected.insert("Work".to_string(), DataType::Number(0.0));
+        expected.insert("Other".to_string(), DataType::Number(0.0));
+        assert_eq!(res, DataType::Dict(expected));
+
+        let code = String::from("return normalize_durations({});");
+        let res = aw_query::query(&code, &interval, &ds).unwrap();
+        assert_eq!(res, DataType::Dict(HashMap::new()));
+
+        let code = String::from(r#"return normalize_durations({"Work": "a"});"#);
+        let res = aw_query::query(&code, &interval, &ds);
+        assert_err_type!(res, QueryError::InvalidFunctionParameters(_));
+    }
+
+    #[test]
+    fn test_concentration() {
+        let ds = setup_datastore_empty();
+        let interval = TimeInterval::new_from_string(TIME_INTERVAL).unwrap();
+
+        // All time in a single category
+        let code = String::from(r#"return concentration({"Editor": 3600});"#);
+        let res = aw_query::query(&code, &interval, &ds).unwrap();
+        assert_eq!(res, DataType::Number(1.0));
+
+        // Spread evenly over four categories
+        let code = String::from(
+            r#"return concentration({"Editor": 600, "Browser": 600, "Terminal": 600, "Chat": 600});"#,
+        );
+        let res = aw_query::query(&code, &interval, &ds).unwrap();
+        assert_eq!(res, DataType::Number(0.25));
+
+        let code = String::from(r#"return concentration({"Editor": 3000, "Browser": 1000});"#);
+        let res = aw_query::query(&code, &interval, &ds).unwrap();
+        assert_eq!(res, DataType::Number(0.625));
+
+        let code = String::from("return concentration({});");
+        let res = aw_query::query(&code, &interval, &ds).unwrap();
+        assert_eq!(res, DataType::Number(0.0));
+    }
+
+    #[test]
+    fn test_activity_entropy() {
+        let ds = setup_datastore_empty();
+        let interval = TimeInterval::new_from_string(TIME_INTERVAL).unwrap();
+
+        // All time in a single category
+        let code = String::from(r#"return activity_entropy({"Editor": 3600, "Chat": 0});"#);
+        let res = aw_query::query(&code, &interval, &ds).unwrap();
+        assert_eq!(res, DataType::Number(0.0));
+
+        // Spread evenly over four categories
+        let code = String::from(
+            r#"return activity_entropy({"Editor": 600, "Browser": 600, "Terminal": 600, "Chat": 600});"#,
+        );
+        let res = aw_query::query(&code, &interval, &ds).unwrap();
+        assert_eq!(res, DataType::Number(2.0));
+
+        let code = String::from(r#"return activity_entropy({"Editor": 3000, "Browser": 1000});"#);
+        let res = aw_query::query(&code, &interval, &ds).unwrap();
+        match res {
+            DataType::Number(entropy) => assert!((entropy - 0.811278).abs() < 1e-6),
+            other => panic!("expected a number, got {other:?}"),
+        }
+
+        let code = String::from("return activity_entropy({});");
+        let res = aw_query::query(&code, &interval, &ds).unwrap();
+        assert_eq!(res, DataType::None());
+    }
+
+    #[test]
+    fn test_sum() {
+        let ds = setup_datastore_empty();
+        let interval = TimeInterval::new_from_string(TIME_INTERVAL).unwrap();
+
+        let code = String::from("return sum([1, 2, 3.5]);");
+        let res = aw_query::query(&code, &interval, &ds).unwrap();
+        assert_eq!(res, DataType::Number(6.5));
+
+        let code = String::from("return sum([]);");
+        let res = aw_query::query(&code, &interval, &ds).unwrap();
+        assert_eq!(res, DataType::Number(0.0));
+
+        let code = String::from(r#"return sum([1, "a"]);"#);
+        let res = aw_query::query(&code, &interval, &ds);
+        assert_err_type!(res, QueryError::InvalidFunctionParameters(_));
+    }
+
+    #[test]
+    fn test_max_min() {
+        let ds = setup_datastore_empty();
+        let interval = TimeInterval::new_from_string(TIME_INTERVAL).unwrap();
+
+        let code = String::from("return max([1, 5, 3]);");
+        let res = aw_query::query(&code, &interval, &ds).unwrap();
+        assert_eq!(res, DataType::Number(5.0));
+
+        let code = String::from("return min([4, 2, 3]);");
+        let res = aw_query::query(&code, &interval, &ds).unwrap();
+        assert_eq!(res, DataType::Number(2.0));
+
+        let code = String::from("return max([]);");
+        let res = aw_query::query(&code, &interval, &ds);
+        assert_err_type!(res, QueryError::InvalidFunctionParameters(_));
+
+        let code = String::from("return min(1);");
+        let res = aw_query::query(&code, &interval, &ds);
+        assert_err_type!(res, QueryError::InvalidFunctionParameters(_));
+    }
+
+    #[test]
+    fn test_len() {
+        let ds = setup_datastore_empty();
+        let interval = TimeInterval::new_from_string(TIME_INTERVAL).unwrap();
+
+        let code = String::from("return len([1, 2, 3]);");
+        let res = aw_query::query(&code, &interval, &ds).unwrap();
+        assert_eq!(res, DataType::Number(3.0));
+
+        let code = String::from(r#"return len({"a": 1, "b": 2});"#);
+        let res = aw_query::query(&code, &interval, &ds).unwrap();
+        assert_eq!(res, DataType::Number(2.0));
+
+        let code = String::from(r#"return len("test");"#);
+        let res = aw_query::query(&code, &interval, &ds).unwrap();
+        assert_eq!(res, DataType::Number(4.0));
+
+        let code = String::from("return len(1);");
+        let res = aw_query::query(&code, &interval, &ds);
+        assert_err_type!(res, QueryError::InvalidFunctionParameters(_));
+    }
+
+    #[test]
+    fn test_keys_values() {
+        let ds = setup_datastore_empty();
+        let interval = TimeInterval::new_from_string(TIME_INTERVAL).unwrap();
+
+        let code = String::from(r#"return keys({"b": 2, "a": 1});"#);
+        let res = aw_query::query(&code, &interval, &ds).unwrap();
+        assert_eq!(
+            res,
+            DataType::List(vec![
+                DataType::String("a".to_string()),
+                DataType::String("b".to_string())
+            ])
+        );
+
+        let code = String::from(r#"return values({"b": 2, "a": 1});"#);
+        let res = aw_query::query(&code, &interval, &ds).unwrap();
+        assert_eq!(
+            res,
+            DataType::List(vec![DataType::Number(1.0), DataType::Number(2.0)])
+        );
+
+        // values can be combined with the numeric functions
+        let code = String::from(r#"return sum(values({"b": 2, "a": 1}));"#);
+        let res = aw_query::query(&code, &interval, &ds).unwrap();
+        assert_eq!(res, DataType::Number(3.0));
+
+        let code = String::from("return keys([1]);");
+        let res = aw_query::query(&code, &interval, &ds);
+        assert_err_type!(res, QueryError::InvalidFunctionParameters(_));
+
+        let code = String::from("return values(1);");
+        let res = aw_query::query(&code, &interval, &ds);
+        assert_err_type!(res, QueryError::InvalidFunctionParameters(_));
+    }
+
+    #[test]
+    fn test_budget() {
+        let ds = setup_datastore_with_bucket();
+        let event = |secs: i64, duration: i64, app: &str| Event {
+            id: None,
+            timestamp: chrono::DateTime::from_timestamp(secs, 0).unwrap(),
+            duration: Duration::seconds(duration),
+            data: json_map! {"app": json!(app)},
+        };
+        ds.insert_events(
+            BUCKET_ID,
+            &[
+                event(1_000_000_000, 3000, "Editor"),
+                event(1_000_005_000, 600, "Editor"),
+                event(1_000_010_000, 1800, "Browser"),
+            ],
+        )
+        .unwrap();
+        let interval = TimeInterval::new_from_string(TIME_INTERVAL).unwrap();
+
+        let code = String::from(
+            r#"
+            events = query_bucket("testid");
+            events = categorize(events, [[["Work", "Code"], { "type": "regex", "regex": "Editor" }]]);
+            return budget(events, { "Work > Code": 1800, "Learning": 600 });"#,
+        );
+        let res = aw_query::query(&code, &interval, &ds).unwrap();
+        assert_eq!(
+            serde_json::to_value(&res).unwrap(),
+            json!({
+                "Work > Code": { "actual": 3600.0, "target": 1800.0, "delta": 1800.0 },
+                "Uncategorized": { "actual": 1800.0, "target": null, "delta": null },
+                "Learning": { "actual": 0.0, "target": 600.0, "delta": -600.0 },
+            })
+        );
+
+        let code = String::from(r#"return budget([], { "Work": "a" });"#);
+        let res = aw_query::query(&code, &interval, &ds);
+        assert_err_type!(res, QueryError::InvalidFunctionParameters(_));
+    }
+
+    #[test]
+    fn test_debounce_status() {
+        let ds = setup_datastore_with_bucket();
+        let event = |secs: i64, duration: i64, status: &str| Event {
+            id: None,
+            timestamp: chrono::DateTime::from_timestamp(secs, 0).unwrap(),
+            duration: Duration::seconds(duration),
+            data: json_map! {"status": json!(status)},
+        };
+        ds.insert_events(
+            BUCKET_ID,
+            &[
+                event(1_000_000_000, 600, "afk"),
+                event(1_000_000_600, 5, "not-afk"),
+                event(1_000_000_605, 600, "afk"),
+            ],
+        )
+        .unwrap();
+        let interval = TimeInterval::new_from_string(TIME_INTERVAL).unwrap();
+
+        let code = String::from(
+            r#"
+            events = query_bucket("testid");
+            return debounce_status(events, "status", 60);"#,
+        );
+        let res = aw_query::query(&code, &interval, &ds).unwrap();
+        let events: Vec<Event> = Vec::try_from(&res).unwrap();
+        assert_eq!(events.len(), 2);
+        assert!(events.iter().all(|e| e.data["status"] == "afk"));
+        assert_eq!(
+            events.iter().map(|e| e.duration).sum::<Duration>(),
+            Duration::seconds(1205)
+        );
+    }
+
+    #[test]
+    fn test_close_gaps() {
+        let ds = setup_datastore_with_bucket();
+        let event = |secs: i64, duration: i64| Event {
+            id: None,
+            timestamp: chrono::DateTime::from_timestamp(secs, 0).unwrap(),
+            duration: Duration::seconds(duration),
+            data: json_map! {"app": json!("Editor")},
+        };
+        ds.insert_events(
+            BUCKET_ID,
+            &[
+                event(1_000_000_000, 9),
+                event(1_000_000_010, 10),
+                event(1_000_000_030, 10),
+            ],
+        )
+        .unwrap();
+        let interval = TimeInterval::new_from_string(TIME_INTERVAL).unwrap();
+
+        let code = String::from(
+            r#"
+            events = query_bucket("testid");
+            return close_gaps(events, 1);"#,
+        );
+        let res = aw_query::query(&code, &interval, &ds).unwrap();
+        let events: Vec<Event> = Vec::try_from(&res).unwrap();
+        let durations: Vec<i64> = events.iter().map(|e| e.duration.num_seconds()).collect();
+        // The 10 second gap is larger than the threshold and is left alone
+        assert_eq!(durations, vec![10, 10, 10]);
+    }
+
+    #[test]
+    fn test_split_at() {
+        let ds = setup_datastore_with_bucket();
+        let e = Event {
+            id: None,
+            timestamp: "2000-01-01T08:30:00Z".parse().unwrap(),
+            duration: Duration::hours(9),
+            data: json_map! {"app": json!("Editor")},
+        };
+        ds.insert_events(BUCKET_ID, &[e]).unwrap();
+        let interval =
+            TimeInterval::new_from_string("2000-01-01T00:00:00Z/2000-01-02T00:00:00Z").unwrap();
+
+        // An event crossing two shift boundaries
+        let code = String::from(
+            r#"
+            events = query_bucket("testid");
+            return split_at(events, ["2000-01-01T16:00:00Z", "2000-01-01T12:00:00Z"], "shift");"#,
+        );
+        let res = aw_query::query(&code, &interval, &ds).unwrap();
+        let events: Vec<Event> = Vec::try_from(&res).unwrap();
+        let parts: Vec<(String, i64, serde_json::Value)> = events
+            .iter()
+            .map(|e| {
+                (
+                    e.timestamp.to_rfc3339(),
+                    e.duration.num_minutes(),
+                    e.data["shift"].clone(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            parts,
+            vec![
+                ("2000-01-01T08:30:00+00:00".to_string(), 210, json!(0)),
+                ("2000-01-01T12:00:00+00:00".to_string(), 240, json!(1)),
+                ("2000-01-01T16:00:00+00:00".to_string(), 90, json!(2)),
+            ]
+        );
+        assert!(events.iter().all(|e| e.data["app"] == "Editor"));
+
+        let code = String::from(
+            r#"
+            events = query_bucket("testid");
+            return split_at(events, ["noon"]);"#,
+        );
+        let res = aw_query::query(&code, &interval, &ds);
+        assert_err_type!(res, QueryError::InvalidFunctionParameters(_));
+    }
+
+    #[test]
+    fn test_redact() {
+        let ds = setup_datastore_with_bucket();
+        let event = |secs: i64, title: &str| Event {
+            id: None,
+            timestamp: chrono::DateTime::from_timestamp(secs, 0).unwrap(),
+            duration: Duration::seconds(5),
+            data: json_map! {"app": json!("Firefox"), "title": json!(title)},
+        };
+        ds.insert_events(
+            BUCKET_ID,
+            &[
+                event(1_000_000_000, "Inbox"),
+                event(1_000_000_010, "Bank"),
+                event(1_000_000_020, "Inbox"),
+            ],
+        )
+        .unwrap();
+        let interval = TimeInterval::new_from_string(TIME_INTERVAL).unwrap();
+
+        let code = String::from(
+            r#"
+            events = sort_by_timestamp(query_bucket("testid"));
+            return [redact(events, ["title", "url"], "[redacted]"), redact_hash(events, ["title"])];"#,
+        );
+        let res = aw_query::query(&code, &interval, &ds).unwrap();
+        let lists: Vec<DataType> = Vec::try_from(&res).unwrap();
+        let replaced: Vec<Event> = Vec::try_from(&lists[0]).unwrap();
+        let hashed: Vec<Event> = Vec::try_from(&lists[1]).unwrap();
+        for events in [&replaced, &hashed] {
+            assert_eq!(events.len(), 3);
+            for e in events.iter() {
+                assert_eq!(e.data["app"], json!("Firefox"));
+                assert_eq!(e.duration, Duration::seconds(5));
+                assert!(!e.data.contains_key("url"));
+            }
+        }
+        assert!(replaced.iter().all(|e| e.data["title"] == "[redacted]"));
+        assert_eq!(hashed[0].data["title"], hashed[2].data["title"]);
+        assert_ne!(hashed[0].data["title"], hashed[1].data["title"]);
+        assert_ne!(hashed[0].data["title"], json!("Inbox"));
+    }
+
+    #[test]
+    fn test_longest_session() {
+        let ds = setup_datastore_with_bucket();
+        let event = |secs: i64, duration: i64, app: &str| Event {
+            id: None,
+            timestamp: chrono::DateTime::from_timestamp(secs, 0).unwrap(),
+            duration: Duration::seconds(duration),
+            data: json_map! {"app": json!(app)},
+        };
+        ds.insert_events(
+            BUCKET_ID,
+            &[
+                // Two sessions of 20s, the earlier one is returned
+                event(1_000_000_000, 10, "Editor"),
+                event(1_000_000_015, 5, "Editor"),
+                event(1_000_000_100, 20, "Editor"),
+                // Doesn't match the filter, so it doesn't bridge the gap
+                event(1_000_000_020, 80, "Browser"),
+            ],
+        )
+        .unwrap();
+        let interval = TimeInterval::new_from_string(TIME_INTERVAL).unwrap();
+
+        let code = String::from(
+            r#"
+            events = query_bucket("testid");
+            return longest_session(events, 5, "app", "Editor");"#,
+        );
+        let res = aw_query::query(&code, &interval, &ds).unwrap();
+        let session = serde_json::to_value(&res).unwrap();
+        assert_eq!(
+            session,
+            json!({
+                "start": "2001-09-09T01:46:40+00:00",
+                "end": "2001-09-09T01:47:00+00:00",
+                "duration": 20.0,
+            })
+        );
+
+        let code = String::from(
+            r#"
+            events = query_bucket("testid");
+            return longest_session(events, 5, "app", "Terminal");"#,
+        );
+        let res = aw_query::query(&code, &interval, &ds).unwrap();
+        assert_eq!(serde_json::to_value(&res).unwrap(), json!(null));
+    }
+
+    #[test]
+    fn test_time_between() {
+        let ds = setup_datastore_with_bucket();
+        let event = |secs: i64, app: &str| Event {
+            id: None,
+            timestamp: chrono::DateTime::from_timestamp(secs, 0).unwrap(),
+            duration: Duration::seconds(1),
+            data: json_map! {"app": json!(app)},
+        };
+        ds.insert_events(
+            BUCKET_ID,
+            &[
+                event(1_000_000_000, "Tickets"),
+                event(1_000_000_010, "Browser"),
+                event(1_000_000_020, "Tickets"),
+                event(1_000_000_030, "Editor"),
+                event(1_000_000_040, "Tickets"),
+                event(1_000_000_045, "Editor"),
+                // Never followed by the editor
+                event(1_000_000_050, "Tickets"),
+            ],
+        )
+        .unwrap();
+        let interval = TimeInterval::new_from_string(TIME_INTERVAL).unwrap();
+
+        let code = String::from(
+            r#"
+            events = query_bucket("testid");
+            return time_between(events, "app", "Tickets", "Editor");"#,
+        );
+        let res = aw_query::query(&code, &interval, &ds).unwrap();
+        assert_eq!(
+            serde_json::to_value(&res).unwrap(),
+            json!([30.0, 5.0, null])
+        );
+    }
+
+    #[test]
+    fn test_flag_distractions() {
+        let ds = setup_datastore_with_bucket();
+        let event = |secs: i64, duration_secs: i64, app: &str| Event {
+            id: None,
+            timestamp: chrono::DateTime::from_timestamp(secs, 0).unwrap(),
+            duration: Duration::seconds(duration_secs),
+            data: json_map! {"app": json!(app)},
+        };
+        ds.insert_events(
+            BUCKET_ID,
+            &[
+                event(1_000_000_000, 600, "Editor"),
+                event(1_000_000_600, 20, "Chat"),
+                event(1_000_000_620, 300, "Editor"),
+                // Leads into a longer event of the same app
+                event(1_000_000_920, 10, "Terminal"),
+                event(1_000_000_930, 200, "Terminal"),
+            ],
+        )
+        .unwrap();
+        let interval = TimeInterval::new_from_string(TIME_INTERVAL).unwrap();
+
+        let code = String::from(
+            r#"
+            events = query_bucket("testid");
+            events = flag_distractions(events, 60, "app");
+            return filter_keyvals(events, "$distraction", [true]);"#,
+        );
+        let res = aw_query::query(&code, &interval, &ds).unwrap();
+        let events: Vec<Event> = (&res).try_into().unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].data["app"], json!("Chat"));
+    }
+
+    #[test]
+    fn test_group_by_numeric() {
+        let ds = setup_datastore_with_bucket();
+        let event = |secs: i64, duration_secs: i64, cpu: serde_json::Value| Event {
+            id: None,
+            timestamp: chrono::DateTime::from_timestamp(secs, 0).unwrap(),
+            duration: Duration::seconds(duration_secs),
+            data: json_map! {"cpu": cpu},
+        };
+        ds.insert_events(
+            BUCKET_ID,
+            &[
+                event(1_000_000_000, 10, json!(5)),
+                event(1_000_000_010, 20, json!(10)),
+                event(1_000_000_030, 5, json!(42.5)),
+                event(1_000_000_035, 1, json!(99)),
+                event(1_000_000_036, 3, json!("high")),
+            ],
+        )
+        .unwrap();
+        let interval = TimeInterval::new_from_string(TIME_INTERVAL).unwrap();
+
+        let code = String::from(
+            r#"
+            events = query_bucket("testid");
+            return group_by_numeric(events, "cpu", [10, 50]);"#,
+        );
+        let res = aw_query::query(&code, &interval, &ds).unwrap();
+        assert_eq!(
+            serde_json::to_value(&res).unwrap(),
+            json!({
+                "bands": {"(-inf, 10)": 10.0, "[10, 50)": 25.0, "[50, inf)": 1.0},
+                "skipped": 1.0
+            })
+        );
+
+        let code = String::from(
+            r#"
+            events = query_bucket("testid");
+            return group_by_numeric(events, "cpu", [50, 10]);"#,
+        );
+        assert_err_type!(
+            aw_query::query(&code, &interval, &ds),
+            QueryError::InvalidFunctionParameters(_)
+        );
+    }
+
+    #[test]
+    fn test_find_gaps() {
+        let ds = setup_datastore_with_bucket();
+        let event = |secs: i64, duration_secs: i64| Event {
+            id: None,
+            timestamp: chrono::DateTime::from_timestamp(secs, 0).unwrap(),
+            duration: Duration::seconds(duration_secs),
+            data: json_map! {"status": json!("not-afk")},
+        };
+        ds.insert_events(
+            BUCKET_ID,
+            &[
+                event(1_000_000_100, 100),
+                // Adjacent to the event before
+                event(1_000_000_200, 100),
+                event(1_000_000_600, 1000),
+            ],
+        )
+        .unwrap();
+        let interval = TimeInterval::new_from_string(TIME_INTERVAL).unwrap();
+
+        let code = String::from(
+            r#"
+            events = query_bucket("testid");
+            return find_gaps(events, "2001-09-09T01:46:40Z", "2001-09-09T02:00:00Z", 60);"#,
+        );
+        let res = aw_query::query(&code, &interval, &ds).unwrap();
+        assert_eq!(
+            serde_json::to_value(&res).unwrap(),
+            json!([
+                {"start": "2001-09-09T01:46:40+00:00", "end": "2001-09-09T01:48:20+00:00", "duration": 100.0},
+                {"start": "2001-09-09T01:51:40+00:00", "end": "2001-09-09T01:56:40+00:00", "duration": 300.0},
+            ])
+        );
+    }
+
+    #[test]
+    fn test_rollup_categories() {
+        let ds = setup_datastore_with_bucket();
+        let event = |secs: i64, duration_secs: i64, title: &str| Event {
+            id: None,
+            timestamp: chrono::DateTime::from_timestamp(secs, 0).unwrap(),
+            duration: Duration::seconds(duration_secs),
+            data: json_map! {"title": json!(title)},
+        };
+        ds.insert_events(
+            BUCKET_ID,
+            &[
+                event(1_000_000_000, 10, "main.rs"),
+                event(1_000_000_010, 4, "main.py"),
+                event(1_000_000_020, 3, "standup"),
+                event(1_000_000_030, 1, "news"),
+            ],
+        )
+        .unwrap();
+        let interval = TimeInterval::new_from_string(TIME_INTERVAL).unwrap();
+
+        let code = String::from(
+            r#"
+            events = query_bucket("testid");
+            events = categorize(events, [
+                [["Work", "Programming", "Rust"], {"type": "regex", "regex": "rs$"}],
+                [["Work", "Programming", "Python"], {"type": "regex", "regex": "py$"}],
+                [["Work", "Meetings"], {"type": "regex", "regex": "standup"}]
+            ]);
+            return rollup_categories(events);"#,
+        );
+        let res = aw_query::query(&code, &interval, &ds).unwrap();
+        let leaf =
+            |secs: f64| json!({"self_duration": secs, "total_duration": secs, "children": {}});
+        assert_eq!(
+            serde_json::to_value(&res).unwrap(),
+            json!({
+                "Work": {
+                    "self_duration": 0.0,
+                    "total_duration": 17.0,
+                    "children": {
+                        "Programming": {
+                            "self_duration": 0.0,
+                            "total_duration": 14.0,
+                            "children": {"Rust": leaf(10.0), "Python": leaf(4.0)}
+                        },
+                        "Meetings": leaf(3.0)
+                    }
+                },
+                "Uncategorized": leaf(1.0)
+            })
+        );
+    }
+
+    #[test]
+    fn test_enrich_browser_events() {
+        let ds = setup_datastore_with_bucket();
+        ds.create_bucket(&Bucket {
+            bid: None,
+            id: "tabs".to_string(),
+            ..ds.get_bucket(BUCKET_ID).unwrap()
+        })
+        .unwrap();
+        let event = |secs: i64, duration_secs: i64, data| Event {
+            id: None,
+            timestamp: chrono::DateTime::from_timestamp(secs, 0).unwrap(),
+            duration: Duration::seconds(duration_secs),
+            data,
+        };
+        ds.insert_events(
+            BUCKET_ID,
+            &[
+                event(1_000_000_000, 10, json_map! {"app": json!("Firefox")}),
+                event(1_000_000_010, 10, json_map! {"app": json!("Editor")}),
+            ],
+        )
+        .unwrap();
+        ds.insert_events(
+            "tabs",
+            &[event(
+                1_000_000_000,
+                20,
+                json_map! {"url": json!("https://github.com/x"), "title": json!("x")},
+            )],
+        )
+        .unwrap();
+        let interval = TimeInterval::new_from_string(TIME_INTERVAL).unwrap();
+
+        let code = String::from(
+            r#"
+            events = query_bucket("testid");
+            tabs = query_bucket("tabs");
+            events = enrich_browser_events(events, tabs, ["Firefox"]);
+            return filter_keyvals(events, "$domain", ["github.com"]);"#,
+        );
+        let res = aw_query::query(&code, &interval, &ds).unwrap();
+        let events: Vec<Event> = (&res).try_into().unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].data["app"], json!("Firefox"));
+        assert_eq!(events[0].data["title"], json!("x"));
+        assert_eq!(events[0].duration, Duration::seconds(10));
+    }
+
+    #[test]
+    fn test_dominant_per_slot() {
+        let ds = setup_datastore_with_bucket();
+        let event = |secs: i64, duration_secs: i64, app: &str| Event {
+            id: None,
+            timestamp: chrono::DateTime::from_timestamp(secs, 0).unwrap(),
+            duration: Duration::seconds(duration_secs),
+            data: json_map! {"app": json!(app)},
+        };
+        ds.insert_events(
+            BUCKET_ID,
+            &[
+                // The first slot is split evenly
+                event(1_000_000_000, 450, "Firefox"),
+                event(1_000_000_450, 450, "Editor"),
+                event(1_000_000_900, 600, "Editor"),
+                event(1_000_001_500, 300, "Firefox"),
+            ],
+        )
+        .unwrap();
+        let interval = TimeInterval::new_from_string(TIME_INTERVAL).unwrap();
+
+        let code = String::from(
+            r#"
+            events = query_bucket("testid");
+            return dominant_per_slot(events, 900, "app", "2001-09-09T01:46:40Z", "2001-09-09T02:31:40Z");"#,
+        );
+        let res = aw_query::query(&code, &interval, &ds).unwrap();
+        assert_eq!(
+            serde_json::to_value(&res).unwrap(),
+            json!([
+                {"start": "2001-09-09T01:46:40+00:00", "end": "2001-09-09T02:01:40+00:00", "value": "Firefox", "duration": 450.0},
+                {"start": "2001-09-09T02:01:40+00:00", "end": "2001-09-09T02:16:40+00:00", "value": "Editor", "duration": 600.0},
+                {"start": "2001-09-09T02:16:40+00:00", "end": "2001-09-09T02:31:40+00:00", "value": null, "duration": 0.0},
+            ])
+        );
+    }
+
+    #[test]
+    fn test_switch_rate() {
+        let ds = setup_datastore_with_bucket();
+        let event = |secs: i64, duration_secs: i64, app: &str| Event {
+            id: None,
+            timestamp: chrono::DateTime::from_timestamp(secs, 0).unwrap(),
+            duration: Duration::seconds(duration_secs),
+            data: json_map! {"app": json!(app)},
+        };
+        ds.insert_events(
+            BUCKET_ID,
+            &[
+                // 1_000_000_200 is 01:50
+                event(1_000_000_000, 100, "Editor"),
+                event(1_000_000_100, 150, "Firefox"),
+                // Counted in the slot of the event it switches to
+                event(1_000_000_250, 550, "Editor"),
+                event(1_000_000_810, 100, "Editor"),
+            ],
+        )
+        .unwrap();
+        let interval = TimeInterval::new_from_string(TIME_INTERVAL).unwrap();
+
+        let code = String::from(
+            r#"
+            events = query_bucket("testid");
+            return switch_rate(events, "app", 600);"#,
+        );
+        let res = aw_query::query(&code, &interval, &ds).unwrap();
+        assert_eq!(
+            serde_json::to_value(&res).unwrap(),
+            json!({
+                "2001-09-09T01:40:00+00:00": 1.0,
+                "2001-09-09T01:50:00+00:00": 1.0,
+                "2001-09-09T02:00:00+00:00": 0.0,
+            })
+        );
+    }
+
+    #[test]
+    fn test_time_matching() {
+        let ds = setup_datastore_with_bucket();
+        let event = |secs: i64, duration_secs: i64, app: &str, title: &str| Event {
+            id: None,
+            timestamp: chrono::DateTime::from_timestamp(secs, 0).unwrap(),
+            duration: Duration::seconds(duration_secs),
+            data: json_map! {"app": json!(app), "title": json!(title)},
+        };
+        ds.insert_events(
+            BUCKET_ID,
+            &[
+                event(1_000_000_000, 10, "Mail", "Invoice for March"),
+                event(1_000_000_010, 5, "INVOICER", "Overview"),
+                event(1_000_000_020, 3, "Browser", "News"),
+            ],
+        )
+        .unwrap();
+        let interval = TimeInterval::new_from_string(TIME_INTERVAL).unwrap();
+
+        let code = String::from(
+            r#"
+            events = query_bucket("testid");
+            RETURN = {
+                "ignore_case": time_matching(events, ["app", "title"], "invoice", true),
+                "match_case": time_matching(events, ["app", "title"], "invoice", false),
+                "missing_keys": time_matching(events, ["url"], "", true)
+            };"#,
+        );
+        let res = aw_query::query(&code, &interval, &ds).unwrap();
+        assert_eq!(
+            serde_json::to_value(&res).unwrap(),
+            json!({"ignore_case": 15.0, "match_case": 0.0, "missing_keys": 0.0})
+        );
+    }
+
+    #[test]
+    fn test_first_activity_times() {
+        let ds = setup_datastore_with_bucket();
+        // Every other day of April 2001, starting between 05:00 and 09:40 UTC, with one more
+        // event later in the day
+        let mut events = Vec::new();
+        for day in (1..=30).step_by(2) {
+            let start = chrono::NaiveDate::from_ymd_opt(2001, 4, day)
+                .unwrap()
+                .and_hms_opt(5, 0, 0)
+                .unwrap()
+                .and_utc()
+                + Duration::minutes(((day * 37) % 280) as i64);
+            for offset in [Duration::hours(3), Duration::zero()] {
+                events.push(Event {
+                    id: None,
+                    timestamp: start + offset,
+                    duration: Duration::minutes(30),
+                    data: json_map! {"status": json!("not-afk")},
+                });
+            }
+        }
+        ds.insert_events(BUCKET_ID, &events).unwrap();
+        let interval = TimeInterval::new_from_string(TIME_INTERVAL).unwrap();
+
+        let code = String::from(
+            r#"
+            events = query_bucket("testid");
+            RETURN = first_activity_times(events, "Europe/Stockholm");"#,
+        );
+        let res = aw_query::query(&code, &interval, &ds).unwrap();
+        let res = serde_json::to_value(&res).unwrap();
+        let days = res["days"].as_object().unwrap();
+        // Days without activity are left out
+        assert_eq!(days.len(), 15);
+        assert!(days.get("2001-04-02").is_none());
+        // In local summer time, two hours ahead of UTC
+        assert_eq!(days["2001-04-01"], json!("07:37:00"));
+        assert_eq!(days["2001-04-03"], json!("08:51:00"));
+        assert_eq!(days["2001-04-09"], json!("07:53:00"));
+        assert_eq!(res["mean"], json!("09:24:20"));
+        assert_eq!(res["median"], json!("09:23:00"));
+
+        let code = String::from(r#"RETURN = first_activity_times([], "UTC");"#);
+        let res = aw_query::query(&code, &interval, &ds).unwrap();
+        assert_eq!(
+            serde_json::to_value(&res).unwrap(),
+            json!({"days": {}, "mean": null, "median": null})
+        );
+    }
+
+    #[test]
+    fn test_daily_category_trend() {
+        let ds = setup_datastore_with_bucket();
+        let event = |timestamp: &str, mins: i64, app: &str| Event {
+            id: None,
+            timestamp: chrono::DateTime::parse_from_rfc3339(timestamp)
+                .unwrap()
+                .to_utc(),
+            duration: Duration::minutes(mins),
+            data: json_map! {"app": json!(app)},
+        };
+        ds.insert_events(
+            BUCKET_ID,
+            &[
+                event("2001-04-01T09:00:00Z", 60, "Editor"),
+                event("2001-04-01T11:00:00Z", 30, "Browser"),
+                // Nothing on 04-02, Work has nothing on 04-03
+                event("2001-04-03T10:00:00Z", 15, "Browser"),
+                event("2001-04-04T09:00:00Z", 120, "Editor"),
+            ],
+        )
+        .unwrap();
+        let interval = TimeInterval::new_from_string(TIME_INTERVAL).unwrap();
+
+        let code = String::from(
+            r#"
+            events = query_bucket("testid");
+            events = categorize(events, [[["Work"], { "type": "regex", "regex": "Editor" }]]);
+            return daily_category_trend(events, "UTC", "2001-04-01", "2001-04-05");"#,
+        );
+        let res = aw_query::query(&code, &interval, &ds).unwrap();
+        assert_eq!(
+            serde_json::to_value(&res).unwrap(),
+            json!({
+                "Work": [3600.0, 0.0, 0.0, 7200.0, 0.0],
+                "Uncategorized": [1800.0, 0.0, 900.0, 0.0, 0.0],
+            })
+        );
+
+        let code =
+            String::from(r#"return daily_category_trend([], "UTC", "2001-04-01", "tomorrow");"#);
+        let res = aw_query::query(&code, &interval, &ds);
+        assert_err_type!(res, QueryError::InvalidFunctionParameters(_));
+    }
+
+    #[test]
+    fn test_productivity_score() {
+        let ds = setup_datastore_with_bucket();
+        let event = |timestamp: &str, mins: i64, app: &str| Event {
+            id: None,
+            timestamp: chrono::DateTime::parse_from_rfc3339(timestamp)
+                .unwrap()
+                .to_utc(),
+            duration: Duration::minutes(mins),
+            data: json_map! {"app": json!(app)},
+        };
+        ds.insert_events(
+            BUCKET_ID,
+            &[
+                // Productive and unproductive time on the same day
+                event("2001-04-01T09:00:00Z", 90, "Editor"),
+                event("2001-04-01T11:00:00Z", 30, "Game"),
+                event("2001-04-02T09:00:00Z", 60, "Mail"),
+            ],
+        )
+        .unwrap();
+        let interval = TimeInterval::new_from_string(TIME_INTERVAL).unwrap();
+
+        // There are no negative literals, -1 is written as 0 - 1
+        let code = String::from(
+            r#"
+            events = query_bucket("testid");
+            events = categorize(events, [
+                [["Work", "Code"], { "type": "regex", "regex": "Editor" }],
+                [["Fun"], { "type": "regex", "regex": "Game" }]
+            ]);
+            return productivity_score(events, { "Work": 1, "Fun": 0 - 1 }, "UTC", 0.5);"#,
+        );
+        let res = aw_query::query(&code, &interval, &ds).unwrap();
+        assert_eq!(
+            serde_json::to_value(&res).unwrap(),
+            json!({ "2001-04-01": 75.0, "2001-04-02": 75.0 })
+        );
+
+        let code = String::from(r#"return productivity_score([], { "Work": 2 }, "UTC");"#);
+        let res = aw_query::query(&code, &interval, &ds);
+        assert_err_type!(res, QueryError::InvalidFunctionParameters(_));
+    }
+
+    #[test]
+    fn test_switching_cost() {
+        let ds = setup_datastore_with_bucket();
+        let event = |secs: i64, app: &str| Event {
+            id: None,
+            timestamp: chrono::DateTime::from_timestamp(990_000_000 + secs, 0).unwrap(),
+            duration: Duration::seconds(1),
+            data: json_map! {"app": json!(app)},
+        };
+        ds.insert_events(
+            BUCKET_ID,
+            &[
+                event(0, "Editor"),
+                event(1, "Browser"),
+                event(2, "Browser"),
+                event(3, "Editor"),
+            ],
+        )
+        .unwrap();
+        let interval = TimeInterval::new_from_string(TIME_INTERVAL).unwrap();
+
+        let code = String::from(
+            r#"
+            events = query_bucket("testid");
+            return switching_cost(events, "app", 90);"#,
+        );
+        let res = aw_query::query(&code, &interval, &ds).unwrap();
+        assert_eq!(
+            serde_json::to_value(&res).unwrap(),
+            json!({ "switches": 2.0, "lost_time": 180.0 })
+        );
+
+        let code = String::from(r#"return switching_cost([], "app", 0 - 1);"#);
+        let res = aw_query::query(&code, &interval, &ds);
+        assert_err_type!(res, QueryError::InvalidFunctionParameters(_));
+    }
+
+    #[test]
+    fn test_query_bucket_daily() {
+        let ds = setup_datastore_with_bucket();
+        let event = |timestamp: &str, mins: i64, app: &str| Event {
+            id: None,
+            timestamp: chrono::DateTime::parse_from_rfc3339(timestamp)
+                .unwrap()
+                .to_utc(),
+            duration: Duration::minutes(mins),
+            data: json_map! {"app": json!(app)},
+        };
+        ds.insert_events(
+            BUCKET_ID,
+            &[
+                event("2001-04-01T09:00:00Z", 30, "Editor"),
+                event("2001-04-01T10:00:00Z", 60, "Editor"),
+                event("2001-04-01T23:30:00Z", 60, "Game"),
+            ],
+        )
+        .unwrap();
+        let interval = TimeInterval::new_from_string(TIME_INTERVAL).unwrap();
+        let code = String::from(
+            r#"
+            events = query_bucket_daily("testid");
+            return merge_events_by_keys(events, ["app"]);"#,
+        );
+
+        let res = aw_query::query(&code, &interval, &ds);
+        assert_err_type!(res, QueryError::BucketQueryError(_));
+
+        ds.set_daily_aggregates(Some(aw_datastore::DailyAggregates {
+            key: "app".to_string(),
+            timezone: Some(chrono_tz::UTC),
+        }))
+        .unwrap();
+        let res = aw_query::query(&code, &interval, &ds).unwrap();
+        let events: Vec<Event> = (&res).try_into().unwrap();
+        let mut totals: Vec<(String, i64)> = events
+            .iter()
+            .map(|e| {
+                (
+                    e.data["app"].as_str().unwrap().to_string(),
+                    e.duration.num_minutes(),
+                )
+            })
+            .collect();
+        totals.sort();
+        assert_eq!(totals, vec![("Editor".into(), 90), ("Game".into(), 60)]);
+
+        // A row per day and data, the Game event is split at midnight
+        let code = String::from(r#"return query_bucket_daily("testid");"#);
+        let res = aw_query::query(&code, &interval, &ds).unwrap();
+        let events: Vec<Event> = (&res).try_into().unwrap();
+        assert_eq!(events.len(), 3);
+        assert_eq!(
+            events[2].timestamp.to_rfc3339(),
+            "2001-04-02T00:00:00+00:00"
+        );
+        assert_eq!(events[2].duration, Duration::minutes(30));
+    }
+
+    #[test]
+    fn test_flow_time() {
+        let ds = setup_datastore_with_bucket();
+        let event = |secs: i64, duration: i64, app: &str| Event {
+            id: None,
+            timestamp: chrono::DateTime::from_timestamp(1_000_000_000 + secs, 0).unwrap(),
+            duration: Duration::seconds(duration),
+            data: json_map! {"app": json!(app)},
+        };
+        ds.insert_events(
+            BUCKET_ID,
+            &[
+                // A session of 30 minutes with a gap of a minute
+                event(0, 900, "Editor"),
+                event(960, 840, "Editor"),
+                // Too short
+                event(3600, 600, "Editor"),
+                // Doesn't match the filter
+                event(7200, 3600, "Browser"),
+            ],
+        )
+        .unwrap();
+        let interval = TimeInterval::new_from_string(TIME_INTERVAL).unwrap();
+
+        let code = String::from(
+            r#"
+            events = query_bucket("testid");
+            return flow_time(events, 1200, 120, "app", "Editor");"#,
+        );
+        let res = aw_query::query(&code, &interval, &ds).unwrap();
+        assert_eq!(
+            serde_json::to_value(&res).unwrap(),
+            json!({ "sessions": 1.0, "duration": 1800.0 })
+        );
+    }
+
+    #[test]
+    fn test_query_params() {
+        let ds = setup_datastore_populated();
+        let interval = TimeInterval::new_from_string(TIME_INTERVAL).unwrap();
+        let cancel = AtomicBool::new(false);
+        let params = json_map! {
+            "bucket": json!("testid"),
+            "min_duration": json!(1),
+            "values": json!(["value", "other"]),
+            "weights": json!({"Work": 2}),
+            "flag": json!(true),
+            "nothing": json!(null),
+            // Is only ever a string, not part of the query
+            "injected": json!("\"); return 1; //")
+        };
+
+        // Strings, numbers and lists
+        let code = String::from(
+            r#"events = query_bucket($bucket);
+            return [filter_keyvals(events, "key", $values), $min_duration * 2];"#,
+        );
+        let res = aw_query::query_with_params(&code, &interval, &ds, &params, &cancel).unwrap();
+        let res: Vec<DataType> = (&res).try_into().unwrap();
+        let events: Vec<Event> = (&res[0]).try_into().unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(res[1], DataType::Number(2.0));
+
+        // Dicts, booleans and null
+        let code = String::from(r#"return [$weights, $flag, $nothing, $injected];"#);
+        let res = aw_query::query_with_params(&code, &interval, &ds, &params, &cancel).unwrap();
+        assert_eq!(
+            serde_json::to_value(&res).unwrap(),
+            json!([{"Work": 2.0}, true, null, "\"); return 1; //"])
+        );
+
+        // Without parameters placeholders are undefined
+        let res = aw_query::query(&code, &interval, &ds);
+        assert_err_type!(res, QueryError::VariableNotDefined(_));
+    }
+
+    #[test]
+    fn test_cancelled() {
+        let ds = setup_datastore_empty();
+        let interval = TimeInterval::new_from_string(TIME_INTERVAL).unwrap();
+
+        let code = String::from("a=1; return a;");
+        let cancel = AtomicBool::new(false);
+        let res = aw_query::query_cancellable(&code, &interval, &ds, &cancel).unwrap();
+        assert_eq!(res, DataType::Number(1.0));
+
+        cancel.store(true, Ordering::Relaxed);
+        let res = aw_query::query_cancellable(&code, &interval, &ds, &cancel);
+        assert_err_type!(res, QueryError::Cancelled());
+    }
+
     #[test]
     fn test_math() {
         let ds = setup_datastore_empty();
@@ -36,7 +36,7 @@ pub mod android {
     use super::*;
 
     use std::path::PathBuf;
-    use std::sync::Mutex;
+    use std::sync::{Arc, Mutex};
 
     use crate::config::AWConfig;
     use crate::endpoints;
@@ -115,7 +115,7 @@ pub mod android {
         // FIXME: Why is unsafe needed here? Can we get rid of it?
         unsafe {
             let server_state: ServerState = endpoints::ServerState {
-                datastore: Mutex::new(openDatastore()),
+                datastore: Arc::new(Mutex::new(openDatastore())),
                 asset_resolver: endpoints::AssetResolver::new(None),
                 device_id: device_id::get_device_id(),
             };
@@ -1,5 +1,6 @@
 use std::fs::File;
 use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
 
 use rocket::config::Config;
 use rocket::data::{Limits, ToByteUnit};
@@ -19,6 +20,13 @@ pub fn is_testing() -> bool {
     unsafe { TESTING }
 }
 
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum EventIdStrategy {
+    Integer,
+    Uuid,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct AWConfig {
     #[serde(default = "default_address")]
@@ -30,9 +38,107 @@ pub struct AWConfig {
     #[serde(skip, default = "default_testing")]
     pub testing: bool, // This is not written to the config file (serde(skip))
 
+    // The file the config was read from, which is read again when the config is reloaded.
+    // This is not written to the config file (serde(skip))
+    #[serde(skip)]
+    pub config_path: Option<PathBuf>,
+
     #[serde(default = "default_cors")]
     pub cors: Vec<String>,
 
+    // Token which requests to the /api/0/admin endpoints have to send in an
+    // "Authorization: Bearer <token>" header. The admin endpoints are refused with 403 Forbidden
+    // while no token is set.
+    #[serde(default = "default_admin_token")]
+    pub admin_token: Option<String>,
+
+    // Compress large event data in the database to save disk space
+    #[serde(default = "default_compress_event_data")]
+    pub compress_event_data: bool,
+
+    // How long database operations wait for locks held by other processes using the database
+    // before failing, in milliseconds
+    #[serde(default = "default_db_busy_timeout_ms")]
+    pub db_busy_timeout_ms: u64,
+
+    // Minimum time in milliseconds the database transaction stays open before inserted events
+    // commit it, so that bursts of inserts are written to disk together. Unset commits as soon
+    // as 100 events are inserted, see Datastore::set_insert_coalescing
+    #[serde(default = "default_db_insert_coalescing_ms")]
+    pub db_insert_coalescing_ms: Option<u64>,
+
+    // Path of a second SQLite database which every write is mirrored to, as a warm backup.
+    // Writes are copied in the background after they are committed to the main database, so
+    // the mirror may lag behind and failures to write to it are only logged. It should start
+    // out as a copy of the main database, or empty for a new one, for event ids to match.
+    #[serde(default = "default_db_mirror_path")]
+    pub db_mirror_path: Option<String>,
+
+    // Keep a table of the time per bucket, day and category of the events up to date, which the
+    // query function query_bucket_daily reads instead of scanning all events of long ranges.
+    // Days are those of `timezone`. Enabling or changing the key or timezone builds the table
+    // from all events, which takes a while on large databases, and disabling drops it.
+    // See Datastore::set_daily_aggregates
+    #[serde(default = "default_db_daily_aggregates")]
+    pub db_daily_aggregates: bool,
+
+    // Key of the event data whose value is the category of the daily aggregates
+    #[serde(default = "default_db_daily_aggregates_key")]
+    pub db_daily_aggregates_key: String,
+
+    // How ids of new events are generated, "integer" for autoincrementing ids or "uuid" for
+    // random UUIDs which won't collide when events are merged between databases. Events which
+    // already exist keep their integer ids, so switching strategy on an existing database
+    // leaves it with a mix of both kinds of ids.
+    #[serde(default = "default_event_id_strategy")]
+    pub event_id_strategy: EventIdStrategy,
+
+    // Length of a query timeperiod which is missing its start or end
+    #[serde(default = "default_query_default_timeperiod_days")]
+    pub query_default_timeperiod_days: u32,
+
+    // Longest allowed timeperiod of a query, each timeperiod is checked on its own so a query
+    // with many timeperiods can still cover a longer time in total. Unlimited if not set.
+    #[serde(default = "default_query_max_timeperiod_days")]
+    pub query_max_timeperiod_days: Option<u32>,
+
+    // Timezone used to resolve timeperiod shortcuts such as "today" in queries, as an IANA name
+    // such as "Europe/Stockholm". The timezone of the system is used if not set.
+    #[serde(default = "default_timezone")]
+    pub timezone: Option<String>,
+
+    // How long handling a request may take before it is aborted with 503 Service Unavailable,
+    // in seconds, 0 disables the timeout. Defaults to 60 seconds for the API in general and to
+    // 600 seconds for the query, import and export routes where requests are expected to be slow.
+    // Only the routes listed in endpoints::timeout can be aborted, the others run until they
+    // finish.
+    #[serde(default = "default_request_timeout_secs")]
+    pub request_timeout_secs: u64,
+
+    #[serde(default = "default_long_request_timeout_secs")]
+    pub long_request_timeout_secs: u64,
+
+    // How long idle HTTP connections are kept open for the next request, in seconds, 0 closes
+    // every connection after its response. Clients sending frequent requests such as watchers
+    // can reuse their connection if this is longer than they pool idle connections, which is
+    // 90 seconds for aw-client-rust.
+    #[serde(default = "default_keep_alive_secs")]
+    pub keep_alive_secs: u32,
+
+    // Directory of a custom build of the web UI to serve instead of the bundled one. Paths which
+    // are not a file in the directory and have no file extension are answered with its
+    // index.html, so that the client-side routes of a single-page app can be loaded directly.
+    #[serde(default = "default_webui_path")]
+    pub webui_path: Option<String>,
+
+    // Log level in the same form as RUST_LOG, a level for everything and/or comma-separated
+    // module=level filters, for example "info,aw_datastore=debug,aw_query=trace". The modules of
+    // the server are aw_server, aw_datastore, aw_query, aw_transform and aw_models, the web
+    // framework logs as rocket and rocket_cors. The RUST_LOG environment variable overrides this
+    // value when set.
+    #[serde(default = "default_log_level")]
+    pub log_level: Option<String>,
+
     // A mapping of watcher names to paths where the
     // custom visualizations are located.
     #[serde(default = "default_custom_static")]
@@ -45,7 +151,24 @@ impl Default for AWConfig {
             address: default_address(),
             port: default_port(),
             testing: default_testing(),
+            config_path: None,
             cors: default_cors(),
+            admin_token: default_admin_token(),
+            compress_event_data: default_compress_event_data(),
+            db_busy_timeout_ms: default_db_busy_timeout_ms(),
+            db_insert_coalescing_ms: default_db_insert_coalescing_ms(),
+            db_mirror_path: default_db_mirror_path(),
+            db_daily_aggregates: default_db_daily_aggregates(),
+            db_daily_aggregates_key: default_db_daily_aggregates_key(),
+            event_id_strategy: default_event_id_strategy(),
+            query_default_timeperiod_days: default_query_default_timeperiod_days(),
+            query_max_timeperiod_days: default_query_max_timeperiod_days(),
+            timezone: default_timezone(),
+            request_timeout_secs: default_request_timeout_secs(),
+            long_request_timeout_secs: default_long_request_timeout_secs(),
+            keep_alive_secs: default_keep_alive_secs(),
+            webui_path: default_webui_path(),
+            log_level: default_log_level(),
             custom_static: default_custom_static(),
         }
     }
@@ -64,15 +187,35 @@ impl AWConfig {
         // Needed for bucket imports
         let limits = Limits::default()
             .limit("json", 1000u64.megabytes())
-            .limit("data-form", 1000u64.megabytes());
+            .limit("data-form", 1000u64.megabytes())
+            // Streamed imports are never kept in memory as a whole
+            .limit("ndjson", 100u64.gibibytes());
 
         config.address = self.address.parse().unwrap();
         config.port = self.port;
-        config.keep_alive = 0;
+        config.keep_alive = self.keep_alive_secs;
         config.limits = limits;
 
         config
     }
+
+    /// The settings of the daily aggregates, `None` if they are disabled
+    pub fn daily_aggregates(&self) -> Result<Option<aw_datastore::DailyAggregates>, String> {
+        if !self.db_daily_aggregates {
+            return Ok(None);
+        }
+        let timezone = match &self.timezone {
+            Some(timezone) => match timezone.parse::<chrono_tz::Tz>() {
+                Ok(tz) => Some(tz),
+                Err(_) => return Err(format!("Invalid timezone '{timezone}' in config")),
+            },
+            None => None,
+        };
+        Ok(Some(aw_datastore::DailyAggregates {
+            key: self.db_daily_aggregates_key.clone(),
+            timezone,
+        }))
+    }
 }
 
 fn default_address() -> String {
@@ -83,6 +226,70 @@ fn default_cors() -> Vec<String> {
     Vec::<String>::new()
 }
 
+fn default_admin_token() -> Option<String> {
+    None
+}
+
+fn default_compress_event_data() -> bool {
+    false
+}
+
+fn default_db_busy_timeout_ms() -> u64 {
+    aw_datastore::DEFAULT_BUSY_TIMEOUT.as_millis() as u64
+}
+
+fn default_db_insert_coalescing_ms() -> Option<u64> {
+    None
+}
+
+fn default_event_id_strategy() -> EventIdStrategy {
+    EventIdStrategy::Integer
+}
+
+fn default_query_default_timeperiod_days() -> u32 {
+    1
+}
+
+fn default_query_max_timeperiod_days() -> Option<u32> {
+    None
+}
+
+fn default_timezone() -> Option<String> {
+    None
+}
+
+fn default_db_mirror_path() -> Option<String> {
+    None
+}
+
+fn default_db_daily_aggregates() -> bool {
+    false
+}
+
+fn default_db_daily_aggregates_key() -> String {
+    "app".to_string()
+}
+
+fn default_webui_path() -> Option<String> {
+    None
+}
+
+fn default_log_level() -> Option<String> {
+    None
+}
+
+fn default_request_timeout_secs() -> u64 {
+    60
+}
+
+fn default_long_request_timeout_secs() -> u64 {
+    600
+}
+
+fn default_keep_alive_secs() -> u32 {
+    0
+}
+
 fn default_testing() -> bool {
     is_testing()
 }
@@ -100,8 +307,14 @@ fn default_custom_static() -> std::collections::HashMap<String, String> {
 }
 
 pub fn create_config(testing: bool) -> AWConfig {
+    try_create_config(testing).unwrap_or_else(|err| panic!("{err}"))
+}
+
+/// Reads the config file, writing one with the defaults commented out if there is none
+pub fn try_create_config(testing: bool) -> Result<AWConfig, String> {
     set_testing(testing);
-    let mut config_path = dirs::get_config_dir().unwrap();
+    let mut config_path =
+        dirs::get_config_dir().map_err(|_| "Unable to get the config dir".to_string())?;
     if !testing {
         config_path.push("config.toml")
     } else {
@@ -112,7 +325,10 @@ pub fn create_config(testing: bool) -> AWConfig {
      * commented out by default in case we would change a default value at some point in the future */
     if !config_path.is_file() {
         debug!("Writing default commented out config at {:?}", config_path);
-        let mut wfile = File::create(config_path.clone()).expect("Unable to create config file");
+        let write_err = |err: std::io::Error| {
+            format!("Unable to write default config file at {config_path:?}: {err}")
+        };
+        let mut wfile = File::create(config_path.clone()).map_err(write_err)?;
         let default_config = AWConfig::default();
         let default_config_str =
             toml::to_string(&default_config).expect("Failed to convert default config to string");
@@ -123,17 +339,24 @@ pub fn create_config(testing: bool) -> AWConfig {
         }
         wfile
             .write_all(&default_config_str_commented.into_bytes())
-            .expect("Failed to write config to file");
-        wfile.sync_all().expect("Unable to sync config file");
+            .map_err(write_err)?;
+        wfile.sync_all().map_err(write_err)?;
     }
 
+    let mut aw_config =
+        load_config(&config_path).map_err(|err| format!("{err} ({config_path:?})"))?;
+    aw_config.config_path = Some(config_path);
+    Ok(aw_config)
+}
+
+/// Reads and parses the config file at `config_path`
+pub fn load_config(config_path: &Path) -> Result<AWConfig, String> {
     debug!("Reading config at {:?}", config_path);
-    let mut rfile = File::open(config_path).expect("Failed to open config file for reading");
+    let mut rfile = File::open(config_path)
+        .map_err(|err| format!("Failed to open config file for reading: {err}"))?;
     let mut content = String::new();
     rfile
         .read_to_string(&mut content)
-        .expect("Failed to read config as a string");
-    let aw_config: AWConfig = toml::from_str(&content).expect("Failed to parse config file");
-
-    aw_config
+        .map_err(|err| format!("Failed to read config as a string: {err}"))?;
+    toml::from_str(&content).map_err(|err| format!("Failed to parse config file: {err}"))
 }
@@ -1,4 +1,4 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 #[cfg(not(target_os = "android"))]
 use std::fs;
@@ -65,9 +65,14 @@ pub fn get_log_dir(module: &str) -> Result<PathBuf, ()> {
     panic!("not implemented on Android");
 }
 
+/// Testing databases are kept in a separate subdirectory so they can never be mixed up with
+/// the production database
 pub fn db_path(testing: bool) -> Result<PathBuf, ()> {
     let mut db_path = get_data_dir()?;
     if testing {
+        db_path.push("test");
+        #[cfg(not(target_os = "android"))]
+        fs::create_dir_all(db_path.clone()).map_err(|_| ())?;
         db_path.push("sqlite-testing.db");
     } else {
         db_path.push("sqlite.db");
@@ -75,6 +80,22 @@ pub fn db_path(testing: bool) -> Result<PathBuf, ()> {
     Ok(db_path)
 }
 
+/// Refuse to use the production database while in testing mode
+pub fn check_db_path(path: &Path, testing: bool) -> Result<(), String> {
+    if !testing {
+        return Ok(());
+    }
+    let prod_db_path = db_path(false).map_err(|_| "Failed to get db path".to_string())?;
+    // Canonicalize to catch relative paths and symlinks, the files might not exist yet
+    let canonical = |path: &Path| path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    if canonical(path) == canonical(&prod_db_path) {
+        return Err(format!(
+            "Refusing to open the production database at {prod_db_path:?} in testing mode"
+        ));
+    }
+    Ok(())
+}
+
 #[cfg(target_os = "android")]
 pub fn set_android_data_dir(path: &str) {
     let mut android_data_dir = ANDROID_DATA_DIR.lock().unwrap();
@@ -91,3 +112,18 @@ fn test_get_dirs() {
     db_path(true).unwrap();
     db_path(false).unwrap();
 }
+
+#[test]
+fn test_testing_db_isolated() {
+    #[cfg(target_os = "android")]
+    set_android_data_dir("/test");
+
+    let testing_db = db_path(true).unwrap();
+    let prod_db = db_path(false).unwrap();
+    assert_ne!(testing_db, prod_db);
+    assert_ne!(testing_db.parent(), prod_db.parent());
+
+    assert!(check_db_path(&testing_db, true).is_ok());
+    assert!(check_db_path(&prod_db, true).is_err());
+    assert!(check_db_path(&prod_db, false).is_ok());
+}
@@ -0,0 +1,246 @@
+use std::sync::RwLock;
+use std::time::Duration;
+
+use rocket::http::Status;
+use rocket::request::{self, FromRequest, Request};
+use rocket::serde::json::Json;
+use rocket::State;
+
+use aw_models::{ConfigReloadResult, VacuumResult};
+
+use crate::config::{self, AWConfig, EventIdStrategy};
+use crate::endpoints::cors::{self, ReloadableCors};
+use crate::endpoints::{HttpErrorJson, ServerState};
+use crate::logging;
+
+/// Proof that a request sent the `admin_token` of the config, see `AWConfig::admin_token`
+///
+/// Taken as `Result<AdminAuth, HttpErrorJson>` so that refused requests get a JSON error, 401
+/// Unauthorized for a missing or wrong token and 403 Forbidden while no token is configured.
+pub struct AdminAuth;
+
+/// Compares in a time which doesn't depend on where the tokens differ
+fn tokens_match(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for AdminAuth {
+    type Error = HttpErrorJson;
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        let config = request.rocket().state::<RwLock<AWConfig>>().unwrap();
+        let admin_token = match &config.read().unwrap().admin_token {
+            Some(admin_token) => admin_token.clone(),
+            None => {
+                let err = HttpErrorJson::new(
+                    Status::Forbidden,
+                    "The admin endpoints are disabled, set admin_token in the config to use them"
+                        .to_string(),
+                );
+                return request::Outcome::Error((Status::Forbidden, err));
+            }
+        };
+        let token = request
+            .headers()
+            .get_one("Authorization")
+            .and_then(|value| value.strip_prefix("Bearer "));
+        match token {
+            Some(token) if tokens_match(token, &admin_token) => {
+                request::Outcome::Success(AdminAuth)
+            }
+            _ => {
+                let err = HttpErrorJson::new(
+                    Status::Unauthorized,
+                    "Missing or wrong admin token in the Authorization header".to_string(),
+                );
+                request::Outcome::Error((Status::Unauthorized, err))
+            }
+        }
+    }
+}
+
+/// Shrinks the database file after large deletions
+///
+/// This can take a while on large databases, the datastore is locked for writes throughout.
+#[post("/vacuum")]
+pub fn vacuum(
+    auth: Result<AdminAuth, HttpErrorJson>,
+    state: &State<ServerState>,
+) -> Result<Json<VacuumResult>, HttpErrorJson> {
+    auth?;
+    let datastore = endpoints_get_lock!(state.datastore);
+    match datastore.vacuum() {
+        Ok(result) => Ok(Json(result)),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Recomputes the daily aggregates from all events, see `db_daily_aggregates` in the config
+///
+/// They are kept up to date without it, this is for when the database was changed by other
+/// means while they were disabled. 400 if they are not enabled.
+#[post("/daily_aggregates/rebuild")]
+pub fn rebuild_daily_aggregates(
+    auth: Result<AdminAuth, HttpErrorJson>,
+    state: &State<ServerState>,
+) -> Result<(), HttpErrorJson> {
+    auth?;
+    let datastore = endpoints_get_lock!(state.datastore);
+    datastore.rebuild_daily_aggregates()?;
+    Ok(())
+}
+
+/// Reads the config file again and applies the settings which can be changed while running
+///
+/// The database, query, CORS, log level and admin token settings are applied right away. The
+/// address, port, web UI path, database mirror, custom static directories and request timeouts
+/// are only reported as requiring a restart and keep their current value until then.
+#[post("/reload")]
+pub fn reload(
+    auth: Result<AdminAuth, HttpErrorJson>,
+    config: &State<RwLock<AWConfig>>,
+    reloadable_cors: &State<ReloadableCors>,
+    state: &State<ServerState>,
+) -> Result<Json<ConfigReloadResult>, HttpErrorJson> {
+    auth?;
+    let config_path = match &config.read().unwrap().config_path {
+        Some(path) => path.clone(),
+        None => {
+            return Err(HttpErrorJson::new(
+                Status::BadRequest,
+                "The server was not started from a config file".to_string(),
+            ))
+        }
+    };
+    let new_config = match config::load_config(&config_path) {
+        Ok(new_config) => new_config,
+        Err(err) => return Err(HttpErrorJson::new(Status::BadRequest, err)),
+    };
+
+    let datastore = endpoints_get_lock!(state.datastore);
+    let mut config = config.write().unwrap();
+    let mut result = ConfigReloadResult::default();
+    // Checked before anything is applied, so that invalid origins leave the config as is
+    let new_cors = match new_config.cors != config.cors {
+        true => match cors::try_cors(&config, &new_config.cors) {
+            Ok(new_cors) => Some(new_cors),
+            Err(err) => {
+                return Err(HttpErrorJson::new(
+                    Status::BadRequest,
+                    format!("Invalid CORS origins: {err}"),
+                ))
+            }
+        },
+        false => None,
+    };
+    let daily_aggregates = match new_config.daily_aggregates() {
+        Ok(daily_aggregates) => daily_aggregates,
+        Err(err) => return Err(HttpErrorJson::new(Status::BadRequest, err)),
+    };
+
+    if new_config.db_busy_timeout_ms != config.db_busy_timeout_ms {
+        datastore.set_busy_timeout(Duration::from_millis(new_config.db_busy_timeout_ms))?;
+        config.db_busy_timeout_ms = new_config.db_busy_timeout_ms;
+        result.changed.push("db_busy_timeout_ms".to_string());
+    }
+    if new_config.db_insert_coalescing_ms != config.db_insert_coalescing_ms {
+        datastore.set_insert_coalescing(
+            new_config
+                .db_insert_coalescing_ms
+                .map(Duration::from_millis),
+        )?;
+        config.db_insert_coalescing_ms = new_config.db_insert_coalescing_ms;
+        result.changed.push("db_insert_coalescing_ms".to_string());
+    }
+    // Also rebuilt when the timezone changes, as it decides the days
+    if config.daily_aggregates().ok().flatten() != daily_aggregates {
+        datastore.set_daily_aggregates(daily_aggregates)?;
+    }
+    if new_config.db_daily_aggregates != config.db_daily_aggregates {
+        config.db_daily_aggregates = new_config.db_daily_aggregates;
+        result.changed.push("db_daily_aggregates".to_string());
+    }
+    if new_config.db_daily_aggregates_key != config.db_daily_aggregates_key {
+        config.db_daily_aggregates_key = new_config.db_daily_aggregates_key.clone();
+        result.changed.push("db_daily_aggregates_key".to_string());
+    }
+    if new_config.compress_event_data != config.compress_event_data {
+        datastore.set_compress_event_data(new_config.compress_event_data)?;
+        config.compress_event_data = new_config.compress_event_data;
+        result.changed.push("compress_event_data".to_string());
+    }
+    if new_config.event_id_strategy != config.event_id_strategy {
+        datastore.set_uuid_event_ids(new_config.event_id_strategy == EventIdStrategy::Uuid)?;
+        config.event_id_strategy = new_config.event_id_strategy;
+        result.changed.push("event_id_strategy".to_string());
+    }
+    if new_config.query_default_timeperiod_days != config.query_default_timeperiod_days {
+        config.query_default_timeperiod_days = new_config.query_default_timeperiod_days;
+        result
+            .changed
+            .push("query_default_timeperiod_days".to_string());
+    }
+    if new_config.query_max_timeperiod_days != config.query_max_timeperiod_days {
+        config.query_max_timeperiod_days = new_config.query_max_timeperiod_days;
+        result.changed.push("query_max_timeperiod_days".to_string());
+    }
+    if new_config.timezone != config.timezone {
+        config.timezone = new_config.timezone.clone();
+        result.changed.push("timezone".to_string());
+    }
+    if let Some(new_cors) = new_cors {
+        reloadable_cors.set(new_cors);
+        config.cors = new_config.cors.clone();
+        result.changed.push("cors".to_string());
+    }
+    if new_config.log_level != config.log_level {
+        logging::reload_log_filters(new_config.log_level.as_deref());
+        config.log_level = new_config.log_level.clone();
+        result.changed.push("log_level".to_string());
+    }
+    if new_config.admin_token != config.admin_token {
+        config.admin_token = new_config.admin_token.clone();
+        result.changed.push("admin_token".to_string());
+    }
+
+    if new_config.address != config.address {
+        result.requires_restart.push("address".to_string());
+    }
+    if new_config.port != config.port {
+        result.requires_restart.push("port".to_string());
+    }
+    if new_config.webui_path != config.webui_path {
+        result.requires_restart.push("webui_path".to_string());
+    }
+    if new_config.db_mirror_path != config.db_mirror_path {
+        result.requires_restart.push("db_mirror_path".to_string());
+    }
+    if new_config.custom_static != config.custom_static {
+        result.requires_restart.push("custom_static".to_string());
+    }
+    // The timeouts are set on the routes when they are mounted
+    if new_config.request_timeout_secs != config.request_timeout_secs {
+        result
+            .requires_restart
+            .push("request_timeout_secs".to_string());
+    }
+    if new_config.long_request_timeout_secs != config.long_request_timeout_secs {
+        result
+            .requires_restart
+            .push("long_request_timeout_secs".to_string());
+    }
+    if new_config.keep_alive_secs != config.keep_alive_secs {
+        result.requires_restart.push("keep_alive_secs".to_string());
+    }
+
+    info!(
+        "Reloaded config from {:?}, changed: {:?}, requires restart: {:?}",
+        config_path, result.changed, result.requires_restart
+    );
+    Ok(Json(result))
+}
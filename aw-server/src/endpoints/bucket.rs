@@ -1,20 +1,40 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::sync::RwLock;
 
 use gethostname::gethostname;
 use rocket::serde::json::Json;
+use serde::Serialize;
+use serde_json::{json, Map, Value};
 
 use chrono::DateTime;
+use chrono::Local;
 use chrono::Utc;
 
 use aw_models::Bucket;
+use aw_models::BucketCreationResult;
+use aw_models::BucketQuality;
+use aw_models::BucketState;
 use aw_models::BucketsExport;
 use aw_models::Event;
+use aw_models::EventFields;
+use aw_models::EventId;
+use aw_models::QualityGaps;
+use aw_models::QualityIssue;
 use aw_models::TryVec;
 
-use rocket::http::Status;
+use aw_datastore::DatastoreError;
+use aw_datastore::GetEventsOptions;
+
+use rocket::data::{Data, Limits, ToByteUnit};
+use rocket::http::{Header, Status};
+use rocket::tokio::io::{AsyncBufReadExt, BufReader};
 use rocket::State;
 
-use crate::endpoints::util::BucketsExportRocket;
+use crate::config::AWConfig;
+use crate::endpoints::util::{
+    bucket_etag, configured_timezone, etag, run_with_datastore, BucketsExportRocket,
+    CacheValidators, ConditionalJson, ExportFormat, IfMatch, TaggedJson,
+};
 use crate::endpoints::{HttpErrorJson, ServerState};
 
 #[get("/")]
@@ -28,22 +48,54 @@ pub fn buckets_get(
     }
 }
 
+/// Last update time and event count of every bucket, for finding the buckets which need syncing
+///
+/// Mounted at `/api/0/bucket-states`, as under `/api/0/buckets` it would be the bucket "states".
+#[get("/")]
+pub fn buckets_states(
+    state: &State<ServerState>,
+) -> Result<Json<HashMap<String, BucketState>>, HttpErrorJson> {
+    let datastore = endpoints_get_lock!(state.datastore);
+    match datastore.get_bucket_states() {
+        Ok(states) => Ok(Json(states)),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Get a bucket, with an ETag of its metadata for making an update conditional, see `bucket_patch`
 #[get("/<bucket_id>")]
 pub fn bucket_get(
     bucket_id: &str,
     state: &State<ServerState>,
-) -> Result<Json<Bucket>, HttpErrorJson> {
+) -> Result<TaggedJson<Bucket>, HttpErrorJson> {
     let datastore = endpoints_get_lock!(state.datastore);
     match datastore.get_bucket(&bucket_id) {
-        Ok(bucket) => Ok(Json(bucket)),
+        Ok(bucket) => {
+            let etag = bucket_etag(&bucket);
+            Ok(TaggedJson(Json(bucket), etag))
+        }
         Err(e) => Err(e.into()),
     }
 }
 
+fn set_local_hostname(bucket: &mut Bucket, device_id: &str) {
+    if bucket.hostname == "!local" {
+        bucket.hostname = gethostname()
+            .into_string()
+            .unwrap_or_else(|_| "unknown".to_string());
+        bucket
+            .data
+            .insert("device_id".to_string(), device_id.into());
+    }
+}
+
 /// Create a new bucket
 ///
 /// If hostname is "!local", the hostname and device_id will be set from the server info.
 /// This is useful for watchers which are known/assumed to run locally but might not know their hostname (like aw-watcher-web).
+///
+/// With `"monotonic_timestamps": true` in the bucket data, inserting an event which starts before
+/// the latest event of the bucket fails with 409 Conflict.
 #[post("/<bucket_id>", data = "<message>", format = "application/json")]
 pub fn bucket_new(
     bucket_id: &str,
@@ -54,14 +106,7 @@ pub fn bucket_new(
     if bucket.id != bucket_id {
         bucket.id = bucket_id.to_string();
     }
-    if bucket.hostname == "!local" {
-        bucket.hostname = gethostname()
-            .into_string()
-            .unwrap_or_else(|_| "unknown".to_string());
-        bucket
-            .data
-            .insert("device_id".to_string(), state.device_id.clone().into());
-    }
+    set_local_hostname(&mut bucket, &state.device_id);
     let datastore = endpoints_get_lock!(state.datastore);
     let ret = datastore.create_bucket(&bucket);
     match ret {
@@ -70,64 +115,328 @@ pub fn bucket_new(
     }
 }
 
-#[get("/<bucket_id>/events?<start>&<end>&<limit>")]
+/// Updates the data of a bucket with a JSON merge patch (RFC 7386), the events are left as they are
+///
+/// With an If-Match header the update is only made if the bucket still has that ETag, otherwise
+/// 412 Precondition Failed is returned, so that concurrent edits aren't silently overwritten.
+/// The response has the new ETag of the bucket.
+#[patch("/<bucket_id>", data = "<patch>", format = "application/json")]
+pub fn bucket_patch(
+    bucket_id: &str,
+    patch: Json<Map<String, Value>>,
+    if_match: IfMatch,
+    state: &State<ServerState>,
+) -> Result<TaggedJson<Bucket>, HttpErrorJson> {
+    let datastore = endpoints_get_lock!(state.datastore);
+    // The datastore stays locked until the update, so the bucket can't change in between
+    let current = match datastore.get_bucket(bucket_id) {
+        Ok(bucket) => bucket_etag(&bucket),
+        Err(err) => return Err(err.into()),
+    };
+    if !if_match.matches(&current) {
+        return Err(HttpErrorJson::new(
+            Status::PreconditionFailed,
+            format!("Bucket '{bucket_id}' has been changed, its current ETag is {current}"),
+        ));
+    }
+    match datastore.update_bucket_data(bucket_id, patch.into_inner()) {
+        Ok(bucket) => {
+            let etag = bucket_etag(&bucket);
+            Ok(TaggedJson(Json(bucket), etag))
+        }
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Create several buckets in one transaction
+///
+/// Returns the result for each bucket by id, buckets which already exist are skipped. With
+/// `strict=true` no bucket is created if any of them already exists. "!local" hostnames are
+/// handled like when creating a single bucket.
+#[post("/?<strict>", data = "<message>", format = "application/json")]
+pub fn buckets_new(
+    strict: Option<bool>,
+    message: Json<Vec<Bucket>>,
+    state: &State<ServerState>,
+) -> Result<Json<HashMap<String, BucketCreationResult>>, HttpErrorJson> {
+    let mut buckets = message.into_inner();
+    for bucket in buckets.iter_mut() {
+        if bucket.id.is_empty() {
+            return Err(HttpErrorJson::new(
+                Status::BadRequest,
+                "Every bucket needs an id".to_string(),
+            ));
+        }
+        set_local_hostname(bucket, &state.device_id);
+    }
+    let datastore = endpoints_get_lock!(state.datastore);
+    match datastore.create_buckets(buckets, strict.unwrap_or(false)) {
+        Ok(results) => Ok(Json(results)),
+        // Unlike for a single bucket nothing was created, so 304 would be misleading
+        Err(DatastoreError::BucketAlreadyExists(bucket_id)) => Err(HttpErrorJson::new(
+            Status::Conflict,
+            format!("Bucket '{bucket_id}' already exists, no buckets were created"),
+        )),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Events returned by `bucket_events_get`, only the requested fields if `fields` was set and
+/// with a `local_timestamp` if `localtime` was set
+#[derive(Serialize)]
+#[serde(untagged)]
+pub enum EventList {
+    Full(Vec<Event>),
+    Projected(Vec<Map<String, Value>>),
+}
+
+/// Get events in a bucket
+///
+/// Supports conditional requests, the ETag and Last-Modified headers are derived from the
+/// bucket's last_updated time and 304 Not Modified is returned if the client's copy is current.
+///
+/// Events overlapping `[start, end]` are returned, including events which only touch `start` or
+/// `end`. With `inclusive_end=false` the interval is `[start, end)` instead, so that
+/// consecutive intervals such as days never return the same boundary event.
+///
+/// Events are returned newest first, or oldest first with `order=asc`. The limit applies after
+/// ordering, so it keeps the newest or the oldest events respectively.
+///
+/// With `fields`, a comma-separated list such as `timestamp,duration,data.app`, only those fields
+/// of the events are returned, see `EventFields`.
+///
+/// With `localtime=true` every event also gets a `local_timestamp`, its timestamp in RFC3339
+/// with the offset of the configured timezone (the system timezone if none is configured). The
+/// offset is the one in effect at the time of the event, so events on either side of a DST
+/// change have different offsets. The field is added to the event or its requested fields, the
+/// UTC `timestamp` is left as is.
+///
+/// With `after_id=N` the events with an integer id greater than N are returned in id order
+/// instead, whole rather than cut to an interval, so that sync clients can resume after the last
+/// event they have seen without the duplicates and skips of a timestamp cursor. It can't be
+/// combined with `start`, `end`, `inclusive_end` or `order`.
+#[get(
+    "/<bucket_id>/events?<start>&<end>&<limit>&<inclusive_end>&<order>&<fields>&<localtime>&<after_id>"
+)]
+#[allow(clippy::too_many_arguments)]
 pub fn bucket_events_get(
     bucket_id: &str,
     start: Option<String>,
     end: Option<String>,
     limit: Option<u64>,
+    inclusive_end: Option<bool>,
+    order: Option<&str>,
+    fields: Option<&str>,
+    localtime: Option<bool>,
+    after_id: Option<i64>,
+    cache_validators: CacheValidators,
     state: &State<ServerState>,
-) -> Result<Json<Vec<Event>>, HttpErrorJson> {
-    let starttime: Option<DateTime<Utc>> = match start {
-        Some(dt_str) => match DateTime::parse_from_rfc3339(&dt_str) {
-            Ok(dt) => Some(dt.with_timezone(&Utc)),
-            Err(e) => {
-                let err_msg = format!(
-                    "Failed to parse starttime, datetime needs to be in rfc3339 format: {e}"
-                );
-                warn!("{}", err_msg);
-                return Err(HttpErrorJson::new(Status::BadRequest, err_msg));
-            }
-        },
-        None => None,
+    config: &State<RwLock<AWConfig>>,
+) -> Result<ConditionalJson<EventList>, HttpErrorJson> {
+    if after_id.is_some()
+        && (start.is_some() || end.is_some() || inclusive_end.is_some() || order.is_some())
+    {
+        return Err(HttpErrorJson::new(
+            Status::BadRequest,
+            "after_id can't be combined with start, end, inclusive_end or order".to_string(),
+        ));
+    }
+    let starttime = parse_time_param("starttime", start)?;
+    let endtime = parse_time_param("endtime", end)?;
+    let ascending = match order {
+        None | Some("desc") => false,
+        Some("asc") => true,
+        Some(order) => {
+            return Err(HttpErrorJson::new(
+                Status::BadRequest,
+                format!("Invalid order '{order}', expected 'asc' or 'desc'"),
+            ))
+        }
     };
-    let endtime: Option<DateTime<Utc>> = match end {
-        Some(dt_str) => match DateTime::parse_from_rfc3339(&dt_str) {
-            Ok(dt) => Some(dt.with_timezone(&Utc)),
-            Err(e) => {
-                let err_msg =
-                    format!("Failed to parse endtime, datetime needs to be in rfc3339 format: {e}");
-                warn!("{}", err_msg);
-                return Err(HttpErrorJson::new(Status::BadRequest, err_msg));
-            }
-        },
+    let fields = match fields.map(EventFields::parse) {
+        Some(Ok(fields)) => Some(fields),
+        Some(Err(err)) => return Err(HttpErrorJson::new(Status::BadRequest, err)),
         None => None,
     };
+    let timezone = match localtime {
+        Some(true) => Some(configured_timezone(&config.read().unwrap())?),
+        _ => None,
+    };
+    let options = GetEventsOptions {
+        inclusive_end: inclusive_end.unwrap_or(true),
+        ascending,
+    };
+    let params = format!(
+        "{starttime:?} {endtime:?} {limit:?} {options:?} {fields:?} {timezone:?} {after_id:?}"
+    );
     let datastore = endpoints_get_lock!(state.datastore);
-    let res = datastore.get_events(bucket_id, starttime, endtime, limit);
-    match res {
-        Ok(events) => Ok(Json(events)),
+    let validators = match datastore.get_bucket(bucket_id) {
+        Ok(bucket) => bucket
+            .last_updated
+            .map(|last_updated| (etag(&last_updated, &params), last_updated)),
+        Err(err) => return Err(err.into()),
+    };
+    if let Some((etag, last_updated)) = &validators {
+        if cache_validators.is_fresh(etag, last_updated) {
+            return Ok(ConditionalJson::NotModified(etag.clone(), *last_updated));
+        }
+    }
+    let res = match after_id {
+        Some(after_id) => datastore.get_events_after_id(bucket_id, after_id, limit),
+        None => datastore.get_events_with_options(bucket_id, starttime, endtime, limit, options),
+    };
+    let events = match (res, fields, timezone) {
+        (Ok(events), None, None) => EventList::Full(events),
+        (Ok(events), fields, timezone) => EventList::Projected(
+            events
+                .iter()
+                .map(|event| {
+                    let mut object = match &fields {
+                        Some(fields) => fields.project(event),
+                        None => match serde_json::to_value(event).unwrap() {
+                            Value::Object(object) => object,
+                            _ => unreachable!("events are serialized as objects"),
+                        },
+                    };
+                    if let Some(timezone) = &timezone {
+                        object.insert(
+                            "local_timestamp".to_string(),
+                            json!(local_timestamp(&event.timestamp, timezone.as_ref())),
+                        );
+                    }
+                    object
+                })
+                .collect(),
+        ),
+        (Err(err), _, _) => return Err(err.into()),
+    };
+    Ok(ConditionalJson::Modified(Json(events), validators))
+}
+
+/// The timestamp in RFC3339 with the offset of the timezone, the system timezone if `None`
+fn local_timestamp(timestamp: &DateTime<Utc>, timezone: Option<&chrono_tz::Tz>) -> String {
+    match timezone {
+        Some(tz) => timestamp.with_timezone(tz).to_rfc3339(),
+        None => timestamp.with_timezone(&Local).to_rfc3339(),
+    }
+}
+
+#[derive(Responder)]
+pub enum OptionalEvent {
+    Found(Json<Event>),
+    #[response(status = 204)]
+    Empty(()),
+}
+
+fn get_boundary_event(
+    bucket_id: &str,
+    ascending: bool,
+    state: &State<ServerState>,
+) -> Result<OptionalEvent, HttpErrorJson> {
+    let datastore = endpoints_get_lock!(state.datastore);
+    let options = GetEventsOptions {
+        ascending,
+        ..Default::default()
+    };
+    match datastore.get_events_with_options(bucket_id, None, None, Some(1), options) {
+        Ok(mut events) => match events.pop() {
+            Some(event) => Ok(OptionalEvent::Found(Json(event))),
+            None => Ok(OptionalEvent::Empty(())),
+        },
         Err(err) => Err(err.into()),
     }
 }
 
+/// The event in the bucket which starts first, or 204 No Content if the bucket is empty
+#[get("/<bucket_id>/events/first")]
+pub fn bucket_events_first(
+    bucket_id: &str,
+    state: &State<ServerState>,
+) -> Result<OptionalEvent, HttpErrorJson> {
+    get_boundary_event(bucket_id, true, state)
+}
+
+/// The event in the bucket which starts last, or 204 No Content if the bucket is empty
+#[get("/<bucket_id>/events/last")]
+pub fn bucket_events_last(
+    bucket_id: &str,
+    state: &State<ServerState>,
+) -> Result<OptionalEvent, HttpErrorJson> {
+    get_boundary_event(bucket_id, false, state)
+}
+
 // Needs unused parameter, otherwise there'll be a route collision
 // See: https://api.rocket.rs/master/rocket/struct.Route.html#resolving-collisions
-#[get("/<bucket_id>/events/<event_id>?<_unused..>")]
+// Ranked after bucket_event_count, bucket_events_first and bucket_events_last since a UUID event
+// id can be any string, including "count"
+#[get("/<bucket_id>/events/<event_id>?<_unused..>", rank = 2)]
 pub fn bucket_events_get_single(
     bucket_id: &str,
-    event_id: i64,
+    event_id: &str,
     _unused: Option<u64>,
     state: &State<ServerState>,
 ) -> Result<Json<Event>, HttpErrorJson> {
     let datastore = endpoints_get_lock!(state.datastore);
-    let res = datastore.get_event(bucket_id, event_id);
+    let res = datastore.get_event(bucket_id, EventId::from(event_id));
     match res {
         Ok(events) => Ok(Json(events)),
         Err(err) => Err(err.into()),
     }
 }
 
+/// Updates the data of an event with a JSON merge patch (RFC 7386)
+///
+/// Timestamp and duration can't be changed through the patch, they have to be supplied as
+/// separate query parameters.
+#[patch(
+    "/<bucket_id>/events/<event_id>?<timestamp>&<duration>",
+    data = "<patch>",
+    format = "application/json"
+)]
+pub fn bucket_events_patch(
+    bucket_id: &str,
+    event_id: &str,
+    timestamp: Option<String>,
+    duration: Option<f64>,
+    patch: Json<Map<String, Value>>,
+    state: &State<ServerState>,
+) -> Result<Json<Event>, HttpErrorJson> {
+    let patch = patch.into_inner();
+    for key in ["timestamp", "duration"] {
+        if patch.contains_key(key) {
+            return Err(HttpErrorJson::new(
+                Status::BadRequest,
+                format!("The patch is applied to the event data, use the '{key}' query parameter to change the {key}"),
+            ));
+        }
+    }
+    let timestamp = parse_time_param("timestamp", timestamp)?;
+    let duration = match duration {
+        Some(d) if d.is_finite() && d >= 0.0 => {
+            Some(chrono::Duration::nanoseconds((d * 1_000_000_000.0) as i64))
+        }
+        Some(_) => {
+            return Err(HttpErrorJson::new(
+                Status::BadRequest,
+                "The duration needs to be a non-negative number of seconds".to_string(),
+            ))
+        }
+        None => None,
+    };
+    let datastore = endpoints_get_lock!(state.datastore);
+    match datastore.update_event_data(
+        bucket_id,
+        EventId::from(event_id),
+        patch,
+        timestamp,
+        duration,
+    ) {
+        Ok(event) => Ok(Json(event)),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Inserts events, returning them with the ids they were given in the order they were sent
 #[post("/<bucket_id>/events", data = "<events>", format = "application/json")]
 pub fn bucket_events_create(
     bucket_id: &str,
@@ -142,6 +451,76 @@ pub fn bucket_events_create(
     }
 }
 
+/// Number of streamed events which are inserted together
+const STREAM_BATCH_SIZE: usize = 1000;
+
+fn insert_events_batch(
+    bucket_id: &str,
+    events: &[Event],
+    state: &State<ServerState>,
+) -> Result<usize, HttpErrorJson> {
+    let datastore = endpoints_get_lock!(state.datastore);
+    match datastore.insert_events(bucket_id, events) {
+        Ok(events) => Ok(events.len()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Inserts events sent as newline-delimited JSON, one event per line
+///
+/// The events are parsed and inserted in batches while the body is being read, so the whole body
+/// is never kept in memory. On an invalid line the batches before it are kept.
+#[post(
+    "/<bucket_id>/events/stream",
+    data = "<data>",
+    format = "application/x-ndjson"
+)]
+pub async fn bucket_events_create_stream(
+    bucket_id: &str,
+    data: Data<'_>,
+    limits: &Limits,
+    state: &State<ServerState>,
+) -> Result<Json<Value>, HttpErrorJson> {
+    let limit = limits.get("ndjson").unwrap_or_else(|| 1.gibibytes());
+    let mut lines = BufReader::new(data.open(limit)).lines();
+    let mut batch = Vec::with_capacity(STREAM_BATCH_SIZE);
+    let mut inserted = 0;
+    let mut line_nr = 0;
+    loop {
+        let line = match lines.next_line().await {
+            Ok(Some(line)) => line,
+            Ok(None) => break,
+            Err(err) => {
+                return Err(HttpErrorJson::new(
+                    Status::BadRequest,
+                    format!("Failed to read request body: {err}"),
+                ))
+            }
+        };
+        line_nr += 1;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str(&line) {
+            Ok(event) => batch.push(event),
+            Err(err) => {
+                return Err(HttpErrorJson::new(
+                    Status::BadRequest,
+                    format!("Invalid event on line {line_nr}: {err}"),
+                ))
+            }
+        }
+        if batch.len() >= STREAM_BATCH_SIZE {
+            inserted += insert_events_batch(bucket_id, &batch, state)?;
+            batch.clear();
+        }
+    }
+    if !batch.is_empty() {
+        inserted += insert_events_batch(bucket_id, &batch, state)?;
+    }
+    Ok(Json(json!({ "inserted": inserted })))
+}
+
 #[post(
     "/<bucket_id>/heartbeat?<pulsetime>",
     data = "<heartbeat_json>",
@@ -174,40 +553,266 @@ pub fn bucket_event_count(
     }
 }
 
+/// The distinct values of the top-level data key `key` in the events overlapping `[start, end]`
+///
+/// Returns the values with the most common ones first, or with `counts=true` objects of each
+/// value with the number of events it appears in. Events without the key are left out.
+#[get("/<bucket_id>/events/distinct?<key>&<start>&<end>&<counts>")]
+pub fn bucket_events_distinct(
+    bucket_id: &str,
+    key: Option<&str>,
+    start: Option<String>,
+    end: Option<String>,
+    counts: Option<bool>,
+    state: &State<ServerState>,
+) -> Result<Json<Value>, HttpErrorJson> {
+    let key = match key {
+        Some(key) if !key.contains('"') => key,
+        Some(_) => {
+            return Err(HttpErrorJson::new(
+                Status::BadRequest,
+                "The key can't contain a double quote".to_string(),
+            ))
+        }
+        None => {
+            return Err(HttpErrorJson::new(
+                Status::BadRequest,
+                "Missing the key to get the values of".to_string(),
+            ))
+        }
+    };
+    let starttime = parse_time_param("starttime", start)?;
+    let endtime = parse_time_param("endtime", end)?;
+    let datastore = endpoints_get_lock!(state.datastore);
+    let values = datastore.get_distinct_values(bucket_id, key, starttime, endtime)?;
+    if counts.unwrap_or(false) {
+        Ok(Json(json!(values)))
+    } else {
+        Ok(Json(Value::Array(
+            values.into_iter().map(|value| value.value).collect(),
+        )))
+    }
+}
+
+/// Parses the query parameter `name` as an RFC 3339 time, with a 400 error if it isn't one
+fn parse_time_param(
+    name: &str,
+    value: Option<String>,
+) -> Result<Option<DateTime<Utc>>, HttpErrorJson> {
+    match value {
+        Some(dt_str) => match DateTime::parse_from_rfc3339(&dt_str) {
+            Ok(dt) => Ok(Some(dt.with_timezone(&Utc))),
+            Err(e) => {
+                let err_msg =
+                    format!("Failed to parse {name}, datetime needs to be in rfc3339 format: {e}");
+                warn!("{}", err_msg);
+                Err(HttpErrorJson::new(Status::BadRequest, err_msg))
+            }
+        },
+        None => Ok(None),
+    }
+}
+
+/// Counts the events and keeps the ids of the first ones as samples
+fn quality_issue<'a>(events: impl Iterator<Item = &'a Event>) -> QualityIssue {
+    let mut issue = QualityIssue::default();
+    for event in events {
+        issue.count += 1;
+        if issue.sample_ids.len() < BucketQuality::SAMPLE_LEN {
+            issue.sample_ids.extend(event.id.clone());
+        }
+    }
+    issue
+}
+
+/// Checks events sorted by timestamp for the problems of `BucketQuality`, looking for gaps from
+/// `start` to `end` or else from the first to the end of the last event
+fn bucket_quality(
+    events: &[Event],
+    start: Option<DateTime<Utc>>,
+    end: Option<DateTime<Utc>>,
+    min_gap: chrono::Duration,
+) -> BucketQuality {
+    let overlapping = aw_transform::find_overlaps(events);
+
+    // Events in the order they were inserted, each compared to the latest start so far
+    let inserted: Option<Vec<(i64, &Event)>> = events
+        .iter()
+        .map(|event| match event.id {
+            Some(EventId::Int(id)) => Some((id, event)),
+            _ => None,
+        })
+        .collect();
+    let out_of_order = inserted.map(|mut inserted| {
+        inserted.sort_by_key(|(id, _)| *id);
+        let mut latest_start: Option<DateTime<Utc>> = None;
+        let mut out_of_order: HashSet<i64> = HashSet::new();
+        for (id, event) in inserted {
+            match latest_start {
+                Some(latest) if event.timestamp < latest => {
+                    out_of_order.insert(id);
+                }
+                _ => latest_start = Some(event.timestamp),
+            }
+        }
+        quality_issue(events.iter().filter(
+            |event| matches!(event.id, Some(EventId::Int(id)) if out_of_order.contains(&id)),
+        ))
+    });
+
+    let range_start = start.or_else(|| events.iter().map(|event| event.timestamp).min());
+    let range_end = end.or_else(|| events.iter().map(|event| event.calculate_endtime()).max());
+    let mut gaps = QualityGaps::default();
+    if let (Some(range_start), Some(range_end)) = (range_start, range_end) {
+        for gap in aw_transform::find_gaps(events, range_start, range_end, min_gap) {
+            let seconds = gap.duration().num_milliseconds() as f64 / 1000.0;
+            gaps.count += 1;
+            gaps.duration += seconds;
+            gaps.longest = gaps.longest.max(seconds);
+        }
+    }
+
+    BucketQuality {
+        event_count: events.len() as u64,
+        overlaps: quality_issue(overlapping.iter()),
+        gaps,
+        out_of_order,
+        zero_duration: quality_issue(
+            events
+                .iter()
+                .filter(|event| event.duration == chrono::Duration::zero()),
+        ),
+    }
+}
+
+/// A health report of the events overlapping `[start, end]`, see `BucketQuality` for what each
+/// metric means
+///
+/// Only gaps longer than `min_gap` seconds are counted, by default all of them.
+#[get("/<bucket_id>/quality?<start>&<end>&<min_gap>")]
+pub fn bucket_quality_get(
+    bucket_id: &str,
+    start: Option<String>,
+    end: Option<String>,
+    min_gap: Option<f64>,
+    state: &State<ServerState>,
+) -> Result<Json<BucketQuality>, HttpErrorJson> {
+    let starttime = parse_time_param("starttime", start)?;
+    let endtime = parse_time_param("endtime", end)?;
+    let min_gap = match min_gap {
+        Some(min_gap) if min_gap.is_finite() && min_gap >= 0.0 => {
+            chrono::Duration::milliseconds((min_gap * 1000.0) as i64)
+        }
+        Some(_) => {
+            return Err(HttpErrorJson::new(
+                Status::BadRequest,
+                "The minimum gap needs to be a non-negative number of seconds".to_string(),
+            ))
+        }
+        None => chrono::Duration::zero(),
+    };
+    let options = GetEventsOptions {
+        ascending: true,
+        ..Default::default()
+    };
+    let datastore = endpoints_get_lock!(state.datastore);
+    let events = datastore.get_events_with_options(bucket_id, starttime, endtime, None, options)?;
+    Ok(Json(bucket_quality(&events, starttime, endtime, min_gap)))
+}
+
+#[derive(Responder)]
+pub struct EventCountResponse {
+    inner: (),
+    event_count: Header<'static>,
+}
+
+/// Total event count of the bucket in the `X-Event-Count` header, for UIs which poll it often
+///
+/// The count is cached by the datastore, so unlike `/events/count` this doesn't scan the events
+/// again unless they have changed.
+#[head("/<bucket_id>/events")]
+pub fn bucket_events_head(
+    bucket_id: &str,
+    state: &State<ServerState>,
+) -> Result<EventCountResponse, HttpErrorJson> {
+    let datastore = endpoints_get_lock!(state.datastore);
+    match datastore.get_cached_event_count(bucket_id) {
+        Ok(eventcount) => Ok(EventCountResponse {
+            inner: (),
+            event_count: Header::new("X-Event-Count", eventcount.to_string()),
+        }),
+        Err(err) => Err(err.into()),
+    }
+}
+
 #[delete("/<bucket_id>/events/<event_id>")]
 pub fn bucket_events_delete_by_id(
     bucket_id: &str,
-    event_id: i64,
+    event_id: &str,
     state: &State<ServerState>,
 ) -> Result<(), HttpErrorJson> {
     let datastore = endpoints_get_lock!(state.datastore);
-    match datastore.delete_events_by_id(bucket_id, vec![event_id]) {
+    match datastore.delete_events_by_id(bucket_id, vec![EventId::from(event_id)]) {
         Ok(_) => Ok(()),
         Err(err) => Err(err.into()),
     }
 }
 
-#[get("/<bucket_id>/export")]
-pub fn bucket_export(
+#[get("/<bucket_id>/export?<format>")]
+pub async fn bucket_export(
     bucket_id: &str,
+    format: Option<&str>,
     state: &State<ServerState>,
 ) -> Result<BucketsExportRocket, HttpErrorJson> {
+    let format = ExportFormat::parse(format)?;
+    let bucket_id = bucket_id.to_string();
+    let export = run_with_datastore(state, move |datastore| {
+        let mut export = BucketsExport {
+            buckets: HashMap::new(),
+        };
+        let mut bucket = match datastore.get_bucket(&bucket_id) {
+            Ok(bucket) => bucket,
+            Err(err) => return Err(err.into()),
+        };
+        /* TODO: Replace expect with http error */
+        let events = datastore
+            .get_events(&bucket_id, None, None, None)
+            .expect("Failed to get events for bucket");
+        bucket.events = Some(TryVec::new(events));
+        export.buckets.insert(bucket_id, bucket);
+        Ok(export)
+    })
+    .await?;
+
+    Ok(BucketsExportRocket::new(export, format))
+}
+
+/// Copies a bucket and all its events to the new bucket `new_id`, e.g. as a sandbox for trying
+/// out transforms and categorization rules without risking the original
+///
+/// The events of the copy get new ids. Returns 409 Conflict if `new_id` already exists.
+#[post("/<bucket_id>/clone?<new_id>")]
+pub fn bucket_clone(
+    bucket_id: &str,
+    new_id: &str,
+    state: &State<ServerState>,
+) -> Result<(), HttpErrorJson> {
+    if new_id.is_empty() {
+        return Err(HttpErrorJson::new(
+            Status::BadRequest,
+            "new_id can't be empty".to_string(),
+        ));
+    }
     let datastore = endpoints_get_lock!(state.datastore);
-    let mut export = BucketsExport {
-        buckets: HashMap::new(),
-    };
-    let mut bucket = match datastore.get_bucket(bucket_id) {
-        Ok(bucket) => bucket,
-        Err(err) => return Err(err.into()),
-    };
-    /* TODO: Replace expect with http error */
-    let events = datastore
-        .get_events(bucket_id, None, None, None)
-        .expect("Failed to get events for bucket");
-    bucket.events = Some(TryVec::new(events));
-    export.buckets.insert(bucket_id.into(), bucket);
-
-    Ok(export.into())
+    match datastore.clone_bucket(bucket_id, new_id) {
+        Ok(_) => Ok(()),
+        // Nothing was created, so 304 would be misleading
+        Err(DatastoreError::BucketAlreadyExists(bucket_id)) => Err(HttpErrorJson::new(
+            Status::Conflict,
+            format!("Bucket '{bucket_id}' already exists"),
+        )),
+        Err(err) => Err(err.into()),
+    }
 }
 
 #[delete("/<bucket_id>")]
@@ -0,0 +1,84 @@
+use std::sync::RwLock;
+
+use rocket::http::Status;
+use rocket::serde::json::Json;
+use rocket::State;
+
+use aw_datastore::GetEventsOptions;
+use aw_models::{CategorizePreview, CategorizePreviewRequest, CategoryPreview};
+use aw_query::DataType;
+use aw_transform::classify::Rule;
+
+use crate::config::AWConfig;
+use crate::endpoints::query::parse_timeperiods;
+use crate::endpoints::{HttpErrorJson, ServerState};
+
+/// Categorizes the events of a bucket in a timeperiod with a rule set without storing anything,
+/// to preview which categories the rules give before saving them
+///
+/// The rules are in the format of the `categorize` query function, which is also the format the
+/// web UI stores them in: a list of `[category, rule]` pairs where the category is a list of
+/// names from the top level down, such as `["Work", "Code"]`, and the rule is one of
+///
+/// - `{"type": "regex", "regex": "...", "ignore_case": false}`, matching events where any
+///   string value of the data matches the regex, `ignore_case` is optional
+/// - `{"type": "none"}`, matching no events, for categories which only group subcategories
+///
+/// An event matched by several rules gets the deepest of their categories. The timeperiod is
+/// parsed like the timeperiods of a query, including shortcuts like "today" and the configured
+/// max length. Invalid rules return 400 Bad Request.
+#[post("/preview", data = "<preview_req>", format = "application/json")]
+pub fn categorize_preview(
+    preview_req: Json<CategorizePreviewRequest>,
+    config: &State<RwLock<AWConfig>>,
+    state: &State<ServerState>,
+) -> Result<Json<CategorizePreview>, HttpErrorJson> {
+    let preview_req = preview_req.into_inner();
+    let rules: Vec<(Vec<String>, Rule)> = match (&DataType::from(&preview_req.rules)).try_into() {
+        Ok(rules) => rules,
+        Err(err) => {
+            return Err(HttpErrorJson::new(
+                Status::BadRequest,
+                format!("Invalid rules: {err}"),
+            ))
+        }
+    };
+    let intervals = parse_timeperiods(&[preview_req.timeperiod], &config.read().unwrap())?;
+    let interval = &intervals[0];
+
+    // The datastore is only locked while reading the events, not while categorizing them
+    let events = {
+        let datastore = endpoints_get_lock!(state.datastore);
+        // Events which only touch the end of the timeperiod would be counted without any time
+        let options = GetEventsOptions {
+            inclusive_end: false,
+            ..Default::default()
+        };
+        datastore.get_events_with_options(
+            &preview_req.bucket,
+            Some(*interval.start()),
+            Some(*interval.end()),
+            None,
+            options,
+        )?
+    };
+
+    let mut preview = CategorizePreview::default();
+    for (category, _) in &rules {
+        preview.categories.entry(category.join(" > ")).or_default();
+    }
+    for event in aw_transform::classify::categorize(events, &rules) {
+        let category: Vec<String> =
+            serde_json::from_value(event.data["$category"].clone()).unwrap_or_default();
+        let entry = match category == ["Uncategorized"] {
+            true => &mut preview.uncategorized,
+            false => preview
+                .categories
+                .entry(category.join(" > "))
+                .or_insert_with(CategoryPreview::default),
+        };
+        entry.event_count += 1;
+        entry.duration += event.duration.num_milliseconds() as f64 / 1000.0;
+    }
+    Ok(Json(preview))
+}
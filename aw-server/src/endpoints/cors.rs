@@ -1,13 +1,24 @@
+use std::sync::{Arc, RwLock};
+
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::uri::{fmt, Segments};
 use rocket::http::Method;
-use rocket_cors::{AllowedHeaders, AllowedOrigins};
+use rocket::{Build, Data, Request, Response, Rocket};
+use rocket_cors::{AllowedHeaders, AllowedOrigins, Cors};
 
 use crate::config::AWConfig;
 
-pub fn cors(config: &AWConfig) -> rocket_cors::Cors {
+pub fn cors(config: &AWConfig) -> Cors {
+    try_cors(config, &config.cors).expect("Failed to set up CORS")
+}
+
+/// The CORS settings of `config` with `origins` as the origins allowed in addition to the web
+/// UI and the browser extensions, failing if one of them is invalid
+pub fn try_cors(config: &AWConfig, origins: &[String]) -> Result<Cors, rocket_cors::Error> {
     let root_url = format!("http://127.0.0.1:{}", config.port);
     let root_url_localhost = format!("http://localhost:{}", config.port);
     let mut allowed_exact_origins = vec![root_url, root_url_localhost];
-    allowed_exact_origins.extend(config.cors.clone());
+    allowed_exact_origins.extend(origins.iter().cloned());
 
     if config.testing {
         allowed_exact_origins.push("http://127.0.0.1:27180".to_string());
@@ -24,7 +35,7 @@ pub fn cors(config: &AWConfig) -> rocket_cors::Cors {
     }
 
     let allowed_origins = AllowedOrigins::some(&allowed_exact_origins, &allowed_regex_origins);
-    let allowed_methods = vec![Method::Get, Method::Post, Method::Delete]
+    let allowed_methods = vec![Method::Get, Method::Post, Method::Patch, Method::Delete]
         .into_iter()
         .map(From::from)
         .collect();
@@ -35,9 +46,65 @@ pub fn cors(config: &AWConfig) -> rocket_cors::Cors {
         allowed_origins,
         allowed_methods,
         allowed_headers,
+        // The query id is needed to cancel queries from the web UI, the request id for tracing
+        expose_headers: ["X-Query-Id".to_string(), "X-Request-Id".to_string()]
+            .into_iter()
+            .collect(),
         allow_credentials: false,
         ..Default::default()
     }
     .to_cors()
-    .expect("Failed to set up CORS")
 }
+
+/// The CORS fairing with settings which can be replaced while running, see `admin::reload`
+///
+/// Clones share the settings, one is attached as the fairing and another one is managed for
+/// reloading them.
+#[derive(Clone)]
+pub struct ReloadableCors {
+    cors: Arc<RwLock<Arc<Cors>>>,
+}
+
+impl ReloadableCors {
+    pub fn new(cors: Cors) -> ReloadableCors {
+        ReloadableCors {
+            cors: Arc::new(RwLock::new(Arc::new(cors))),
+        }
+    }
+
+    /// Applies `cors` to the requests received from now on
+    pub fn set(&self, cors: Cors) {
+        *self.cors.write().unwrap() = Arc::new(cors);
+    }
+
+    fn current(&self) -> Arc<Cors> {
+        self.cors.read().unwrap().clone()
+    }
+}
+
+#[rocket::async_trait]
+impl Fairing for ReloadableCors {
+    fn info(&self) -> Info {
+        Info {
+            name: "CORS",
+            kind: Kind::Ignite | Kind::Request | Kind::Response,
+        }
+    }
+
+    async fn on_ignite(&self, rocket: Rocket<Build>) -> rocket::fairing::Result {
+        self.current().on_ignite(rocket).await
+    }
+
+    async fn on_request(&self, request: &mut Request<'_>, data: &mut Data<'_>) {
+        self.current().on_request(request, data).await
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        self.current().on_response(request, response).await
+    }
+}
+
+/// Answers preflight requests to paths without an OPTIONS route of their own, the CORS fairing
+/// adds the CORS headers to the response
+#[options("/<_path..>", rank = 100)]
+pub fn catch_all_options(_path: Segments<'_, fmt::Path>) {}
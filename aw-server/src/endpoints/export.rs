@@ -5,27 +5,34 @@ use rocket::State;
 use aw_models::BucketsExport;
 use aw_models::TryVec;
 
-use crate::endpoints::util::BucketsExportRocket;
+use crate::endpoints::util::{run_with_datastore, BucketsExportRocket, ExportFormat};
 use crate::endpoints::{HttpErrorJson, ServerState};
 
-#[get("/")]
-pub fn buckets_export(state: &State<ServerState>) -> Result<BucketsExportRocket, HttpErrorJson> {
-    let datastore = endpoints_get_lock!(state.datastore);
-    let mut export = BucketsExport {
-        buckets: HashMap::new(),
-    };
-    let mut buckets = match datastore.get_buckets() {
-        Ok(buckets) => buckets,
-        Err(err) => return Err(err.into()),
-    };
-    for (bid, mut bucket) in buckets.drain() {
-        let events = match datastore.get_events(&bid, None, None, None) {
-            Ok(events) => events,
+#[get("/?<format>")]
+pub async fn buckets_export(
+    format: Option<&str>,
+    state: &State<ServerState>,
+) -> Result<BucketsExportRocket, HttpErrorJson> {
+    let format = ExportFormat::parse(format)?;
+    let export = run_with_datastore(state, |datastore| {
+        let mut export = BucketsExport {
+            buckets: HashMap::new(),
+        };
+        let mut buckets = match datastore.get_buckets() {
+            Ok(buckets) => buckets,
             Err(err) => return Err(err.into()),
         };
-        bucket.events = Some(TryVec::new(events));
-        export.buckets.insert(bid, bucket);
-    }
+        for (bid, mut bucket) in buckets.drain() {
+            let events = match datastore.get_events(&bid, None, None, None) {
+                Ok(events) => events,
+                Err(err) => return Err(err.into()),
+            };
+            bucket.events = Some(TryVec::new(events));
+            export.buckets.insert(bid, bucket);
+        }
+        Ok(export)
+    })
+    .await?;
 
-    Ok(export.into())
+    Ok(BucketsExportRocket::new(export, format))
 }
@@ -114,7 +114,7 @@ impl Fairing for HostCheck {
 
 #[cfg(test)]
 mod tests {
-    use std::sync::Mutex;
+    use std::sync::{Arc, Mutex};
 
     use rocket::http::{ContentType, Header, Status};
     use rocket::Rocket;
@@ -124,7 +124,7 @@ mod tests {
 
     fn setup_testserver(address: String) -> Rocket<rocket::Build> {
         let state = endpoints::ServerState {
-            datastore: Mutex::new(aw_datastore::Datastore::new_in_memory(false)),
+            datastore: Arc::new(Mutex::new(aw_datastore::Datastore::new_in_memory(false))),
             asset_resolver: endpoints::AssetResolver::new(None),
             device_id: "test_id".to_string(),
         };
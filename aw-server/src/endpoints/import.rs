@@ -7,15 +7,28 @@ use std::sync::Mutex;
 
 use aw_models::BucketsExport;
 
-use aw_datastore::Datastore;
+use aw_datastore::{Datastore, DatastoreError};
 
 use crate::endpoints::{HttpErrorJson, ServerState};
 
-fn import(datastore_mutex: &Mutex<Datastore>, import: BucketsExport) -> Result<(), HttpErrorJson> {
+/// Imports all buckets of an export, new event ids are assigned unless `preserve_ids` is set
+///
+/// With `preserve_ids` the ids from the export are kept, which requires that none of them is
+/// used by an event already on the server, in any bucket. On a collision the import of that
+/// bucket fails with 409 Conflict, buckets imported before it are kept.
+fn import(
+    datastore_mutex: &Mutex<Datastore>,
+    import: BucketsExport,
+    preserve_ids: bool,
+) -> Result<(), HttpErrorJson> {
     let datastore = endpoints_get_lock!(datastore_mutex);
     for (_bucketname, bucket) in import.buckets {
-        match datastore.create_bucket(&bucket) {
+        match datastore.import_bucket(&bucket, preserve_ids) {
             Ok(_) => (),
+            Err(e @ DatastoreError::EventAlreadyExists(_)) => {
+                warn!("Failed to import bucket {}: {e:?}", bucket.id);
+                return Err(e.into());
+            }
             Err(e) => {
                 let err_msg = format!("Failed to import bucket: {e:?}");
                 warn!("{}", err_msg);
@@ -26,12 +39,17 @@ fn import(datastore_mutex: &Mutex<Datastore>, import: BucketsExport) -> Result<(
     Ok(())
 }
 
-#[post("/", data = "<json_data>", format = "application/json")]
+#[post("/?<preserve_ids>", data = "<json_data>", format = "application/json")]
 pub fn bucket_import_json(
     state: &State<ServerState>,
     json_data: Json<BucketsExport>,
+    preserve_ids: Option<bool>,
 ) -> Result<(), HttpErrorJson> {
-    import(&state.datastore, json_data.into_inner())
+    import(
+        &state.datastore,
+        json_data.into_inner(),
+        preserve_ids.unwrap_or(false),
+    )
 }
 
 #[derive(FromForm)]
@@ -44,10 +62,15 @@ pub struct ImportForm {
     import: Json<BucketsExport>,
 }
 
-#[post("/", data = "<form>", format = "multipart/form-data")]
+#[post("/?<preserve_ids>", data = "<form>", format = "multipart/form-data")]
 pub fn bucket_import_form(
     state: &State<ServerState>,
     form: Form<ImportForm>,
+    preserve_ids: Option<bool>,
 ) -> Result<(), HttpErrorJson> {
-    import(&state.datastore, form.into_inner().import.into_inner())
+    import(
+        &state.datastore,
+        form.into_inner().import.into_inner(),
+        preserve_ids.unwrap_or(false),
+    )
 }
@@ -1,18 +1,19 @@
 use rust_embed::RustEmbed;
 use std::ffi::OsStr;
 use std::path::{Path, PathBuf};
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex, RwLock};
 
 use gethostname::gethostname;
+use rocket::fairing::AdHoc;
 use rocket::fs::FileServer;
 use rocket::http::ContentType;
 use rocket::serde::json::Json;
 use rocket::State;
 
-use crate::config::AWConfig;
+use crate::config::{AWConfig, EventIdStrategy};
 
 use aw_datastore::Datastore;
-use aw_models::Info;
+use aw_models::{Capabilities, Info};
 
 #[derive(RustEmbed)]
 #[folder = "$AW_WEBUI_DIR"]
@@ -39,20 +40,27 @@ impl AssetResolver {
 }
 
 pub struct ServerState {
-    pub datastore: Mutex<Datastore>,
+    /// Shared with the blocking tasks of `util::run_with_datastore`
+    pub datastore: Arc<Mutex<Datastore>>,
     pub asset_resolver: AssetResolver,
     pub device_id: String,
 }
 
 #[macro_use]
 mod util;
+mod admin;
 mod bucket;
+mod categorize;
 mod cors;
 mod export;
 mod hostcheck;
 mod import;
 mod query;
+mod request_id;
 mod settings;
+mod timeout;
+
+use timeout::with_timeout;
 
 pub use util::HttpErrorJson;
 
@@ -101,17 +109,96 @@ fn root_manifest(state: &State<ServerState>) -> Option<(ContentType, Vec<u8>)> {
     get_file("manifest.json".into(), state)
 }
 
+/// Serves the web UI from `webui_path` in the config
+///
+/// Unknown paths without a file extension fall back to index.html for client-side routing,
+/// missing files such as scripts and images are still 404 Not Found, as is anything under /api.
+#[get("/<file..>", rank = 20)]
+fn webui_file(file: PathBuf, config: &State<RwLock<AWConfig>>) -> Option<(ContentType, Vec<u8>)> {
+    let webui_path = PathBuf::from(config.read().unwrap().webui_path.as_ref()?);
+    if file.starts_with("api") {
+        return None;
+    }
+
+    let path = webui_path.join(&file);
+    if path.is_file() {
+        let content_type = file
+            .extension()
+            .and_then(OsStr::to_str)
+            .and_then(ContentType::from_extension)
+            .unwrap_or(ContentType::Bytes);
+        return Some((content_type, std::fs::read(path).ok()?));
+    }
+    if file.extension().is_some() {
+        return None;
+    }
+    Some((
+        ContentType::HTML,
+        std::fs::read(webui_path.join("index.html")).ok()?,
+    ))
+}
+
 #[get("/")]
-fn server_info(config: &State<AWConfig>, state: &State<ServerState>) -> Json<Info> {
+fn server_info(
+    config: &State<RwLock<AWConfig>>,
+    state: &State<ServerState>,
+) -> Result<Json<Info>, HttpErrorJson> {
     #[allow(clippy::or_fun_call)]
     let hostname = gethostname().into_string().unwrap_or("unknown".to_string());
     const VERSION: Option<&'static str> = option_env!("CARGO_PKG_VERSION");
+    let stats = endpoints_get_lock!(state.datastore).stats().snapshot();
+    let config = config.read().unwrap();
 
-    Json(Info {
+    Ok(Json(Info {
         hostname,
         version: format!("v{} (rust)", VERSION.unwrap_or("(unknown)")),
         testing: config.testing,
         device_id: state.device_id.clone(),
+        stats: Some(stats),
+        capabilities: Some(capabilities(&config)),
+    }))
+}
+
+/// What this server supports, the routes are always mounted and the rest depends on the config
+fn capabilities(config: &AWConfig) -> Capabilities {
+    Capabilities {
+        query_streaming: true,
+        query_cancel: true,
+        event_streaming: true,
+        metrics: true,
+        uuid_event_ids: config.event_id_strategy == EventIdStrategy::Uuid,
+        daily_aggregates: config.db_daily_aggregates,
+        query_functions: aw_query::function_names(),
+    }
+}
+
+/// The counters of `/api/0/info` in the Prometheus text format, counted since the server started
+#[get("/")]
+fn server_metrics(state: &State<ServerState>) -> Result<(ContentType, String), HttpErrorJson> {
+    let stats = endpoints_get_lock!(state.datastore).stats().snapshot();
+    let counters = [
+        ("events_inserted", "Events inserted", stats.events_inserted),
+        ("events_deleted", "Events deleted", stats.events_deleted),
+        ("queries_run", "Queries run", stats.queries_run),
+        ("query_errors", "Queries which failed", stats.query_errors),
+    ];
+    let mut body = String::new();
+    for (name, help, value) in counters {
+        body.push_str(&format!(
+            "# HELP aw_{name}_total {help}\n# TYPE aw_{name}_total counter\naw_{name}_total {value}\n"
+        ));
+    }
+    Ok((ContentType::Plain, body))
+}
+
+/// Tells clients that the connection is closed after the response when keep-alive is disabled,
+/// hyper closes it without saying so and a client pooling the connection would send its next
+/// request over a closed connection
+fn announce_connection_close() -> AdHoc {
+    AdHoc::on_response("Announce connection close", |_, res| {
+        Box::pin(async move {
+            res.set_raw_header("Connection", "close");
+        })
     })
 }
 
@@ -132,17 +219,127 @@ pub fn build_rocket(server_state: ServerState, config: AWConfig) -> rocket::Rock
         "Starting aw-server-rust at {}:{}",
         config.address, config.port
     );
-    let cors = cors::cors(&config);
+    let cors = cors::ReloadableCors::new(cors::cors(&config));
     let hostcheck = hostcheck::HostCheck::new(&config);
     let custom_static = config.custom_static.clone();
+    let webui_path = config.webui_path.clone();
+    let timeout = config.request_timeout_secs;
+    let long_timeout = config.long_request_timeout_secs;
+    let keep_alive = config.keep_alive_secs;
 
     let mut rocket = rocket::custom(config.to_rocket_config())
         .attach(cors.clone())
         .attach(hostcheck)
+        .attach(request_id::RequestIdLog)
+        .attach(query::cancel_on_shutdown())
         .manage(cors)
         .manage(server_state)
-        .manage(config)
+        // Behind a lock so that it can be reloaded while running, see admin::reload
+        .manage(RwLock::new(config))
+        .manage(query::QueryRegistry::default())
+        .mount("/api/0/info", with_timeout(routes![server_info], timeout))
+        .mount(
+            "/api/0/metrics",
+            with_timeout(routes![server_metrics], timeout),
+        )
+        .mount(
+            "/api/0/buckets",
+            with_timeout(
+                routes![
+                    bucket::bucket_new,
+                    bucket::bucket_patch,
+                    bucket::buckets_new,
+                    bucket::bucket_delete,
+                    bucket::buckets_get,
+                    bucket::bucket_get,
+                    bucket::bucket_events_get,
+                    bucket::bucket_events_create,
+                    bucket::bucket_events_heartbeat,
+                    bucket::bucket_event_count,
+                    bucket::bucket_events_head,
+                    bucket::bucket_events_first,
+                    bucket::bucket_events_last,
+                    bucket::bucket_events_distinct,
+                    bucket::bucket_quality_get,
+                    bucket::bucket_events_get_single,
+                    bucket::bucket_events_delete_by_id,
+                    bucket::bucket_events_patch,
+                ],
+                timeout,
+            ),
+        )
+        .mount(
+            "/api/0/bucket-states",
+            with_timeout(routes![bucket::buckets_states], timeout),
+        )
+        .mount(
+            "/api/0/buckets",
+            with_timeout(
+                routes![
+                    bucket::bucket_events_create_stream,
+                    bucket::bucket_export,
+                    bucket::bucket_clone
+                ],
+                long_timeout,
+            ),
+        )
         .mount(
+            "/api/0/query",
+            with_timeout(
+                routes![query::query, query::query_stream, query::query_cancel],
+                long_timeout,
+            ),
+        )
+        .mount(
+            "/api/0/categorize",
+            with_timeout(routes![categorize::categorize_preview], long_timeout),
+        )
+        .mount(
+            "/api/0/import",
+            with_timeout(
+                routes![import::bucket_import_json, import::bucket_import_form],
+                long_timeout,
+            ),
+        )
+        .mount(
+            "/api/0/export",
+            with_timeout(routes![export::buckets_export], long_timeout),
+        )
+        .mount(
+            "/api/0/admin",
+            with_timeout(
+                routes![
+                    admin::vacuum,
+                    admin::reload,
+                    admin::rebuild_daily_aggregates
+                ],
+                long_timeout,
+            ),
+        )
+        .mount(
+            "/api/0/settings",
+            with_timeout(
+                routes![
+                    settings::setting_get,
+                    settings::setting_set,
+                    settings::setting_delete,
+                    settings::settings_get,
+                ],
+                timeout,
+            ),
+        )
+        .mount("/", routes![cors::catch_all_options]);
+
+    if keep_alive == 0 {
+        rocket = rocket.attach(announce_connection_close());
+    }
+
+    rocket = match webui_path {
+        Some(webui_path) => {
+            info!("Serving the web UI from {}", webui_path);
+            rocket.mount("/", routes![webui_file])
+        }
+        None => rocket.mount(
             "/",
             routes![
                 root_index,
@@ -156,40 +353,8 @@ pub fn build_rocket(server_state: ServerState, config: AWConfig) -> rocket::Rock
                 root_logo,
                 root_manifest
             ],
-        )
-        .mount("/api/0/info", routes![server_info])
-        .mount(
-            "/api/0/buckets",
-            routes![
-                bucket::bucket_new,
-                bucket::bucket_delete,
-                bucket::buckets_get,
-                bucket::bucket_get,
-                bucket::bucket_events_get,
-                bucket::bucket_events_create,
-                bucket::bucket_events_heartbeat,
-                bucket::bucket_event_count,
-                bucket::bucket_events_get_single,
-                bucket::bucket_events_delete_by_id,
-                bucket::bucket_export
-            ],
-        )
-        .mount("/api/0/query", routes![query::query])
-        .mount(
-            "/api/0/import",
-            routes![import::bucket_import_json, import::bucket_import_form],
-        )
-        .mount("/api/0/export", routes![export::buckets_export])
-        .mount(
-            "/api/0/settings",
-            routes![
-                settings::setting_get,
-                settings::setting_set,
-                settings::setting_delete,
-                settings::settings_get,
-            ],
-        )
-        .mount("/", rocket_cors::catch_all_options_routes());
+        ),
+    };
 
     // for each custom static directory, mount it at the given name
     for (name, dir) in custom_static {
@@ -1,29 +1,454 @@
-use rocket::http::Status;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+
+use chrono::{DateTime, Local, TimeZone, Utc};
+use rocket::fairing::AdHoc;
+use rocket::futures::stream;
+use rocket::http::{ContentType, Header, Status};
+use rocket::request::{self, FromRequest, Request};
+use rocket::response::stream::ByteStream;
+use rocket::response::{self, Responder, Response};
 use rocket::serde::json::{json, Json, Value};
 use rocket::State;
 
-use aw_models::Query;
+use aw_models::{Query, QueryStreamFrame, TimeInterval};
+use aw_query::DataType;
+use aw_transform::start_of_day;
+use serde_json::Map;
 
+use crate::config::AWConfig;
+use crate::endpoints::util::{configured_timezone, run_with_datastore};
 use crate::endpoints::{HttpErrorJson, ServerState};
 
+/// Registry of the queries currently being executed, so that they can be cancelled
+#[derive(Default)]
+pub struct QueryRegistry {
+    queries: Mutex<HashMap<String, Arc<AtomicBool>>>,
+}
+
+impl QueryRegistry {
+    /// Fails with 409 Conflict if a query with the same id is still running, which would
+    /// otherwise be replaced in the registry and could no longer be cancelled
+    fn register(&self, id: &str) -> Result<Arc<AtomicBool>, HttpErrorJson> {
+        match self.queries.lock().unwrap().entry(id.to_string()) {
+            Entry::Occupied(_) => Err(HttpErrorJson::new(
+                Status::Conflict,
+                format!("A query with id '{id}' is already running"),
+            )),
+            Entry::Vacant(entry) => Ok(entry.insert(Arc::new(AtomicBool::new(false))).clone()),
+        }
+    }
+
+    fn unregister(&self, id: &str) {
+        self.queries.lock().unwrap().remove(id);
+    }
+
+    fn cancel(&self, id: &str) -> bool {
+        match self.queries.lock().unwrap().get(id) {
+            Some(cancel) => {
+                cancel.store(true, Ordering::Relaxed);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn cancel_all(&self) {
+        for cancel in self.queries.lock().unwrap().values() {
+            cancel.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Cancels all in-flight queries when the server shuts down
+pub fn cancel_on_shutdown() -> AdHoc {
+    AdHoc::on_shutdown("Cancel in-flight queries", |rocket| {
+        Box::pin(async move {
+            if let Some(registry) = rocket.state::<QueryRegistry>() {
+                registry.cancel_all();
+            }
+        })
+    })
+}
+
+/// Id of a query, taken from the X-Query-Id header if the client wants to pick it in advance
+/// (which is needed to cancel it), otherwise generated
+pub struct QueryId(String);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for QueryId {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, ()> {
+        let id = match req.headers().get_one("X-Query-Id") {
+            Some(id) if !id.is_empty() => id.to_string(),
+            _ => uuid::Uuid::new_v4().to_string(),
+        };
+        request::Outcome::Success(QueryId(id))
+    }
+}
+
+#[derive(Responder)]
+pub struct QueryResponse {
+    inner: Value,
+    query_id: Header<'static>,
+}
+
+/// Removes the query from the registry when it finishes, even on an early return
+///
+/// Also cancels the query, which only matters if it is still running, such as the blocking task
+/// of `query` after its handler timed out or a streamed query whose client went away.
+struct RegistryGuard<'a> {
+    registry: &'a QueryRegistry,
+    id: String,
+    cancel: Arc<AtomicBool>,
+}
+
+impl Drop for RegistryGuard<'_> {
+    fn drop(&mut self) {
+        self.cancel.store(true, Ordering::Relaxed);
+        self.registry.unregister(&self.id);
+    }
+}
+
+/// Resolves a timeperiod shortcut into an interval of whole local days, which are not always 24
+/// hours long because of DST. `now` decides both the current day and the timezone.
+///
+/// - "today": from midnight today to midnight tomorrow
+/// - "yesterday": from midnight yesterday to midnight today
+/// - "last_7_days": the 7 days ending with today, from midnight 6 days ago to midnight tomorrow
+fn resolve_timeperiod_shortcut<Tz: TimeZone>(
+    shortcut: &str,
+    now: &DateTime<Tz>,
+) -> Option<TimeInterval> {
+    let today = now.date_naive();
+    let (first_day, days) = match shortcut {
+        "today" => (today, 1),
+        "yesterday" => (today.pred_opt()?, 1),
+        "last_7_days" => (today - chrono::Duration::days(6), 7),
+        _ => return None,
+    };
+    let tz = now.timezone();
+    let start = start_of_day(first_day, &tz);
+    let end = start_of_day(first_day + chrono::Duration::days(days), &tz);
+    Some(TimeInterval::new(start, end))
+}
+
+/// Resolves a timeperiod shortcut in the configured timezone, or the system timezone if there
+/// is none
+fn resolve_timeperiod_shortcut_configured(
+    shortcut: &str,
+    config: &AWConfig,
+) -> Result<Option<TimeInterval>, HttpErrorJson> {
+    match configured_timezone(config)? {
+        Some(tz) => Ok(resolve_timeperiod_shortcut(
+            shortcut,
+            &Utc::now().with_timezone(&tz),
+        )),
+        None => Ok(resolve_timeperiod_shortcut(shortcut, &Local::now())),
+    }
+}
+
+/// Parses the timeperiods of a query, filling in a missing start or end with the configured
+/// default and rejecting timeperiods longer than the configured max.
+/// The max is applied to each timeperiod separately.
+/// A timeperiod can also be a shortcut, see `resolve_timeperiod_shortcut`.
+pub(crate) fn parse_timeperiods(
+    timeperiods: &[String],
+    config: &AWConfig,
+) -> Result<Vec<TimeInterval>, HttpErrorJson> {
+    let default_duration = chrono::Duration::days(config.query_default_timeperiod_days.into());
+    let max_duration = config
+        .query_max_timeperiod_days
+        .map(|days| chrono::Duration::days(days.into()));
+
+    let mut intervals = Vec::new();
+    for timeperiod in timeperiods {
+        let interval = match resolve_timeperiod_shortcut_configured(timeperiod, config)? {
+            Some(interval) => interval,
+            None => TimeInterval::new_from_string_with_default(timeperiod, default_duration)
+                .map_err(|_| {
+                    HttpErrorJson::new(
+                        Status::BadRequest,
+                        format!("Invalid timeperiod '{timeperiod}'"),
+                    )
+                })?,
+        };
+        if let Some(max_duration) = max_duration {
+            if interval.duration() > max_duration {
+                return Err(HttpErrorJson::new(
+                    Status::BadRequest,
+                    format!(
+                        "Timeperiod '{timeperiod}' is longer than the max of {} days",
+                        max_duration.num_days()
+                    ),
+                ));
+            }
+        }
+        intervals.push(interval);
+    }
+    Ok(intervals)
+}
+
+/// Evaluates the query for each timeperiod, on a blocking thread so that the request can time
+/// out while the query runs
 #[post("/", data = "<query_req>", format = "application/json")]
-pub fn query(query_req: Json<Query>, state: &State<ServerState>) -> Result<Value, HttpErrorJson> {
-    let query_code = query_req.0.query.join("\n");
-    let intervals = &query_req.0.timeperiods;
-    let mut results = Vec::new();
-    let datastore = endpoints_get_lock!(state.datastore);
-    for interval in intervals {
-        let result = match aw_query::query(&query_code, interval, &datastore) {
-            Ok(data) => data,
+pub async fn query(
+    query_req: Json<Query>,
+    query_id: QueryId,
+    registry: &State<QueryRegistry>,
+    config: &State<RwLock<AWConfig>>,
+    state: &State<ServerState>,
+) -> Result<QueryResponse, HttpErrorJson> {
+    let query_req = query_req.into_inner();
+    let query_code = query_req.query.join("\n");
+    let intervals = parse_timeperiods(&query_req.timeperiods, &config.read().unwrap())?;
+
+    let id = query_id.0;
+    let cancel = registry.register(&id)?;
+    let _guard = RegistryGuard {
+        registry,
+        id: id.clone(),
+        cancel: cancel.clone(),
+    };
+
+    let query_id = id.clone();
+    let results = run_with_datastore(state, move |datastore| {
+        let mut results = Vec::new();
+        for interval in &intervals {
+            let result = match aw_query::query_with_params(
+                &query_code,
+                interval,
+                datastore,
+                &query_req.params,
+                &cancel,
+            ) {
+                Ok(data) => data,
+                Err(aw_query::QueryError::Cancelled()) => {
+                    // Cancelling is up to the client, so it isn't counted as an error
+                    datastore.stats().record_query(true);
+                    info!("Query {} was cancelled", query_id);
+                    return Err(HttpErrorJson::new(
+                        Status::ServiceUnavailable,
+                        format!("Query {query_id} was cancelled"),
+                    ));
+                }
+                Err(e) => {
+                    datastore.stats().record_query(false);
+                    warn!("Query failed: {:?}", e);
+                    return Err(HttpErrorJson::new(
+                        Status::InternalServerError,
+                        e.to_string(),
+                    ));
+                }
+            };
+            results.push(result);
+        }
+        datastore.stats().record_query(true);
+        Ok(results)
+    })
+    .await?;
+    Ok(QueryResponse {
+        inner: json!(results),
+        query_id: Header::new("X-Query-Id", id),
+    })
+}
+
+pub struct QueryStreamResponse<'r> {
+    frames: QueryFrames<'r>,
+    query_id: String,
+}
+
+impl<'r> Responder<'r, 'r> for QueryStreamResponse<'r> {
+    fn respond_to(self, req: &'r Request<'_>) -> response::Result<'r> {
+        Response::build_from(ByteStream(stream::iter(self.frames)).respond_to(req)?)
+            .header(ContentType::new("application", "x-ndjson"))
+            .header(Header::new("X-Query-Id", self.query_id))
+            .ok()
+    }
+}
+
+/// Evaluates the timeperiods of a streamed query one at a time as the response is written,
+/// yielding a line per `QueryStreamFrame`
+struct QueryFrames<'r> {
+    query_code: String,
+    params: Map<String, Value>,
+    intervals: std::iter::Enumerate<std::vec::IntoIter<TimeInterval>>,
+    /// Index of the timeperiod the pending items are from
+    timeperiod: usize,
+    pending: std::vec::IntoIter<DataType>,
+    finished: bool,
+    cancel: Arc<AtomicBool>,
+    state: &'r ServerState,
+    guard: RegistryGuard<'r>,
+}
+
+impl QueryFrames<'_> {
+    /// Evaluates the next timeperiod, returning the frame to write unless the result is a list,
+    /// whose items are then pending
+    fn evaluate_next(&mut self) -> Option<QueryStreamFrame> {
+        let (i, interval) = match self.intervals.next() {
+            Some(next) => next,
+            None => {
+                self.finished = true;
+                if let Ok(datastore) = self.state.datastore.lock() {
+                    datastore.stats().record_query(true);
+                }
+                return Some(QueryStreamFrame::Done);
+            }
+        };
+        let fail = |this: &mut Self, message: String| {
+            this.finished = true;
+            Some(QueryStreamFrame::Error {
+                timeperiod: i,
+                message,
+            })
+        };
+        let datastore = match self.state.datastore.lock() {
+            Ok(datastore) => datastore,
+            Err(e) => {
+                let message = format!("Taking datastore lock failed: {e}");
+                warn!("{}", message);
+                return fail(self, message);
+            }
+        };
+        let result = aw_query::query_with_params(
+            &self.query_code,
+            &interval,
+            &datastore,
+            &self.params,
+            &self.cancel,
+        );
+        match result {
+            Ok(DataType::List(items)) => {
+                self.timeperiod = i;
+                self.pending = items.into_iter();
+                None
+            }
+            Ok(value) => Some(QueryStreamFrame::Value {
+                timeperiod: i,
+                value: json!(value),
+            }),
+            Err(aw_query::QueryError::Cancelled()) => {
+                datastore.stats().record_query(true);
+                info!("Query {} was cancelled", self.guard.id);
+                let message = format!("Query {} was cancelled", self.guard.id);
+                drop(datastore);
+                fail(self, message)
+            }
             Err(e) => {
+                datastore.stats().record_query(false);
                 warn!("Query failed: {:?}", e);
-                return Err(HttpErrorJson::new(
-                    Status::InternalServerError,
-                    e.to_string(),
-                ));
+                drop(datastore);
+                fail(self, e.to_string())
+            }
+        }
+    }
+}
+
+impl Iterator for QueryFrames<'_> {
+    type Item = Vec<u8>;
+
+    fn next(&mut self) -> Option<Vec<u8>> {
+        let frame = loop {
+            if let Some(item) = self.pending.next() {
+                break QueryStreamFrame::Item {
+                    timeperiod: self.timeperiod,
+                    item: json!(item),
+                };
+            }
+            if self.finished {
+                return None;
+            }
+            if let Some(frame) = self.evaluate_next() {
+                break frame;
             }
         };
-        results.push(result);
+        let mut line = serde_json::to_vec(&frame).expect("Failed to serialize query frame");
+        line.push(b'\n');
+        Some(line)
     }
-    Ok(json!(results))
+}
+
+/// Like `query`, but responds with newline-delimited JSON, see `QueryStreamFrame` for the framing
+///
+/// The interpreter evaluates a timeperiod as a whole, so results are not streamed while a
+/// timeperiod is evaluated, but the response is written after each timeperiod and one list item
+/// at a time, without serializing the results of all timeperiods into one document first. The
+/// status is decided before the first timeperiod is evaluated, so only invalid requests get an
+/// error status and failing queries end the stream with an error frame instead.
+#[post("/stream", data = "<query_req>", format = "application/json")]
+pub fn query_stream<'r>(
+    query_req: Json<Query>,
+    query_id: QueryId,
+    registry: &'r State<QueryRegistry>,
+    config: &State<RwLock<AWConfig>>,
+    state: &'r State<ServerState>,
+) -> Result<QueryStreamResponse<'r>, HttpErrorJson> {
+    let query_req = query_req.into_inner();
+    let intervals = parse_timeperiods(&query_req.timeperiods, &config.read().unwrap())?;
+
+    let id = query_id.0;
+    let cancel = registry.register(&id)?;
+    let frames = QueryFrames {
+        query_code: query_req.query.join("\n"),
+        params: query_req.params,
+        intervals: intervals.into_iter().enumerate(),
+        timeperiod: 0,
+        pending: Vec::new().into_iter(),
+        finished: false,
+        cancel: cancel.clone(),
+        state,
+        guard: RegistryGuard {
+            registry,
+            id: id.clone(),
+            cancel,
+        },
+    };
+    Ok(QueryStreamResponse {
+        frames,
+        query_id: id,
+    })
+}
+
+#[post("/<query_id>/cancel")]
+pub fn query_cancel(query_id: &str, registry: &State<QueryRegistry>) -> Result<(), HttpErrorJson> {
+    match registry.cancel(query_id) {
+        true => Ok(()),
+        false => Err(HttpErrorJson::new(
+            Status::NotFound,
+            format!("No query with id '{query_id}' is running"),
+        )),
+    }
+}
+
+#[test]
+fn test_resolve_timeperiod_shortcut() {
+    use std::str::FromStr;
+
+    let tz = chrono_tz::Europe::Stockholm;
+    let utc = |s: &str| DateTime::<Utc>::from_str(s).unwrap();
+
+    // DST starts on 2021-03-28, which makes the day 23 hours long
+    let now = utc("2021-03-28T12:00:00Z").with_timezone(&tz);
+    let today = resolve_timeperiod_shortcut("today", &now).unwrap();
+    assert_eq!(today.start(), &utc("2021-03-27T23:00:00Z"));
+    assert_eq!(today.end(), &utc("2021-03-28T22:00:00Z"));
+
+    let yesterday = resolve_timeperiod_shortcut("yesterday", &now).unwrap();
+    assert_eq!(yesterday.start(), &utc("2021-03-26T23:00:00Z"));
+    assert_eq!(yesterday.end(), &utc("2021-03-27T23:00:00Z"));
+
+    let last_7_days = resolve_timeperiod_shortcut("last_7_days", &now).unwrap();
+    assert_eq!(last_7_days.start(), &utc("2021-03-21T23:00:00Z"));
+    assert_eq!(last_7_days.end(), &utc("2021-03-28T22:00:00Z"));
+
+    // Just before local midnight it's still the previous day
+    let now = utc("2021-03-27T22:59:59Z").with_timezone(&tz);
+    let today = resolve_timeperiod_shortcut("today", &now).unwrap();
+    assert_eq!(today.start(), &utc("2021-03-26T23:00:00Z"));
+
+    assert!(resolve_timeperiod_shortcut("tomorrow", &now).is_none());
 }
@@ -0,0 +1,43 @@
+//! Logs requests which carry an `X-Request-Id` header together with the id, so that a request
+//! can be traced from the client sending it through the server logs
+//!
+//! The id is chosen by the client, aw-client-rust sends one when built with `request_ids`. It is
+//! also sent back in the same header of the response. Requests without the header are not
+//! logged here.
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::Header;
+use rocket::{Request, Response};
+
+/// Name of the header carrying the id of a request
+pub const REQUEST_ID_HEADER: &str = "X-Request-Id";
+
+/// Ids longer than this are cut off in the log, so that a client can't flood it
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+pub struct RequestIdLog;
+
+#[rocket::async_trait]
+impl Fairing for RequestIdLog {
+    fn info(&self) -> Info {
+        Info {
+            name: "RequestIdLog",
+            kind: Kind::Response,
+        }
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let request_id = match request.headers().get_one(REQUEST_ID_HEADER) {
+            Some(request_id) => request_id,
+            None => return,
+        };
+        let request_id: String = request_id.chars().take(MAX_REQUEST_ID_LEN).collect();
+        info!(
+            "{} {} => {} (request id {})",
+            request.method(),
+            request.uri(),
+            response.status(),
+            request_id
+        );
+        response.set_header(Header::new(REQUEST_ID_HEADER, request_id));
+    }
+}
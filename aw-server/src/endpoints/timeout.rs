@@ -0,0 +1,112 @@
+//! Per-route request timeouts, so that a single stuck request doesn't tie up a worker forever.
+//!
+//! Rocket fairings can't wrap the handling of a request, so instead the handler of each route is
+//! wrapped in a `TimeoutHandler` which runs it with `tokio::time::timeout`. If the handler has
+//! not finished in time it is dropped and 503 Service Unavailable is returned.
+//!
+//! The timeout can only interrupt a handler while it waits at an await point. The routes which
+//! have one, and so are covered, are:
+//!
+//! - `POST /api/0/query` and the exports `GET /api/0/export` and
+//!   `GET /api/0/buckets/<id>/export`, which use the datastore through
+//!   `util::run_with_datastore`. If one times out its datastore work still finishes on its
+//!   blocking thread, a query is cancelled at its next check.
+//! - `POST /api/0/buckets/<id>/events/stream`, while it reads the request body.
+//!
+//! All other routes are synchronous handlers which wait for the datastore without an await
+//! point, they hold their worker until they finish and their response is sent even if it is
+//! late. These are the bucket and event routes including heartbeats, `POST /api/0/query/stream`
+//! (whose timeperiods are evaluated while the response is written, after the handler returned),
+//! bucket cloning, imports, the categorization preview, settings, the admin routes, info and
+//! metrics. They are wrapped all the same, so that they are covered once they become asynchronous.
+use std::time::Duration;
+
+use rocket::http::Status;
+use rocket::route::{Handler, Outcome};
+use rocket::{Data, Request, Route};
+
+use crate::endpoints::HttpErrorJson;
+
+#[derive(Clone)]
+struct TimeoutHandler {
+    inner: Box<dyn Handler>,
+    timeout: Duration,
+}
+
+#[rocket::async_trait]
+impl Handler for TimeoutHandler {
+    async fn handle<'r>(&self, request: &'r Request<'_>, data: Data<'r>) -> Outcome<'r> {
+        match rocket::tokio::time::timeout(self.timeout, self.inner.handle(request, data)).await {
+            Ok(outcome) => outcome,
+            Err(_) => {
+                let err_msg = format!(
+                    "Request {} {} did not finish within {:?}",
+                    request.method(),
+                    request.uri(),
+                    self.timeout
+                );
+                warn!("{}", err_msg);
+                Outcome::from(
+                    request,
+                    HttpErrorJson::new(Status::ServiceUnavailable, err_msg),
+                )
+            }
+        }
+    }
+}
+
+/// Wraps the handlers of `routes` to time out after `timeout_secs`, 0 disables the timeout
+pub fn with_timeout(routes: Vec<Route>, timeout_secs: u64) -> Vec<Route> {
+    if timeout_secs == 0 {
+        return routes;
+    }
+    let timeout = Duration::from_secs(timeout_secs);
+    routes
+        .into_iter()
+        .map(|mut route| {
+            route.handler = Box::new(TimeoutHandler {
+                inner: route.handler,
+                timeout,
+            });
+            route
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use rocket::http::Status;
+    use rocket::local::blocking::Client;
+
+    use super::with_timeout;
+
+    #[get("/slow")]
+    async fn slow() -> &'static str {
+        rocket::tokio::time::sleep(Duration::from_secs(5)).await;
+        "slow"
+    }
+
+    #[get("/fast")]
+    async fn fast() -> &'static str {
+        "fast"
+    }
+
+    #[test]
+    fn test_timeout() {
+        let server = rocket::build().mount("/", with_timeout(routes![slow, fast], 1));
+        let client = Client::untracked(server).expect("valid instance");
+
+        let res = client.get("/fast").dispatch();
+        assert_eq!(res.status(), Status::Ok);
+        assert_eq!(res.into_string().unwrap(), "fast");
+
+        let res = client.get("/slow").dispatch();
+        assert_eq!(res.status(), Status::ServiceUnavailable);
+        assert_eq!(
+            res.into_string().unwrap(),
+            r#"{"message":"Request GET /slow did not finish within 1s"}"#
+        );
+    }
+}
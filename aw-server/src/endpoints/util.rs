@@ -1,13 +1,20 @@
 use std::io::Cursor;
 
+use chrono::{DateTime, Utc};
 use rocket::http::ContentType;
 use rocket::http::Header;
 use rocket::http::Status;
-use rocket::request::Request;
+use rocket::request::{self, FromRequest, Request};
 use rocket::response::{self, Responder, Response};
+use rocket::serde::json::Json;
 use serde::Serialize;
 
+use aw_models::Bucket;
 use aw_models::BucketsExport;
+use aw_models::PythonBucketsExport;
+
+use crate::config::AWConfig;
+use crate::endpoints::ServerState;
 
 #[derive(Serialize, Debug)]
 pub struct HttpErrorJson {
@@ -37,26 +44,54 @@ impl<'r> Responder<'r, 'static> for HttpErrorJson {
     }
 }
 
+/// The format of an export, chosen with the `format` query parameter of the export endpoints
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// The models of this server as they are
+    Rust,
+    /// The format of the Python aw-server, see `PythonBucketsExport`
+    AwPython,
+}
+
+impl ExportFormat {
+    pub fn parse(format: Option<&str>) -> Result<ExportFormat, HttpErrorJson> {
+        match format {
+            None => Ok(ExportFormat::Rust),
+            Some("aw-python") => Ok(ExportFormat::AwPython),
+            Some(format) => Err(HttpErrorJson::new(
+                Status::BadRequest,
+                format!("Unknown export format '{format}', expected 'aw-python'"),
+            )),
+        }
+    }
+}
+
 pub struct BucketsExportRocket {
     inner: BucketsExport,
+    format: ExportFormat,
 }
 
-impl From<BucketsExport> for BucketsExportRocket {
-    fn from(val: BucketsExport) -> Self {
-        BucketsExportRocket { inner: val }
+impl BucketsExportRocket {
+    pub fn new(inner: BucketsExport, format: ExportFormat) -> Self {
+        BucketsExportRocket { inner, format }
     }
 }
 
 impl<'r> Responder<'r, 'static> for BucketsExportRocket {
     fn respond_to(self, _: &Request) -> response::Result<'static> {
-        let body = serde_json::to_string(&self.inner).unwrap();
         let header_content = match self.inner.buckets.len() == 1 {
             true => format!(
                 "attachment; filename=aw-bucket-export_{}.json",
-                self.inner.buckets.into_keys().next().unwrap()
+                self.inner.buckets.keys().next().unwrap()
             ),
             false => "attachment; filename=aw-buckets-export.json".to_string(),
         };
+        let body = match self.format {
+            ExportFormat::Rust => serde_json::to_string(&self.inner).unwrap(),
+            ExportFormat::AwPython => {
+                serde_json::to_string(&PythonBucketsExport::from(self.inner)).unwrap()
+            }
+        };
         // TODO: Fix unwrap
         Response::build()
             .status(Status::Ok)
@@ -67,7 +102,169 @@ impl<'r> Responder<'r, 'static> for BucketsExportRocket {
     }
 }
 
-use aw_datastore::DatastoreError;
+/// The validators sent by a client in a conditional GET request
+pub struct CacheValidators {
+    if_none_match: Option<String>,
+    if_modified_since: Option<DateTime<Utc>>,
+}
+
+/// FNV-1a, which unlike `DefaultHasher` gives the same hash across builds
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325u64, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// ETag of a response for the data of a bucket as of `last_updated`
+///
+/// `params` are the normalized parameters of the request, so that differently filtered
+/// responses of the same bucket get different tags.
+pub fn etag(last_updated: &DateTime<Utc>, params: &str) -> String {
+    let key = format!("{}\n{params}", last_updated.timestamp_nanos_opt().unwrap());
+    format!("\"{:016x}\"", fnv1a(key.as_bytes()))
+}
+
+fn http_date(last_updated: &DateTime<Utc>) -> String {
+    last_updated.format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
+
+impl CacheValidators {
+    /// Returns true if the client's cached copy is still current.
+    /// If-None-Match takes precedence over If-Modified-Since, see RFC 7232 section 6.
+    pub fn is_fresh(&self, etag: &str, last_updated: &DateTime<Utc>) -> bool {
+        if let Some(if_none_match) = &self.if_none_match {
+            return if_none_match
+                .split(',')
+                .map(|tag| tag.trim().trim_start_matches("W/"))
+                .any(|tag| tag == "*" || tag == etag);
+        }
+        match self.if_modified_since {
+            // HTTP dates have second precision, changes later in the same second as the date the
+            // client has could be missed, so only dates of a later second count as fresh
+            Some(since) => last_updated.timestamp() < since.timestamp(),
+            None => false,
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for CacheValidators {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, ()> {
+        let headers = req.headers();
+        let if_none_match = headers.get_one("If-None-Match").map(|s| s.to_string());
+        // Unparseable dates are ignored, as mandated by RFC 7232
+        let if_modified_since = headers
+            .get_one("If-Modified-Since")
+            .and_then(|s| DateTime::parse_from_rfc2822(s).ok())
+            .map(|dt| dt.with_timezone(&Utc));
+        request::Outcome::Success(CacheValidators {
+            if_none_match,
+            if_modified_since,
+        })
+    }
+}
+
+/// A JSON response which is replaced by 304 Not Modified if the client already has it cached
+///
+/// Both variants carry the ETag and the last modification of the response, if known.
+pub enum ConditionalJson<T> {
+    NotModified(String, DateTime<Utc>),
+    Modified(Json<T>, Option<(String, DateTime<Utc>)>),
+}
+
+impl<'r, T: Serialize> Responder<'r, 'static> for ConditionalJson<T> {
+    fn respond_to(self, req: &'r Request<'_>) -> response::Result<'static> {
+        let (mut builder, validators) = match self {
+            ConditionalJson::NotModified(etag, last_updated) => {
+                let mut builder = Response::build();
+                builder.status(Status::NotModified);
+                (builder, Some((etag, last_updated)))
+            }
+            ConditionalJson::Modified(json, validators) => {
+                (Response::build_from(json.respond_to(req)?), validators)
+            }
+        };
+        if let Some((etag, last_updated)) = validators {
+            builder
+                .header(Header::new("ETag", etag))
+                .header(Header::new("Last-Modified", http_date(&last_updated)));
+        }
+        builder.ok()
+    }
+}
+
+/// ETag of the metadata of a bucket, which changes when any of it changes
+///
+/// Derived from the content of the metadata rather than from `last_updated`, which changes with
+/// every event inserted into the bucket.
+pub fn bucket_etag(bucket: &Bucket) -> String {
+    let metadata = serde_json::to_string(&serde_json::json!({
+        "id": bucket.id,
+        "type": bucket._type,
+        "client": bucket.client,
+        "hostname": bucket.hostname,
+        "created": bucket.created,
+        "data": bucket.data,
+    }))
+    .unwrap();
+    format!("\"{:016x}\"", fnv1a(metadata.as_bytes()))
+}
+
+/// A JSON response with an ETag header
+pub struct TaggedJson<T>(pub Json<T>, pub String);
+
+impl<'r, T: Serialize> Responder<'r, 'static> for TaggedJson<T> {
+    fn respond_to(self, req: &'r Request<'_>) -> response::Result<'static> {
+        Response::build_from(self.0.respond_to(req)?)
+            .header(Header::new("ETag", self.1))
+            .ok()
+    }
+}
+
+/// The If-Match header of a request which should only be applied to the version the client has
+pub struct IfMatch(Option<String>);
+
+impl IfMatch {
+    /// Returns true if there was no If-Match header or it matches `current`, weak tags never
+    /// match as RFC 7232 requires the strong comparison for If-Match
+    pub fn matches(&self, current: &str) -> bool {
+        match &self.0 {
+            Some(if_match) => if_match
+                .split(',')
+                .map(|tag| tag.trim())
+                .any(|tag| tag == "*" || tag == current),
+            None => true,
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for IfMatch {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, ()> {
+        let if_match = req.headers().get_one("If-Match").map(|s| s.to_string());
+        request::Outcome::Success(IfMatch(if_match))
+    }
+}
+
+/// The timezone set in the config, `None` if the system timezone should be used
+pub fn configured_timezone(config: &AWConfig) -> Result<Option<chrono_tz::Tz>, HttpErrorJson> {
+    match &config.timezone {
+        Some(timezone) => match timezone.parse() {
+            Ok(tz) => Ok(Some(tz)),
+            Err(_) => Err(HttpErrorJson::new(
+                Status::InternalServerError,
+                format!("Invalid timezone '{timezone}' in config"),
+            )),
+        },
+        None => Ok(None),
+    }
+}
+
+use aw_datastore::{Datastore, DatastoreError};
 
 impl From<DatastoreError> for HttpErrorJson {
     fn from(val: DatastoreError) -> Self {
@@ -80,6 +277,17 @@ impl From<DatastoreError> for HttpErrorJson {
                 Status::NotModified,
                 format!("Bucket '{bucket_id}' already exists"),
             ),
+            DatastoreError::EventAlreadyExists(event_id) => HttpErrorJson::new(
+                Status::Conflict,
+                format!("An event with id '{event_id}' already exists"),
+            ),
+            DatastoreError::NoSuchEvent(event_id) => HttpErrorJson::new(
+                Status::NotFound,
+                format!("The requested event '{event_id}' does not exist"),
+            ),
+            DatastoreError::EventOutOfOrder(msg) => HttpErrorJson::new(Status::Conflict, msg),
+            DatastoreError::InvalidEvent(msg) => HttpErrorJson::new(Status::BadRequest, msg),
+            DatastoreError::Disabled(msg) => HttpErrorJson::new(Status::BadRequest, msg),
             DatastoreError::NoSuchKey(key) => HttpErrorJson::new(
                 Status::NotFound,
                 format!("The requested key(s) '{key}' do not exist"),
@@ -116,3 +324,26 @@ macro_rules! endpoints_get_lock {
         }
     };
 }
+
+/// Runs `f` with the datastore on a blocking thread, so that the handler awaits it and can be
+/// timed out by `endpoints::timeout` however long the datastore takes
+///
+/// If the handler is timed out `f` still runs to completion, but the Rocket worker is freed.
+pub async fn run_with_datastore<T, F>(state: &ServerState, f: F) -> Result<T, HttpErrorJson>
+where
+    T: Send + 'static,
+    F: FnOnce(&Datastore) -> Result<T, HttpErrorJson> + Send + 'static,
+{
+    let datastore = state.datastore.clone();
+    let task = rocket::tokio::task::spawn_blocking(move || {
+        let datastore = endpoints_get_lock!(datastore);
+        f(&datastore)
+    });
+    match task.await {
+        Ok(result) => result,
+        Err(err) => Err(HttpErrorJson::new(
+            Status::InternalServerError,
+            format!("Datastore task failed: {err}"),
+        )),
+    }
+}
@@ -27,6 +27,7 @@ pub mod device_id;
 pub mod dirs;
 pub mod endpoints;
 pub mod logging;
+pub mod startup;
 
 #[cfg(target_os = "android")]
 pub mod android;
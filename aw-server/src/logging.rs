@@ -1,11 +1,146 @@
 use std::fs;
 use std::path::PathBuf;
+use std::sync::RwLock;
 
 use fern::colors::{Color, ColoredLevelConfig};
 
 use crate::dirs;
 
-pub fn setup_logger(module: &str, testing: bool, verbose: bool) -> Result<(), fern::InitError> {
+/// Log levels parsed from a `RUST_LOG`-style filter such as `info,aw_datastore=debug`
+#[derive(Debug, Default, PartialEq)]
+struct LogFilters {
+    /// Level for all modules without a filter of their own
+    level: Option<log::LevelFilter>,
+    /// Levels of single modules, a module filter also applies to its submodules
+    modules: Vec<(String, log::LevelFilter)>,
+    /// Entries which could not be parsed
+    invalid: Vec<String>,
+}
+
+fn parse_log_filters(filters: &str) -> LogFilters {
+    let mut result = LogFilters::default();
+    for entry in filters.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+        match entry.split_once('=') {
+            Some((module, level)) => match level.trim().parse() {
+                Ok(level) if !module.trim().is_empty() => {
+                    result.modules.push((module.trim().to_string(), level))
+                }
+                _ => result.invalid.push(entry.to_string()),
+            },
+            None => match entry.parse() {
+                Ok(level) => result.level = Some(level),
+                Err(_) => result.invalid.push(entry.to_string()),
+            },
+        }
+    }
+    result
+}
+
+/// The level of each module which the logger lets through
+#[derive(Debug, Clone, PartialEq)]
+struct ActiveLevels {
+    /// Level for all modules without a level of their own
+    level: log::LevelFilter,
+    /// Levels of single modules and their submodules, the longest matching module wins and of
+    /// equal ones the last
+    modules: Vec<(String, log::LevelFilter)>,
+}
+
+impl ActiveLevels {
+    /// The levels of `filters`, with `default_level` for everything they don't give a level
+    fn new(filters: &LogFilters, default_level: log::LevelFilter) -> ActiveLevels {
+        let level = filters.level.unwrap_or(default_level);
+        let mut modules = Vec::new();
+        // Set some Rocket messages to debug level
+        let is_debug = matches!(level, log::LevelFilter::Trace | log::LevelFilter::Debug);
+        if !is_debug {
+            modules.push(("rocket".to_string(), log::LevelFilter::Warn));
+            // rocket_cors has a lot of unhelpful info messages that spam the log on every request
+            // https://github.com/ActivityWatch/activitywatch/issues/975
+            modules.push(("rocket_cors".to_string(), log::LevelFilter::Warn));
+            modules.push(("_".to_string(), log::LevelFilter::Warn)); // Rocket requests
+            modules.push(("launch_".to_string(), log::LevelFilter::Warn)); // Rocket config info
+        }
+        // Filters for single modules go last so that they replace the levels set above
+        modules.extend(filters.modules.iter().cloned());
+        ActiveLevels { level, modules }
+    }
+
+    fn level_for(&self, target: &str) -> log::LevelFilter {
+        let mut level = self.level;
+        let mut matched_len = None;
+        for (module, module_level) in &self.modules {
+            let matches = target == module
+                || (target.starts_with(module.as_str())
+                    && target[module.len()..].starts_with("::"));
+            if matches && matched_len.is_none_or(|len| module.len() >= len) {
+                level = *module_level;
+                matched_len = Some(module.len());
+            }
+        }
+        level
+    }
+
+    /// The most verbose level of any module
+    fn max_level(&self) -> log::LevelFilter {
+        self.modules
+            .iter()
+            .map(|(_, level)| *level)
+            .fold(self.level, std::cmp::max)
+    }
+}
+
+struct LoggerState {
+    /// Level for everything which no filter gives a level, from the environment
+    default_level: log::LevelFilter,
+    /// Whether the filters are from `RUST_LOG`, which overrides the config
+    from_env: bool,
+    levels: ActiveLevels,
+}
+
+/// Set by `setup_logger`, the levels are replaced by `reload_log_filters`
+static LOGGER_STATE: RwLock<Option<LoggerState>> = RwLock::new(None);
+
+fn is_enabled(metadata: &log::Metadata) -> bool {
+    match &*LOGGER_STATE.read().unwrap() {
+        Some(state) => metadata.level() <= state.levels.level_for(metadata.target()),
+        None => true,
+    }
+}
+
+/// Replaces the log levels with those of `log_filters`, the new `log_level` of the config
+///
+/// Does nothing if the logger isn't set up or its filters are from `RUST_LOG`, which overrides
+/// the config.
+pub fn reload_log_filters(log_filters: Option<&str>) {
+    let filters = log_filters.map(parse_log_filters).unwrap_or_default();
+    {
+        let mut logger_state = LOGGER_STATE.write().unwrap();
+        let state = match logger_state.as_mut() {
+            Some(state) if !state.from_env => state,
+            _ => return,
+        };
+        state.levels = ActiveLevels::new(&filters, state.default_level);
+        log::set_max_level(state.levels.max_level());
+    }
+    for entry in filters.invalid {
+        warn!("Ignoring invalid log filter '{}'", entry);
+    }
+}
+
+/// Sets up logging to stdout and to a logfile in the log dir of `module`
+///
+/// `log_filters` is a `RUST_LOG`-style filter, usually the `log_level` from the config. The
+/// `RUST_LOG` environment variable takes precedence over it. A level for everything which is not
+/// given by the filter is taken from the `LOG_LEVEL` environment variable, or is debug when
+/// `testing` or `verbose` and info otherwise. The filter can be replaced later with
+/// `reload_log_filters`.
+pub fn setup_logger(
+    module: &str,
+    testing: bool,
+    verbose: bool,
+    log_filters: Option<&str>,
+) -> Result<(), fern::InitError> {
     let mut logfile_path: PathBuf =
         dirs::get_log_dir(module).expect("Unable to get log dir to store logs in");
     fs::create_dir_all(logfile_path.clone()).expect("Unable to create folder for logs");
@@ -31,7 +166,13 @@ pub fn setup_logger(module: &str, testing: bool, verbose: bool) -> Result<(), fe
         log::LevelFilter::Info
     };
 
-    let log_level = std::env::var("LOG_LEVEL").map_or(default_log_level, |level| {
+    let env_filters = std::env::var("RUST_LOG").ok();
+    let filters = match &env_filters {
+        Some(env_filters) => parse_log_filters(env_filters),
+        None => log_filters.map(parse_log_filters).unwrap_or_default(),
+    };
+
+    let env_log_level = std::env::var("LOG_LEVEL").map_or(default_log_level, |level| {
         match level.to_lowercase().as_str() {
             "trace" => log::LevelFilter::Trace,
             "debug" => log::LevelFilter::Debug,
@@ -41,22 +182,18 @@ pub fn setup_logger(module: &str, testing: bool, verbose: bool) -> Result<(), fe
             _ => default_log_level,
         }
     });
+    let levels = ActiveLevels::new(&filters, env_log_level);
+    let max_level = levels.max_level();
+    *LOGGER_STATE.write().unwrap() = Some(LoggerState {
+        default_level: env_log_level,
+        from_env: env_filters.is_some(),
+        levels,
+    });
 
-    let mut dispatch = fern::Dispatch::new().level(log_level);
-    // Set some Rocket messages to debug level
-
-    let is_debug = matches!(log_level, log::LevelFilter::Trace | log::LevelFilter::Debug);
-    if !is_debug {
-        dispatch = dispatch
-            .level_for("rocket", log::LevelFilter::Warn)
-            // rocket_cors has a lot of unhelpful info messages that spam the log on every request
-            // https://github.com/ActivityWatch/activitywatch/issues/975
-            .level_for("rocket_cors", log::LevelFilter::Warn)
-            .level_for("_", log::LevelFilter::Warn) // Rocket requests
-            .level_for("launch_", log::LevelFilter::Warn); // Rocket config info
-    }
-
-    dispatch
+    // Filtered by is_enabled instead of fern levels, so that the levels can be replaced
+    fern::Dispatch::new()
+        .level(log::LevelFilter::Trace)
+        .filter(is_enabled)
         // Formatting
         .format(move |out, message, record| {
             out.finish(format_args!(
@@ -81,12 +218,17 @@ pub fn setup_logger(module: &str, testing: bool, verbose: bool) -> Result<(), fe
                 .chain(fern::log_file(logfile_path)?),
         )
         .apply()?;
+    log::set_max_level(max_level);
+
+    for entry in filters.invalid {
+        warn!("Ignoring invalid log filter '{}'", entry);
+    }
     Ok(())
 }
 
 #[cfg(test)]
 mod tests {
-    use super::setup_logger;
+    use super::{parse_log_filters, setup_logger, ActiveLevels};
 
     /* disable this test.
      * This is due to it failing in GitHub actions, claiming that the logger
@@ -94,6 +236,60 @@ mod tests {
     #[ignore]
     #[test]
     fn test_setup_logger() {
-        setup_logger("aw-server-rust", true, true).unwrap();
+        setup_logger("aw-server-rust", true, true, None).unwrap();
+    }
+
+    #[test]
+    fn test_parse_log_filters() {
+        let filters = parse_log_filters("aw_datastore=debug, warn,aw_query=TRACE");
+        assert_eq!(filters.level, Some(log::LevelFilter::Warn));
+        assert_eq!(
+            filters.modules,
+            vec![
+                ("aw_datastore".to_string(), log::LevelFilter::Debug),
+                ("aw_query".to_string(), log::LevelFilter::Trace),
+            ]
+        );
+        assert!(filters.invalid.is_empty());
+
+        let filters = parse_log_filters("aw_server=off");
+        assert_eq!(filters.level, None);
+        assert_eq!(
+            filters.modules,
+            vec![("aw_server".to_string(), log::LevelFilter::Off)]
+        );
+
+        let filters = parse_log_filters("loud,aw_query=verbose,=info,info");
+        assert_eq!(filters.level, Some(log::LevelFilter::Info));
+        assert!(filters.modules.is_empty());
+        assert_eq!(filters.invalid, vec!["loud", "aw_query=verbose", "=info"]);
+
+        assert_eq!(parse_log_filters(""), Default::default());
+    }
+
+    #[test]
+    fn test_active_levels() {
+        let filters =
+            parse_log_filters("aw_datastore=debug,aw_datastore::worker=trace,rocket=info");
+        let levels = ActiveLevels::new(&filters, log::LevelFilter::Info);
+        assert_eq!(levels.level_for("aw_server"), log::LevelFilter::Info);
+        assert_eq!(
+            levels.level_for("aw_datastore::datastore"),
+            log::LevelFilter::Debug
+        );
+        assert_eq!(
+            levels.level_for("aw_datastore::worker"),
+            log::LevelFilter::Trace
+        );
+        // Only whole modules match
+        assert_eq!(levels.level_for("aw_datastore_x"), log::LevelFilter::Info);
+        // The filter replaces the default level of Rocket
+        assert_eq!(levels.level_for("rocket::server"), log::LevelFilter::Info);
+        assert_eq!(levels.level_for("rocket_cors"), log::LevelFilter::Warn);
+        assert_eq!(levels.max_level(), log::LevelFilter::Trace);
+
+        // Rocket logs everything when debugging
+        let levels = ActiveLevels::new(&parse_log_filters("debug"), log::LevelFilter::Info);
+        assert_eq!(levels.level_for("rocket_cors"), log::LevelFilter::Debug);
     }
 }
@@ -61,7 +61,7 @@ struct Opts {
 async fn main() -> Result<(), rocket::Error> {
     let opts: Opts = Opts::parse();
 
-    use std::sync::Mutex;
+    use std::sync::{Arc, Mutex};
 
     let mut testing = opts.testing;
 
@@ -70,14 +70,31 @@ async fn main() -> Result<(), rocket::Error> {
         testing = true;
     }
 
-    logging::setup_logger("aw-server-rust", testing, opts.verbose)
-        .expect("Failed to setup logging");
+    // Logging isn't set up yet, as the log level is read from the config
+    let mut config = match config::try_create_config(testing) {
+        Ok(config) => config,
+        Err(err) => {
+            let err = startup::StartupError::Config(err);
+            eprintln!("{err}");
+            std::process::exit(err.exit_code());
+        }
+    };
+
+    logging::setup_logger(
+        "aw-server-rust",
+        testing,
+        opts.verbose,
+        config.log_level.as_deref(),
+    )
+    .expect("Failed to setup logging");
 
     if testing {
         info!("Running server in Testing mode");
     }
-
-    let mut config = config::create_config(testing);
+    // The config is read before logging is set up, as it holds the log level
+    if let Some(config_path) = &config.config_path {
+        debug!("Using config at {:?}", config_path);
+    }
 
     // set host if overridden
     if let Some(host) = opts.host {
@@ -86,7 +103,16 @@ async fn main() -> Result<(), rocket::Error> {
 
     // set port if overridden
     if let Some(port) = opts.port {
-        config.port = port.parse().unwrap();
+        config.port = match port.parse() {
+            Ok(port) => port,
+            Err(_) => {
+                let err = startup::StartupError::Config(format!(
+                    "--port '{port}' is not a port number between 0 and 65535"
+                ));
+                error!("{}", err);
+                std::process::exit(err.exit_code());
+            }
+        };
     }
 
     // set custom_static if overridden, transform into map
@@ -122,6 +148,10 @@ async fn main() -> Result<(), rocket::Error> {
             .unwrap()
             .to_string()
     };
+    if let Err(err) = startup::self_check(&config, std::path::Path::new(&db_path), testing) {
+        error!("{}", err);
+        std::process::exit(err.exit_code());
+    }
     info!("Using DB at path {:?}", db_path);
 
     let asset_path = opts.webpath.map(|webpath| PathBuf::from(webpath));
@@ -139,10 +169,53 @@ async fn main() -> Result<(), rocket::Error> {
         device_id::get_device_id()
     };
 
+    // Even if legacy_import is set to true it is disabled on Android so
+    // it will not happen there
+    let datastore = aw_datastore::Datastore::new(db_path, legacy_import);
+    datastore
+        .set_busy_timeout(std::time::Duration::from_millis(config.db_busy_timeout_ms))
+        .expect("Failed to set database busy timeout");
+    if let Some(window_ms) = config.db_insert_coalescing_ms {
+        datastore
+            .set_insert_coalescing(Some(std::time::Duration::from_millis(window_ms)))
+            .expect("Failed to set database insert coalescing");
+    }
+    if config.db_daily_aggregates {
+        info!("Daily aggregates are enabled");
+    }
+    let daily_aggregates = config
+        .daily_aggregates()
+        .expect("Invalid daily aggregates config");
+    datastore
+        .set_daily_aggregates(daily_aggregates.clone())
+        .expect("Failed to set up daily aggregates");
+    if config.compress_event_data {
+        info!("Compression of large event data is enabled");
+        datastore
+            .set_compress_event_data(true)
+            .expect("Failed to enable event data compression");
+    }
+    if config.event_id_strategy == config::EventIdStrategy::Uuid {
+        info!("New events are given UUIDs as ids");
+        datastore
+            .set_uuid_event_ids(true)
+            .expect("Failed to enable UUID event ids");
+    }
+
+    if let Some(mirror_path) = &config.db_mirror_path {
+        info!("Mirroring writes to database at {}", mirror_path);
+        let mirror = aw_datastore::Datastore::new(mirror_path.clone(), false);
+        mirror
+            .set_compress_event_data(config.compress_event_data)
+            .and_then(|_| mirror.set_daily_aggregates(daily_aggregates))
+            .expect("Failed to configure mirror database");
+        datastore
+            .set_mirror(Some(mirror))
+            .expect("Failed to set database mirror");
+    }
+
     let server_state = endpoints::ServerState {
-        // Even if legacy_import is set to true it is disabled on Android so
-        // it will not happen there
-        datastore: Mutex::new(aw_datastore::Datastore::new(db_path, legacy_import)),
+        datastore: Arc::new(Mutex::new(datastore)),
         asset_resolver: endpoints::AssetResolver::new(asset_path),
         device_id,
     };
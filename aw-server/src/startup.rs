@@ -0,0 +1,196 @@
+//! Checks run before the server starts, so that a broken setup fails with a clear message
+//!
+//! Each kind of failure exits with its own code, taken from the BSD `sysexits.h` so that scripts
+//! starting the server can tell them apart:
+//!
+//! - `EXIT_CONFIG` (78): the config file can't be read or has an invalid value
+//! - `EXIT_DATASTORE` (73): the database can't be created or written
+//! - `EXIT_BIND` (69): the server can't listen on the configured address and port
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::net::{IpAddr, TcpListener};
+use std::path::Path;
+
+use crate::config::AWConfig;
+use crate::dirs;
+
+pub const EXIT_CONFIG: i32 = 78;
+pub const EXIT_DATASTORE: i32 = 73;
+pub const EXIT_BIND: i32 = 69;
+
+/// A problem which keeps the server from starting, see the module docs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StartupError {
+    Config(String),
+    Datastore(String),
+    Bind(String),
+}
+
+impl StartupError {
+    /// The code the server exits with on this error
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            StartupError::Config(_) => EXIT_CONFIG,
+            StartupError::Datastore(_) => EXIT_DATASTORE,
+            StartupError::Bind(_) => EXIT_BIND,
+        }
+    }
+}
+
+impl fmt::Display for StartupError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StartupError::Config(msg) => write!(f, "Invalid config: {msg}"),
+            StartupError::Datastore(msg) => write!(f, "Unusable database: {msg}"),
+            StartupError::Bind(msg) => write!(f, "Unable to listen: {msg}"),
+        }
+    }
+}
+
+/// Runs all the checks, returning the first problem found
+pub fn self_check(config: &AWConfig, db_path: &Path, testing: bool) -> Result<(), StartupError> {
+    check_config(config)?;
+    dirs::check_db_path(db_path, testing).map_err(StartupError::Datastore)?;
+    check_db_writable(db_path)?;
+    if let Some(mirror_path) = &config.db_mirror_path {
+        check_db_writable(Path::new(mirror_path))?;
+    }
+    // The address was validated by check_config
+    check_bind(config.address.parse().unwrap(), config.port)
+}
+
+/// Checks the values of the config which would otherwise only fail once they are used
+pub fn check_config(config: &AWConfig) -> Result<(), StartupError> {
+    let config_file = match &config.config_path {
+        Some(path) => format!("{path:?}"),
+        None => "the config file".to_string(),
+    };
+    if config.address.parse::<IpAddr>().is_err() {
+        return Err(StartupError::Config(format!(
+            "address '{}' is not an IP address, set it to one such as 127.0.0.1 in {config_file} or pass --host",
+            config.address
+        )));
+    }
+    if let Some(timezone) = &config.timezone {
+        if timezone.parse::<chrono_tz::Tz>().is_err() {
+            return Err(StartupError::Config(format!(
+                "timezone '{timezone}' is unknown, set it to an IANA name such as Europe/Stockholm in {config_file}"
+            )));
+        }
+    }
+    if let Some(webui_path) = &config.webui_path {
+        if !Path::new(webui_path).is_dir() {
+            return Err(StartupError::Config(format!(
+                "webui_path '{webui_path}' is not a directory, fix or remove it in {config_file}"
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Checks that the database at `path` can be written, creating its directory if needed
+///
+/// SQLite also writes journal files next to the database, so the directory has to be writable
+/// too, which is checked by creating and removing a file in it.
+pub fn check_db_writable(path: &Path) -> Result<(), StartupError> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    fs::create_dir_all(dir).map_err(|err| {
+        StartupError::Datastore(format!(
+            "failed to create the directory {dir:?} for the database: {err}, check its permissions or choose another path with --dbpath"
+        ))
+    })?;
+    if path.exists() {
+        OpenOptions::new().write(true).open(path).map_err(|err| {
+            StartupError::Datastore(format!(
+                "database {path:?} can't be opened for writing: {err}, check its permissions or choose another path with --dbpath"
+            ))
+        })?;
+    }
+    let probe = dir.join(format!(".aw-server-selfcheck-{}", std::process::id()));
+    let created = OpenOptions::new().write(true).create_new(true).open(&probe);
+    match created {
+        Ok(_) => {
+            let _ = fs::remove_file(&probe);
+            Ok(())
+        }
+        Err(err) => Err(StartupError::Datastore(format!(
+            "directory {dir:?} of the database is not writable: {err}, check its permissions or choose another path with --dbpath"
+        ))),
+    }
+}
+
+/// Checks that the server can listen on `address` and `port` by binding the port once
+pub fn check_bind(address: IpAddr, port: u16) -> Result<(), StartupError> {
+    match TcpListener::bind((address, port)) {
+        Ok(_) => Ok(()),
+        Err(err) if err.kind() == std::io::ErrorKind::AddrInUse => Err(StartupError::Bind(format!(
+            "port {port} on {address} is already in use, stop the program using it (another aw-server?) or choose another port with --port"
+        ))),
+        Err(err) => Err(StartupError::Bind(format!(
+            "failed to bind port {port} on {address}: {err}, choose another address with --host or port with --port"
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+
+    use super::{check_bind, check_config, check_db_writable, StartupError, EXIT_BIND};
+    use crate::config::AWConfig;
+
+    #[test]
+    fn test_check_config() {
+        assert_eq!(check_config(&AWConfig::default()), Ok(()));
+
+        let config = AWConfig {
+            address: "localhost:5600".to_string(),
+            ..Default::default()
+        };
+        assert!(matches!(
+            check_config(&config),
+            Err(StartupError::Config(_))
+        ));
+
+        let config = AWConfig {
+            timezone: Some("Mars/Olympus_Mons".to_string()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            check_config(&config),
+            Err(StartupError::Config(_))
+        ));
+    }
+
+    #[test]
+    fn test_check_db_writable() {
+        let dir =
+            std::env::temp_dir().join(format!("aw-server-selfcheck-{}", uuid::Uuid::new_v4()));
+        // Missing directories are created
+        let db_path = dir.join("nested").join("sqlite.db");
+        assert_eq!(check_db_writable(&db_path), Ok(()));
+        assert!(dir.join("nested").is_dir());
+        assert_eq!(std::fs::read_dir(dir.join("nested")).unwrap().count(), 0);
+
+        // A directory where the database should be
+        std::fs::create_dir(&db_path).unwrap();
+        assert!(matches!(
+            check_db_writable(&db_path),
+            Err(StartupError::Datastore(_))
+        ));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_check_bind() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let err = check_bind("127.0.0.1".parse().unwrap(), port).unwrap_err();
+        assert_eq!(err.exit_code(), EXIT_BIND);
+        drop(listener);
+        assert_eq!(check_bind("127.0.0.1".parse().unwrap(), port), Ok(()));
+    }
+}
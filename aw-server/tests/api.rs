@@ -8,7 +8,7 @@ extern crate aw_server;
 #[cfg(test)]
 mod api_tests {
     use std::collections::HashMap;
-    use std::sync::Mutex;
+    use std::sync::{Arc, Mutex};
 
     use rocket::http::{ContentType, Header, Status};
     use serde_json::{json, Value};
@@ -16,12 +16,12 @@ mod api_tests {
     use aw_server::config;
     use aw_server::endpoints;
 
-    use aw_models::{Bucket, BucketsExport};
+    use aw_models::{Bucket, BucketsExport, Event, EventId};
     use rocket::local::blocking::Client;
 
     fn setup_testserver() -> rocket::Rocket<rocket::Build> {
         let state = endpoints::ServerState {
-            datastore: Mutex::new(aw_datastore::Datastore::new_in_memory(false)),
+            datastore: Arc::new(Mutex::new(aw_datastore::Datastore::new_in_memory(false))),
             asset_resolver: endpoints::AssetResolver::new(None),
             device_id: "test_id".to_string(),
         };
@@ -29,6 +29,19 @@ mod api_tests {
         endpoints::build_rocket(state, aw_config)
     }
 
+    const ADMIN_TOKEN: &str = "test-admin-token";
+
+    fn admin_config() -> config::AWConfig {
+        config::AWConfig {
+            admin_token: Some(ADMIN_TOKEN.to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn admin_auth() -> Header<'static> {
+        Header::new("Authorization", format!("Bearer {ADMIN_TOKEN}"))
+    }
+
     #[test]
     fn test_bucket() {
         let server = setup_testserver();
@@ -125,6 +138,67 @@ mod api_tests {
         assert_eq!(bucket.metadata.start, None);
         assert_eq!(bucket.metadata.end, None);
 
+        // Patch bucket data
+        res = client
+            .patch("/api/0/buckets/id")
+            .header(ContentType::JSON)
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .body(r#"{"schema_version": 2}"#)
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::Ok);
+        let bucket: Bucket = serde_json::from_str(&res.into_string().unwrap()).unwrap();
+        assert_eq!(bucket.schema_version(), Some(2));
+        res = client
+            .get("/api/0/buckets/id")
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .dispatch();
+        let etag = res.headers().get_one("ETag").unwrap().to_string();
+        let bucket: Bucket = serde_json::from_str(&res.into_string().unwrap()).unwrap();
+        assert_eq!(bucket.schema_version(), Some(2));
+
+        // Conditional patch, only applied if the bucket hasn't changed since it was read
+        res = client
+            .patch("/api/0/buckets/id")
+            .header(ContentType::JSON)
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .header(Header::new("If-Match", etag.clone()))
+            .body(r#"{"name": "first"}"#)
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::Ok);
+        let new_etag = res.headers().get_one("ETag").unwrap().to_string();
+        assert_ne!(new_etag, etag);
+        res = client
+            .patch("/api/0/buckets/id")
+            .header(ContentType::JSON)
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .header(Header::new("If-Match", etag))
+            .body(r#"{"name": "second"}"#)
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::PreconditionFailed);
+        // Inserting events doesn't change the ETag of the metadata
+        res = client
+            .post("/api/0/buckets/id/events")
+            .header(ContentType::JSON)
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .body(r#"[{"timestamp": "2018-01-01T01:01:01Z", "duration": 1.0, "data": {}}]"#)
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::Ok);
+        res = client
+            .get("/api/0/buckets/id")
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .dispatch();
+        assert_eq!(res.headers().get_one("ETag"), Some(new_etag.as_str()));
+        let bucket: Bucket = serde_json::from_str(&res.into_string().unwrap()).unwrap();
+        assert_eq!(bucket.data["name"], "first");
+
+        res = client
+            .patch("/api/0/buckets/invalid_bucket")
+            .header(ContentType::JSON)
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .body(r#"{"schema_version": 2}"#)
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::NotFound);
+
         // Get non-existing bucket
         res = client
             .get("/api/0/buckets/invalid_bucket")
@@ -161,6 +235,64 @@ mod api_tests {
         assert_eq!(buckets.len(), 0);
     }
 
+    #[test]
+    fn test_buckets_create() {
+        let server = setup_testserver();
+        let client = Client::untracked(server).expect("valid instance");
+
+        let res = client
+            .post("/api/0/buckets/id")
+            .header(ContentType::JSON)
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .body(r#"{"id": "id", "type": "type", "client": "client", "hostname": "hostname"}"#)
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::Ok);
+
+        let body = r#"[
+            {"id": "id", "type": "type", "client": "client", "hostname": "hostname"},
+            {"id": "id2", "type": "type", "client": "client", "hostname": "hostname"}
+        ]"#;
+
+        // Strict creation fails if any bucket exists
+        let res = client
+            .post("/api/0/buckets/?strict=true")
+            .header(ContentType::JSON)
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .body(body)
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::Conflict);
+
+        let res = client
+            .post("/api/0/buckets/")
+            .header(ContentType::JSON)
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .body(body)
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::Ok);
+        let results: serde_json::Value = serde_json::from_str(&res.into_string().unwrap()).unwrap();
+        assert_eq!(
+            results,
+            serde_json::json!({"id": {"status": "skipped"}, "id2": {"status": "created"}})
+        );
+
+        let res = client
+            .get("/api/0/buckets/")
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .dispatch();
+        let buckets: HashMap<String, Bucket> =
+            serde_json::from_str(&res.into_string().unwrap()).unwrap();
+        assert_eq!(buckets.len(), 2);
+
+        // Buckets without id are rejected
+        let res = client
+            .post("/api/0/buckets/")
+            .header(ContentType::JSON)
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .body(r#"[{"type": "type", "client": "client", "hostname": "hostname"}]"#)
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::BadRequest);
+    }
+
     #[test]
     fn test_events() {
         let server = setup_testserver();
@@ -213,6 +345,22 @@ mod api_tests {
             r#"[{"id":1,"timestamp":"2018-01-01T01:01:01Z","duration":1.0,"data":{}}]"#
         );
 
+        // An event starting at the end is only included if the end is inclusive
+        let res = client
+            .get("/api/0/buckets/id/events?end=2018-01-01T01:01:01Z")
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .dispatch();
+        assert_eq!(
+            res.into_string().unwrap(),
+            r#"[{"id":1,"timestamp":"2018-01-01T01:01:01Z","duration":0.0,"data":{}}]"#
+        );
+        let res = client
+            .get("/api/0/buckets/id/events?end=2018-01-01T01:01:01Z&inclusive_end=false")
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::Ok);
+        assert_eq!(res.into_string().unwrap(), "[]");
+
         // Heartbeat
         let res = client
             .post("/api/0/buckets/id/heartbeat?pulsetime=2")
@@ -244,6 +392,72 @@ mod api_tests {
             r#"[{"id":1,"timestamp":"2018-01-01T01:01:01Z","duration":2.0,"data":{}}]"#
         );
 
+        // Patch event data
+        let res = client
+            .patch("/api/0/buckets/id/events/1")
+            .header(ContentType::JSON)
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .body(r#"{"title": "fixed"}"#)
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::Ok);
+        assert_eq!(
+            res.into_string().unwrap(),
+            r#"{"id":1,"timestamp":"2018-01-01T01:01:01Z","duration":2.0,"data":{"title":"fixed"}}"#
+        );
+
+        // Changing the timestamp or duration through the patch is rejected
+        let res = client
+            .patch("/api/0/buckets/id/events/1")
+            .header(ContentType::JSON)
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .body(r#"{"duration": 5.0}"#)
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::BadRequest);
+
+        // But works when given as a separate field
+        let res = client
+            .patch("/api/0/buckets/id/events/1?duration=5.0")
+            .header(ContentType::JSON)
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .body(r#"{"title": null}"#)
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::Ok);
+        assert_eq!(
+            res.into_string().unwrap(),
+            r#"{"id":1,"timestamp":"2018-01-01T01:01:01Z","duration":5.0,"data":{}}"#
+        );
+        for duration in ["-1.0", "NaN", "inf"] {
+            let res = client
+                .patch(format!("/api/0/buckets/id/events/1?duration={duration}"))
+                .header(ContentType::JSON)
+                .header(Header::new("Host", "127.0.0.1:5600"))
+                .body("{}")
+                .dispatch();
+            assert_eq!(res.status(), rocket::http::Status::BadRequest);
+        }
+        // As are times which can't be stored in nanoseconds
+        for query in ["duration=1e300", "timestamp=3000-01-01T00:00:00Z"] {
+            let res = client
+                .patch(format!("/api/0/buckets/id/events/1?{query}"))
+                .header(ContentType::JSON)
+                .header(Header::new("Host", "127.0.0.1:5600"))
+                .body("{}")
+                .dispatch();
+            assert_eq!(res.status(), rocket::http::Status::BadRequest);
+        }
+        // Patching an event which doesn't exist
+        let res = client
+            .patch("/api/0/buckets/id/events/1000")
+            .header(ContentType::JSON)
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .body(r#"{"title": "fixed"}"#)
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::NotFound);
+        assert_eq!(
+            res.into_string().unwrap(),
+            r#"{"message":"The requested event '1000' does not exist"}"#
+        );
+
         // Delete event
         client
             .delete("/api/0/buckets/id/events/1")
@@ -259,6 +473,24 @@ mod api_tests {
         assert_eq!(res.status(), rocket::http::Status::Ok);
         assert_eq!(res.into_string().unwrap(), "0");
 
+        // Get bucket states
+        let res = client
+            .get("/api/0/bucket-states")
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::Ok);
+        let states: HashMap<String, aw_models::BucketState> =
+            serde_json::from_str(&res.into_string().unwrap()).unwrap();
+        assert_eq!(states.len(), 1);
+        assert_eq!(states["id"].event_count, 0);
+        assert!(states["id"].last_updated.is_some());
+        // Doesn't shadow a bucket named "states"
+        let res = client
+            .get("/api/0/buckets/states")
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::NotFound);
+
         // Delete bucket
         let res = client
             .delete("/api/0/buckets/id")
@@ -269,212 +501,1545 @@ mod api_tests {
     }
 
     #[test]
-    fn test_import_export() {
+    fn test_events_order() {
         let server = setup_testserver();
         let client = Client::untracked(server).expect("valid instance");
 
-        // Import bucket
         let res = client
-            .post("/api/0/import")
+            .post("/api/0/buckets/id")
             .header(ContentType::JSON)
             .header(Header::new("Host", "127.0.0.1:5600"))
-            .body(
-                r#"{"buckets":
-            {"id1": {
-                "id": "id1",
-                "type": "type",
-                "client": "client",
-                "hostname": "hostname",
-                "events": [{
-                    "timestamp":"2000-01-01T00:00:00Z",
-                    "duration":1.0,
-                    "data": {}
-                }]
-            }}}"#,
-            )
+            .body(r#"{"id": "id", "type": "type", "client": "client", "hostname": "hostname"}"#)
             .dispatch();
         assert_eq!(res.status(), rocket::http::Status::Ok);
 
-        // TODO: test more error cases
-        // Import already existing bucket
         let res = client
-            .post("/api/0/import")
+            .post("/api/0/buckets/id/events")
             .header(ContentType::JSON)
             .header(Header::new("Host", "127.0.0.1:5600"))
             .body(
-                r#"{"buckets":
-            {"id1": {
-                "id": "id1",
-                "type": "type",
-                "client": "client",
-                "hostname": "hostname",
-                "events": [{
-                    "timestamp":"2000-01-01T00:00:00Z",
-                    "duration":1.0,
-                    "data": {}
-                }]
-            }}}"#,
+                r#"[{"timestamp": "2018-01-01T01:01:01Z", "duration": 1.0, "data": {"n": 1}},
+                    {"timestamp": "2018-01-01T01:01:05Z", "duration": 1.0, "data": {"n": 2}}]"#,
             )
             .dispatch();
-        assert_eq!(res.status(), rocket::http::Status::InternalServerError);
+        assert_eq!(res.status(), rocket::http::Status::Ok);
+
+        let get_ns = |uri: &str| -> Vec<i64> {
+            let res = client
+                .get(uri)
+                .header(Header::new("Host", "127.0.0.1:5600"))
+                .dispatch();
+            assert_eq!(res.status(), rocket::http::Status::Ok);
+            let events: Vec<Event> = serde_json::from_str(&res.into_string().unwrap()).unwrap();
+            events
+                .iter()
+                .map(|e| e.data["n"].as_i64().unwrap())
+                .collect()
+        };
+
+        // Newest first by default
+        assert_eq!(get_ns("/api/0/buckets/id/events"), vec![2, 1]);
+
+        let get_n = |uri: &str| -> i64 {
+            let res = client
+                .get(uri)
+                .header(Header::new("Host", "127.0.0.1:5600"))
+                .dispatch();
+            assert_eq!(res.status(), rocket::http::Status::Ok);
+            let event: Event = serde_json::from_str(&res.into_string().unwrap()).unwrap();
+            event.data["n"].as_i64().unwrap()
+        };
+        assert_eq!(get_n("/api/0/buckets/id/events/first"), 1);
+        assert_eq!(get_n("/api/0/buckets/id/events/last"), 2);
+        assert_eq!(get_ns("/api/0/buckets/id/events?order=desc"), vec![2, 1]);
+        assert_eq!(get_ns("/api/0/buckets/id/events?order=asc"), vec![1, 2]);
+        // The limit keeps the first events in the requested order
         assert_eq!(
-            res.into_string().unwrap(),
-            r#"{"message":"Failed to import bucket: BucketAlreadyExists(\"id1\")"}"#
+            get_ns("/api/0/buckets/id/events?order=asc&limit=1"),
+            vec![1]
         );
 
-        // Export single created bucket
         let res = client
-            .get("/api/0/buckets/id1/export")
-            .header(ContentType::JSON)
+            .get("/api/0/buckets/id/events?order=sideways")
             .header(Header::new("Host", "127.0.0.1:5600"))
             .dispatch();
-        assert_eq!(res.status(), rocket::http::Status::Ok);
-        let export: BucketsExport = serde_json::from_str(&res.into_string().unwrap()).unwrap();
+        assert_eq!(res.status(), rocket::http::Status::BadRequest);
+    }
+
+    #[test]
+    fn test_events_after_id() {
+        let server = setup_testserver();
+        let client = Client::untracked(server).expect("valid instance");
 
-        // Delete bucket so we can import it again
         let res = client
-            .delete("/api/0/buckets/id1")
+            .post("/api/0/buckets/id")
             .header(ContentType::JSON)
             .header(Header::new("Host", "127.0.0.1:5600"))
+            .body(r#"{"id": "id", "type": "type", "client": "client", "hostname": "hostname"}"#)
             .dispatch();
         assert_eq!(res.status(), rocket::http::Status::Ok);
 
-        // Import exported bucket again but with multipart/form-data
-        let boundary_start = b"--a\r\n";
-        let disposition = b"Content-Disposition: form-data; name=\"buckets.json\"\r\n";
-        let content_type = b"Content-Type: application/json\r\n";
-        let data_start = b"\r\n";
-        let content = serde_json::to_vec(&export).unwrap();
-        let boundary_end = b"\r\n--a--";
-        let sum = [
-            &boundary_start[..],
-            &disposition[..],
-            &content_type[..],
-            &data_start[..],
-            &content[..],
-            &boundary_end[..],
-        ]
-        .concat();
-
+        // All at the same timestamp, which a timestamp cursor can't tell apart
         let res = client
-            .post("/api/0/import")
-            .header(Header::new(
-                "Content-Type",
-                "multipart/form-data; boundary=a",
-            ))
+            .post("/api/0/buckets/id/events")
+            .header(ContentType::JSON)
             .header(Header::new("Host", "127.0.0.1:5600"))
-            .body(&sum[..])
+            .body(
+                r#"[{"timestamp": "2018-01-01T01:01:01Z", "duration": 1.0, "data": {"n": 1}},
+                    {"timestamp": "2018-01-01T01:01:01Z", "duration": 1.0, "data": {"n": 2}},
+                    {"timestamp": "2018-01-01T01:01:01Z", "duration": 1.0, "data": {"n": 3}},
+                    {"timestamp": "2018-01-01T01:01:01Z", "duration": 1.0, "data": {"n": 4}}]"#,
+            )
             .dispatch();
         assert_eq!(res.status(), rocket::http::Status::Ok);
 
-        // Get created bucket
+        let get_events = |uri: &str| -> Vec<(i64, i64)> {
+            let res = client
+                .get(uri)
+                .header(Header::new("Host", "127.0.0.1:5600"))
+                .dispatch();
+            assert_eq!(res.status(), rocket::http::Status::Ok);
+            let events: Vec<Event> = serde_json::from_str(&res.into_string().unwrap()).unwrap();
+            events
+                .iter()
+                .map(|e| match e.id {
+                    Some(EventId::Int(id)) => (id, e.data["n"].as_i64().unwrap()),
+                    ref id => panic!("unexpected event id {id:?}"),
+                })
+                .collect()
+        };
+        let all = get_events("/api/0/buckets/id/events?after_id=0");
+        assert_eq!(
+            all.iter().map(|(_, n)| *n).collect::<Vec<_>>(),
+            vec![1, 2, 3, 4]
+        );
+
+        // Deleting an event leaves a gap in the ids, which is skipped over
         let res = client
-            .get("/api/0/buckets/id1")
-            .header(ContentType::JSON)
+            .delete(format!("/api/0/buckets/id/events/{}", all[1].0))
             .header(Header::new("Host", "127.0.0.1:5600"))
             .dispatch();
         assert_eq!(res.status(), rocket::http::Status::Ok);
-        println!("{:?}", res.into_string());
+        let page = get_events(&format!(
+            "/api/0/buckets/id/events?after_id={}&limit=2",
+            all[0].0
+        ));
+        assert_eq!(page, vec![all[2], all[3]]);
+        let page = get_events(&format!("/api/0/buckets/id/events?after_id={}", all[3].0));
+        assert!(page.is_empty());
 
-        // Export all buckets
         let res = client
-            .get("/api/0/export")
-            .header(ContentType::JSON)
+            .get("/api/0/buckets/id/events?after_id=0&order=asc")
             .header(Header::new("Host", "127.0.0.1:5600"))
             .dispatch();
-        assert_eq!(res.status(), rocket::http::Status::Ok);
-        let export: BucketsExport = serde_json::from_str(&res.into_string().unwrap()).unwrap();
-        let mut buckets = export.buckets;
-        assert_eq!(buckets.len(), 1);
-        let b = buckets.remove("id1").unwrap();
-        assert_eq!(b.events.unwrap().take_inner().len(), 1);
-
-        assert_eq!(buckets.len(), 0);
+        assert_eq!(res.status(), rocket::http::Status::BadRequest);
     }
 
     #[test]
-    fn test_query() {
+    fn test_events_fields() {
         let server = setup_testserver();
         let client = Client::untracked(server).expect("valid instance");
 
-        // Minimal query
         let res = client
-            .post("/api/0/query")
+            .post("/api/0/buckets/id")
             .header(ContentType::JSON)
             .header(Header::new("Host", "127.0.0.1:5600"))
-            .body(
-                r#"{
-                "timeperiods": ["2000-01-01T00:00:00Z/2020-01-01T00:00:00Z"],
-                "query": ["return 1;"]
-            }"#,
-            )
+            .body(r#"{"id": "id", "type": "type", "client": "client", "hostname": "hostname"}"#)
             .dispatch();
         assert_eq!(res.status(), rocket::http::Status::Ok);
-        assert_eq!(res.into_string().unwrap(), r#"[1.0]"#);
 
-        // Create bucket to query later
         let res = client
-            .post("/api/0/buckets/id")
+            .post("/api/0/buckets/id/events")
             .header(ContentType::JSON)
             .header(Header::new("Host", "127.0.0.1:5600"))
             .body(
-                r#"{
-                "id": "id",
-                "type": "type",
-                "client": "client",
-                "hostname": "hostname"
+                r#"[{"timestamp": "2018-01-01T01:01:01Z", "duration": 1.0, "data": {"app": "a", "title": "x"}},
+                    {"timestamp": "2018-01-01T01:01:05Z", "duration": 2.5, "data": {"title": "y"}}]"#,
+            )
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::Ok);
+
+        let res = client
+            .get("/api/0/buckets/id/events?order=asc&fields=timestamp,duration")
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::Ok);
+        let events: serde_json::Value = serde_json::from_str(&res.into_string().unwrap()).unwrap();
+        assert_eq!(
+            events,
+            json!([
+                {"timestamp": "2018-01-01T01:01:01Z", "duration": 1.0},
+                {"timestamp": "2018-01-01T01:01:05Z", "duration": 2.5},
+            ])
+        );
+
+        // Missing data keys are left out
+        let res = client
+            .get("/api/0/buckets/id/events?order=asc&fields=data.app")
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::Ok);
+        let events: serde_json::Value = serde_json::from_str(&res.into_string().unwrap()).unwrap();
+        assert_eq!(events, json!([{"data": {"app": "a"}}, {"data": {}}]));
+
+        let res = client
+            .get("/api/0/buckets/id/events?fields=title")
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::BadRequest);
+    }
+
+    #[test]
+    fn test_events_localtime() {
+        let state = endpoints::ServerState {
+            datastore: Arc::new(Mutex::new(aw_datastore::Datastore::new_in_memory(false))),
+            asset_resolver: endpoints::AssetResolver::new(None),
+            device_id: "test_id".to_string(),
+        };
+        let aw_config = config::AWConfig {
+            timezone: Some("Europe/Stockholm".to_string()),
+            ..Default::default()
+        };
+        let server = endpoints::build_rocket(state, aw_config);
+        let client = Client::untracked(server).expect("valid instance");
+
+        let res = client
+            .post("/api/0/buckets/id")
+            .header(ContentType::JSON)
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .body(r#"{"id": "id", "type": "type", "client": "client", "hostname": "hostname"}"#)
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::Ok);
+
+        // DST starts in between the events
+        let res = client
+            .post("/api/0/buckets/id/events")
+            .header(ContentType::JSON)
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .body(
+                r#"[{"timestamp": "2018-03-25T00:30:00Z", "duration": 1.0, "data": {"app": "a"}},
+                    {"timestamp": "2018-03-25T01:30:00Z", "duration": 2.0, "data": {"app": "b"}}]"#,
+            )
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::Ok);
+
+        let res = client
+            .get("/api/0/buckets/id/events?order=asc&localtime=true")
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::Ok);
+        let events: serde_json::Value = serde_json::from_str(&res.into_string().unwrap()).unwrap();
+        assert_eq!(events[0]["timestamp"], "2018-03-25T00:30:00Z");
+        assert_eq!(events[0]["local_timestamp"], "2018-03-25T01:30:00+01:00");
+        assert_eq!(events[0]["data"], json!({"app": "a"}));
+        assert_eq!(events[1]["timestamp"], "2018-03-25T01:30:00Z");
+        assert_eq!(events[1]["local_timestamp"], "2018-03-25T03:30:00+02:00");
+
+        // Combined with fields
+        let res = client
+            .get("/api/0/buckets/id/events?order=asc&localtime=true&fields=data.app")
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::Ok);
+        let events: serde_json::Value = serde_json::from_str(&res.into_string().unwrap()).unwrap();
+        assert_eq!(
+            events,
+            json!([
+                {"data": {"app": "a"}, "local_timestamp": "2018-03-25T01:30:00+01:00"},
+                {"data": {"app": "b"}, "local_timestamp": "2018-03-25T03:30:00+02:00"},
+            ])
+        );
+
+        // Not added unless asked for
+        let res = client
+            .get("/api/0/buckets/id/events?localtime=false")
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::Ok);
+        let events: serde_json::Value = serde_json::from_str(&res.into_string().unwrap()).unwrap();
+        assert!(events[0].get("local_timestamp").is_none());
+    }
+
+    #[test]
+    fn test_events_monotonic_timestamps() {
+        let server = setup_testserver();
+        let client = Client::untracked(server).expect("valid instance");
+
+        let insert = |bucket_id: &str, timestamp: &str| {
+            client
+                .post(format!("/api/0/buckets/{bucket_id}/events"))
+                .header(ContentType::JSON)
+                .header(Header::new("Host", "127.0.0.1:5600"))
+                .body(format!(
+                    r#"[{{"timestamp": "{timestamp}", "duration": 1.0, "data": {{}}}}]"#
+                ))
+                .dispatch()
+                .status()
+        };
+        for (bucket_id, data) in [
+            ("strict", r#"{"monotonic_timestamps": true}"#),
+            ("plain", "{}"),
+        ] {
+            let res = client
+                .post(format!("/api/0/buckets/{bucket_id}"))
+                .header(ContentType::JSON)
+                .header(Header::new("Host", "127.0.0.1:5600"))
+                .body(format!(
+                    r#"{{"type": "type", "client": "client", "hostname": "hostname", "data": {data}}}"#
+                ))
+                .dispatch();
+            assert_eq!(res.status(), rocket::http::Status::Ok);
+            assert_eq!(insert(bucket_id, "2018-01-01T01:01:01Z"), Status::Ok);
+        }
+
+        assert_eq!(insert("strict", "2018-01-01T01:00:00Z"), Status::Conflict);
+        assert_eq!(insert("strict", "2018-01-01T01:01:01Z"), Status::Ok);
+        assert_eq!(insert("strict", "2018-01-01T02:00:00Z"), Status::Ok);
+
+        // Buckets without the flag can be backfilled
+        assert_eq!(insert("plain", "2018-01-01T01:00:00Z"), Status::Ok);
+    }
+
+    #[test]
+    fn test_events_distinct() {
+        let server = setup_testserver();
+        let client = Client::untracked(server).expect("valid instance");
+
+        let res = client
+            .post("/api/0/buckets/id")
+            .header(ContentType::JSON)
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .body(r#"{"id": "id", "type": "type", "client": "client", "hostname": "hostname"}"#)
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::Ok);
+        let res = client
+            .post("/api/0/buckets/id/events")
+            .header(ContentType::JSON)
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .body(
+                r#"[
+                {"timestamp": "2018-01-01T01:00:00Z", "duration": 1.0, "data": {"app": "Editor"}},
+                {"timestamp": "2018-01-01T02:00:00Z", "duration": 1.0, "data": {"app": "Browser"}},
+                {"timestamp": "2018-01-01T03:00:00Z", "duration": 1.0, "data": {"app": "Browser"}}
+            ]"#,
+            )
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::Ok);
+
+        let res = client
+            .get("/api/0/buckets/id/events/distinct?key=app")
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::Ok);
+        assert_eq!(res.into_string().unwrap(), r#"["Browser","Editor"]"#);
+
+        let res = client
+            .get("/api/0/buckets/id/events/distinct?key=app&counts=true&start=2018-01-01T01:30:00Z")
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::Ok);
+        assert_eq!(
+            res.into_string().unwrap(),
+            r#"[{"count":2,"value":"Browser"}]"#
+        );
+
+        let res = client
+            .get("/api/0/buckets/id/events/distinct")
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::BadRequest);
+
+        let res = client
+            .get("/api/0/buckets/missing/events/distinct?key=app")
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::NotFound);
+    }
+
+    #[test]
+    fn test_bucket_quality() {
+        let server = setup_testserver();
+        let client = Client::untracked(server).expect("valid instance");
+
+        let res = client
+            .post("/api/0/buckets/id")
+            .header(ContentType::JSON)
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .body(r#"{"id": "id", "type": "type", "client": "client", "hostname": "hostname"}"#)
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::Ok);
+        // The first two overlap, the third has no duration and the last is inserted after it
+        // even though it starts before it
+        let res = client
+            .post("/api/0/buckets/id/events")
+            .header(ContentType::JSON)
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .body(
+                r#"[
+                {"timestamp": "2018-01-01T01:00:00Z", "duration": 60.0, "data": {"app": "Editor"}},
+                {"timestamp": "2018-01-01T01:00:30Z", "duration": 60.0, "data": {"app": "Browser"}},
+                {"timestamp": "2018-01-01T03:00:00Z", "duration": 0.0, "data": {"app": "Browser"}},
+                {"timestamp": "2018-01-01T02:00:00Z", "duration": 60.0, "data": {"app": "Editor"}}
+            ]"#,
+            )
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::Ok);
+
+        let res = client
+            .get("/api/0/buckets/id/quality")
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::Ok);
+        let quality: Value = serde_json::from_str(&res.into_string().unwrap()).unwrap();
+        assert_eq!(
+            quality,
+            json!({
+                "event_count": 4,
+                "overlaps": {"count": 2, "sample_ids": [1, 2]},
+                "gaps": {"count": 2, "duration": 7050.0, "longest": 3540.0},
+                "out_of_order": {"count": 1, "sample_ids": [4]},
+                "zero_duration": {"count": 1, "sample_ids": [3]},
+            })
+        );
+
+        // The gap before 02:00 is shorter than the minimum, the last one now lasts until the end
+        // of the range as the event without a duration covers no time
+        let res = client
+            .get("/api/0/buckets/id/quality?min_gap=3520&end=2018-01-01T04:00:00Z")
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::Ok);
+        let quality: Value = serde_json::from_str(&res.into_string().unwrap()).unwrap();
+        assert_eq!(
+            quality["gaps"],
+            json!({"count": 1, "duration": 7140.0, "longest": 7140.0})
+        );
+
+        let res = client
+            .get("/api/0/buckets/id/quality?start=yesterday")
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::BadRequest);
+
+        let res = client
+            .get("/api/0/buckets/missing/quality")
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::NotFound);
+
+        // The insertion order of events with UUIDs isn't known
+        let datastore = aw_datastore::Datastore::new_in_memory(false);
+        datastore.set_uuid_event_ids(true).unwrap();
+        let state = endpoints::ServerState {
+            datastore: Arc::new(Mutex::new(datastore)),
+            asset_resolver: endpoints::AssetResolver::new(None),
+            device_id: "test_id".to_string(),
+        };
+        let client = Client::untracked(endpoints::build_rocket(state, config::AWConfig::default()))
+            .expect("valid instance");
+        let res = client
+            .post("/api/0/buckets/id")
+            .header(ContentType::JSON)
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .body(r#"{"id": "id", "type": "type", "client": "client", "hostname": "hostname"}"#)
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::Ok);
+        let res = client
+            .post("/api/0/buckets/id/events")
+            .header(ContentType::JSON)
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .body(r#"[{"timestamp": "2018-01-01T01:00:00Z", "duration": 60.0, "data": {}}]"#)
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::Ok);
+        let res = client
+            .get("/api/0/buckets/id/quality")
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::Ok);
+        let quality: Value = serde_json::from_str(&res.into_string().unwrap()).unwrap();
+        assert_eq!(quality["event_count"], 1);
+        assert_eq!(quality["out_of_order"], Value::Null);
+    }
+
+    #[test]
+    fn test_request_id() {
+        let server = setup_testserver();
+        let client = Client::untracked(server).expect("valid instance");
+
+        let res = client
+            .get("/api/0/info")
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .header(Header::new("X-Request-Id", "watcher-42"))
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::Ok);
+        assert_eq!(res.headers().get_one("X-Request-Id"), Some("watcher-42"));
+
+        // Errors get it too
+        let res = client
+            .get("/api/0/buckets/missing")
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .header(Header::new("X-Request-Id", "watcher-43"))
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::NotFound);
+        assert_eq!(res.headers().get_one("X-Request-Id"), Some("watcher-43"));
+
+        let res = client
+            .get("/api/0/info")
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .dispatch();
+        assert_eq!(res.headers().get_one("X-Request-Id"), None);
+    }
+
+    #[test]
+    fn test_events_head_count() {
+        let server = setup_testserver();
+        let client = Client::untracked(server).expect("valid instance");
+
+        let head_count = |client: &Client| {
+            let res = client
+                .head("/api/0/buckets/id/events")
+                .header(Header::new("Host", "127.0.0.1:5600"))
+                .dispatch();
+            assert_eq!(res.status(), rocket::http::Status::Ok);
+            let count = res.headers().get_one("X-Event-Count").map(str::to_string);
+            assert!(res.into_string().unwrap_or_default().is_empty());
+            count
+        };
+
+        let res = client
+            .head("/api/0/buckets/id/events")
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::NotFound);
+
+        let res = client
+            .post("/api/0/buckets/id")
+            .header(ContentType::JSON)
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .body(r#"{"id": "id", "type": "type", "client": "client", "hostname": "hostname"}"#)
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::Ok);
+        assert_eq!(head_count(&client).as_deref(), Some("0"));
+
+        // An empty bucket has no first or last event
+        let res = client
+            .get("/api/0/buckets/id/events/first")
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::NoContent);
+
+        let res = client
+            .post("/api/0/buckets/id/events")
+            .header(ContentType::JSON)
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .body(
+                r#"[{"timestamp": "2018-01-01T01:01:01Z", "duration": 1.0, "data": {}},
+                    {"timestamp": "2018-01-01T01:01:05Z", "duration": 1.0, "data": {}}]"#,
+            )
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::Ok);
+        assert_eq!(head_count(&client).as_deref(), Some("2"));
+
+        client
+            .delete("/api/0/buckets/id/events/1")
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .dispatch();
+        assert_eq!(head_count(&client).as_deref(), Some("1"));
+    }
+
+    #[test]
+    fn test_events_stream() {
+        let server = setup_testserver();
+        let client = Client::untracked(server).expect("valid instance");
+        let ndjson = ContentType::new("application", "x-ndjson");
+
+        // Create bucket
+        let res = client
+            .post("/api/0/buckets/id")
+            .header(ContentType::JSON)
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .body(
+                r#"{
+                "id": "id",
+                "type": "type",
+                "client": "client",
+                "hostname": "hostname"
+            }"#,
+            )
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::Ok);
+
+        // Insert events, empty lines are skipped
+        let body = (0..2500)
+            .map(|i| {
+                format!(
+                    r#"{{"timestamp": "2018-01-01T01:01:01Z", "duration": 1.0, "data": {{"i": {i}}}}}"#
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+            + "\n\n";
+        let res = client
+            .post("/api/0/buckets/id/events/stream")
+            .header(ndjson.clone())
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .body(body)
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::Ok);
+        assert_eq!(
+            res.into_json::<Value>().unwrap(),
+            json!({ "inserted": 2500 })
+        );
+
+        let res = client
+            .get("/api/0/buckets/id/events/count")
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .dispatch();
+        assert_eq!(res.into_string().unwrap(), "2500");
+
+        // Invalid line
+        let res = client
+            .post("/api/0/buckets/id/events/stream")
+            .header(ndjson)
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .body("{\"timestamp\": \"2018-01-01T01:01:01Z\", \"duration\": 1.0, \"data\": {}}\ninvalid")
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::BadRequest);
+    }
+
+    #[test]
+    fn test_events_conditional_get() {
+        let server = setup_testserver();
+        let client = Client::untracked(server).expect("valid instance");
+
+        // Create bucket
+        let res = client
+            .post("/api/0/buckets/id")
+            .header(ContentType::JSON)
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .body(
+                r#"{
+                "id": "id",
+                "type": "type",
+                "client": "client",
+                "hostname": "hostname"
+            }"#,
+            )
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::Ok);
+
+        // Get events, response should contain validators
+        let res = client
+            .get("/api/0/buckets/id/events")
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::Ok);
+        let etag = res.headers().get_one("ETag").unwrap().to_string();
+        let last_modified = res.headers().get_one("Last-Modified").unwrap().to_string();
+
+        // Unchanged bucket returns 304
+        let res = client
+            .get("/api/0/buckets/id/events")
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .header(Header::new("If-None-Match", etag.clone()))
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::NotModified);
+        assert_eq!(res.into_string(), None);
+
+        // The ETag depends on the parameters of the request
+        let res = client
+            .get("/api/0/buckets/id/events?limit=1")
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .header(Header::new("If-None-Match", etag.clone()))
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::Ok);
+        assert_ne!(res.headers().get_one("ETag").unwrap(), etag);
+
+        // The bucket could still change within the second of Last-Modified
+        let res = client
+            .get("/api/0/buckets/id/events")
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .header(Header::new("If-Modified-Since", last_modified))
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::Ok);
+
+        let res = client
+            .get("/api/0/buckets/id/events")
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .header(Header::new(
+                "If-Modified-Since",
+                "Fri, 01 Jan 2100 00:00:00 GMT",
+            ))
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::NotModified);
+
+        // Insert an event, the old ETag is no longer current
+        let res = client
+            .post("/api/0/buckets/id/events")
+            .header(ContentType::JSON)
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .body(
+                r#"[{
+                "timestamp": "2018-01-01T01:01:01Z",
+                "duration": 1.0,
+                "data": {}
+            }]"#,
+            )
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::Ok);
+
+        let res = client
+            .get("/api/0/buckets/id/events")
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .header(Header::new("If-None-Match", etag.clone()))
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::Ok);
+        assert_ne!(res.headers().get_one("ETag").unwrap(), etag);
+        assert_eq!(
+            res.into_string().unwrap(),
+            r#"[{"id":1,"timestamp":"2018-01-01T01:01:01Z","duration":1.0,"data":{}}]"#
+        );
+
+        // Validators from a date far in the past are stale
+        let res = client
+            .get("/api/0/buckets/id/events")
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .header(Header::new(
+                "If-Modified-Since",
+                "Mon, 01 Jan 2018 00:00:00 GMT",
+            ))
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::Ok);
+    }
+
+    #[test]
+    fn test_import_export() {
+        let server = setup_testserver();
+        let client = Client::untracked(server).expect("valid instance");
+
+        // Import bucket
+        let res = client
+            .post("/api/0/import")
+            .header(ContentType::JSON)
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .body(
+                r#"{"buckets":
+            {"id1": {
+                "id": "id1",
+                "type": "type",
+                "client": "client",
+                "hostname": "hostname",
+                "events": [{
+                    "timestamp":"2000-01-01T00:00:00Z",
+                    "duration":1.0,
+                    "data": {}
+                }]
+            }}}"#,
+            )
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::Ok);
+
+        // TODO: test more error cases
+        // Import already existing bucket
+        let res = client
+            .post("/api/0/import")
+            .header(ContentType::JSON)
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .body(
+                r#"{"buckets":
+            {"id1": {
+                "id": "id1",
+                "type": "type",
+                "client": "client",
+                "hostname": "hostname",
+                "events": [{
+                    "timestamp":"2000-01-01T00:00:00Z",
+                    "duration":1.0,
+                    "data": {}
+                }]
+            }}}"#,
+            )
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::InternalServerError);
+        assert_eq!(
+            res.into_string().unwrap(),
+            r#"{"message":"Failed to import bucket: BucketAlreadyExists(\"id1\")"}"#
+        );
+
+        // Export single created bucket
+        let res = client
+            .get("/api/0/buckets/id1/export")
+            .header(ContentType::JSON)
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::Ok);
+        let export: BucketsExport = serde_json::from_str(&res.into_string().unwrap()).unwrap();
+
+        // Delete bucket so we can import it again
+        let res = client
+            .delete("/api/0/buckets/id1")
+            .header(ContentType::JSON)
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::Ok);
+
+        // Import exported bucket again but with multipart/form-data
+        let boundary_start = b"--a\r\n";
+        let disposition = b"Content-Disposition: form-data; name=\"buckets.json\"\r\n";
+        let content_type = b"Content-Type: application/json\r\n";
+        let data_start = b"\r\n";
+        let content = serde_json::to_vec(&export).unwrap();
+        let boundary_end = b"\r\n--a--";
+        let sum = [
+            &boundary_start[..],
+            &disposition[..],
+            &content_type[..],
+            &data_start[..],
+            &content[..],
+            &boundary_end[..],
+        ]
+        .concat();
+
+        let res = client
+            .post("/api/0/import")
+            .header(Header::new(
+                "Content-Type",
+                "multipart/form-data; boundary=a",
+            ))
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .body(&sum[..])
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::Ok);
+
+        // Get created bucket
+        let res = client
+            .get("/api/0/buckets/id1")
+            .header(ContentType::JSON)
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::Ok);
+        println!("{:?}", res.into_string());
+
+        // Export all buckets
+        let res = client
+            .get("/api/0/export")
+            .header(ContentType::JSON)
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::Ok);
+        let export: BucketsExport = serde_json::from_str(&res.into_string().unwrap()).unwrap();
+        let mut buckets = export.buckets;
+        assert_eq!(buckets.len(), 1);
+        let b = buckets.remove("id1").unwrap();
+        assert_eq!(b.events.unwrap().take_inner().len(), 1);
+
+        assert_eq!(buckets.len(), 0);
+    }
+
+    #[test]
+    fn test_export_python_format() {
+        let server = setup_testserver();
+        let client = Client::untracked(server).expect("valid instance");
+
+        // The export of the Python aw-server, compacted
+        let python_export = concat!(
+            r#"{"buckets":{"aw-watcher-afk_host":{"id":"aw-watcher-afk_host","#,
+            r#""created":"2023-01-02T10:00:00.123456+00:00","name":null,"type":"afkstatus","#,
+            r#""client":"aw-watcher-afk","hostname":"host","data":{},"events":["#,
+            r#"{"timestamp":"2023-01-02T10:05:00.250000+00:00","duration":30.5,"#,
+            r#""data":{"status":"afk"}},"#,
+            r#"{"timestamp":"2023-01-02T10:00:00+00:00","duration":300.0,"#,
+            r#""data":{"status":"not-afk"}}]}}}"#
+        );
+        let res = client
+            .post("/api/0/import")
+            .header(ContentType::JSON)
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .body(python_export)
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::Ok);
+
+        for url in [
+            "/api/0/export?format=aw-python",
+            "/api/0/buckets/aw-watcher-afk_host/export?format=aw-python",
+        ] {
+            let res = client
+                .get(url)
+                .header(Header::new("Host", "127.0.0.1:5600"))
+                .dispatch();
+            assert_eq!(res.status(), rocket::http::Status::Ok);
+            assert_eq!(res.into_string().unwrap(), python_export);
+        }
+
+        let res = client
+            .get("/api/0/export?format=aw-go")
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::BadRequest);
+    }
+
+    #[test]
+    fn test_import_preserve_ids() {
+        let server = setup_testserver();
+        let client = Client::untracked(server).expect("valid instance");
+
+        let import_body = |bucket_id: &str| {
+            format!(
+                r#"{{"buckets":
+            {{"{bucket_id}": {{
+                "id": "{bucket_id}",
+                "type": "type",
+                "client": "client",
+                "hostname": "hostname",
+                "events": [
+                    {{"id": 10, "timestamp":"2000-01-01T00:00:00Z", "duration":1.0, "data": {{}}}},
+                    {{"id": 11, "timestamp":"2000-01-01T00:00:01Z", "duration":1.0, "data": {{}}}}
+                ]
+            }}}}}}"#
+            )
+        };
+        let event_ids = |bucket_id: &str| {
+            let res = client
+                .get(format!("/api/0/buckets/{bucket_id}/events"))
+                .header(Header::new("Host", "127.0.0.1:5600"))
+                .dispatch();
+            assert_eq!(res.status(), rocket::http::Status::Ok);
+            let events: Vec<Event> = serde_json::from_str(&res.into_string().unwrap()).unwrap();
+            let mut ids: Vec<i64> = events
+                .into_iter()
+                .map(|e| match e.id {
+                    Some(EventId::Int(id)) => id,
+                    id => panic!("unexpected event id {id:?}"),
+                })
+                .collect();
+            ids.sort();
+            ids
+        };
+
+        // Ids from the export are kept
+        let res = client
+            .post("/api/0/import?preserve_ids=true")
+            .header(ContentType::JSON)
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .body(import_body("id1"))
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::Ok);
+        assert_eq!(event_ids("id1"), vec![10, 11]);
+
+        // Colliding ids fail the import instead of replacing the existing events
+        let res = client
+            .post("/api/0/import?preserve_ids=true")
+            .header(ContentType::JSON)
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .body(import_body("id2"))
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::Conflict);
+        assert_eq!(
+            res.into_string().unwrap(),
+            r#"{"message":"An event with id '10' already exists"}"#
+        );
+        let res = client
+            .get("/api/0/buckets/id2")
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::NotFound);
+        assert_eq!(event_ids("id1"), vec![10, 11]);
+
+        // Without preserve_ids new ids are assigned
+        let res = client
+            .post("/api/0/import")
+            .header(ContentType::JSON)
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .body(import_body("id2"))
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::Ok);
+        let ids = event_ids("id2");
+        assert_eq!(ids.len(), 2);
+        assert!(!ids.contains(&10) && !ids.contains(&11));
+        assert_eq!(event_ids("id1"), vec![10, 11]);
+    }
+
+    #[test]
+    fn test_bucket_clone() {
+        let server = setup_testserver();
+        let client = Client::untracked(server).expect("valid instance");
+
+        let res = client
+            .post("/api/0/buckets/id")
+            .header(ContentType::JSON)
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .body(r#"{"id": "id", "type": "type", "client": "client", "hostname": "hostname"}"#)
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::Ok);
+        let res = client
+            .post("/api/0/buckets/id/events")
+            .header(ContentType::JSON)
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .body(r#"[{"timestamp":"2000-01-01T00:00:00Z", "duration":1.0, "data": {"a": 1}}]"#)
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::Ok);
+
+        let clone = || {
+            client
+                .post("/api/0/buckets/id/clone?new_id=sandbox")
+                .header(Header::new("Host", "127.0.0.1:5600"))
+                .dispatch()
+        };
+        assert_eq!(clone().status(), rocket::http::Status::Ok);
+        let res = client
+            .get("/api/0/buckets/sandbox/events")
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::Ok);
+        let events: Vec<Event> = serde_json::from_str(&res.into_string().unwrap()).unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].data["a"], 1);
+
+        // The target already exists
+        let res = clone();
+        assert_eq!(res.status(), rocket::http::Status::Conflict);
+        assert_eq!(
+            res.into_string().unwrap(),
+            r#"{"message":"Bucket 'sandbox' already exists"}"#
+        );
+
+        let res = client
+            .post("/api/0/buckets/nonexistent/clone?new_id=other")
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::NotFound);
+    }
+
+    #[test]
+    fn test_query() {
+        let server = setup_testserver();
+        let client = Client::untracked(server).expect("valid instance");
+
+        // Minimal query
+        let res = client
+            .post("/api/0/query")
+            .header(ContentType::JSON)
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .body(
+                r#"{
+                "timeperiods": ["2000-01-01T00:00:00Z/2020-01-01T00:00:00Z"],
+                "query": ["return 1;"]
+            }"#,
+            )
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::Ok);
+        assert_eq!(res.into_string().unwrap(), r#"[1.0]"#);
+
+        // Create bucket to query later
+        let res = client
+            .post("/api/0/buckets/id")
+            .header(ContentType::JSON)
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .body(
+                r#"{
+                "id": "id",
+                "type": "type",
+                "client": "client",
+                "hostname": "hostname"
+            }"#,
+            )
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::Ok);
+
+        // Insert a event to query later
+        let res = client
+            .post("/api/0/buckets/id/events")
+            .header(ContentType::JSON)
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .body(
+                r#"[{
+                "timestamp": "2018-01-01T01:01:01Z",
+                "duration": 1.0,
+                "data": {}
+            }]"#,
+            )
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::Ok);
+
+        // Query events
+        let res = client
+            .post("/api/0/query")
+            .header(ContentType::JSON)
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .body(
+                r#"{
+                "timeperiods": ["2000-01-01T00:00:00Z/2020-01-01T00:00:00Z"],
+                "query": ["return query_bucket(\"id\");"]
+            }"#,
+            )
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::Ok);
+        assert_eq!(
+            res.into_string().unwrap(),
+            r#"[[{"data":{},"duration":1.0,"id":1,"timestamp":"2018-01-01T01:01:01Z"}]]"#
+        );
+
+        // Test error
+        let res = client
+            .post("/api/0/query")
+            .header(ContentType::JSON)
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .body(
+                r#"{
+                "timeperiods": ["2000-01-01T00:00:00Z/2020-01-01T00:00:00Z"],
+                "query": [""]
+            }"#,
+            )
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::InternalServerError);
+        assert_eq!(res.into_string().unwrap(), r#"{"message":"EmptyQuery"}"#);
+
+        // Parameters are bound to placeholders
+        let res = client
+            .post("/api/0/query")
+            .header(ContentType::JSON)
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .body(
+                r#"{
+                "timeperiods": ["2000-01-01T00:00:00Z/2020-01-01T00:00:00Z"],
+                "query": ["return [query_bucket($bucket), $factor * 2];"],
+                "params": {"bucket": "id", "factor": 1.5}
+            }"#,
+            )
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::Ok);
+        assert_eq!(
+            res.into_string().unwrap(),
+            r#"[[[{"data":{},"duration":1.0,"id":1,"timestamp":"2018-01-01T01:01:01Z"}],3.0]]"#
+        );
+    }
+
+    #[test]
+    fn test_stats() {
+        let server = setup_testserver();
+        let client = Client::untracked(server).expect("valid instance");
+
+        let res = client
+            .post("/api/0/buckets/id")
+            .header(ContentType::JSON)
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .body(r#"{"id": "id", "type": "type", "client": "client", "hostname": "hostname"}"#)
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::Ok);
+
+        let res = client
+            .post("/api/0/buckets/id/events")
+            .header(ContentType::JSON)
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .body(
+                r#"[
+                {"timestamp": "2018-01-01T01:01:01Z", "duration": 1.0, "data": {}},
+                {"timestamp": "2018-01-01T01:01:02Z", "duration": 1.0, "data": {}}
+            ]"#,
+            )
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::Ok);
+
+        // Heartbeats count when they create an event, not when they are merged into one
+        for timestamp in ["2018-01-01T01:01:05Z", "2018-01-01T01:01:06Z"] {
+            let res = client
+                .post("/api/0/buckets/id/heartbeat?pulsetime=10")
+                .header(ContentType::JSON)
+                .header(Header::new("Host", "127.0.0.1:5600"))
+                .body(format!(
+                    r#"{{"timestamp": "{timestamp}", "duration": 0.0, "data": {{"a": 1}}}}"#
+                ))
+                .dispatch();
+            assert_eq!(res.status(), rocket::http::Status::Ok);
+        }
+
+        let res = client
+            .delete("/api/0/buckets/id/events/1")
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::Ok);
+
+        for query in [r#"["return 1;"]"#, r#"[""]"#] {
+            client
+                .post("/api/0/query")
+                .header(ContentType::JSON)
+                .header(Header::new("Host", "127.0.0.1:5600"))
+                .body(format!(
+                    r#"{{"timeperiods": ["2000-01-01T00:00:00Z/2020-01-01T00:00:00Z"], "query": {query}}}"#
+                ))
+                .dispatch();
+        }
+
+        let res = client
+            .get("/api/0/info")
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::Ok);
+        let info: Value = serde_json::from_str(&res.into_string().unwrap()).unwrap();
+        assert_eq!(
+            info["stats"],
+            json!({
+                "events_inserted": 3,
+                "events_deleted": 1,
+                "queries_run": 2,
+                "query_errors": 1,
+            })
+        );
+        assert_eq!(info["capabilities"]["query_streaming"], true);
+        assert_eq!(info["capabilities"]["uuid_event_ids"], false);
+        assert!(info["capabilities"]["query_functions"]
+            .as_array()
+            .unwrap()
+            .contains(&json!("query_bucket")));
+
+        let res = client
+            .get("/api/0/metrics")
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::Ok);
+        let metrics = res.into_string().unwrap();
+        assert!(metrics.contains("# TYPE aw_events_inserted_total counter\n"));
+        assert!(metrics.contains("aw_events_inserted_total 3\n"));
+        assert!(metrics.contains("aw_events_deleted_total 1\n"));
+        assert!(metrics.contains("aw_queries_run_total 2\n"));
+        assert!(metrics.contains("aw_query_errors_total 1\n"));
+    }
+
+    #[test]
+    fn test_query_timeperiods() {
+        let state = endpoints::ServerState {
+            datastore: Arc::new(Mutex::new(aw_datastore::Datastore::new_in_memory(false))),
+            asset_resolver: endpoints::AssetResolver::new(None),
+            device_id: "test_id".to_string(),
+        };
+        let aw_config = config::AWConfig {
+            query_default_timeperiod_days: 7,
+            query_max_timeperiod_days: Some(30),
+            timezone: Some("UTC".to_string()),
+            ..Default::default()
+        };
+        let server = endpoints::build_rocket(state, aw_config);
+        let client = Client::untracked(server).expect("valid instance");
+
+        let query = |timeperiods: &str| {
+            client
+                .post("/api/0/query")
+                .header(ContentType::JSON)
+                .header(Header::new("Host", "127.0.0.1:5600"))
+                .body(format!(
+                    r#"{{
+                    "timeperiods": {timeperiods},
+                    "query": ["RETURN = TIMEINTERVAL;"]
+                }}"#
+                ))
+                .dispatch()
+        };
+
+        // Missing end is filled in with the default
+        let res = query(r#"["2000-01-01T00:00:00Z/"]"#);
+        assert_eq!(res.status(), rocket::http::Status::Ok);
+        assert_eq!(
+            res.into_string().unwrap(),
+            r#"["2000-01-01T00:00:00+00:00/2000-01-08T00:00:00+00:00"]"#
+        );
+
+        // Missing start is filled in with the default
+        let res = query(r#"["/2000-01-08T00:00:00Z"]"#);
+        assert_eq!(res.status(), rocket::http::Status::Ok);
+        assert_eq!(
+            res.into_string().unwrap(),
+            r#"["2000-01-01T00:00:00+00:00/2000-01-08T00:00:00+00:00"]"#
+        );
+
+        // Timeperiod longer than the max is rejected
+        let res = query(r#"["2000-01-01T00:00:00Z/2000-03-01T00:00:00Z"]"#);
+        assert_eq!(res.status(), rocket::http::Status::BadRequest);
+
+        // The max applies to each timeperiod separately
+        let res = query(
+            r#"["2000-01-01T00:00:00Z/2000-01-30T00:00:00Z", "2000-01-30T00:00:00Z/2000-02-28T00:00:00Z"]"#,
+        );
+        assert_eq!(res.status(), rocket::http::Status::Ok);
+
+        // Invalid timeperiod
+        let res = query(r#"["2000-01-01T00:00:00Z"]"#);
+        assert_eq!(res.status(), rocket::http::Status::BadRequest);
+
+        // Shortcuts are resolved in the configured timezone
+        let res = query(r#"["today"]"#);
+        assert_eq!(res.status(), rocket::http::Status::Ok);
+        let today = chrono::Utc::now()
+            .date_naive()
+            .and_hms_opt(0, 0, 0)
+            .unwrap();
+        assert_eq!(
+            res.into_json::<Value>().unwrap(),
+            json!([format!(
+                "{}/{}",
+                today.and_utc().to_rfc3339(),
+                (today + chrono::Duration::days(1)).and_utc().to_rfc3339()
+            )])
+        );
+    }
+
+    #[test]
+    fn test_query_cancel() {
+        let server = setup_testserver();
+        let client = Client::untracked(server).expect("valid instance");
+
+        // Query id is returned in a header, client can choose it
+        let res = client
+            .post("/api/0/query")
+            .header(ContentType::JSON)
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .header(Header::new("X-Query-Id", "my-query"))
+            .body(
+                r#"{
+                "timeperiods": ["2000-01-01T00:00:00Z/2020-01-01T00:00:00Z"],
+                "query": ["return 1;"]
             }"#,
             )
             .dispatch();
         assert_eq!(res.status(), rocket::http::Status::Ok);
+        assert_eq!(res.headers().get_one("X-Query-Id"), Some("my-query"));
 
-        // Insert a event to query later
+        // Otherwise it's generated
         let res = client
-            .post("/api/0/buckets/id/events")
+            .post("/api/0/query")
             .header(ContentType::JSON)
             .header(Header::new("Host", "127.0.0.1:5600"))
             .body(
-                r#"[{
-                "timestamp": "2018-01-01T01:01:01Z",
-                "duration": 1.0,
-                "data": {}
-            }]"#,
+                r#"{
+                "timeperiods": ["2000-01-01T00:00:00Z/2020-01-01T00:00:00Z"],
+                "query": ["return 1;"]
+            }"#,
             )
             .dispatch();
         assert_eq!(res.status(), rocket::http::Status::Ok);
+        assert!(res.headers().get_one("X-Query-Id").is_some());
 
-        // Query events
+        // Finished queries are removed from the registry and can't be cancelled
         let res = client
+            .post("/api/0/query/my-query/cancel")
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::NotFound);
+    }
+
+    #[rocket::async_test]
+    async fn test_query_cancel_running() {
+        use rocket::local::asynchronous::Client;
+
+        let datastore = Arc::new(Mutex::new(aw_datastore::Datastore::new_in_memory(false)));
+        let state = endpoints::ServerState {
+            datastore: datastore.clone(),
+            asset_resolver: endpoints::AssetResolver::new(None),
+            device_id: "test_id".to_string(),
+        };
+        let client = Client::untracked(endpoints::build_rocket(state, config::AWConfig::default()))
+            .await
+            .expect("valid instance");
+
+        // The query is kept running by holding the datastore until it has been cancelled
+        let busy = datastore.lock().unwrap();
+        let query = client
             .post("/api/0/query")
             .header(ContentType::JSON)
             .header(Header::new("Host", "127.0.0.1:5600"))
+            .header(Header::new("X-Query-Id", "long-query"))
             .body(
                 r#"{
                 "timeperiods": ["2000-01-01T00:00:00Z/2020-01-01T00:00:00Z"],
-                "query": ["return query_bucket(\"id\");"]
+                "query": ["return 1;"]
             }"#,
             )
             .dispatch();
-        assert_eq!(res.status(), rocket::http::Status::Ok);
+        let cancel = async {
+            let res = client
+                .post("/api/0/query/long-query/cancel")
+                .header(Header::new("Host", "127.0.0.1:5600"))
+                .dispatch()
+                .await;
+            drop(busy);
+            res.status()
+        };
+        let (res, cancel_status) = rocket::tokio::join!(query, cancel);
+        assert_eq!(cancel_status, rocket::http::Status::Ok);
+        assert_eq!(res.status(), rocket::http::Status::ServiceUnavailable);
+        assert_eq!(
+            res.into_string().await.unwrap(),
+            r#"{"message":"Query long-query was cancelled"}"#
+        );
+
+        // The query has been removed from the registry
+        let res = client
+            .post("/api/0/query/long-query/cancel")
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .dispatch()
+            .await;
+        assert_eq!(res.status(), rocket::http::Status::NotFound);
+    }
+
+    #[test]
+    fn test_request_timeout() {
+        let datastore = Arc::new(Mutex::new(aw_datastore::Datastore::new_in_memory(false)));
+        let state = endpoints::ServerState {
+            datastore: datastore.clone(),
+            asset_resolver: endpoints::AssetResolver::new(None),
+            device_id: "test_id".to_string(),
+        };
+        let config = config::AWConfig {
+            long_request_timeout_secs: 1,
+            ..Default::default()
+        };
+        let client =
+            Client::untracked(endpoints::build_rocket(state, config)).expect("valid instance");
+        let query = r#"{
+            "timeperiods": ["2000-01-01T00:00:00Z/2020-01-01T00:00:00Z"],
+            "query": ["return 1;"]
+        }"#;
+
+        // The datastore is busy for longer than the timeout
+        let busy = datastore.lock().unwrap();
+        let res = client
+            .post("/api/0/query")
+            .header(ContentType::JSON)
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .body(query)
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::ServiceUnavailable);
         assert_eq!(
             res.into_string().unwrap(),
-            r#"[[{"data":{},"duration":1.0,"id":1,"timestamp":"2018-01-01T01:01:01Z"}]]"#
+            r#"{"message":"Request POST /api/0/query did not finish within 1s"}"#
         );
+        let res = client
+            .get("/api/0/export")
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::ServiceUnavailable);
+        drop(busy);
 
-        // Test error
         let res = client
             .post("/api/0/query")
             .header(ContentType::JSON)
             .header(Header::new("Host", "127.0.0.1:5600"))
+            .body(query)
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::Ok);
+        assert_eq!(res.into_string().unwrap(), "[1.0]");
+    }
+
+    #[test]
+    fn test_query_stream() {
+        let server = setup_testserver();
+        let client = Client::untracked(server).expect("valid instance");
+        let query_stream = |timeperiods: &str, query: &str| {
+            let res = client
+                .post("/api/0/query/stream")
+                .header(ContentType::JSON)
+                .header(Header::new("Host", "127.0.0.1:5600"))
+                .header(Header::new("X-Query-Id", "streamed"))
+                .body(format!(
+                    r#"{{"timeperiods": {timeperiods}, "query": ["{query}"]}}"#
+                ))
+                .dispatch();
+            let status = res.status();
+            let body = res.into_string().unwrap();
+            let lines: Vec<Value> = body
+                .lines()
+                .map(|line| serde_json::from_str(line).unwrap())
+                .collect();
+            (status, lines)
+        };
+
+        let res = client
+            .post("/api/0/buckets/id")
+            .header(ContentType::JSON)
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .body(r#"{"id": "id", "type": "type", "client": "client", "hostname": "hostname"}"#)
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::Ok);
+        let res = client
+            .post("/api/0/buckets/id/events")
+            .header(ContentType::JSON)
+            .header(Header::new("Host", "127.0.0.1:5600"))
             .body(
-                r#"{
-                "timeperiods": ["2000-01-01T00:00:00Z/2020-01-01T00:00:00Z"],
-                "query": [""]
-            }"#,
+                r#"[{"timestamp": "2018-01-01T01:01:01Z", "duration": 1.0, "data": {"n": 1}},
+                    {"timestamp": "2018-01-01T01:01:05Z", "duration": 1.0, "data": {"n": 2}}]"#,
             )
             .dispatch();
-        assert_eq!(res.status(), rocket::http::Status::InternalServerError);
-        assert_eq!(res.into_string().unwrap(), r#"{"message":"EmptyQuery"}"#);
+        assert_eq!(res.status(), rocket::http::Status::Ok);
+
+        // A line per event, the second timeperiod has none
+        let (status, lines) = query_stream(
+            r#"["2018-01-01T00:00:00Z/2018-01-02T00:00:00Z", "2019-01-01T00:00:00Z/2019-01-02T00:00:00Z"]"#,
+            r#"RETURN = sort_by_timestamp(query_bucket(\"id\"));"#,
+        );
+        assert_eq!(status, rocket::http::Status::Ok);
+        assert_eq!(lines.len(), 3);
+        for (line, n) in lines[..2].iter().zip([1, 2]) {
+            assert_eq!(line["type"], "item");
+            assert_eq!(line["timeperiod"], 0);
+            assert_eq!(line["item"]["data"]["n"], n);
+        }
+        assert_eq!(lines[2], json!({"type": "done"}));
+
+        // Results which aren't lists are written whole
+        let (_, lines) = query_stream(
+            r#"["2018-01-01T00:00:00Z/2018-01-02T00:00:00Z", "2019-01-01T00:00:00Z/2019-01-02T00:00:00Z"]"#,
+            "RETURN = 1;",
+        );
+        assert_eq!(
+            lines,
+            vec![
+                json!({"type": "value", "timeperiod": 0, "value": 1.0}),
+                json!({"type": "value", "timeperiod": 1, "value": 1.0}),
+                json!({"type": "done"}),
+            ]
+        );
+
+        // Failing queries end the stream with an error
+        let (status, lines) = query_stream(
+            r#"["2018-01-01T00:00:00Z/2018-01-02T00:00:00Z"]"#,
+            "RETURN = undefined_variable;",
+        );
+        assert_eq!(status, rocket::http::Status::Ok);
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0]["type"], "error");
+        assert_eq!(lines[0]["timeperiod"], 0);
+
+        // Invalid timeperiods are rejected before streaming
+        let (status, _) = query_stream(r#"["not a timeperiod"]"#, "RETURN = 1;");
+        assert_eq!(status, rocket::http::Status::BadRequest);
+
+        // Ids of running queries can't be reused, the streamed query runs until it's written
+        let streamed = client
+            .post("/api/0/query/stream")
+            .header(ContentType::JSON)
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .header(Header::new("X-Query-Id", "streamed"))
+            .body(r#"{"timeperiods": ["2018-01-01T00:00:00Z/2018-01-02T00:00:00Z"], "query": ["RETURN = 1;"]}"#)
+            .dispatch();
+        let res = client
+            .post("/api/0/query")
+            .header(ContentType::JSON)
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .header(Header::new("X-Query-Id", "streamed"))
+            .body(r#"{"timeperiods": ["2018-01-01T00:00:00Z/2018-01-02T00:00:00Z"], "query": ["RETURN = 1;"]}"#)
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::Conflict);
+        assert_eq!(streamed.status(), rocket::http::Status::Ok);
+        assert!(streamed
+            .into_string()
+            .unwrap()
+            .ends_with("{\"type\":\"done\"}\n"));
+
+        // Streamed queries are removed from the registry once written
+        let res = client
+            .post("/api/0/query/streamed/cancel")
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::NotFound);
     }
 
     fn set_setting_request(client: &Client, key: &str, value: &Value) -> Status {
@@ -488,6 +2053,309 @@ mod api_tests {
         res.status()
     }
 
+    #[test]
+    fn test_categorize_preview() {
+        let server = setup_testserver();
+        let client = Client::untracked(server).expect("valid instance");
+
+        let res = client
+            .post("/api/0/buckets/id")
+            .header(ContentType::JSON)
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .body(r#"{"id": "id", "type": "type", "client": "client", "hostname": "hostname"}"#)
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::Ok);
+        let res = client
+            .post("/api/0/buckets/id/events")
+            .header(ContentType::JSON)
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .body(
+                r#"[{"timestamp": "2000-01-01T00:00:00Z", "duration": 60.0, "data": {"app": "vim"}},
+                    {"timestamp": "2000-01-01T00:01:00Z", "duration": 30.0, "data": {"app": "Code"}},
+                    {"timestamp": "2000-01-01T00:02:00Z", "duration": 10.0, "data": {"app": "Firefox"}},
+                    {"timestamp": "2000-01-02T00:00:00Z", "duration": 10.0, "data": {"app": "vim"}}]"#,
+            )
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::Ok);
+
+        let preview = |rules: &str| {
+            client
+                .post("/api/0/categorize/preview")
+                .header(ContentType::JSON)
+                .header(Header::new("Host", "127.0.0.1:5600"))
+                .body(format!(
+                    r#"{{"bucket": "id", "timeperiod": "2000-01-01T00:00:00Z/2000-01-02T00:00:00Z", "rules": {rules}}}"#
+                ))
+                .dispatch()
+        };
+        let res = preview(
+            r#"[[["Work"], {"type": "none"}],
+                [["Work", "Code"], {"type": "regex", "regex": "vim|code", "ignore_case": true}],
+                [["Fun"], {"type": "regex", "regex": "Steam"}]]"#,
+        );
+        assert_eq!(res.status(), rocket::http::Status::Ok);
+        let preview_res: serde_json::Value =
+            serde_json::from_str(&res.into_string().unwrap()).unwrap();
+        assert_eq!(
+            preview_res,
+            serde_json::json!({
+                "categories": {
+                    "Work": {"event_count": 0, "duration": 0.0},
+                    "Work > Code": {"event_count": 2, "duration": 90.0},
+                    "Fun": {"event_count": 0, "duration": 0.0},
+                },
+                "uncategorized": {"event_count": 1, "duration": 10.0},
+            })
+        );
+
+        let res = preview(r#"[[["Work"], {"type": "glob"}]]"#);
+        assert_eq!(res.status(), rocket::http::Status::BadRequest);
+    }
+
+    #[test]
+    fn test_vacuum() {
+        // Admin endpoints are disabled without a token in the config
+        let server = setup_testserver();
+        let client = Client::untracked(server).expect("valid instance");
+        let res = client
+            .post("/api/0/admin/vacuum")
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::Forbidden);
+
+        let state = endpoints::ServerState {
+            datastore: Arc::new(Mutex::new(aw_datastore::Datastore::new_in_memory(false))),
+            asset_resolver: endpoints::AssetResolver::new(None),
+            device_id: "test_id".to_string(),
+        };
+        let server = endpoints::build_rocket(state, admin_config());
+        let client = Client::untracked(server).expect("valid instance");
+        let res = client
+            .post("/api/0/admin/vacuum")
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::Unauthorized);
+        let res = client
+            .post("/api/0/admin/vacuum")
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .header(Header::new("Authorization", "Bearer wrong-token"))
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::Unauthorized);
+
+        let res = client
+            .post("/api/0/admin/vacuum")
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .header(admin_auth())
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::Ok);
+        let result: Value = res.into_json().unwrap();
+        assert!(result["size_before"].is_u64());
+        assert!(result["size_after"].is_u64());
+    }
+
+    #[test]
+    fn test_rebuild_daily_aggregates() {
+        let state = endpoints::ServerState {
+            datastore: Arc::new(Mutex::new(aw_datastore::Datastore::new_in_memory(false))),
+            asset_resolver: endpoints::AssetResolver::new(None),
+            device_id: "test_id".to_string(),
+        };
+        let server = endpoints::build_rocket(state, admin_config());
+        let client = Client::untracked(server).expect("valid instance");
+        let res = client
+            .post("/api/0/admin/daily_aggregates/rebuild")
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .header(admin_auth())
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::BadRequest);
+
+        let config = config::AWConfig {
+            db_daily_aggregates: true,
+            ..admin_config()
+        };
+        let datastore = aw_datastore::Datastore::new_in_memory(false);
+        datastore
+            .set_daily_aggregates(config.daily_aggregates().unwrap())
+            .unwrap();
+        let state = endpoints::ServerState {
+            datastore: Arc::new(Mutex::new(datastore)),
+            asset_resolver: endpoints::AssetResolver::new(None),
+            device_id: "test_id".to_string(),
+        };
+        let server = endpoints::build_rocket(state, config);
+        let client = Client::untracked(server).expect("valid instance");
+        let res = client
+            .post("/api/0/admin/daily_aggregates/rebuild")
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .header(admin_auth())
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::Ok);
+    }
+
+    #[test]
+    fn test_webui_path() {
+        let webui_path =
+            std::env::temp_dir().join(format!("aw-server-test-webui-{}", std::process::id()));
+        std::fs::create_dir_all(webui_path.join("js")).unwrap();
+        std::fs::write(webui_path.join("index.html"), "<html>custom</html>").unwrap();
+        std::fs::write(webui_path.join("js/app.js"), "console.log(1)").unwrap();
+
+        let state = endpoints::ServerState {
+            datastore: Arc::new(Mutex::new(aw_datastore::Datastore::new_in_memory(false))),
+            asset_resolver: endpoints::AssetResolver::new(None),
+            device_id: "test_id".to_string(),
+        };
+        let aw_config = config::AWConfig {
+            webui_path: Some(webui_path.to_str().unwrap().to_string()),
+            ..Default::default()
+        };
+        let server = endpoints::build_rocket(state, aw_config);
+        let client = Client::untracked(server).expect("valid instance");
+        let get = |uri: &str| {
+            client
+                .get(uri.to_string())
+                .header(Header::new("Host", "127.0.0.1:5600"))
+                .dispatch()
+        };
+
+        let res = get("/");
+        assert_eq!(res.status(), Status::Ok);
+        assert_eq!(res.content_type(), Some(ContentType::HTML));
+        assert_eq!(res.into_string().unwrap(), "<html>custom</html>");
+
+        let res = get("/js/app.js");
+        assert_eq!(res.status(), Status::Ok);
+        assert_eq!(res.content_type(), Some(ContentType::JavaScript));
+        assert_eq!(res.into_string().unwrap(), "console.log(1)");
+
+        // Client-side routes fall back to index.html, missing assets don't
+        let res = get("/activity/host/view");
+        assert_eq!(res.status(), Status::Ok);
+        assert_eq!(res.into_string().unwrap(), "<html>custom</html>");
+        assert_eq!(get("/js/missing.js").status(), Status::NotFound);
+        assert_eq!(get("/api/0/missing").status(), Status::NotFound);
+
+        // The API is still served
+        assert_eq!(get("/api/0/buckets/").status(), Status::Ok);
+
+        std::fs::remove_dir_all(&webui_path).unwrap();
+    }
+
+    #[test]
+    fn test_reload_config() {
+        // Without a config file there is nothing to reload
+        let state = endpoints::ServerState {
+            datastore: Arc::new(Mutex::new(aw_datastore::Datastore::new_in_memory(false))),
+            asset_resolver: endpoints::AssetResolver::new(None),
+            device_id: "test_id".to_string(),
+        };
+        let server = endpoints::build_rocket(state, admin_config());
+        let client = Client::untracked(server).expect("valid instance");
+        let res = client
+            .post("/api/0/admin/reload")
+            .header(Header::new("Host", "127.0.0.1:5600"))
+            .header(admin_auth())
+            .dispatch();
+        assert_eq!(res.status(), rocket::http::Status::BadRequest);
+
+        let config_path =
+            std::env::temp_dir().join(format!("aw-server-test-reload-{}.toml", std::process::id()));
+        // The token has to stay in the file, or the reload disables the admin endpoints
+        let admin_token = format!("admin_token = \"{ADMIN_TOKEN}\"\n");
+        std::fs::write(&config_path, &admin_token).unwrap();
+        let state = endpoints::ServerState {
+            datastore: Arc::new(Mutex::new(aw_datastore::Datastore::new_in_memory(false))),
+            asset_resolver: endpoints::AssetResolver::new(None),
+            device_id: "test_id".to_string(),
+        };
+        let aw_config = config::AWConfig {
+            config_path: Some(config_path.clone()),
+            ..admin_config()
+        };
+        let server = endpoints::build_rocket(state, aw_config);
+        let client = Client::untracked(server).expect("valid instance");
+        let reload = || {
+            client
+                .post("/api/0/admin/reload")
+                .header(Header::new("Host", "127.0.0.1:5600"))
+                .header(admin_auth())
+                .dispatch()
+        };
+        let query = || {
+            client
+                .post("/api/0/query")
+                .header(ContentType::JSON)
+                .header(Header::new("Host", "127.0.0.1:5600"))
+                .body(
+                    r#"{
+                    "timeperiods": ["2000-01-01T00:00:00Z/2000-03-01T00:00:00Z"],
+                    "query": ["RETURN = 1;"]
+                }"#,
+                )
+                .dispatch()
+        };
+
+        // Nothing changed
+        let res = reload();
+        assert_eq!(res.status(), rocket::http::Status::Ok);
+        assert_eq!(
+            res.into_json::<Value>().unwrap(),
+            json!({"changed": [], "requires_restart": []})
+        );
+        assert_eq!(query().status(), rocket::http::Status::Ok);
+        let from_example = || {
+            client
+                .get("/api/0/buckets/")
+                .header(Header::new("Host", "127.0.0.1:5600"))
+                .header(Header::new("Origin", "http://example.com"))
+                .dispatch()
+        };
+        assert_eq!(from_example().status(), rocket::http::Status::Forbidden);
+
+        // Live settings are applied right away, others are only reported
+        std::fs::write(
+            &config_path,
+            format!("{admin_token}query_max_timeperiod_days = 30\nport = 1234\nrequest_timeout_secs = 5\ncors = [\"http://example.com\"]\nlog_level = \"warn\"\n"),
+        )
+        .unwrap();
+        let res = reload();
+        assert_eq!(res.status(), rocket::http::Status::Ok);
+        assert_eq!(
+            res.into_json::<Value>().unwrap(),
+            json!({
+                "changed": ["query_max_timeperiod_days", "cors", "log_level"],
+                "requires_restart": ["port", "request_timeout_secs"],
+            })
+        );
+        assert_eq!(query().status(), rocket::http::Status::BadRequest);
+        let res = from_example();
+        assert_eq!(res.status(), rocket::http::Status::Ok);
+        assert_eq!(
+            res.headers().get_one("Access-Control-Allow-Origin"),
+            Some("http://example.com")
+        );
+
+        // Invalid origins are refused
+        std::fs::write(
+            &config_path,
+            format!("{admin_token}cors = [\"not an origin\"]\n"),
+        )
+        .unwrap();
+        assert_eq!(reload().status(), rocket::http::Status::BadRequest);
+        assert_eq!(from_example().status(), rocket::http::Status::Ok);
+
+        // An invalid config leaves the running config as is
+        std::fs::write(
+            &config_path,
+            format!("{admin_token}query_max_timeperiod_days = \"many\"\n"),
+        )
+        .unwrap();
+        assert_eq!(reload().status(), rocket::http::Status::BadRequest);
+        assert_eq!(query().status(), rocket::http::Status::BadRequest);
+
+        std::fs::remove_file(&config_path).unwrap();
+    }
+
     #[test]
     fn test_illegally_long_key() {
         let server = setup_testserver();
@@ -90,10 +90,16 @@ impl AccessMethod for AwClient {
         end: Option<DateTime<Utc>>,
         limit: Option<u64>,
     ) -> Result<Vec<Event>, String> {
-        Ok(AwClient::get_events(self, bucket_id, start, end, limit).unwrap())
+        Ok(
+            AwClient::get_events(self, bucket_id, start, end, limit, None, None)
+                .unwrap()
+                .unwrap_or_default(),
+        )
     }
     fn insert_events(&self, bucket_id: &str, events: Vec<Event>) -> Result<(), String> {
-        AwClient::insert_events(self, bucket_id, events).map_err(|e| e.to_string())
+        AwClient::insert_events(self, bucket_id, events)
+            .map(|_| ())
+            .map_err(|e| e.to_string())
     }
     fn get_event_count(&self, bucket_id: &str) -> Result<i64, String> {
         Ok(AwClient::get_event_count(self, bucket_id).unwrap())
@@ -119,7 +119,7 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     info!("Started aw-sync...");
 
-    aw_server::logging::setup_logger("aw-sync", opts.testing, verbose)?;
+    aw_server::logging::setup_logger("aw-sync", opts.testing, verbose, None)?;
 
     // if sync_dir, set env var
     if let Some(sync_dir) = opts.sync_dir {
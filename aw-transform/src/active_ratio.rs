@@ -0,0 +1,169 @@
+use aw_models::Event;
+use chrono::{DateTime, Duration, NaiveDate, TimeZone, Utc};
+
+use crate::filter_period_intersect;
+use crate::period_union;
+use crate::weekday_weekend::next_local_midnight;
+
+/// The fraction of the time of each local day between `start` and `stop` the user was not AFK
+/// according to the events of an AFK bucket
+///
+/// A day is the time from one local midnight to the next in the given timezone, so days with a
+/// daylight saving time change are 23 or 25 hours long. The first and last day are clipped to
+/// `start` and `stop`, the ratio being of the part of the day within the range. The ratio of a
+/// day without any AFK events is `None` rather than zero, as nothing is known about it.
+///
+/// # Example
+/// ```ignore
+///   timezone: UTC
+///   start:    01-01 12:00
+///   stop:     01-03 12:00
+///   events:   [01-01 12:00 - 18:00 not-afk] [01-02 00:00 - 24:00 afk]
+///   result:   01-01: 0.5, 01-02: 0.0, 01-03: None
+/// ```
+pub fn active_ratio<Tz: TimeZone>(
+    afk_events: &[Event],
+    start: DateTime<Utc>,
+    stop: DateTime<Utc>,
+    tz: &Tz,
+) -> Vec<(NaiveDate, Option<f64>)> {
+    let mut days = Vec::new();
+    let mut day_start = start;
+    while day_start < stop {
+        let next_midnight = next_local_midnight(day_start, tz);
+        let day_end = if next_midnight < stop {
+            next_midnight
+        } else {
+            stop
+        };
+        days.push(Event {
+            id: None,
+            timestamp: day_start,
+            duration: day_end - day_start,
+            data: serde_json::Map::new(),
+        });
+        day_start = day_end;
+    }
+
+    // Merged so that overlapping events aren't counted twice
+    let not_afk: Vec<Event> = afk_events
+        .iter()
+        .filter(|e| e.data.get("status").and_then(|s| s.as_str()) == Some("not-afk"))
+        .cloned()
+        .collect();
+    let not_afk = filter_period_intersect(period_union(&not_afk, &[]), days.clone());
+    let known = filter_period_intersect(afk_events.to_vec(), days.clone());
+
+    days.iter()
+        .map(|day| {
+            let date = day.timestamp.with_timezone(tz).date_naive();
+            let day_end = day.calculate_endtime();
+            let in_day = |e: &&Event| e.timestamp >= day.timestamp && e.timestamp < day_end;
+            if !known.iter().any(|e| in_day(&e)) {
+                return (date, None);
+            }
+            let active = not_afk
+                .iter()
+                .filter(in_day)
+                .fold(Duration::zero(), |total, e| total + e.duration);
+            let ratio = active.num_milliseconds() as f64 / day.duration.num_milliseconds() as f64;
+            (date, Some(ratio))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use chrono::DateTime;
+    use chrono::Duration;
+    use chrono::NaiveDate;
+    use chrono::Utc;
+    use serde_json::json;
+
+    use crate::test_util::event;
+
+    use super::active_ratio;
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    #[test]
+    fn test_active_ratio() {
+        let events = vec![
+            event(
+                "2000-01-01T12:00:00Z",
+                Duration::hours(6),
+                json_map! {"status": json!("not-afk")},
+            ),
+            event(
+                "2000-01-01T18:00:00Z",
+                Duration::hours(12),
+                json_map! {"status": json!("afk")},
+            ),
+            // Overlaps the previous event and the first day's end
+            event(
+                "2000-01-01T23:00:00Z",
+                Duration::hours(7),
+                json_map! {"status": json!("not-afk")},
+            ),
+            event(
+                "2000-01-02T06:00:00Z",
+                Duration::hours(6),
+                json_map! {"status": json!("not-afk")},
+            ),
+            // Outside of the range
+            event(
+                "2000-01-04T00:00:00Z",
+                Duration::hours(1),
+                json_map! {"status": json!("not-afk")},
+            ),
+        ];
+        let ratios = active_ratio(
+            &events,
+            DateTime::from_str("2000-01-01T12:00:00Z").unwrap(),
+            DateTime::from_str("2000-01-03T12:00:00Z").unwrap(),
+            &Utc,
+        );
+        assert_eq!(
+            ratios,
+            vec![
+                // The first day is clipped to the 12 hours after the start
+                (date(2000, 1, 1), Some(7.0 / 12.0)),
+                (date(2000, 1, 2), Some(0.5)),
+                // No data isn't the same as being AFK all day
+                (date(2000, 1, 3), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_active_ratio_dst() {
+        // Sweden changes to summer time on 2021-03-28, making the day 23 hours long
+        let tz: chrono_tz::Tz = "Europe/Stockholm".parse().unwrap();
+        let events = vec![
+            event(
+                "2021-03-27T23:00:00Z",
+                Duration::minutes(11 * 60 + 30),
+                json_map! {"status": json!("not-afk")},
+            ),
+            event(
+                "2021-03-28T10:30:00Z",
+                Duration::minutes(11 * 60 + 30),
+                json_map! {"status": json!("afk")},
+            ),
+        ];
+        let ratios = active_ratio(
+            &events,
+            DateTime::from_str("2021-03-27T23:00:00Z").unwrap(),
+            DateTime::from_str("2021-03-29T22:00:00Z").unwrap(),
+            &tz,
+        );
+        assert_eq!(
+            ratios,
+            vec![(date(2021, 3, 28), Some(0.5)), (date(2021, 3, 29), None)]
+        );
+    }
+}
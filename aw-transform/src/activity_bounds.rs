@@ -0,0 +1,88 @@
+use std::collections::HashMap;
+
+use aw_models::Event;
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+
+/// Start of the first and end of the last event in a group, see `activity_bounds`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ActivityBounds {
+    pub first: DateTime<Utc>,
+    pub last: DateTime<Utc>,
+}
+
+/// Finds when the activity of each group started and ended
+///
+/// Events are grouped by the value of `group_key`, string values are used as is while other
+/// values are used as their JSON representation. Events without the key are ignored. The order
+/// of the events doesn't matter and `last` is where the event ending last ends, not where the
+/// last event starts.
+///
+/// # Example
+/// ```ignore
+/// group_key: "app"
+/// input:  [a (10-20)] [b (15-16)] [a (0-5)]
+/// output: { "a": { first: 0, last: 20 }, "b": { first: 15, last: 16 } }
+/// ```
+pub fn activity_bounds(events: &[Event], group_key: &str) -> HashMap<String, ActivityBounds> {
+    let mut bounds: HashMap<String, ActivityBounds> = HashMap::new();
+    for event in events {
+        let group = match event.data.get(group_key) {
+            Some(Value::String(s)) => s.clone(),
+            Some(value) => value.to_string(),
+            None => continue,
+        };
+        let start = event.timestamp;
+        let end = event.calculate_endtime();
+        bounds
+            .entry(group)
+            .and_modify(|b| {
+                b.first = b.first.min(start);
+                b.last = b.last.max(end);
+            })
+            .or_insert(ActivityBounds {
+                first: start,
+                last: end,
+            });
+    }
+    bounds
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::DateTime;
+    use chrono::Duration;
+    use serde_json::json;
+
+    use crate::test_util::event;
+
+    use super::{activity_bounds, ActivityBounds};
+
+    fn bounds(first: i64, last: i64) -> ActivityBounds {
+        ActivityBounds {
+            first: DateTime::from_timestamp(first, 0).unwrap(),
+            last: DateTime::from_timestamp(last, 0).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_activity_bounds() {
+        // Out of order, with an event which ends after a later starting one
+        let events = vec![
+            event(10, Duration::seconds(10), json_map! {"app": "a"}),
+            event(15, Duration::seconds(1), json_map! {"app": "b"}),
+            event(0, Duration::seconds(5), json_map! {"app": "a"}),
+            event(12, Duration::seconds(2), json_map! {"app": "a"}),
+            event(100, Duration::seconds(1), json_map! {"title": "no app"}),
+            event(7, Duration::seconds(1), json_map! {"app": 1}),
+        ];
+        let res = activity_bounds(&events, "app");
+        assert_eq!(res.len(), 3);
+        assert_eq!(res["a"], bounds(0, 20));
+        assert_eq!(res["b"], bounds(15, 16));
+        assert_eq!(res["1"], bounds(7, 8));
+
+        assert!(activity_bounds(&[], "app").is_empty());
+        assert!(activity_bounds(&events, "missing").is_empty());
+    }
+}
@@ -0,0 +1,85 @@
+use aw_models::Event;
+use chrono::{Duration, TimeZone, Timelike};
+
+/// Sums up the duration of events per hour of the day (0-23) in the given timezone
+///
+/// Events crossing hour boundaries are split between the hours they cover, durations for the
+/// same hour on different days are summed together.
+///
+/// # Example
+/// ```ignore
+///   timezone: UTC
+///   events:   [22:30 - 00:40] [01:00 - 01:10]
+///   result:   22: 30min, 23: 60min, 0: 40min, 1: 10min, all other hours: 0
+/// ```
+pub fn by_hour_of_day<Tz: TimeZone>(events: &[Event], tz: &Tz) -> Vec<Duration> {
+    let mut hours = vec![Duration::zero(); 24];
+    for event in events {
+        let end = event.calculate_endtime();
+        let mut start = event.timestamp;
+        while start < end {
+            let local = start.with_timezone(tz);
+            let into_hour = Duration::seconds((local.minute() * 60 + local.second()) as i64)
+                + Duration::nanoseconds(local.nanosecond() as i64);
+            let next_hour = start - into_hour + Duration::hours(1);
+            let slice_end = if next_hour < end { next_hour } else { end };
+            hours[local.hour() as usize] += slice_end - start;
+            start = slice_end;
+        }
+    }
+    hours
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use chrono::FixedOffset;
+    use chrono::Utc;
+    use serde_json::json;
+
+    use crate::test_util::event;
+
+    use super::by_hour_of_day;
+
+    #[test]
+    fn test_by_hour_of_day_multiple_hours() {
+        let events = vec![
+            event(
+                "2000-01-01T10:30:00Z",
+                Duration::minutes(150),
+                json_map! {"test": json!(1)},
+            ),
+            event(
+                "2000-01-02T10:50:00Z",
+                Duration::minutes(5),
+                json_map! {"test": json!(1)},
+            ),
+        ];
+        let hours = by_hour_of_day(&events, &Utc);
+        assert_eq!(hours.len(), 24);
+        assert_eq!(hours[10], Duration::minutes(35));
+        assert_eq!(hours[11], Duration::minutes(60));
+        assert_eq!(hours[12], Duration::minutes(60));
+        assert_eq!(hours[13], Duration::zero());
+        let total = hours.iter().fold(Duration::zero(), |acc, d| acc + *d);
+        assert_eq!(total, Duration::minutes(155));
+    }
+
+    #[test]
+    fn test_by_hour_of_day_across_midnight() {
+        // 21:30 to 00:40 in UTC+01:00
+        let events = vec![event(
+            "2000-01-01T20:30:00Z",
+            Duration::minutes(190),
+            json_map! {"test": json!(1)},
+        )];
+        let tz = FixedOffset::east_opt(3600).unwrap();
+        let hours = by_hour_of_day(&events, &tz);
+        assert_eq!(hours[20], Duration::zero());
+        assert_eq!(hours[21], Duration::minutes(30));
+        assert_eq!(hours[22], Duration::minutes(60));
+        assert_eq!(hours[23], Duration::minutes(60));
+        assert_eq!(hours[0], Duration::minutes(40));
+        assert_eq!(hours[1], Duration::zero());
+    }
+}
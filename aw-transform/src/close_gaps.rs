@@ -0,0 +1,93 @@
+use aw_models::Event;
+use chrono::Duration;
+
+/// Extends events to the start of the next event if the gap between them is at most `max_gap`
+///
+/// Removes the small gaps between consecutive events caused by watcher latency, while unlike
+/// merging the events are kept as they are apart from their duration. Events are sorted by
+/// timestamp, larger gaps and overlapping events are left alone.
+///
+/// # Example
+/// ```ignore
+/// max_gap: 1s
+/// input:  [a ] [b ]   [c ]
+/// output: [a  ][b ]   [c ]
+/// ```
+pub fn close_gaps(mut events: Vec<Event>, max_gap: Duration) -> Vec<Event> {
+    events.sort_by_key(|e| e.timestamp);
+
+    for i in 1..events.len() {
+        let next_start = events[i].timestamp;
+        let event = &mut events[i - 1];
+        let gap = next_start - event.calculate_endtime();
+        if gap > Duration::zero() && gap <= max_gap {
+            event.duration = next_start - event.timestamp;
+        }
+    }
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::DateTime;
+    use chrono::Duration;
+    use serde_json::json;
+
+    use crate::test_util::event;
+
+    use super::close_gaps;
+
+    #[test]
+    fn test_close_gaps() {
+        let events = vec![
+            event(
+                DateTime::from_timestamp_millis(10_000).unwrap(),
+                Duration::milliseconds(1000),
+                json_map! {"app": json!("b")},
+            ),
+            event(
+                DateTime::from_timestamp_millis(0).unwrap(),
+                Duration::milliseconds(9500),
+                json_map! {"app": json!("a")},
+            ),
+            // Larger gap than the threshold
+            event(
+                DateTime::from_timestamp_millis(13_000).unwrap(),
+                Duration::milliseconds(1000),
+                json_map! {"app": json!("c")},
+            ),
+            // Overlapping the previous event
+            event(
+                DateTime::from_timestamp_millis(13_500).unwrap(),
+                Duration::milliseconds(1000),
+                json_map! {"app": json!("d")},
+            ),
+        ];
+        let closed = close_gaps(events, Duration::seconds(1));
+        assert_eq!(
+            closed,
+            vec![
+                event(
+                    DateTime::from_timestamp_millis(0).unwrap(),
+                    Duration::milliseconds(10_000),
+                    json_map! {"app": json!("a")}
+                ),
+                event(
+                    DateTime::from_timestamp_millis(10_000).unwrap(),
+                    Duration::milliseconds(1000),
+                    json_map! {"app": json!("b")}
+                ),
+                event(
+                    DateTime::from_timestamp_millis(13_000).unwrap(),
+                    Duration::milliseconds(1000),
+                    json_map! {"app": json!("c")}
+                ),
+                event(
+                    DateTime::from_timestamp_millis(13_500).unwrap(),
+                    Duration::milliseconds(1000),
+                    json_map! {"app": json!("d")}
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,133 @@
+use std::collections::BTreeMap;
+
+use aw_models::Event;
+use chrono::{DateTime, Duration, NaiveDate, TimeZone, Utc};
+
+use crate::start_of_day;
+
+/// Sums the time of each category of events categorized by `classify` per local day from `start`
+/// to `stop`, both included, for plotting a line per category
+///
+/// Every category gets a total for every day, with zero for days without its events, so that the
+/// totals at the same index are of the same day, `start` plus the index in days. Categories are
+/// named by their path joined with " > ", such as "Work > Programming", and only count the time
+/// of events in exactly that category. Events crossing midnight are split between the days they
+/// cover, time outside of the days is left out. Events without a `$category` which is a
+/// non-empty list of strings are skipped. Returns nothing if `stop` is before `start`.
+///
+/// # Example
+/// ```ignore
+/// timezone: UTC, start: 01-01, stop: 01-03
+/// input:  [01-01 Work (1h)] [01-01 Fun (1h)] [01-03 23:30 Work (1h)]
+/// output: { Fun: [1h, 0, 0], Work: [1h, 0, 30min] }
+/// ```
+pub fn daily_category_trend<Tz: TimeZone>(
+    events: &[Event],
+    tz: &Tz,
+    start: NaiveDate,
+    stop: NaiveDate,
+) -> BTreeMap<String, Vec<Duration>> {
+    let mut trends: BTreeMap<String, Vec<Duration>> = BTreeMap::new();
+    if stop < start {
+        return trends;
+    }
+    let day_count = (stop - start).num_days() as usize + 1;
+    let day_starts: Vec<DateTime<Utc>> = start
+        .iter_days()
+        .take(day_count + 1)
+        .map(|day| start_of_day(day, tz))
+        .collect();
+
+    for event in events {
+        let path = match event.data.get("$category").and_then(|c| c.as_array()) {
+            Some(path) if !path.is_empty() => path,
+            _ => continue,
+        };
+        let path: Option<Vec<&str>> = path.iter().map(|name| name.as_str()).collect();
+        let category = match path {
+            Some(path) => path.join(" > "),
+            None => continue,
+        };
+
+        let totals = trends
+            .entry(category)
+            .or_insert_with(|| vec![Duration::zero(); day_count]);
+        let end = event.calculate_endtime();
+        for (i, total) in totals.iter_mut().enumerate() {
+            let overlap = end.min(day_starts[i + 1]) - event.timestamp.max(day_starts[i]);
+            if overlap > Duration::zero() {
+                *total += overlap;
+            }
+        }
+    }
+    trends
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use chrono::NaiveDate;
+    use serde_json::json;
+
+    use crate::test_util::event;
+
+    use super::daily_category_trend;
+
+    #[test]
+    fn test_daily_category_trend() {
+        let date = |day: u32| NaiveDate::from_ymd_opt(2000, 1, day).unwrap();
+        let uncategorized = event("2000-01-01T12:00:00Z", Duration::minutes(30), json_map! {});
+        let events = vec![
+            event(
+                "2000-01-01T09:00:00Z",
+                Duration::minutes(60),
+                json_map! {"$category": json!(&["Work"])},
+            ),
+            event(
+                "2000-01-01T10:00:00Z",
+                Duration::minutes(60),
+                json_map! {"$category": json!(&["Fun"])},
+            ),
+            event(
+                "2000-01-01T11:00:00Z",
+                Duration::minutes(15),
+                json_map! {"$category": json!(&["Work", "Programming"])},
+            ),
+            // Crosses midnight, Work has nothing on the day between
+            event(
+                "2000-01-03T23:30:00Z",
+                Duration::minutes(60),
+                json_map! {"$category": json!(&["Work"])},
+            ),
+            // Before the first day
+            event(
+                "1999-12-31T08:00:00Z",
+                Duration::minutes(60),
+                json_map! {"$category": json!(&["Fun"])},
+            ),
+            uncategorized,
+        ];
+        let trends = daily_category_trend(&events, &chrono_tz::UTC, date(1), date(4));
+        let mins = |mins: &[i64]| {
+            mins.iter()
+                .map(|m| Duration::minutes(*m))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            trends.into_iter().collect::<Vec<_>>(),
+            vec![
+                ("Fun".to_string(), mins(&[60, 0, 0, 0])),
+                ("Work".to_string(), mins(&[60, 0, 30, 30])),
+                ("Work > Programming".to_string(), mins(&[15, 0, 0, 0])),
+            ]
+        );
+
+        // The days are local days, 23:30 UTC is already the next day in Stockholm
+        let trends = daily_category_trend(&events, &chrono_tz::Europe::Stockholm, date(3), date(4));
+        assert_eq!(trends["Work"], mins(&[0, 60]));
+        // Categories without time in the range still get all days
+        assert_eq!(trends["Fun"], mins(&[0, 0]));
+
+        assert!(daily_category_trend(&events, &chrono_tz::UTC, date(2), date(1)).is_empty());
+    }
+}
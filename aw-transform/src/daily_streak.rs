@@ -0,0 +1,98 @@
+use std::collections::HashMap;
+
+use chrono::NaiveDate;
+
+/// Current and longest streak of consecutive days, see `daily_streak`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct DailyStreak {
+    pub current: u32,
+    pub longest: u32,
+}
+
+/// Finds the streaks of consecutive days where the total is at least `threshold`
+///
+/// `per_day_totals` maps each day to its total, such as the number of seconds spent on some
+/// category. A day which is missing from the map breaks a streak just like a day below the
+/// threshold. The current streak is the one which includes the latest day in the map, so it is
+/// 0 if that day is below the threshold.
+///
+/// # Example
+/// ```ignore
+/// threshold: 3600
+/// input:  { 01-01: 4000, 01-02: 3600, 01-04: 5000, 01-05: 4000, 01-06: 4000 }
+/// output: { current: 3, longest: 3 }
+/// ```
+pub fn daily_streak(per_day_totals: &HashMap<NaiveDate, f64>, threshold: f64) -> DailyStreak {
+    let mut days: Vec<(&NaiveDate, &f64)> = per_day_totals.iter().collect();
+    days.sort_by_key(|(day, _)| **day);
+
+    let mut streak = DailyStreak::default();
+    let mut prev_day: Option<NaiveDate> = None;
+    for (day, total) in days {
+        let follows_prev = prev_day.and_then(|prev| prev.succ_opt()) == Some(*day);
+        if *total < threshold {
+            streak.current = 0;
+        } else if follows_prev {
+            streak.current += 1;
+        } else {
+            streak.current = 1;
+        }
+        streak.longest = streak.longest.max(streak.current);
+        prev_day = Some(*day);
+    }
+    streak
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use chrono::NaiveDate;
+
+    use super::{daily_streak, DailyStreak};
+
+    fn totals(days: &[(u32, f64)]) -> HashMap<NaiveDate, f64> {
+        days.iter()
+            .map(|(day, total)| (NaiveDate::from_ymd_opt(2000, 1, *day).unwrap(), *total))
+            .collect()
+    }
+
+    fn streak(current: u32, longest: u32) -> DailyStreak {
+        DailyStreak { current, longest }
+    }
+
+    #[test]
+    fn test_daily_streak_perfect() {
+        let days = totals(&[(1, 3600.0), (2, 4000.0), (3, 7200.0), (4, 3600.0)]);
+        assert_eq!(daily_streak(&days, 3600.0), streak(4, 4));
+        assert_eq!(daily_streak(&HashMap::new(), 3600.0), streak(0, 0));
+    }
+
+    #[test]
+    fn test_daily_streak_gaps() {
+        // The missing 3rd breaks the streak
+        let days = totals(&[
+            (1, 4000.0),
+            (2, 4000.0),
+            (4, 4000.0),
+            (5, 4000.0),
+            (6, 4000.0),
+        ]);
+        assert_eq!(daily_streak(&days, 3600.0), streak(3, 3));
+
+        // As does a day below the threshold
+        let days = totals(&[(1, 4000.0), (2, 4000.0), (3, 100.0), (4, 4000.0)]);
+        assert_eq!(daily_streak(&days, 3600.0), streak(1, 2));
+    }
+
+    #[test]
+    fn test_daily_streak_below_threshold_at_ends() {
+        // Leading days below the threshold aren't part of any streak
+        let days = totals(&[(1, 0.0), (2, 10.0), (3, 4000.0), (4, 4000.0)]);
+        assert_eq!(daily_streak(&days, 3600.0), streak(2, 2));
+
+        // A trailing day below the threshold ends the current streak
+        let days = totals(&[(1, 4000.0), (2, 4000.0), (3, 4000.0), (4, 10.0)]);
+        assert_eq!(daily_streak(&days, 3600.0), streak(0, 3));
+    }
+}
@@ -0,0 +1,160 @@
+use aw_models::Event;
+use chrono::Duration;
+use serde_json::Value;
+
+/// Removes status changes shorter than `min_duration` by absorbing them into the surrounding
+/// period, such as a short not-afk blip from a mouse jiggle during a long afk period
+///
+/// Events are sorted by timestamp and grouped into periods of consecutive events with the same
+/// value of `key`. A period shorter than `min_duration` is removed if the periods before and after
+/// it have the same value, and the last event before it is extended to cover it. The first and
+/// last periods are always kept, since a change there may not have finished yet.
+///
+/// # Example
+/// ```ignore
+/// key:          status
+/// min_duration: 1m
+/// input:  [afk          ][not-afk 5s][afk          ][not-afk      ]
+/// output: [afk                      ][afk          ][not-afk      ]
+/// ```
+pub fn debounce_status(mut events: Vec<Event>, key: &str, min_duration: Duration) -> Vec<Event> {
+    events.sort_by_key(|e| e.timestamp);
+
+    let mut periods: Vec<Vec<Event>> = Vec::new();
+    for event in events {
+        match periods.last_mut() {
+            Some(period) if period[0].data.get(key) == event.data.get(key) => period.push(event),
+            _ => periods.push(vec![event]),
+        }
+    }
+
+    let mut kept: Vec<Vec<Event>> = Vec::new();
+    let mut periods = periods.into_iter().peekable();
+    while let Some(period) = periods.next() {
+        let value: Option<&Value> = period[0].data.get(key);
+        let start = period[0].timestamp;
+        let end = period.iter().map(|e| e.calculate_endtime()).max().unwrap();
+        let is_flicker = end - start < min_duration
+            && match (kept.last(), periods.peek()) {
+                (Some(prev), Some(next)) => {
+                    prev[0].data.get(key) == next[0].data.get(key) && prev[0].data.get(key) != value
+                }
+                _ => false,
+            };
+        if is_flicker {
+            let last = kept.last_mut().unwrap().last_mut().unwrap();
+            if last.calculate_endtime() < end {
+                last.duration = end - last.timestamp;
+            }
+            continue;
+        }
+        match kept.last_mut() {
+            // The period after an absorbed flicker continues the one before it
+            Some(prev) if prev[0].data.get(key) == value => prev.extend(period),
+            _ => kept.push(period),
+        }
+    }
+    kept.into_iter().flatten().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use serde_json::json;
+
+    use crate::test_util::event;
+
+    use super::debounce_status;
+
+    #[test]
+    fn test_debounce_status_flicker() {
+        let events = vec![
+            event(
+                0,
+                Duration::seconds(600),
+                json_map! {"status": json!("afk")},
+            ),
+            event(
+                600,
+                Duration::seconds(5),
+                json_map! {"status": json!("not-afk")},
+            ),
+            event(
+                605,
+                Duration::seconds(600),
+                json_map! {"status": json!("afk")},
+            ),
+        ];
+        let debounced = debounce_status(events, "status", Duration::seconds(60));
+        assert_eq!(
+            debounced,
+            vec![
+                event(
+                    0,
+                    Duration::seconds(605),
+                    json_map! {"status": json!("afk")}
+                ),
+                event(
+                    605,
+                    Duration::seconds(600),
+                    json_map! {"status": json!("afk")}
+                )
+            ]
+        );
+    }
+
+    #[test]
+    fn test_debounce_status_keeps_changes() {
+        // A short period between two different statuses is kept, as are the first and last periods
+        let events = vec![
+            event(
+                0,
+                Duration::seconds(5),
+                json_map! {"status": json!("not-afk")},
+            ),
+            event(
+                5,
+                Duration::seconds(600),
+                json_map! {"status": json!("afk")},
+            ),
+            event(
+                605,
+                Duration::seconds(5),
+                json_map! {"status": json!("not-afk")},
+            ),
+            event(
+                610,
+                Duration::seconds(600),
+                json_map! {"status": json!("locked")},
+            ),
+            event(
+                1210,
+                Duration::seconds(5),
+                json_map! {"status": json!("afk")},
+            ),
+        ];
+        let debounced = debounce_status(events.clone(), "status", Duration::seconds(60));
+        assert_eq!(debounced, events);
+
+        // Periods at least as long as the minimum are kept
+        let events = vec![
+            event(
+                0,
+                Duration::seconds(600),
+                json_map! {"status": json!("afk")},
+            ),
+            event(
+                600,
+                Duration::seconds(60),
+                json_map! {"status": json!("not-afk")},
+            ),
+            event(
+                660,
+                Duration::seconds(600),
+                json_map! {"status": json!("afk")},
+            ),
+        ];
+        let debounced = debounce_status(events.clone(), "status", Duration::seconds(60));
+        assert_eq!(debounced, events);
+    }
+}
@@ -0,0 +1,142 @@
+use aw_models::Event;
+use chrono::{DateTime, Duration, Utc};
+use serde_json::Value;
+
+/// A time slot and the value of a key which has the most time in it, see `dominant_per_slot`
+#[derive(Debug, Clone, PartialEq)]
+pub struct SlotActivity {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    /// The dominant value, `None` if no event with the key overlaps the slot
+    pub value: Option<Value>,
+    /// Time the dominant value has in the slot
+    pub duration: Duration,
+}
+
+/// Splits the time from `start` to `stop` into slots of length `slot` and finds the value of
+/// `key` which has the most time in each slot, for drawing a compact timeline
+///
+/// Events are clipped to the slots they overlap, so an event spanning several slots counts
+/// towards each of them with the part inside it. Events without `key` are skipped. If several
+/// values have the same time in a slot the one of the earliest event wins. The last slot ends at
+/// `stop` and is shorter than `slot` if the range isn't a multiple of it. Every slot is returned,
+/// slots without events have no value and a zero duration. Returns no slots if `slot` isn't
+/// positive.
+///
+/// # Example
+/// ```ignore
+/// start: 0, stop: 30, slot: 15s, key: app
+/// input:  [a (0-5)] [b (5-15)] [a (15-22)] [b (22-29)]
+/// output: [(0-15) b 10s] [(15-30) a 7s]
+/// ```
+pub fn dominant_per_slot(
+    events: &[Event],
+    slot: Duration,
+    key: &str,
+    start: DateTime<Utc>,
+    stop: DateTime<Utc>,
+) -> Vec<SlotActivity> {
+    if slot <= Duration::zero() {
+        return Vec::new();
+    }
+    let mut events: Vec<&Event> = events.iter().filter(|e| e.data.contains_key(key)).collect();
+    events.sort_by_key(|e| e.timestamp);
+
+    let mut slots = Vec::new();
+    let mut slot_start = start;
+    while slot_start < stop {
+        let slot_end = (slot_start + slot).min(stop);
+        // Values in the order their first event starts, so that ties go to the earliest
+        let mut totals: Vec<(&Value, Duration)> = Vec::new();
+        for event in &events {
+            let overlap = event.calculate_endtime().min(slot_end) - event.timestamp.max(slot_start);
+            if overlap <= Duration::zero() {
+                continue;
+            }
+            let value = &event.data[key];
+            match totals.iter_mut().find(|(v, _)| *v == value) {
+                Some((_, total)) => *total += overlap,
+                None => totals.push((value, overlap)),
+            }
+        }
+        let mut dominant: Option<(&Value, Duration)> = None;
+        for (value, total) in totals {
+            if dominant.is_none_or(|(_, max)| total > max) {
+                dominant = Some((value, total));
+            }
+        }
+        slots.push(SlotActivity {
+            start: slot_start,
+            end: slot_end,
+            value: dominant.map(|(value, _)| value.clone()),
+            duration: dominant.map_or(Duration::zero(), |(_, total)| total),
+        });
+        slot_start = slot_end;
+    }
+    slots
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::DateTime;
+    use chrono::Duration;
+    use serde_json::json;
+
+    use crate::test_util::event;
+
+    use super::{dominant_per_slot, SlotActivity};
+
+    fn slot(start: i64, end: i64, app: Option<&str>, duration_secs: i64) -> SlotActivity {
+        SlotActivity {
+            start: DateTime::from_timestamp(start, 0).unwrap(),
+            end: DateTime::from_timestamp(end, 0).unwrap(),
+            value: app.map(|app| json!(app)),
+            duration: Duration::seconds(duration_secs),
+        }
+    }
+
+    #[test]
+    fn test_dominant_per_slot() {
+        let time = |secs: i64| DateTime::from_timestamp(secs, 0).unwrap();
+        let mut no_app = event(30, Duration::seconds(15), json_map! {"app": json!("a")});
+        no_app.data.clear();
+        let events = vec![
+            // Spans two slots
+            event(10, Duration::seconds(10), json_map! {"app": json!("b")}),
+            event(0, Duration::seconds(10), json_map! {"app": json!("a")}),
+            event(20, Duration::seconds(3), json_map! {"app": json!("a")}),
+            no_app,
+        ];
+        assert_eq!(
+            dominant_per_slot(&events, Duration::seconds(15), "app", time(0), time(50)),
+            vec![
+                slot(0, 15, Some("a"), 10),
+                slot(15, 30, Some("b"), 5),
+                slot(30, 45, None, 0),
+                slot(45, 50, None, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_dominant_per_slot_tie() {
+        let time = |secs: i64| DateTime::from_timestamp(secs, 0).unwrap();
+        // The slot is split evenly, the app of the earliest event wins
+        let events = vec![
+            event(5, Duration::seconds(5), json_map! {"app": json!("a")}),
+            event(0, Duration::seconds(5), json_map! {"app": json!("b")}),
+        ];
+        assert_eq!(
+            dominant_per_slot(&events, Duration::seconds(10), "app", time(0), time(10)),
+            vec![slot(0, 10, Some("b"), 5)]
+        );
+        let events = vec![
+            event(0, Duration::seconds(5), json_map! {"app": json!("a")}),
+            event(5, Duration::seconds(5), json_map! {"app": json!("b")}),
+        ];
+        assert_eq!(
+            dominant_per_slot(&events, Duration::seconds(10), "app", time(0), time(10)),
+            vec![slot(0, 10, Some("a"), 5)]
+        );
+    }
+}
@@ -0,0 +1,49 @@
+use aw_models::Event;
+
+/// Counts the events in each duration bin, with `bin_edges` as the bin boundaries in seconds
+///
+/// The first bin counts the events shorter than the first edge, each following bin the events
+/// from one edge up to the next, and the last bin is an overflow bin for the events at least as
+/// long as the last edge. `bin_edges` has to be in ascending order, so there is always one more
+/// count than there are edges.
+///
+/// # Example
+/// ```ignore
+/// bin_edges: [60, 300]
+/// input:     [30s] [2m] [4m] [10m] [1h]
+/// output:    [1, 2, 2]
+/// ```
+pub fn duration_histogram(events: &[Event], bin_edges: &[f64]) -> Vec<u64> {
+    let mut counts = vec![0; bin_edges.len() + 1];
+    for event in events {
+        let duration = event.duration.num_milliseconds() as f64 / 1000.0;
+        let bin = bin_edges.partition_point(|edge| *edge <= duration);
+        counts[bin] += 1;
+    }
+    counts
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use serde_json::Map;
+
+    use crate::test_util::event;
+
+    use super::duration_histogram;
+
+    #[test]
+    fn test_duration_histogram() {
+        let events = vec![
+            event(0, Duration::seconds(30), Map::new()),
+            event(0, Duration::seconds(60), Map::new()),
+            event(0, Duration::seconds(240), Map::new()),
+            event(0, Duration::seconds(600), Map::new()),
+            event(0, Duration::seconds(3600), Map::new()),
+        ];
+        assert_eq!(duration_histogram(&events, &[60.0, 300.0]), vec![1, 2, 2]);
+        // Without edges everything ends up in the overflow bin
+        assert_eq!(duration_histogram(&events, &[]), vec![5]);
+        assert_eq!(duration_histogram(&[], &[60.0]), vec![0, 0]);
+    }
+}
@@ -0,0 +1,154 @@
+use aw_models::Event;
+use chrono::{DateTime, Utc};
+
+use crate::split_url_event;
+
+/// Overlays the url and title of the active browser tab onto the window events of browsers, so
+/// that the sites visited can be categorized like apps
+///
+/// Window events whose `app` is one of `browser_apps`, compared case-insensitively, are split at
+/// the starts and ends of the tab events which overlap them. The parts during a tab get the
+/// `url` and `title` of the tab, replacing the window title, and the `$domain`, `$path`,
+/// `$protocol` and `$params` of the url as from `split_url_event`. Parts without a tab keep the
+/// window data as it is, as do the events of other apps. Events are returned sorted by timestamp.
+///
+/// # Example
+/// ```ignore
+/// browser_apps: [Firefox]
+/// window: [Firefox (0-10)             ] [Editor (10-15)]
+/// tabs:      [github.com (2-6)][docs.rs (6-20)   ]
+/// output: [Firefox][Firefox, github.com][Firefox, docs.rs] [Editor]
+/// ```
+pub fn enrich_browser_events(
+    mut window_events: Vec<Event>,
+    tab_events: &[Event],
+    browser_apps: &[String],
+) -> Vec<Event> {
+    window_events.sort_by_key(|e| e.timestamp);
+    let mut tabs: Vec<&Event> = tab_events.iter().collect();
+    tabs.sort_by_key(|e| e.timestamp);
+    let browser_apps: Vec<String> = browser_apps.iter().map(|a| a.to_lowercase()).collect();
+    let is_browser = |event: &Event| {
+        event
+            .data
+            .get("app")
+            .and_then(|app| app.as_str())
+            .is_some_and(|app| browser_apps.contains(&app.to_lowercase()))
+    };
+    let part = |event: &Event, start: DateTime<Utc>, end: DateTime<Utc>| Event {
+        timestamp: start,
+        duration: end - start,
+        ..event.clone()
+    };
+
+    let mut enriched = Vec::new();
+    for event in window_events {
+        if !is_browser(&event) || event.duration <= chrono::Duration::zero() {
+            enriched.push(event);
+            continue;
+        }
+        let end = event.calculate_endtime();
+        let mut covered_until = event.timestamp;
+        // Tabs starting after the window event can't overlap it
+        let end_idx = tabs.partition_point(|tab| tab.timestamp < end);
+        for tab in &tabs[..end_idx] {
+            let tab_start = tab.timestamp.max(covered_until);
+            let tab_end = tab.calculate_endtime().min(end);
+            if tab_start >= tab_end {
+                continue;
+            }
+            if tab_start > covered_until {
+                enriched.push(part(&event, covered_until, tab_start));
+            }
+            let mut overlay = part(&event, tab_start, tab_end);
+            for key in ["url", "title"] {
+                if let Some(value) = tab.data.get(key) {
+                    overlay.data.insert(key.to_string(), value.clone());
+                }
+            }
+            split_url_event(&mut overlay);
+            enriched.push(overlay);
+            covered_until = tab_end;
+        }
+        if covered_until < end {
+            enriched.push(part(&event, covered_until, end));
+        }
+    }
+    enriched
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use serde_json::json;
+
+    use crate::test_util::event;
+
+    use super::enrich_browser_events;
+
+    #[test]
+    fn test_enrich_browser_events() {
+        let window = vec![
+            event(
+                10,
+                Duration::seconds(5),
+                json_map! {"app": "Editor", "title": "main.rs"},
+            ),
+            event(
+                0,
+                Duration::seconds(10),
+                json_map! {"app": "firefox", "title": "Mozilla Firefox"},
+            ),
+        ];
+        let tabs = vec![
+            event(
+                6,
+                Duration::seconds(14),
+                json_map! {"url": "https://docs.rs/chrono", "title": "chrono"},
+            ),
+            event(
+                2,
+                Duration::seconds(4),
+                json_map! {"url": "https://www.github.com/", "title": "GitHub"},
+            ),
+        ];
+        let res = enrich_browser_events(window, &tabs, &["Firefox".to_string()]);
+        assert_eq!(
+            res,
+            vec![
+                event(
+                    0,
+                    Duration::seconds(2),
+                    json_map! {"app": "firefox", "title": "Mozilla Firefox"}
+                ),
+                event(
+                    2,
+                    Duration::seconds(4),
+                    json_map! {"app": "firefox", "title": "GitHub", "url": "https://www.github.com/",
+                    "$protocol": "https", "$domain": "github.com", "$path": "/", "$params": ""}
+                ),
+                event(
+                    6,
+                    Duration::seconds(4),
+                    json_map! {"app": "firefox", "title": "chrono", "url": "https://docs.rs/chrono",
+                    "$protocol": "https", "$domain": "docs.rs", "$path": "/chrono", "$params": ""}
+                ),
+                // Other apps are left alone even during a tab
+                event(
+                    10,
+                    Duration::seconds(5),
+                    json_map! {"app": "Editor", "title": "main.rs"}
+                ),
+            ]
+        );
+
+        // Without tabs the browser events are unchanged
+        let window = vec![event(
+            0,
+            Duration::seconds(10),
+            json_map! {"app": "Firefox"},
+        )];
+        let res = enrich_browser_events(window.clone(), &[], &["Firefox".to_string()]);
+        assert_eq!(res, window);
+    }
+}
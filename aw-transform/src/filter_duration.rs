@@ -0,0 +1,139 @@
+use aw_models::Event;
+use chrono::Duration;
+
+/// Keeps only the events which have a duration within `[min, max]`, both bounds are inclusive
+///
+/// Useful for removing short noise events, such as window flickers shorter than a second.
+///
+/// # Example
+/// ```ignore
+/// min: 2s
+/// max: 4s
+/// input:  [a][b  ][c    ][d      ]
+/// output:    [b  ][c    ]
+/// ```
+pub fn filter_duration(
+    mut events: Vec<Event>,
+    min: Option<Duration>,
+    max: Option<Duration>,
+) -> Vec<Event> {
+    events.retain(|event| {
+        if let Some(min) = min {
+            if event.duration < min {
+                return false;
+            }
+        }
+        if let Some(max) = max {
+            if event.duration > max {
+                return false;
+            }
+        }
+        true
+    });
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use serde_json::json;
+
+    use crate::test_util::event;
+
+    use super::filter_duration;
+
+    #[test]
+    fn test_filter_duration() {
+        let events = vec![
+            event(
+                "2000-01-01T00:00:00Z",
+                Duration::milliseconds(999),
+                json_map! {"test": json!(1)},
+            ),
+            event(
+                "2000-01-01T00:00:00Z",
+                Duration::seconds(1),
+                json_map! {"test": json!(1)},
+            ),
+            event(
+                "2000-01-01T00:00:00Z",
+                Duration::seconds(3),
+                json_map! {"test": json!(1)},
+            ),
+            event(
+                "2000-01-01T00:00:00Z",
+                Duration::seconds(5),
+                json_map! {"test": json!(1)},
+            ),
+            event(
+                "2000-01-01T00:00:00Z",
+                Duration::milliseconds(5001),
+                json_map! {"test": json!(1)},
+            ),
+        ];
+
+        // Events exactly on the boundaries are kept
+        let res = filter_duration(
+            events.clone(),
+            Some(Duration::seconds(1)),
+            Some(Duration::seconds(5)),
+        );
+        assert_eq!(
+            res,
+            vec![
+                event(
+                    "2000-01-01T00:00:00Z",
+                    Duration::seconds(1),
+                    json_map! {"test": json!(1)}
+                ),
+                event(
+                    "2000-01-01T00:00:00Z",
+                    Duration::seconds(3),
+                    json_map! {"test": json!(1)}
+                ),
+                event(
+                    "2000-01-01T00:00:00Z",
+                    Duration::seconds(5),
+                    json_map! {"test": json!(1)}
+                ),
+            ]
+        );
+
+        let res = filter_duration(events.clone(), Some(Duration::seconds(5)), None);
+        assert_eq!(
+            res,
+            vec![
+                event(
+                    "2000-01-01T00:00:00Z",
+                    Duration::seconds(5),
+                    json_map! {"test": json!(1)}
+                ),
+                event(
+                    "2000-01-01T00:00:00Z",
+                    Duration::milliseconds(5001),
+                    json_map! {"test": json!(1)}
+                ),
+            ]
+        );
+
+        let res = filter_duration(events.clone(), None, Some(Duration::seconds(1)));
+        assert_eq!(
+            res,
+            vec![
+                event(
+                    "2000-01-01T00:00:00Z",
+                    Duration::milliseconds(999),
+                    json_map! {"test": json!(1)}
+                ),
+                event(
+                    "2000-01-01T00:00:00Z",
+                    Duration::seconds(1),
+                    json_map! {"test": json!(1)}
+                ),
+            ]
+        );
+
+        let res = filter_duration(events.clone(), None, None);
+        assert_eq!(res, events);
+    }
+}
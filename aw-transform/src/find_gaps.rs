@@ -0,0 +1,112 @@
+use aw_models::{Event, TimeInterval};
+use chrono::{DateTime, Duration, Utc};
+
+/// Finds the time between `start` and `stop` which no event covers, for spotting when a watcher
+/// wasn't running
+///
+/// Returns the uncovered intervals longer than `min_gap`, oldest first, including the time from
+/// `start` to the first event and from the last event to `stop`. Events are clipped to the
+/// range and may overlap or be in any order, events which touch each other leave no gap.
+///
+/// # Example
+/// ```ignore
+/// start: 0, stop: 100, min_gap: 5s
+/// input:  [a (10-20)] [b (20-30)] [c (32-40)] [d (60-120)]
+/// output: [(0-10), (40-60)]
+/// ```
+pub fn find_gaps(
+    events: &[Event],
+    start: DateTime<Utc>,
+    stop: DateTime<Utc>,
+    min_gap: Duration,
+) -> Vec<TimeInterval> {
+    let mut intervals: Vec<(DateTime<Utc>, DateTime<Utc>)> = events
+        .iter()
+        .map(|e| (e.timestamp.max(start), e.calculate_endtime().min(stop)))
+        .filter(|(event_start, event_end)| event_start < event_end)
+        .collect();
+    intervals.sort();
+
+    let mut gaps = Vec::new();
+    let mut covered_until = start;
+    for (event_start, event_end) in intervals {
+        if event_start > covered_until {
+            gaps.push(TimeInterval::new(covered_until, event_start));
+        }
+        covered_until = covered_until.max(event_end);
+    }
+    if stop > covered_until {
+        gaps.push(TimeInterval::new(covered_until, stop));
+    }
+    gaps.retain(|gap| gap.duration() > min_gap);
+    gaps
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::DateTime;
+    use chrono::Duration;
+    use serde_json::json;
+
+    use aw_models::TimeInterval;
+
+    use crate::test_util::event;
+
+    use super::find_gaps;
+
+    fn interval(start: i64, end: i64) -> TimeInterval {
+        TimeInterval::new(
+            DateTime::from_timestamp(start, 0).unwrap(),
+            DateTime::from_timestamp(end, 0).unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_find_gaps() {
+        let time = |secs: i64| DateTime::from_timestamp(secs, 0).unwrap();
+        let events = vec![
+            event(
+                60,
+                Duration::seconds(60),
+                json_map! {"app": json!("Editor")},
+            ),
+            // Touching events leave no gap
+            event(
+                10,
+                Duration::seconds(10),
+                json_map! {"app": json!("Editor")},
+            ),
+            event(
+                20,
+                Duration::seconds(10),
+                json_map! {"app": json!("Editor")},
+            ),
+            // Gap of 2s, shorter than min_gap
+            event(32, Duration::seconds(8), json_map! {"app": json!("Editor")}),
+            // Within another event
+            event(65, Duration::seconds(5), json_map! {"app": json!("Editor")}),
+        ];
+        assert_eq!(
+            find_gaps(&events, time(0), time(100), Duration::seconds(5)),
+            vec![interval(0, 10), interval(40, 60)]
+        );
+        assert_eq!(
+            find_gaps(&events, time(0), time(100), Duration::zero()),
+            vec![interval(0, 10), interval(30, 32), interval(40, 60)]
+        );
+
+        // Events outside of the range are ignored, the gap reaches the end of the range
+        assert_eq!(
+            find_gaps(&events, time(15), time(200), Duration::seconds(5)),
+            vec![interval(40, 60), interval(120, 200)]
+        );
+        assert_eq!(
+            find_gaps(&[], time(0), time(100), Duration::seconds(5)),
+            vec![interval(0, 100)]
+        );
+        assert_eq!(
+            find_gaps(&events, time(100), time(0), Duration::seconds(5)),
+            vec![]
+        );
+    }
+}
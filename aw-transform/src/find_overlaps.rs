@@ -0,0 +1,134 @@
+use aw_models::Event;
+
+/// Returns the events which overlap with at least one other event
+///
+/// Events are returned unmodified and in their original order. Events which only touch, where
+/// one ends at the same time as the next one starts, are not considered overlapping.
+///
+/// # Example
+/// ```ignore
+///   input:  [a   ][b]  [c  ][d]
+///              [e  ]
+///   output: [a   ][b]
+///              [e  ]
+/// ```
+pub fn find_overlaps(events: &[Event]) -> Vec<Event> {
+    let mut order: Vec<usize> = (0..events.len()).collect();
+    order.sort_by_key(|&i| events[i].timestamp);
+
+    let mut overlapping = vec![false; events.len()];
+    // The event reaching furthest so far, any later starting event overlapping
+    // a previous event also overlaps this one
+    let mut furthest: Option<usize> = None;
+    for i in order {
+        let event = &events[i];
+        if let Some(f) = furthest {
+            let furthest_end = events[f].calculate_endtime();
+            if event.timestamp < furthest_end {
+                overlapping[i] = true;
+                overlapping[f] = true;
+            }
+            if event.calculate_endtime() <= furthest_end {
+                continue;
+            }
+        }
+        furthest = Some(i);
+    }
+
+    events
+        .iter()
+        .zip(overlapping)
+        .filter(|(_, overlapping)| *overlapping)
+        .map(|(event, _)| event.clone())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use serde_json::json;
+
+    use crate::test_util::event;
+
+    use super::find_overlaps;
+
+    #[test]
+    fn test_find_overlaps() {
+        let events = vec![
+            event(
+                "2000-01-01T00:00:10Z",
+                Duration::seconds(5),
+                json_map! {"n": json!(1)},
+            ),
+            event(
+                "2000-01-01T00:00:00Z",
+                Duration::seconds(4),
+                json_map! {"n": json!(2)},
+            ),
+            event(
+                "2000-01-01T00:00:03Z",
+                Duration::seconds(2),
+                json_map! {"n": json!(3)},
+            ),
+            event(
+                "2000-01-01T00:00:05Z",
+                Duration::seconds(5),
+                json_map! {"n": json!(4)},
+            ),
+        ];
+        // Event 1 and 4 only touch, 2 and 3 overlap
+        let res = find_overlaps(&events);
+        assert_eq!(res, vec![events[1].clone(), events[2].clone()]);
+    }
+
+    #[test]
+    fn test_find_overlaps_adjacent() {
+        let events = vec![
+            event(
+                "2000-01-01T00:00:00Z",
+                Duration::seconds(1),
+                json_map! {"n": json!(1)},
+            ),
+            event(
+                "2000-01-01T00:00:01Z",
+                Duration::seconds(1),
+                json_map! {"n": json!(2)},
+            ),
+            event(
+                "2000-01-01T00:00:02Z",
+                Duration::seconds(1),
+                json_map! {"n": json!(3)},
+            ),
+        ];
+        assert_eq!(find_overlaps(&events), vec![]);
+    }
+
+    #[test]
+    fn test_find_overlaps_contained() {
+        // A long event containing several short ones, which don't overlap each other
+        let events = vec![
+            event(
+                "2000-01-01T00:00:00Z",
+                Duration::seconds(10),
+                json_map! {"n": json!(1)},
+            ),
+            event(
+                "2000-01-01T00:00:01Z",
+                Duration::seconds(1),
+                json_map! {"n": json!(2)},
+            ),
+            event(
+                "2000-01-01T00:00:05Z",
+                Duration::seconds(1),
+                json_map! {"n": json!(3)},
+            ),
+            event(
+                "2000-01-01T00:00:10Z",
+                Duration::seconds(1),
+                json_map! {"n": json!(4)},
+            ),
+        ];
+        let res = find_overlaps(&events);
+        assert_eq!(res, events[0..3].to_vec());
+    }
+}
@@ -0,0 +1,188 @@
+use std::collections::BTreeMap;
+
+use aw_models::Event;
+use chrono::{NaiveDate, NaiveTime, TimeZone, Timelike};
+
+/// The local time of the first event of each local day in the given timezone, such as when the
+/// user started their computer in the morning
+///
+/// An event belongs to the day it starts in, so an event running past midnight doesn't count
+/// as activity of the next day. Days without events are left out. The order of the events
+/// doesn't matter.
+///
+/// # Example
+/// ```ignore
+/// timezone: UTC
+/// input:  [01-01 09:10] [01-01 07:30] [01-03 23:50 - 01-04 00:30] [01-04 08:00]
+/// output: { 01-01: 07:30, 01-03: 23:50, 01-04: 08:00 }
+/// ```
+pub fn first_activity_times<Tz: TimeZone>(
+    events: &[Event],
+    tz: &Tz,
+) -> BTreeMap<NaiveDate, NaiveTime> {
+    let mut days: BTreeMap<NaiveDate, NaiveTime> = BTreeMap::new();
+    for event in events {
+        let local = event.timestamp.with_timezone(tz);
+        days.entry(local.date_naive())
+            .and_modify(|first| *first = (*first).min(local.time()))
+            .or_insert(local.time());
+    }
+    days
+}
+
+fn millis_from_midnight(time: &NaiveTime) -> i64 {
+    time.num_seconds_from_midnight() as i64 * 1000 + (time.nanosecond() / 1_000_000) as i64
+}
+
+fn from_millis_from_midnight(millis: i64) -> NaiveTime {
+    NaiveTime::from_num_seconds_from_midnight_opt(
+        (millis / 1000) as u32,
+        ((millis % 1000) * 1_000_000) as u32,
+    )
+    .unwrap()
+}
+
+/// The mean of times of the day, to the millisecond, `None` if there are none
+///
+/// The times are averaged as the time since midnight, so times on both sides of midnight such as
+/// 23:00 and 01:00 average to noon rather than to midnight.
+pub fn mean_time_of_day(times: &[NaiveTime]) -> Option<NaiveTime> {
+    if times.is_empty() {
+        return None;
+    }
+    let total: i64 = times.iter().map(millis_from_midnight).sum();
+    Some(from_millis_from_midnight(total / times.len() as i64))
+}
+
+/// The median of times of the day, to the millisecond, `None` if there are none
+///
+/// With an even number of times it is the mean of the two middle ones.
+pub fn median_time_of_day(times: &[NaiveTime]) -> Option<NaiveTime> {
+    if times.is_empty() {
+        return None;
+    }
+    let mut times = times.to_vec();
+    times.sort();
+    let middle = times.len() / 2;
+    match times.len() % 2 {
+        1 => Some(times[middle]),
+        _ => mean_time_of_day(&times[middle - 1..=middle]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use chrono::Duration;
+    use chrono::NaiveDate;
+    use chrono::NaiveTime;
+    use serde_json::json;
+
+    use crate::test_util::event;
+
+    use super::{first_activity_times, mean_time_of_day, median_time_of_day};
+
+    fn time(s: &str) -> NaiveTime {
+        NaiveTime::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn test_first_activity_times() {
+        let events = vec![
+            event(
+                "2000-01-01T09:10:00Z",
+                Duration::minutes(10),
+                json_map! {"status": json!("not-afk")},
+            ),
+            event(
+                "2000-01-01T07:30:00Z",
+                Duration::minutes(10),
+                json_map! {"status": json!("not-afk")},
+            ),
+            // Runs past midnight
+            event(
+                "2000-01-03T23:50:00Z",
+                Duration::minutes(40),
+                json_map! {"status": json!("not-afk")},
+            ),
+            event(
+                "2000-01-04T08:00:00Z",
+                Duration::minutes(10),
+                json_map! {"status": json!("not-afk")},
+            ),
+        ];
+        let days = first_activity_times(&events, &chrono_tz::UTC);
+        let date = |day: u32| NaiveDate::from_ymd_opt(2000, 1, day).unwrap();
+        assert_eq!(
+            days.into_iter().collect::<Vec<_>>(),
+            vec![
+                (date(1), time("07:30:00")),
+                (date(3), time("23:50:00")),
+                (date(4), time("08:00:00")),
+            ]
+        );
+
+        // 23:50 UTC is on the next day in Stockholm
+        let days = first_activity_times(&events, &chrono_tz::Europe::Stockholm);
+        assert_eq!(
+            days.into_iter().collect::<Vec<_>>(),
+            vec![(date(1), time("08:30:00")), (date(4), time("00:50:00"))]
+        );
+    }
+
+    #[test]
+    fn test_first_activity_times_month() {
+        // Every day of January 2000, starting between 06:00 and 08:30 with a later event first
+        let day_start = |day: u32| {
+            NaiveDate::from_ymd_opt(2000, 1, day)
+                .unwrap()
+                .and_hms_opt(6, 0, 0)
+                .unwrap()
+                .and_utc()
+                + Duration::minutes((day * 23 % 150) as i64)
+        };
+        let mut events = Vec::new();
+        for day in 1..=31 {
+            for offset in [Duration::hours(4), Duration::zero()] {
+                events.push(event(
+                    day_start(day) + offset,
+                    Duration::minutes(30),
+                    json_map! {"status": json!("not-afk")},
+                ));
+            }
+        }
+        let days = first_activity_times(&events, &chrono_tz::UTC);
+        assert_eq!(days.len(), 31);
+        assert_eq!(
+            days[&NaiveDate::from_ymd_opt(2000, 1, 1).unwrap()],
+            time("06:23:00")
+        );
+        assert_eq!(
+            days[&NaiveDate::from_ymd_opt(2000, 1, 7).unwrap()],
+            time("06:11:00")
+        );
+
+        let times: Vec<NaiveTime> = days.into_values().collect();
+        assert_eq!(mean_time_of_day(&times), Some(time("07:17:40.645")));
+        assert_eq!(median_time_of_day(&times), Some(time("07:19:00")));
+    }
+
+    #[test]
+    fn test_mean_median_time_of_day() {
+        let times = vec![time("07:00:00"), time("09:00:00"), time("07:30:00")];
+        assert_eq!(mean_time_of_day(&times), Some(time("07:50:00")));
+        assert_eq!(median_time_of_day(&times), Some(time("07:30:00")));
+
+        let times = vec![
+            time("08:00:00"),
+            time("07:00:00"),
+            time("10:00:00"),
+            time("07:00:01"),
+        ];
+        assert_eq!(median_time_of_day(&times), Some(time("07:30:00.500")));
+
+        assert_eq!(mean_time_of_day(&[]), None);
+        assert_eq!(median_time_of_day(&[]), None);
+    }
+}
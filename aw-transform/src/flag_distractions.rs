@@ -0,0 +1,150 @@
+use aw_models::Event;
+use chrono::Duration;
+use serde_json::Value;
+
+/// Data key set to `true` on the events found by `flag_distractions`
+pub const DISTRACTION_KEY: &str = "$distraction";
+
+/// Flags short events which interrupt longer activities as distractions, setting
+/// `data["$distraction"]` to `true`
+///
+/// Events are sorted by timestamp first. An event is a distraction if it is shorter than
+/// `min_focus` and both the event before and after it last at least `min_focus` and have
+/// another value of `key` than it, whether or not they have the same value as each other. The
+/// first and last events have only one neighbour and are never distractions, neither are events
+/// without `key`. Other events are left as they are.
+///
+/// # Example
+/// ```ignore
+/// min_focus: 60s
+/// input:  [editor 600s] [chat 20s] [editor 300s] [terminal 10s] [terminal 200s]
+/// output: [editor 600s] [chat 20s, $distraction] [editor 300s] [terminal 10s] [terminal 200s]
+/// ```
+pub fn flag_distractions(mut events: Vec<Event>, min_focus: Duration, key: &str) -> Vec<Event> {
+    events.sort_by_key(|e| e.timestamp);
+
+    let mut distractions = Vec::new();
+    for (i, window) in events.windows(3).enumerate() {
+        let (prev, event, next) = (&window[0], &window[1], &window[2]);
+        let value = match event.data.get(key) {
+            Some(value) => value,
+            None => continue,
+        };
+        let interrupts =
+            |other: &Event| other.duration >= min_focus && other.data.get(key) != Some(value);
+        if event.duration < min_focus && interrupts(prev) && interrupts(next) {
+            distractions.push(i + 1);
+        }
+    }
+    for i in distractions {
+        events[i]
+            .data
+            .insert(DISTRACTION_KEY.to_string(), Value::Bool(true));
+    }
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use serde_json::json;
+
+    use aw_models::Event;
+
+    use crate::test_util::event;
+
+    use super::{flag_distractions, DISTRACTION_KEY};
+
+    fn flagged(events: &[Event]) -> Vec<bool> {
+        events
+            .iter()
+            .map(|e| e.data.get(DISTRACTION_KEY) == Some(&json!(true)))
+            .collect()
+    }
+
+    #[test]
+    fn test_flag_distractions() {
+        let events = vec![
+            event(
+                0,
+                Duration::seconds(600),
+                json_map! {"app": json!("Editor")},
+            ),
+            // Interrupts the editor, a distraction
+            event(600, Duration::seconds(20), json_map! {"app": json!("Chat")}),
+            event(
+                620,
+                Duration::seconds(300),
+                json_map! {"app": json!("Editor")},
+            ),
+            // A short task leading into a long one of the same app
+            event(
+                920,
+                Duration::seconds(10),
+                json_map! {"app": json!("Terminal")},
+            ),
+            event(
+                930,
+                Duration::seconds(200),
+                json_map! {"app": json!("Terminal")},
+            ),
+            // Between two different long activities
+            event(1130, Duration::seconds(5), json_map! {"app": json!("Mail")}),
+            event(
+                1135,
+                Duration::seconds(100),
+                json_map! {"app": json!("Browser")},
+            ),
+        ];
+        let result = flag_distractions(events.clone(), Duration::seconds(60), "app");
+        assert_eq!(
+            flagged(&result),
+            vec![false, true, false, false, false, true, false]
+        );
+        assert_eq!(result[1].data["app"], json!("Chat"));
+
+        // Unsorted input is sorted first
+        let mut reversed = events;
+        reversed.reverse();
+        let result = flag_distractions(reversed, Duration::seconds(60), "app");
+        assert_eq!(
+            flagged(&result),
+            vec![false, true, false, false, false, true, false]
+        );
+    }
+
+    #[test]
+    fn test_flag_distractions_edges() {
+        // Short first and last events only have one neighbour
+        let events = vec![
+            event(0, Duration::seconds(5), json_map! {"app": json!("Chat")}),
+            event(
+                5,
+                Duration::seconds(600),
+                json_map! {"app": json!("Editor")},
+            ),
+            event(605, Duration::seconds(5), json_map! {"app": json!("Chat")}),
+        ];
+        let result = flag_distractions(events, Duration::seconds(60), "app");
+        assert_eq!(flagged(&result), vec![false, false, false]);
+
+        // Neighbours which are short themselves aren't focused activities
+        let events = vec![
+            event(
+                0,
+                Duration::seconds(600),
+                json_map! {"app": json!("Editor")},
+            ),
+            event(600, Duration::seconds(5), json_map! {"app": json!("Chat")}),
+            event(605, Duration::seconds(30), json_map! {"app": json!("Mail")}),
+            event(
+                635,
+                Duration::seconds(600),
+                json_map! {"app": json!("Editor")},
+            ),
+        ];
+        let result = flag_distractions(events, Duration::seconds(60), "app");
+        assert_eq!(flagged(&result), vec![false, false, false, false]);
+        assert!(flag_distractions(vec![], Duration::seconds(60), "app").is_empty());
+    }
+}
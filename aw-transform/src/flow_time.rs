@@ -0,0 +1,94 @@
+use aw_models::Event;
+use chrono::Duration;
+
+use crate::longest_session::sessions;
+
+/// The sessions long enough to count as flow and their total length, see `flow_time`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FlowTime {
+    pub sessions: u64,
+    pub duration: Duration,
+}
+
+/// Sums the length of the sessions which are at least `min_session` long, such as the time
+/// spent in uninterrupted deep work
+///
+/// Sessions are found as in `longest_session`, a session being a run of events where no gap
+/// between the end of the events so far and the start of the next one is longer than `max_gap`,
+/// and their length includes the gaps within them. The order of the events doesn't matter.
+///
+/// # Example
+/// ```ignore
+/// min_session: 20s, max_gap: 5s
+/// input:  [a (0-10)] [b (12-25)] [c (40-50)] [d (100-130)]
+/// output: { sessions: 2, duration: 55s }
+/// ```
+pub fn flow_time(events: &[Event], min_session: Duration, max_gap: Duration) -> FlowTime {
+    let flow: Vec<Duration> = sessions(events, max_gap)
+        .iter()
+        .map(|session| session.duration())
+        .filter(|duration| *duration >= min_session)
+        .collect();
+    FlowTime {
+        sessions: flow.len() as u64,
+        duration: flow
+            .into_iter()
+            .fold(Duration::zero(), |total, d| total + d),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use serde_json::json;
+
+    use crate::test_util::event;
+
+    use super::{flow_time, FlowTime};
+
+    #[test]
+    fn test_flow_time() {
+        let events = vec![
+            event(
+                100,
+                Duration::seconds(30),
+                json_map! {"app": json!("Editor")},
+            ),
+            // A gap of 2s within the max gap, a session of 25s
+            event(
+                12,
+                Duration::seconds(13),
+                json_map! {"app": json!("Editor")},
+            ),
+            event(0, Duration::seconds(10), json_map! {"app": json!("Editor")}),
+            // Too short
+            event(
+                40,
+                Duration::seconds(10),
+                json_map! {"app": json!("Editor")},
+            ),
+        ];
+        assert_eq!(
+            flow_time(&events, Duration::seconds(20), Duration::seconds(5)),
+            FlowTime {
+                sessions: 2,
+                duration: Duration::seconds(55)
+            }
+        );
+        // The gap of 1s breaks the first session into two short ones
+        assert_eq!(
+            flow_time(&events, Duration::seconds(20), Duration::seconds(1)),
+            FlowTime {
+                sessions: 1,
+                duration: Duration::seconds(30)
+            }
+        );
+        assert_eq!(
+            flow_time(&[], Duration::seconds(20), Duration::seconds(5)),
+            FlowTime {
+                sessions: 0,
+                duration: Duration::zero()
+            }
+        );
+    }
+}
@@ -0,0 +1,82 @@
+use aw_models::Event;
+use chrono::Duration;
+
+/// Durations of the events in each value band, see `group_by_numeric`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NumericBands {
+    /// One more than there are edges, the first band is below the first edge and the last one
+    /// at or above the last edge
+    pub durations: Vec<Duration>,
+    /// Number of events without the key or with a value which isn't a number
+    pub skipped: usize,
+}
+
+/// Sums the durations of the events by which band the number at `key` in their data falls in
+///
+/// The edges must be in ascending order, band `i` is from `edges[i - 1]` to `edges[i]`,
+/// including the lower and excluding the upper edge, and the first and last bands are open
+/// ended. Events without `key` or where it isn't a number are skipped and counted.
+///
+/// # Example
+/// ```ignore
+/// key: cpu, edges: [10, 50]
+/// input:  [cpu: 5 (10s)] [cpu: 10 (20s)] [cpu: 30 (5s)] [cpu: 90 (1s)] [cpu: "high" (3s)]
+/// output: { durations: [10s, 25s, 1s], skipped: 1 }
+/// ```
+pub fn group_by_numeric(events: &[Event], key: &str, edges: &[f64]) -> NumericBands {
+    let mut bands = NumericBands {
+        durations: vec![Duration::zero(); edges.len() + 1],
+        skipped: 0,
+    };
+    for event in events {
+        match event.data.get(key).and_then(|value| value.as_f64()) {
+            Some(value) => {
+                let band = edges.partition_point(|edge| *edge <= value);
+                bands.durations[band] += event.duration;
+            }
+            None => bands.skipped += 1,
+        }
+    }
+    bands
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use serde_json::json;
+
+    use crate::test_util::event;
+
+    use super::{group_by_numeric, NumericBands};
+
+    #[test]
+    fn test_group_by_numeric() {
+        let mut events = vec![
+            event(0, Duration::seconds(10), json_map! {"cpu": json!(5)}),
+            // On an edge, part of the band above it
+            event(0, Duration::seconds(20), json_map! {"cpu": json!(10)}),
+            event(0, Duration::seconds(5), json_map! {"cpu": json!(30.5)}),
+            event(0, Duration::seconds(1), json_map! {"cpu": json!(90)}),
+            event(0, Duration::seconds(2), json_map! {"cpu": json!(-3)}),
+            event(0, Duration::seconds(3), json_map! {"cpu": json!("high")}),
+        ];
+        events.push(event(0, Duration::seconds(4), json_map! {}));
+        assert_eq!(
+            group_by_numeric(&events, "cpu", &[10.0, 50.0]),
+            NumericBands {
+                durations: vec![
+                    Duration::seconds(12),
+                    Duration::seconds(25),
+                    Duration::seconds(1)
+                ],
+                skipped: 2,
+            }
+        );
+
+        // Without edges everything is in one band
+        assert_eq!(
+            group_by_numeric(&events, "cpu", &[]).durations,
+            vec![Duration::seconds(38)]
+        );
+    }
+}
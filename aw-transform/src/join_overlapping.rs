@@ -0,0 +1,106 @@
+use aw_models::Event;
+use chrono::Duration;
+
+/// Copies `key` from the data of the right event covering the start of each left event
+///
+/// A right event covers a left event if the left event starts within `[start, end)` of the right
+/// event. If several right events cover it the one which started last is used. Left events are
+/// returned in their original order and are left unchanged if no right event covers them or if
+/// the covering event does not have the key.
+///
+/// # Example
+/// ```ignore
+/// key:    status
+/// left:   [a  ][b   ][c ]  [d]
+/// right:  [not-afk][afk      ]
+/// output: [a, not-afk][b, not-afk][c, afk][d, afk]
+/// ```
+pub fn join_overlapping(mut left: Vec<Event>, mut right: Vec<Event>, key: &str) -> Vec<Event> {
+    right.sort_by_key(|e| e.timestamp);
+    let longest = right
+        .iter()
+        .map(|e| e.duration)
+        .max()
+        .unwrap_or_else(Duration::zero);
+
+    for event in left.iter_mut() {
+        let start = event.timestamp;
+        // Right events starting after the left event can't cover it
+        let end_idx = right.partition_point(|r| r.timestamp <= start);
+        let covering = right[..end_idx]
+            .iter()
+            .rev()
+            // No earlier right event can reach the start of the left event
+            .take_while(|r| r.timestamp + longest >= start)
+            .find(|r| r.timestamp == start || r.calculate_endtime() > start);
+        if let Some(value) = covering.and_then(|r| r.data.get(key)) {
+            event.data.insert(key.to_string(), value.clone());
+        }
+    }
+    left
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use serde_json::json;
+
+    use crate::test_util::event;
+
+    use super::join_overlapping;
+
+    #[test]
+    fn test_join_overlapping() {
+        let left = vec![
+            event(0, Duration::seconds(5), json_map! {"app": "a"}),
+            event(5, Duration::seconds(5), json_map! {"app": "b"}),
+            event(12, Duration::seconds(2), json_map! {"app": "c"}),
+            event(30, Duration::seconds(1), json_map! {"app": "d"}),
+        ];
+        let right = vec![
+            event(10, Duration::seconds(10), json_map! {"status": "afk"}),
+            event(0, Duration::seconds(10), json_map! {"status": "not-afk"}),
+        ];
+        let res = join_overlapping(left, right, "status");
+        assert_eq!(
+            res,
+            vec![
+                event(
+                    0,
+                    Duration::seconds(5),
+                    json_map! {"app": "a", "status": "not-afk"}
+                ),
+                event(
+                    5,
+                    Duration::seconds(5),
+                    json_map! {"app": "b", "status": "not-afk"}
+                ),
+                event(
+                    12,
+                    Duration::seconds(2),
+                    json_map! {"app": "c", "status": "afk"}
+                ),
+                // Nothing covers the start
+                event(30, Duration::seconds(1), json_map! {"app": "d"}),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_join_overlapping_multiple() {
+        // The right event which started last is used, even if shorter
+        let left = vec![event(5, Duration::seconds(1), json_map! {"app": "a"})];
+        let right = vec![
+            event(0, Duration::seconds(100), json_map! {"status": "long"}),
+            event(4, Duration::seconds(2), json_map! {"status": "short"}),
+            event(2, Duration::seconds(1), json_map! {"status": "ended"}),
+        ];
+        let res = join_overlapping(left.clone(), right, "status");
+        assert_eq!(res[0].data, json_map! {"app": "a", "status": "short"});
+
+        // A covering event without the key leaves the event as is
+        let right = vec![event(0, Duration::seconds(10), json_map! {"other": "x"})];
+        let res = join_overlapping(left.clone(), right, "status");
+        assert_eq!(res, left);
+    }
+}
@@ -18,6 +18,9 @@ macro_rules! json_map {
 
 pub mod classify;
 
+#[cfg(test)]
+mod test_util;
+
 mod heartbeat;
 pub use heartbeat::heartbeat;
 
@@ -30,23 +33,128 @@ pub use flood::flood;
 mod merge;
 pub use merge::merge_events_by_keys;
 
+mod merge_consecutive;
+pub use merge_consecutive::merge_consecutive;
+
 mod chunk;
 pub use chunk::chunk_events_by_key;
 
 mod sort;
 pub use sort::{sort_by_duration, sort_by_timestamp};
 
+mod rank;
+pub use rank::{rank_by_duration, rank_by_key};
+
 mod filter_keyvals;
 pub use filter_keyvals::{exclude_keyvals, filter_keyvals, filter_keyvals_regex};
 
 mod filter_period;
 pub use filter_period::filter_period_intersect;
 
+mod filter_duration;
+pub use filter_duration::filter_duration;
+
+mod rename_keys;
+pub use rename_keys::rename_keys;
+
 mod split_url;
 pub use split_url::split_url_event;
 
 mod period_union;
 pub use period_union::period_union;
 
+mod period_intersect;
+pub use period_intersect::{overlap_duration, period_intersect};
+
 mod union_no_overlap;
 pub use union_no_overlap::union_no_overlap;
+
+mod find_overlaps;
+pub use find_overlaps::find_overlaps;
+
+mod join_overlapping;
+pub use join_overlapping::join_overlapping;
+
+mod activity_bounds;
+pub use activity_bounds::{activity_bounds, ActivityBounds};
+
+mod transition_counts;
+pub use transition_counts::transition_counts;
+
+mod by_hour_of_day;
+pub use by_hour_of_day::by_hour_of_day;
+
+mod weekday_weekend;
+pub use weekday_weekend::split_weekday_weekend;
+
+mod daily_streak;
+pub use daily_streak::{daily_streak, DailyStreak};
+
+mod duration_histogram;
+pub use duration_histogram::duration_histogram;
+
+mod debounce_status;
+pub use debounce_status::debounce_status;
+
+mod close_gaps;
+pub use close_gaps::close_gaps;
+
+mod split_at;
+pub use split_at::split_at;
+
+mod redact;
+pub use redact::{redact, Redaction};
+
+mod longest_session;
+pub use longest_session::{longest_session, Session};
+
+mod active_ratio;
+pub use active_ratio::active_ratio;
+
+mod time_between;
+pub use time_between::time_between;
+
+mod flag_distractions;
+pub use flag_distractions::{flag_distractions, DISTRACTION_KEY};
+
+mod group_by_numeric;
+pub use group_by_numeric::{group_by_numeric, NumericBands};
+
+mod find_gaps;
+pub use find_gaps::find_gaps;
+
+mod rollup_categories;
+pub use rollup_categories::{rollup_categories, CategoryNode};
+
+mod enrich_browser_events;
+pub use enrich_browser_events::enrich_browser_events;
+
+mod dominant_per_slot;
+pub use dominant_per_slot::{dominant_per_slot, SlotActivity};
+
+mod switch_rate;
+pub use switch_rate::switch_rate;
+
+mod time_matching;
+pub use time_matching::time_matching;
+
+mod first_activity_times;
+pub use first_activity_times::{first_activity_times, mean_time_of_day, median_time_of_day};
+
+mod daily_category_trend;
+pub use daily_category_trend::daily_category_trend;
+
+mod productivity_score;
+pub use productivity_score::productivity_score;
+
+mod switching_cost;
+pub use switching_cost::{switching_cost, SwitchingCost};
+
+mod flow_time;
+pub use flow_time::{flow_time, FlowTime};
+
+mod rolling_average;
+pub use rolling_average::{rolling_average, MissingDays};
+
+mod start_of_day;
+pub use start_of_day::start_of_day;
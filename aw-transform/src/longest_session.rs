@@ -0,0 +1,129 @@
+use aw_models::Event;
+use chrono::{DateTime, Duration, Utc};
+
+/// A run of events without gaps longer than a threshold, see `longest_session`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Session {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+}
+
+impl Session {
+    pub fn duration(&self) -> Duration {
+        self.end - self.start
+    }
+}
+
+/// Finds the longest session in the events, a session being a run of events where no gap
+/// between the end of the events so far and the start of the next one is longer than `max_gap`
+///
+/// The length of a session is the time from its start to its end, including the gaps within
+/// it. Of several sessions of the same length the earliest is returned, `None` if there are no
+/// events. The order of the events doesn't matter.
+///
+/// # Example
+/// ```ignore
+/// max_gap: 1s
+/// input:  [a (0-5)] [b (6-8)] [c (30-40)] [d (40-60)]
+/// output: { start: 30, end: 60 }
+/// ```
+pub fn longest_session(events: &[Event], max_gap: Duration) -> Option<Session> {
+    sessions(events, max_gap)
+        .into_iter()
+        .reduce(
+            |longest, session| match session.duration() > longest.duration() {
+                true => session,
+                false => longest,
+            },
+        )
+}
+
+/// Splits the events into sessions as in `longest_session`, oldest first
+pub(crate) fn sessions(events: &[Event], max_gap: Duration) -> Vec<Session> {
+    let mut events: Vec<&Event> = events.iter().collect();
+    events.sort_by_key(|e| e.timestamp);
+
+    let mut sessions: Vec<Session> = Vec::new();
+    for event in events {
+        let end = event.calculate_endtime();
+        match sessions.last_mut() {
+            Some(session) if event.timestamp - session.end <= max_gap => {
+                session.end = session.end.max(end);
+            }
+            _ => sessions.push(Session {
+                start: event.timestamp,
+                end,
+            }),
+        }
+    }
+    sessions
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::DateTime;
+    use chrono::Duration;
+    use serde_json::json;
+
+    use crate::test_util::event;
+
+    use super::{longest_session, Session};
+
+    fn session(start: i64, end: i64) -> Session {
+        Session {
+            start: DateTime::from_timestamp(start, 0).unwrap(),
+            end: DateTime::from_timestamp(end, 0).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_longest_session() {
+        let events = vec![
+            event(
+                30,
+                Duration::seconds(10),
+                json_map! {"app": json!("Editor")},
+            ),
+            // Gap of 2s, a session of its own
+            event(0, Duration::seconds(5), json_map! {"app": json!("Editor")}),
+            event(6, Duration::seconds(2), json_map! {"app": json!("Editor")}),
+            // Overlapping events
+            event(
+                40,
+                Duration::seconds(20),
+                json_map! {"app": json!("Editor")},
+            ),
+            event(45, Duration::seconds(5), json_map! {"app": json!("Editor")}),
+        ];
+        assert_eq!(
+            longest_session(&events, Duration::seconds(1)),
+            Some(session(30, 60))
+        );
+        assert_eq!(
+            longest_session(&events, Duration::seconds(30)),
+            Some(session(0, 60))
+        );
+        assert_eq!(longest_session(&[], Duration::seconds(1)), None);
+    }
+
+    #[test]
+    fn test_longest_session_tie() {
+        let events = vec![
+            event(
+                100,
+                Duration::seconds(10),
+                json_map! {"app": json!("Editor")},
+            ),
+            event(0, Duration::seconds(10), json_map! {"app": json!("Editor")}),
+            event(
+                50,
+                Duration::seconds(10),
+                json_map! {"app": json!("Editor")},
+            ),
+        ];
+        assert_eq!(
+            longest_session(&events, Duration::seconds(1)),
+            Some(session(0, 10))
+        );
+    }
+}
@@ -0,0 +1,148 @@
+use aw_models::Event;
+use chrono::Duration;
+
+use crate::sort_by_timestamp;
+
+/// Merge runs of consecutive events with identical data
+///
+/// Unlike `merge_events_by_keys` this respects time ordering: events are only merged if they
+/// are neighbours and the gap between them is at most `max_gap`, so separate sessions with the
+/// same data are kept apart.
+/// The merged event keeps the timestamp of the first event in the run and the durations are summed.
+///
+/// # Example
+/// ```ignore
+///   max_gap: 1s
+///   input:  | aa a  bb a   aa |
+///   output: | aaa   bb a   aa |
+/// ```
+pub fn merge_consecutive(events: Vec<Event>, max_gap: Duration) -> Vec<Event> {
+    let mut merged_events: Vec<Event> = Vec::new();
+    // End of the latest event in the current run, the gap is measured from here
+    let mut run_end = None;
+    for event in sort_by_timestamp(events) {
+        let event_end = event.calculate_endtime();
+        if let (Some(last), Some(end)) = (merged_events.last_mut(), run_end) {
+            if last.data == event.data && event.timestamp - end <= max_gap {
+                last.duration += event.duration;
+                if event_end > end {
+                    run_end = Some(event_end);
+                }
+                continue;
+            }
+        }
+        run_end = Some(event_end);
+        merged_events.push(event);
+    }
+    merged_events
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use serde_json::json;
+
+    use crate::test_util::event;
+
+    use super::merge_consecutive;
+
+    #[test]
+    fn test_merge_consecutive() {
+        let events = vec![
+            event(
+                "2000-01-01T00:00:00Z",
+                Duration::seconds(1),
+                json_map! {"app": json!("a")},
+            ),
+            event(
+                "2000-01-01T00:00:01Z",
+                Duration::seconds(2),
+                json_map! {"app": json!("a")},
+            ),
+            event(
+                "2000-01-01T00:00:03Z",
+                Duration::seconds(1),
+                json_map! {"app": json!("b")},
+            ),
+            event(
+                "2000-01-01T00:00:04Z",
+                Duration::seconds(1),
+                json_map! {"app": json!("a")},
+            ),
+            event(
+                "2000-01-01T00:00:05Z",
+                Duration::seconds(1),
+                json_map! {"app": json!("b")},
+            ),
+            event(
+                "2000-01-01T00:00:06Z",
+                Duration::seconds(1),
+                json_map! {"app": json!("b")},
+            ),
+        ];
+        let res = merge_consecutive(events, Duration::seconds(0));
+        assert_eq!(
+            res,
+            vec![
+                event(
+                    "2000-01-01T00:00:00Z",
+                    Duration::seconds(3),
+                    json_map! {"app": json!("a")}
+                ),
+                event(
+                    "2000-01-01T00:00:03Z",
+                    Duration::seconds(1),
+                    json_map! {"app": json!("b")}
+                ),
+                event(
+                    "2000-01-01T00:00:04Z",
+                    Duration::seconds(1),
+                    json_map! {"app": json!("a")}
+                ),
+                event(
+                    "2000-01-01T00:00:05Z",
+                    Duration::seconds(2),
+                    json_map! {"app": json!("b")}
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_merge_consecutive_gap() {
+        let events = vec![
+            event(
+                "2000-01-01T00:00:10Z",
+                Duration::seconds(1),
+                json_map! {"app": json!("a")},
+            ),
+            event(
+                "2000-01-01T00:00:00Z",
+                Duration::seconds(1),
+                json_map! {"app": json!("a")},
+            ),
+            event(
+                "2000-01-01T00:00:03Z",
+                Duration::seconds(1),
+                json_map! {"app": json!("a")},
+            ),
+        ];
+        // Gap of 2s is merged, gap of 6s is a separate session
+        let res = merge_consecutive(events, Duration::seconds(2));
+        assert_eq!(
+            res,
+            vec![
+                event(
+                    "2000-01-01T00:00:00Z",
+                    Duration::seconds(2),
+                    json_map! {"app": json!("a")}
+                ),
+                event(
+                    "2000-01-01T00:00:10Z",
+                    Duration::seconds(1),
+                    json_map! {"app": json!("a")}
+                ),
+            ]
+        );
+    }
+}
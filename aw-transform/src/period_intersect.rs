@@ -0,0 +1,192 @@
+use super::period_union;
+use aw_models::Event;
+use chrono::Duration;
+use serde_json::Map;
+
+/// Takes two lists of events and returns a new list of events covering the timeperiods which are
+/// covered by both lists, with no overlapping events. The counterpart of `period_union`.
+///
+/// Overlapping events within one of the lists are treated as one period. Periods which only
+/// touch, where one ends when the other starts, don't intersect.
+///
+/// WARNING: This function strips all data from events as it cannot keep it consistent.
+///
+/// # Example
+/// ```ignore
+///   events1   |   -------       --------- |
+///   events2   | ------  ---  --    ----   |
+///   result    |   ----  -          ----   |
+/// ```
+pub fn period_intersect(events1: &[Event], events2: &[Event]) -> Vec<Event> {
+    // Merge overlapping events within each list so that the periods are sorted and disjoint
+    let periods1 = period_union(events1, &[]);
+    let periods2 = period_union(events2, &[]);
+
+    let mut events_intersection = Vec::new();
+    let (mut i1, mut i2) = (0, 0);
+    while i1 < periods1.len() && i2 < periods2.len() {
+        let p1 = periods1[i1].interval();
+        let p2 = periods2[i2].interval();
+        if let Some(intersection) = p1.intersection(&p2) {
+            events_intersection.push(Event::new(
+                *intersection.start(),
+                intersection.duration(),
+                Map::new(),
+            ));
+        }
+        // The period ending first can't intersect with any later period of the other list
+        if p1.end() < p2.end() {
+            i1 += 1;
+        } else {
+            i2 += 1;
+        }
+    }
+    events_intersection
+}
+
+/// The total time covered by both lists of events, e.g. the time spent coding while in a meeting
+///
+/// Like in `period_intersect` the events of each list are first merged into their union, so time
+/// where several events of one list overlap is only counted once.
+///
+/// # Example
+/// ```ignore
+///   events1   |   -------       --------- |
+///   events2   | ------  ---  --    ----   |
+///   result    | 9 (4 + 1 + 4)             |
+/// ```
+pub fn overlap_duration(events1: &[Event], events2: &[Event]) -> Duration {
+    period_intersect(events1, events2)
+        .iter()
+        .fold(Duration::zero(), |total, event| total + event.duration)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use serde_json::json;
+
+    use aw_models::Event;
+
+    use crate::test_util::event;
+
+    use super::{overlap_duration, period_intersect};
+
+    fn period(secs: i64, duration: i64) -> Event {
+        event(secs, Duration::seconds(duration), json_map! {})
+    }
+
+    #[test]
+    fn test_period_intersect_empty() {
+        assert!(period_intersect(&[], &[]).is_empty());
+        assert!(period_intersect(
+            &[event(
+                0,
+                Duration::seconds(10),
+                json_map! {"test": json!(1)}
+            )],
+            &[]
+        )
+        .is_empty());
+        assert!(period_intersect(
+            &[],
+            &[event(
+                0,
+                Duration::seconds(10),
+                json_map! {"test": json!(1)}
+            )]
+        )
+        .is_empty());
+    }
+
+    #[test]
+    fn test_period_intersect_disjoint() {
+        // Touching periods don't intersect
+        let res = period_intersect(
+            &[
+                event(0, Duration::seconds(10), json_map! {"test": json!(1)}),
+                event(30, Duration::seconds(10), json_map! {"test": json!(1)}),
+            ],
+            &[
+                event(10, Duration::seconds(10), json_map! {"test": json!(1)}),
+                event(50, Duration::seconds(1), json_map! {"test": json!(1)}),
+            ],
+        );
+        assert!(res.is_empty());
+    }
+
+    #[test]
+    fn test_period_intersect() {
+        let events1 = [
+            event(2, Duration::seconds(7), json_map! {"test": json!(1)}),
+            event(16, Duration::seconds(9), json_map! {"test": json!(1)}),
+        ];
+        let events2 = [
+            event(0, Duration::seconds(6), json_map! {"test": json!(1)}),
+            event(8, Duration::seconds(3), json_map! {"test": json!(1)}),
+            event(13, Duration::seconds(2), json_map! {"test": json!(1)}),
+            event(19, Duration::seconds(4), json_map! {"test": json!(1)}),
+        ];
+        let res = period_intersect(&events1, &events2);
+        assert_eq!(res, vec![period(2, 4), period(8, 1), period(19, 4)]);
+    }
+
+    #[test]
+    fn test_period_intersect_nested() {
+        // Overlapping events within a list are merged before intersecting
+        let events1 = [
+            event(0, Duration::seconds(100), json_map! {"test": json!(1)}),
+            event(10, Duration::seconds(10), json_map! {"test": json!(1)}),
+        ];
+        let events2 = [
+            event(50, Duration::seconds(10), json_map! {"test": json!(1)}),
+            event(20, Duration::seconds(5), json_map! {"test": json!(1)}),
+            event(22, Duration::seconds(5), json_map! {"test": json!(1)}),
+        ];
+        let res = period_intersect(&events1, &events2);
+        assert_eq!(res, vec![period(20, 7), period(50, 10)]);
+
+        // Order of the arguments doesn't matter
+        assert_eq!(period_intersect(&events2, &events1), res);
+    }
+
+    #[test]
+    fn test_overlap_duration() {
+        assert_eq!(
+            overlap_duration(
+                &[],
+                &[event(
+                    0,
+                    Duration::seconds(10),
+                    json_map! {"test": json!(1)}
+                )]
+            ),
+            Duration::zero()
+        );
+        // Partial overlaps
+        let events1 = [
+            event(2, Duration::seconds(7), json_map! {"test": json!(1)}),
+            event(16, Duration::seconds(9), json_map! {"test": json!(1)}),
+        ];
+        let events2 = [
+            event(0, Duration::seconds(6), json_map! {"test": json!(1)}),
+            event(8, Duration::seconds(3), json_map! {"test": json!(1)}),
+            event(13, Duration::seconds(2), json_map! {"test": json!(1)}),
+            event(19, Duration::seconds(4), json_map! {"test": json!(1)}),
+        ];
+        assert_eq!(overlap_duration(&events1, &events2), Duration::seconds(9));
+
+        // A nested event isn't counted twice
+        let events1 = [
+            event(0, Duration::seconds(100), json_map! {"test": json!(1)}),
+            event(10, Duration::seconds(10), json_map! {"test": json!(1)}),
+        ];
+        let events2 = [event(
+            5,
+            Duration::seconds(10),
+            json_map! {"test": json!(1)},
+        )];
+        assert_eq!(overlap_duration(&events1, &events2), Duration::seconds(10));
+        assert_eq!(overlap_duration(&events2, &events1), Duration::seconds(10));
+    }
+}
@@ -0,0 +1,137 @@
+use std::collections::{BTreeMap, HashMap};
+
+use aw_models::Event;
+use chrono::{NaiveDate, TimeZone};
+
+use crate::start_of_day;
+
+/// The weight of a category path, from the category itself or else its closest parent with one
+fn category_weight(path: &[&str], weights: &HashMap<String, f64>) -> Option<f64> {
+    (1..=path.len())
+        .rev()
+        .find_map(|len| weights.get(&path[..len].join(" > ")).copied())
+}
+
+/// Scores each local day from 0 to 100 by how productive the time spent in it was, given a
+/// productivity weight from -1 to 1 for categories of events categorized by `classify`
+///
+/// Categories are named by their path joined with " > ", such as "Work > Programming", and a
+/// category without a weight gets the one of its closest parent with one. The score of a day is
+/// the mean weight of its time mapped from -1..1 to 0..100, so a day of only weight 1 scores 100,
+/// of only weight -1 scores 0 and of neutral time 50. Time of events without a `$category` which
+/// is a non-empty list of strings, or whose category and parents have no weight, has the weight
+/// `uncategorized_weight`. Events crossing midnight are split between the days they cover. Days
+/// without any time are left out.
+///
+/// # Example
+/// ```ignore
+/// weights: { Work: 1, Fun: -1 }, uncategorized_weight: 0, timezone: UTC
+/// input:  [01-01 Work > Code (3h)] [01-01 Fun (1h)] [01-02 Uncategorized (1h)]
+/// output: { 01-01: 75, 01-02: 50 }
+/// ```
+pub fn productivity_score<Tz: TimeZone>(
+    events: &[Event],
+    weights: &HashMap<String, f64>,
+    uncategorized_weight: f64,
+    tz: &Tz,
+) -> BTreeMap<NaiveDate, f64> {
+    // Weighted and total seconds per day
+    let mut days: BTreeMap<NaiveDate, (f64, f64)> = BTreeMap::new();
+    for event in events {
+        let path: Option<Vec<&str>> = event
+            .data
+            .get("$category")
+            .and_then(|c| c.as_array())
+            .and_then(|path| path.iter().map(|name| name.as_str()).collect());
+        let weight = path
+            .and_then(|path| category_weight(&path, weights))
+            .unwrap_or(uncategorized_weight);
+
+        let end = event.calculate_endtime();
+        let mut day = event.timestamp.with_timezone(tz).date_naive();
+        let mut start = event.timestamp;
+        while start < end {
+            let day_end = start_of_day(day.succ_opt().unwrap(), tz);
+            let slice_end = end.min(day_end);
+            let seconds = (slice_end - start).num_milliseconds() as f64 / 1000.0;
+            let (weighted, total) = days.entry(day).or_insert((0.0, 0.0));
+            *weighted += weight * seconds;
+            *total += seconds;
+            start = slice_end;
+            day = day.succ_opt().unwrap();
+        }
+    }
+    days.into_iter()
+        .filter(|(_, (_, total))| *total > 0.0)
+        .map(|(day, (weighted, total))| (day, (weighted / total + 1.0) * 50.0))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use chrono::Duration;
+    use chrono::NaiveDate;
+    use serde_json::json;
+
+    use crate::test_util::event;
+
+    use super::productivity_score;
+
+    #[test]
+    fn test_productivity_score() {
+        let date = |day: u32| NaiveDate::from_ymd_opt(2000, 1, day).unwrap();
+        let weights: HashMap<String, f64> = [
+            ("Work".to_string(), 1.0),
+            ("Work > Email".to_string(), 0.0),
+            ("Fun".to_string(), -1.0),
+        ]
+        .into();
+        let no_category = event("2000-01-03T12:00:00Z", Duration::minutes(60), json_map! {});
+        let events = vec![
+            // Mixed day, the weight of Work applies to its subcategory
+            event(
+                "2000-01-01T09:00:00Z",
+                Duration::minutes(180),
+                json_map! {"$category": json!(&["Work", "Programming"])},
+            ),
+            event(
+                "2000-01-01T13:00:00Z",
+                Duration::minutes(60),
+                json_map! {"$category": json!(&["Fun"])},
+            ),
+            // Half of it is on the next day, which also has neutral time
+            event(
+                "2000-01-01T23:30:00Z",
+                Duration::minutes(60),
+                json_map! {"$category": json!(&["Fun", "Games"])},
+            ),
+            event(
+                "2000-01-02T09:00:00Z",
+                Duration::minutes(90),
+                json_map! {"$category": json!(&["Work", "Email"])},
+            ),
+            no_category,
+            event(
+                "2000-01-03T13:00:00Z",
+                Duration::minutes(60),
+                json_map! {"$category": json!(&["Uncategorized"])},
+            ),
+        ];
+
+        let scores = productivity_score(&events, &weights, 0.0, &chrono_tz::UTC);
+        // (180 - 60 - 30) / 270 = 1/3
+        assert!((scores[&date(1)] - 200.0 / 3.0).abs() < 1e-9);
+        // -30 / 120
+        assert_eq!(scores[&date(2)], 37.5);
+        assert_eq!(scores[&date(3)], 50.0);
+        assert_eq!(scores.len(), 3);
+
+        // Uncategorized time counts as productive
+        let scores = productivity_score(&events, &weights, 1.0, &chrono_tz::UTC);
+        assert_eq!(scores[&date(3)], 100.0);
+
+        assert!(productivity_score(&[], &weights, 0.0, &chrono_tz::UTC).is_empty());
+    }
+}
@@ -0,0 +1,126 @@
+use std::cmp::{Ordering, Reverse};
+
+use aw_models::Event;
+use serde_json::Value;
+
+/// Sorts events by duration with the longest first and sets a 1-based `$rank` in their data
+///
+/// Events with equal durations share the same rank and the next rank skips the tied positions,
+/// tied events keep their relative order from the input.
+///
+/// # Example
+/// ```ignore
+/// input:  [a 1s] [b 3s] [c 2s] [d 3s]
+/// output: [b 3s, $rank: 1] [d 3s, $rank: 1] [c 2s, $rank: 3] [a 1s, $rank: 4]
+/// ```
+pub fn rank_by_duration(mut events: Vec<Event>) -> Vec<Event> {
+    events.sort_by_key(|e| Reverse(e.duration));
+    assign_ranks(events, |e1, e2| e1.duration == e2.duration)
+}
+
+/// Sorts events by a numeric value in their data with the highest first and sets a 1-based
+/// `$rank` in their data
+///
+/// Ties are ranked like in [`rank_by_duration`]. Events where the key is missing or not a number
+/// are put last and share the last rank.
+pub fn rank_by_key(mut events: Vec<Event>, key: &str) -> Vec<Event> {
+    let value = |event: &Event| event.data.get(key).and_then(Value::as_f64);
+    events.sort_by(|e1, e2| match (value(e1), value(e2)) {
+        (Some(v1), Some(v2)) => v2.partial_cmp(&v1).unwrap_or(Ordering::Equal),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    });
+    assign_ranks(events, |e1, e2| value(e1) == value(e2))
+}
+
+fn assign_ranks<F>(mut events: Vec<Event>, tied: F) -> Vec<Event>
+where
+    F: Fn(&Event, &Event) -> bool,
+{
+    let mut rank = 0;
+    for i in 0..events.len() {
+        if i == 0 || !tied(&events[i - 1], &events[i]) {
+            rank = i + 1;
+        }
+        events[i].data.insert("$rank".to_string(), rank.into());
+    }
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use serde_json::json;
+
+    use aw_models::Event;
+
+    use crate::test_util::event;
+
+    use super::{rank_by_duration, rank_by_key};
+
+    fn ranks(events: &[Event]) -> Vec<(String, u64)> {
+        events
+            .iter()
+            .map(|e| {
+                (
+                    e.data["name"].as_str().unwrap().to_string(),
+                    e.data["$rank"].as_u64().unwrap(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_rank_by_duration() {
+        let events = vec![
+            event(0, Duration::seconds(1), json_map! {"name": "a"}),
+            event(0, Duration::seconds(3), json_map! {"name": "b"}),
+            event(0, Duration::seconds(2), json_map! {"name": "c"}),
+            event(0, Duration::seconds(3), json_map! {"name": "d"}),
+        ];
+        let res = rank_by_duration(events);
+        assert_eq!(
+            ranks(&res),
+            vec![
+                ("b".to_string(), 1),
+                ("d".to_string(), 1),
+                ("c".to_string(), 3),
+                ("a".to_string(), 4)
+            ]
+        );
+        assert_eq!(res[0].duration, Duration::seconds(3));
+
+        assert!(rank_by_duration(vec![]).is_empty());
+    }
+
+    #[test]
+    fn test_rank_by_key() {
+        let events = vec![
+            event(0, Duration::seconds(1), json_map! {"name": "a", "score": 5}),
+            event(0, Duration::seconds(1), json_map! {"name": "b"}),
+            event(
+                0,
+                Duration::seconds(1),
+                json_map! {"name": "c", "score": 7.5},
+            ),
+            event(
+                0,
+                Duration::seconds(1),
+                json_map! {"name": "d", "score": "high"},
+            ),
+            event(0, Duration::seconds(1), json_map! {"name": "e", "score": 5}),
+        ];
+        let res = rank_by_key(events, "score");
+        assert_eq!(
+            ranks(&res),
+            vec![
+                ("c".to_string(), 1),
+                ("a".to_string(), 2),
+                ("e".to_string(), 2),
+                ("b".to_string(), 4),
+                ("d".to_string(), 4)
+            ]
+        );
+    }
+}
@@ -0,0 +1,124 @@
+use aw_models::Event;
+use serde_json::Value;
+
+/// What the values of redacted keys are replaced with
+#[derive(Debug, Clone, PartialEq)]
+pub enum Redaction {
+    /// The same placeholder for every value
+    Replace(Value),
+    /// A hash of the value, so that events with the same value can still be told apart from
+    /// events with other values, which is the same across runs and versions of the server
+    ///
+    /// The hash is not salted, short or guessable values such as app names can be found out by
+    /// hashing candidates.
+    Hash,
+}
+
+/// Replaces the values of the top-level data `keys` of the events, for example to strip window
+/// titles and URLs before sharing data
+///
+/// Other keys, the timestamps and the durations are left intact. Events which lack a key are
+/// left as they are for that key.
+///
+/// # Example
+/// ```ignore
+/// keys:      ["title"]
+/// redaction: Replace("[redacted]")
+/// input:  [{"app": "firefox", "title": "Inbox"}]
+/// output: [{"app": "firefox", "title": "[redacted]"}]
+/// ```
+pub fn redact(mut events: Vec<Event>, keys: &[String], redaction: &Redaction) -> Vec<Event> {
+    for event in events.iter_mut() {
+        for key in keys {
+            if let Some(value) = event.data.get_mut(key) {
+                *value = match redaction {
+                    Redaction::Replace(replacement) => replacement.clone(),
+                    Redaction::Hash => Value::String(hash_value(value)),
+                };
+            }
+        }
+    }
+    events
+}
+
+/// FNV-1a of the JSON of the value, the algorithm of `DefaultHasher` may change between Rust
+/// releases, which would make redacted exports from different versions incomparable
+fn hash_value(value: &Value) -> String {
+    let hash = value
+        .to_string()
+        .bytes()
+        .fold(0xcbf29ce484222325u64, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+        });
+    format!("{hash:016x}")
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use serde_json::json;
+
+    use crate::test_util::event;
+
+    use super::{redact, Redaction};
+
+    #[test]
+    fn test_redact() {
+        let events = vec![
+            event(
+                0,
+                Duration::seconds(1),
+                json_map! {"app": json!("firefox"), "title": json!("Inbox"), "count": json!(1)},
+            ),
+            event(
+                0,
+                Duration::seconds(1),
+                json_map! {"app": json!("editor"), "title": json!("notes.txt"), "count": json!(1)},
+            ),
+        ];
+        let keys = vec!["title".to_string(), "url".to_string()];
+
+        let redacted = redact(
+            events.clone(),
+            &keys,
+            &Redaction::Replace(json!("[redacted]")),
+        );
+        assert_eq!(redacted.len(), 2);
+        for (redacted, event) in redacted.iter().zip(events.iter()) {
+            assert_eq!(redacted.timestamp, event.timestamp);
+            assert_eq!(redacted.duration, event.duration);
+            assert_eq!(redacted.data["title"], json!("[redacted]"));
+            // Keys which are not listed are untouched, missing keys aren't added
+            assert_eq!(redacted.data["app"], event.data["app"]);
+            assert_eq!(redacted.data["count"], event.data["count"]);
+            assert!(!redacted.data.contains_key("url"));
+        }
+
+        let events = vec![
+            event(
+                0,
+                Duration::seconds(1),
+                json_map! {"app": json!("firefox"), "title": json!("Inbox"), "count": json!(1)},
+            ),
+            event(
+                0,
+                Duration::seconds(1),
+                json_map! {"app": json!("editor"), "title": json!("notes.txt"), "count": json!(1)},
+            ),
+            event(
+                0,
+                Duration::seconds(1),
+                json_map! {"app": json!("firefox"), "title": json!("Inbox"), "count": json!(1)},
+            ),
+        ];
+        let hashed = redact(events, &keys, &Redaction::Hash);
+        let titles: Vec<&str> = hashed
+            .iter()
+            .map(|e| e.data["title"].as_str().unwrap())
+            .collect();
+        assert_eq!(titles[0], titles[2]);
+        assert_ne!(titles[0], titles[1]);
+        assert_eq!(titles[0], "ad75d01a4234123b");
+        assert_eq!(hashed[0].data["app"], json!("firefox"));
+    }
+}
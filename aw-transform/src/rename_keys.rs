@@ -0,0 +1,138 @@
+use std::collections::HashMap;
+
+use aw_models::Event;
+
+/// Renames top-level data keys of the events according to `mapping` (old key -> new key)
+///
+/// Keys which are not in the mapping are left intact, events which lack a key in the mapping
+/// are left as they are for that key. All keys are renamed at once, so renames can't chain.
+///
+/// If the new key already exists in the event it is overwritten by the renamed value.
+/// If several keys of an event are renamed to the same key, the old key which sorts last wins.
+///
+/// # Example
+/// ```ignore
+/// mapping: {"appname": "app"}
+/// input:  [{"appname": "firefox"}][{"app": "chrome"}]
+/// output: [{"app": "firefox"}][{"app": "chrome"}]
+/// ```
+pub fn rename_keys(mut events: Vec<Event>, mapping: &HashMap<String, String>) -> Vec<Event> {
+    let mut mapping: Vec<(&String, &String)> = mapping.iter().collect();
+    mapping.sort();
+    for event in events.iter_mut() {
+        let mut renamed = Vec::new();
+        for (from, to) in mapping.iter() {
+            if let Some(value) = event.data.remove(*from) {
+                renamed.push((to.to_string(), value));
+            }
+        }
+        for (to, value) in renamed {
+            event.data.insert(to, value);
+        }
+    }
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use chrono::Duration;
+    use serde_json::json;
+
+    use crate::test_util::event;
+
+    use super::rename_keys;
+
+    fn mapping(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(from, to)| (from.to_string(), to.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_rename_keys() {
+        let events = vec![
+            event(
+                "2000-01-01T00:00:00Z",
+                Duration::seconds(1),
+                json_map! {"appname": json!("firefox"), "title": json!("a")},
+            ),
+            event(
+                "2000-01-01T00:00:00Z",
+                Duration::seconds(1),
+                json_map! {"app": json!("chrome"), "title": json!("b")},
+            ),
+        ];
+        let res = rename_keys(events, &mapping(&[("appname", "app")]));
+        assert_eq!(
+            res,
+            vec![
+                event(
+                    "2000-01-01T00:00:00Z",
+                    Duration::seconds(1),
+                    json_map! {"app": json!("firefox"), "title": json!("a")}
+                ),
+                event(
+                    "2000-01-01T00:00:00Z",
+                    Duration::seconds(1),
+                    json_map! {"app": json!("chrome"), "title": json!("b")}
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rename_keys_swap() {
+        let events = vec![event(
+            "2000-01-01T00:00:00Z",
+            Duration::seconds(1),
+            json_map! {"a": json!(1), "b": json!(2)},
+        )];
+        let res = rename_keys(events, &mapping(&[("a", "b"), ("b", "a")]));
+        assert_eq!(
+            res,
+            vec![event(
+                "2000-01-01T00:00:00Z",
+                Duration::seconds(1),
+                json_map! {"a": json!(2), "b": json!(1)}
+            )]
+        );
+    }
+
+    #[test]
+    fn test_rename_keys_collision() {
+        // The renamed value overwrites an existing key
+        let events = vec![event(
+            "2000-01-01T00:00:00Z",
+            Duration::seconds(1),
+            json_map! {"app": json!("old"), "appname": json!("new")},
+        )];
+        let res = rename_keys(events, &mapping(&[("appname", "app")]));
+        assert_eq!(
+            res,
+            vec![event(
+                "2000-01-01T00:00:00Z",
+                Duration::seconds(1),
+                json_map! {"app": json!("new")}
+            )]
+        );
+
+        // The old key which sorts last wins when several are renamed to the same key
+        let events = vec![event(
+            "2000-01-01T00:00:00Z",
+            Duration::seconds(1),
+            json_map! {"a": json!(1), "b": json!(2), "c": json!(3)},
+        )];
+        let res = rename_keys(events, &mapping(&[("b", "x"), ("a", "x")]));
+        assert_eq!(
+            res,
+            vec![event(
+                "2000-01-01T00:00:00Z",
+                Duration::seconds(1),
+                json_map! {"c": json!(3), "x": json!(2)}
+            )]
+        );
+    }
+}
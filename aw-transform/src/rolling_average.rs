@@ -0,0 +1,109 @@
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::{Duration, NaiveDate};
+
+/// How days missing from the totals of `rolling_average` are counted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MissingDays {
+    /// As days with a total of zero, which also get an average
+    Zero,
+    /// Not at all, averaging only the days with a total and giving no average for missing days
+    Skip,
+}
+
+/// Smooths daily totals, such as the seconds spent on some category per day, with the mean of
+/// each day and the `window_days - 1` days before it
+///
+/// Days before the first day of the map are not counted, so the windows of the first days are
+/// partial and only average the days from the first day on. Days missing from the map are
+/// counted as set by `missing`. Returns nothing if `window_days` is 0.
+///
+/// # Example
+/// ```ignore
+/// window_days: 3
+/// input:          { 01-01: 3, 01-02: 6, 01-04: 9 }
+/// output (Zero):  { 01-01: 3, 01-02: 4.5, 01-03: 3, 01-04: 5 }
+/// output (Skip):  { 01-01: 3, 01-02: 4.5, 01-04: 7.5 }
+/// ```
+pub fn rolling_average(
+    per_day_totals: &HashMap<NaiveDate, f64>,
+    window_days: u32,
+    missing: MissingDays,
+) -> BTreeMap<NaiveDate, f64> {
+    let totals: BTreeMap<NaiveDate, f64> = per_day_totals
+        .iter()
+        .map(|(day, total)| (*day, *total))
+        .collect();
+    let (first, last) = match (totals.keys().next(), totals.keys().next_back()) {
+        (Some(first), Some(last)) if window_days > 0 => (*first, *last),
+        _ => return BTreeMap::new(),
+    };
+
+    let days: Vec<NaiveDate> = match missing {
+        MissingDays::Zero => first.iter_days().take_while(|day| *day <= last).collect(),
+        MissingDays::Skip => totals.keys().copied().collect(),
+    };
+    let mut averages = BTreeMap::new();
+    for day in days {
+        let window_start = (day - Duration::days(window_days as i64 - 1)).max(first);
+        let window: Vec<f64> = totals.range(window_start..=day).map(|(_, t)| *t).collect();
+        let day_count = match missing {
+            MissingDays::Zero => (day - window_start).num_days() as f64 + 1.0,
+            MissingDays::Skip => window.len() as f64,
+        };
+        averages.insert(day, window.iter().sum::<f64>() / day_count);
+    }
+    averages
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use chrono::NaiveDate;
+
+    use super::{rolling_average, MissingDays};
+
+    #[test]
+    fn test_rolling_average() {
+        let date = |day: u32| NaiveDate::from_ymd_opt(2000, 1, day).unwrap();
+        // 01-03 is missing
+        let totals: HashMap<NaiveDate, f64> = [
+            (date(1), 3.0),
+            (date(2), 6.0),
+            (date(4), 9.0),
+            (date(5), 12.0),
+        ]
+        .into();
+
+        let averages = rolling_average(&totals, 3, MissingDays::Zero);
+        assert_eq!(
+            averages.into_iter().collect::<Vec<_>>(),
+            vec![
+                (date(1), 3.0),
+                (date(2), 4.5),
+                (date(3), 3.0),
+                (date(4), 5.0),
+                (date(5), 7.0),
+            ]
+        );
+
+        let averages = rolling_average(&totals, 3, MissingDays::Skip);
+        assert_eq!(
+            averages.into_iter().collect::<Vec<_>>(),
+            vec![
+                (date(1), 3.0),
+                (date(2), 4.5),
+                (date(4), 7.5),
+                (date(5), 10.5)
+            ]
+        );
+
+        // A window of a day is the totals themselves
+        let averages = rolling_average(&totals, 1, MissingDays::Skip);
+        assert_eq!(averages.into_iter().collect::<HashMap<_, _>>(), totals);
+
+        assert!(rolling_average(&totals, 0, MissingDays::Zero).is_empty());
+        assert!(rolling_average(&HashMap::new(), 7, MissingDays::Zero).is_empty());
+    }
+}
@@ -0,0 +1,165 @@
+use std::collections::BTreeMap;
+
+use aw_models::Event;
+use chrono::Duration;
+
+/// A category in the tree built by `rollup_categories`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CategoryNode {
+    /// Duration of the events in exactly this category
+    pub self_duration: Duration,
+    /// Duration of the events in this category and all its subcategories
+    pub total_duration: Duration,
+    pub children: BTreeMap<String, CategoryNode>,
+}
+
+impl Default for CategoryNode {
+    fn default() -> Self {
+        CategoryNode {
+            self_duration: Duration::zero(),
+            total_duration: Duration::zero(),
+            children: BTreeMap::new(),
+        }
+    }
+}
+
+/// Builds the tree of the categories of events categorized by `classify`, with how long was
+/// spent in each category
+///
+/// The `$category` of an event is its path in the tree, such as `["Work", "Programming"]`.
+/// The duration of an event counts towards the self duration of its category and the total
+/// duration of the category and all its parents. Events without a `$category` which is a
+/// non-empty list of strings are skipped. Returns the top level categories.
+///
+/// # Example
+/// ```ignore
+/// input:  [Work > Programming (10s)] [Work (5s)] [Work > Meetings (3s)] [Fun (1s)]
+/// output: Work (self 5s, total 18s)
+///           Programming (self 10s, total 10s)
+///           Meetings (self 3s, total 3s)
+///         Fun (self 1s, total 1s)
+/// ```
+pub fn rollup_categories(events: &[Event]) -> BTreeMap<String, CategoryNode> {
+    let mut roots: BTreeMap<String, CategoryNode> = BTreeMap::new();
+    for event in events {
+        let path = match event.data.get("$category").and_then(|c| c.as_array()) {
+            Some(path) if !path.is_empty() => path,
+            _ => continue,
+        };
+        let path: Option<Vec<&str>> = path.iter().map(|name| name.as_str()).collect();
+        let path = match path {
+            Some(path) => path,
+            None => continue,
+        };
+
+        let mut nodes = &mut roots;
+        for (depth, name) in path.iter().enumerate() {
+            let node = nodes.entry(name.to_string()).or_default();
+            node.total_duration += event.duration;
+            if depth == path.len() - 1 {
+                node.self_duration += event.duration;
+            }
+            nodes = &mut node.children;
+        }
+    }
+    roots
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use chrono::Duration;
+    use serde_json::json;
+
+    use crate::test_util::event;
+
+    use super::{rollup_categories, CategoryNode};
+
+    fn node(self_secs: i64, total_secs: i64, children: Vec<(&str, CategoryNode)>) -> CategoryNode {
+        CategoryNode {
+            self_duration: Duration::seconds(self_secs),
+            total_duration: Duration::seconds(total_secs),
+            children: children
+                .into_iter()
+                .map(|(name, child)| (name.to_string(), child))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_rollup_categories() {
+        let events = vec![
+            event(
+                0,
+                Duration::seconds(10),
+                json_map! {"$category": json!(["Work", "Programming", "Rust"])},
+            ),
+            event(
+                0,
+                Duration::seconds(4),
+                json_map! {"$category": json!(["Work", "Programming", "Python"])},
+            ),
+            event(
+                0,
+                Duration::seconds(2),
+                json_map! {"$category": json!(["Work", "Programming"])},
+            ),
+            event(
+                0,
+                Duration::seconds(3),
+                json_map! {"$category": json!(["Work", "Meetings"])},
+            ),
+            event(
+                0,
+                Duration::seconds(5),
+                json_map! {"$category": json!(["Work", "Programming", "Rust"])},
+            ),
+            event(
+                0,
+                Duration::seconds(1),
+                json_map! {"$category": json!(["Fun"])},
+            ),
+            // Skipped
+            event(0, Duration::seconds(7), json_map! {"$category": json!([])}),
+            event(
+                0,
+                Duration::seconds(7),
+                json_map! {"$category": json!("Work")},
+            ),
+            event(
+                0,
+                Duration::seconds(7),
+                json_map! {"$category": json!(["Work", 1])},
+            ),
+        ];
+        let expected: BTreeMap<String, CategoryNode> = vec![
+            (
+                "Work".to_string(),
+                node(
+                    0,
+                    24,
+                    vec![
+                        (
+                            "Programming",
+                            node(
+                                2,
+                                21,
+                                vec![
+                                    ("Rust", node(15, 15, vec![])),
+                                    ("Python", node(4, 4, vec![])),
+                                ],
+                            ),
+                        ),
+                        ("Meetings", node(3, 3, vec![])),
+                    ],
+                ),
+            ),
+            ("Fun".to_string(), node(1, 1, vec![])),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(rollup_categories(&events), expected);
+        assert!(rollup_categories(&[]).is_empty());
+    }
+}
@@ -0,0 +1,99 @@
+use aw_models::Event;
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+
+/// Splits events crossing any of the `boundaries` into one event per part between them
+///
+/// Each part keeps the data of the event it was cut from and covers its share of the duration,
+/// boundaries at the very start or end of an event don't split it. The boundaries don't need to
+/// be sorted. If `segment_key` is given the index of the segment each part falls in is stored
+/// under that key, 0 being the segment before the first boundary.
+///
+/// # Example
+/// ```ignore
+/// boundaries:     |    |
+/// input:      [a         ]  [b]
+/// output:     [a ][a  ][a]  [b]
+/// segment:     0    1   2    2
+/// ```
+pub fn split_at(
+    events: Vec<Event>,
+    boundaries: &[DateTime<Utc>],
+    segment_key: Option<&str>,
+) -> Vec<Event> {
+    let mut boundaries = boundaries.to_vec();
+    boundaries.sort();
+    boundaries.dedup();
+
+    let mut split_events = Vec::new();
+    for event in events {
+        let end = event.calculate_endtime();
+        // Index of the first boundary after the start of the event
+        let mut segment = boundaries.partition_point(|b| *b <= event.timestamp);
+        let mut start = event.timestamp;
+        loop {
+            let part_end = match boundaries.get(segment) {
+                Some(boundary) if *boundary < end => *boundary,
+                _ => end,
+            };
+            let mut part = event.clone();
+            part.timestamp = start;
+            part.duration = part_end - start;
+            if let Some(key) = segment_key {
+                part.data.insert(key.to_string(), Value::from(segment));
+            }
+            split_events.push(part);
+            if part_end >= end {
+                break;
+            }
+            start = part_end;
+            segment += 1;
+        }
+    }
+    split_events
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::DateTime;
+    use chrono::Duration;
+    use serde_json::json;
+
+    use crate::test_util::event;
+
+    use super::split_at;
+
+    #[test]
+    fn test_split_at() {
+        let boundaries = vec![
+            DateTime::from_timestamp(60, 0).unwrap(),
+            DateTime::from_timestamp(30, 0).unwrap(),
+        ];
+        let events = vec![
+            // Crosses both boundaries
+            event(10, Duration::seconds(70), json_map! {"app": json!("a")}),
+            // Ends at a boundary
+            event(0, Duration::seconds(30), json_map! {"app": json!("a")}),
+            event(100, Duration::seconds(10), json_map! {"app": json!("a")}),
+        ];
+        let split = split_at(events.clone(), &boundaries, None);
+        assert_eq!(
+            split,
+            vec![
+                event(10, Duration::seconds(20), json_map! {"app": json!("a")}),
+                event(30, Duration::seconds(30), json_map! {"app": json!("a")}),
+                event(60, Duration::seconds(20), json_map! {"app": json!("a")}),
+                event(0, Duration::seconds(30), json_map! {"app": json!("a")}),
+                event(100, Duration::seconds(10), json_map! {"app": json!("a")}),
+            ]
+        );
+
+        let split = split_at(events, &boundaries, Some("segment"));
+        let segments: Vec<_> = split.iter().map(|e| e.data["segment"].clone()).collect();
+        assert_eq!(
+            segments,
+            vec![json!(0), json!(1), json!(2), json!(0), json!(2)]
+        );
+        assert_eq!(split[1].data["app"], json!("a"));
+    }
+}
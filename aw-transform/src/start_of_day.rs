@@ -0,0 +1,41 @@
+use chrono::{DateTime, Duration, NaiveDate, TimeZone, Utc};
+
+/// The start of the local day `day` in `tz`, which is midnight unless the clocks skip it when
+/// changing to daylight saving time, the day then starts at the first local time which exists
+///
+/// Days are not always 24 hours long, so the end of a day is the start of the next one. If
+/// midnight happens twice, the day starts at the first one.
+pub fn start_of_day<Tz: TimeZone>(day: NaiveDate, tz: &Tz) -> DateTime<Utc> {
+    let mut time = day.and_hms_opt(0, 0, 0).unwrap();
+    loop {
+        if let Some(start) = tz.from_local_datetime(&time).earliest() {
+            return start.with_timezone(&Utc);
+        }
+        // DST changes are at least half an hour
+        time += Duration::minutes(30);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use chrono::{DateTime, NaiveDate, Utc};
+
+    use super::start_of_day;
+
+    #[test]
+    fn test_start_of_day() {
+        let date = |y, m, d| NaiveDate::from_ymd_opt(y, m, d).unwrap();
+        let time = |s| DateTime::<Utc>::from_str(s).unwrap();
+        assert_eq!(
+            start_of_day(date(2000, 1, 1), &chrono_tz::Europe::Stockholm),
+            time("1999-12-31T23:00:00Z")
+        );
+        // Midnight was skipped in São Paulo in 2018, the day started at 01:00 (-02:00)
+        assert_eq!(
+            start_of_day(date(2018, 11, 4), &chrono_tz::America::Sao_Paulo),
+            time("2018-11-04T03:00:00Z")
+        );
+    }
+}
@@ -0,0 +1,85 @@
+use std::collections::BTreeMap;
+
+use aw_models::Event;
+use chrono::{DateTime, Duration, Utc};
+
+use crate::transition_counts::transitions;
+
+/// Counts how often the value of `key` changes in each slot of length `slot`, to see when the
+/// switching between e.g. apps is most frequent
+///
+/// Changes are detected as in `transition_counts`. A change is counted in the slot where it
+/// happens, which is the slot the event with the new value starts in, even if the event before
+/// it started in an earlier slot. Slots are aligned to the Unix epoch, so hourly slots are UTC
+/// hours, and named by their start. Only slots in which an event with the key starts are
+/// returned, including those without changes, so that a short slot over a long period doesn't
+/// give a slot for every moment of it. Returns nothing if `slot` isn't positive.
+///
+/// # Example
+/// ```ignore
+/// key:    app
+/// slot:   10s
+/// input:  [editor (0-8)] [browser (8-12)] [editor (12-25)] [editor (25-30)]
+/// output: { 0: 1, 10: 1, 20: 0 }
+/// ```
+pub fn switch_rate(events: &[Event], key: &str, slot: Duration) -> BTreeMap<DateTime<Utc>, u64> {
+    let mut counts = BTreeMap::new();
+    let slot_millis = slot.num_milliseconds();
+    if slot_millis <= 0 {
+        return counts;
+    }
+    let slot_start = |time: DateTime<Utc>| {
+        let millis = time.timestamp_millis();
+        let start = millis - millis.rem_euclid(slot_millis);
+        DateTime::from_timestamp_millis(start).unwrap()
+    };
+
+    for event in events.iter().filter(|e| e.data.contains_key(key)) {
+        counts.insert(slot_start(event.timestamp), 0);
+    }
+    for (_, _, time) in transitions(events, key) {
+        *counts.entry(slot_start(time)).or_insert(0) += 1;
+    }
+    counts
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::DateTime;
+    use chrono::Duration;
+    use serde_json::json;
+
+    use crate::test_util::event;
+
+    use super::switch_rate;
+
+    #[test]
+    fn test_switch_rate() {
+        let time = |secs: i64| DateTime::from_timestamp(secs, 0).unwrap();
+        let events = vec![
+            event(8, Duration::seconds(4), json_map! {"app": json!("browser")}),
+            event(0, Duration::seconds(8), json_map! {"app": json!("editor")}),
+            // Switch from the browser event of the first slot
+            event(
+                12,
+                Duration::seconds(13),
+                json_map! {"app": json!("editor")},
+            ),
+            event(25, Duration::seconds(5), json_map! {"app": json!("editor")}),
+            // No slot for the time without events before it
+            event(
+                42,
+                Duration::seconds(1),
+                json_map! {"app": json!("browser")},
+            ),
+        ];
+        let res = switch_rate(&events, "app", Duration::seconds(10));
+        assert_eq!(
+            res.into_iter().collect::<Vec<_>>(),
+            vec![(time(0), 1), (time(10), 1), (time(20), 0), (time(40), 1)]
+        );
+
+        assert!(switch_rate(&[], "app", Duration::seconds(10)).is_empty());
+        assert!(switch_rate(&events, "app", Duration::zero()).is_empty());
+    }
+}
@@ -0,0 +1,89 @@
+use aw_models::Event;
+use chrono::Duration;
+
+use crate::transition_counts::transitions;
+
+/// The switches between values of a key and the time they are modeled to cost, see
+/// `switching_cost`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SwitchingCost {
+    pub switches: u64,
+    /// `switches` times the cost per switch
+    pub lost_time: Duration,
+}
+
+/// Counts how often the value of `key` changes between consecutive events, such as switches
+/// between apps, and models the time lost to them as `cost_per_switch` for each switch
+///
+/// Changes are detected as in `transition_counts`, so events are sorted by timestamp first,
+/// consecutive events with the same value are not a switch and events without the key are
+/// skipped. The lost time is in whole milliseconds and saturates instead of overflowing.
+///
+/// # Example
+/// ```ignore
+/// key: app, cost_per_switch: 5min
+/// input:  [editor] [browser] [browser] [editor]
+/// output: { switches: 2, lost_time: 10min }
+/// ```
+pub fn switching_cost(events: &[Event], key: &str, cost_per_switch: Duration) -> SwitchingCost {
+    let switches = transitions(events, key).len() as u64;
+    let lost_millis = cost_per_switch
+        .num_milliseconds()
+        .saturating_mul(i64::try_from(switches).unwrap_or(i64::MAX));
+    SwitchingCost {
+        switches,
+        // i64::MIN milliseconds is out of the range of a Duration
+        lost_time: Duration::milliseconds(lost_millis.max(-i64::MAX)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use serde_json::json;
+
+    use crate::test_util::event;
+
+    use super::{switching_cost, SwitchingCost};
+
+    #[test]
+    fn test_switching_cost() {
+        // Sorted by timestamp it is editor, browser, browser, editor, terminal
+        let mut no_app = event(5, Duration::seconds(1), json_map! {"app": json!("")});
+        no_app.data.clear();
+        let events = vec![
+            event(0, Duration::seconds(1), json_map! {"app": json!("editor")}),
+            event(3, Duration::seconds(1), json_map! {"app": json!("editor")}),
+            event(1, Duration::seconds(1), json_map! {"app": json!("browser")}),
+            event(2, Duration::seconds(1), json_map! {"app": json!("browser")}),
+            no_app,
+            event(
+                6,
+                Duration::seconds(1),
+                json_map! {"app": json!("terminal")},
+            ),
+        ];
+        assert_eq!(
+            switching_cost(&events, "app", Duration::minutes(5)),
+            SwitchingCost {
+                switches: 3,
+                lost_time: Duration::minutes(15)
+            }
+        );
+        let max = Duration::milliseconds(i64::MAX);
+        assert_eq!(switching_cost(&events, "app", max / 2).lost_time, max);
+        assert_eq!(switching_cost(&events, "app", -max).lost_time, -max);
+
+        // No switches without changes
+        let events = vec![
+            event(0, Duration::seconds(1), json_map! {"app": json!("editor")}),
+            event(1, Duration::seconds(1), json_map! {"app": json!("editor")}),
+        ];
+        let zero = SwitchingCost {
+            switches: 0,
+            lost_time: Duration::zero(),
+        };
+        assert_eq!(switching_cost(&events, "app", Duration::minutes(5)), zero);
+        assert_eq!(switching_cost(&[], "app", Duration::minutes(5)), zero);
+    }
+}
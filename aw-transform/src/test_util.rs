@@ -0,0 +1,41 @@
+//! Helpers shared by the tests of the transforms
+use std::str::FromStr;
+
+use chrono::{DateTime, Duration, Utc};
+use serde_json::{Map, Value};
+
+use aw_models::Event;
+
+/// The start of an event built by `event`, either as seconds since the epoch, as an RFC 3339
+/// timestamp or as a time
+pub(crate) trait EventStart {
+    fn into_timestamp(self) -> DateTime<Utc>;
+}
+
+impl EventStart for i64 {
+    fn into_timestamp(self) -> DateTime<Utc> {
+        DateTime::from_timestamp(self, 0).unwrap()
+    }
+}
+
+impl EventStart for &str {
+    fn into_timestamp(self) -> DateTime<Utc> {
+        DateTime::from_str(self).unwrap()
+    }
+}
+
+impl EventStart for DateTime<Utc> {
+    fn into_timestamp(self) -> DateTime<Utc> {
+        self
+    }
+}
+
+/// An event without an id
+pub(crate) fn event(start: impl EventStart, duration: Duration, data: Map<String, Value>) -> Event {
+    Event {
+        id: None,
+        timestamp: start.into_timestamp(),
+        duration,
+        data,
+    }
+}
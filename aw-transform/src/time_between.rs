@@ -0,0 +1,100 @@
+use aw_models::Event;
+use chrono::Duration;
+use serde_json::Value;
+
+/// Measures the time from an event where `key` is `value_a` to the next event where it is
+/// `value_b`, for every time this happens
+///
+/// Events are sorted by timestamp first and the time is from the start of the first `value_a`
+/// event to the start of the `value_b` event, other occurrences of `value_a` in between are part
+/// of the same pattern. The next pattern starts with the next `value_a` after that. If the last
+/// `value_a` is never followed by `value_b` it is `None` at the end of the list.
+///
+/// # Example
+/// ```ignore
+/// value_a: a, value_b: b
+/// input:  [a (0)] [a (20)] [b (30)] [b (40)] [a (50)] [c (55)] [b (70)] [a (80)]
+/// output: [30s, 20s, None]
+/// ```
+pub fn time_between(
+    events: &[Event],
+    key: &str,
+    value_a: &Value,
+    value_b: &Value,
+) -> Vec<Option<Duration>> {
+    let mut events: Vec<&Event> = events.iter().collect();
+    events.sort_by_key(|e| e.timestamp);
+
+    let mut times = Vec::new();
+    let mut pattern_start = None;
+    for event in events {
+        let value = match event.data.get(key) {
+            Some(value) => value,
+            None => continue,
+        };
+        match pattern_start {
+            None if value == value_a => pattern_start = Some(event.timestamp),
+            Some(start) if value == value_b => {
+                times.push(Some(event.timestamp - start));
+                pattern_start = None;
+            }
+            _ => (),
+        }
+    }
+    if pattern_start.is_some() {
+        times.push(None);
+    }
+    times
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use serde_json::json;
+
+    use crate::test_util::event;
+
+    use super::time_between;
+
+    #[test]
+    fn test_time_between() {
+        let events = vec![
+            event(20, Duration::seconds(1), json_map! {"app": json!("ticket")}),
+            event(0, Duration::seconds(1), json_map! {"app": json!("ticket")}),
+            event(
+                10,
+                Duration::seconds(1),
+                json_map! {"app": json!("browser")},
+            ),
+            event(30, Duration::seconds(1), json_map! {"app": json!("editor")}),
+            // Not preceded by another ticket
+            event(40, Duration::seconds(1), json_map! {"app": json!("editor")}),
+            event(50, Duration::seconds(1), json_map! {"app": json!("ticket")}),
+            event(
+                55,
+                Duration::seconds(1),
+                json_map! {"app": json!("browser")},
+            ),
+            event(70, Duration::seconds(1), json_map! {"app": json!("editor")}),
+            // Never followed by the editor
+            event(80, Duration::seconds(1), json_map! {"app": json!("ticket")}),
+        ];
+        assert_eq!(
+            time_between(&events, "app", &json!("ticket"), &json!("editor")),
+            vec![
+                Some(Duration::seconds(30)),
+                Some(Duration::seconds(20)),
+                None
+            ]
+        );
+        assert_eq!(
+            time_between(&events[..4], "app", &json!("ticket"), &json!("editor")),
+            vec![Some(Duration::seconds(30))]
+        );
+        assert_eq!(
+            time_between(&events, "app", &json!("editor"), &json!("mail")),
+            vec![None]
+        );
+        assert!(time_between(&events, "title", &json!("ticket"), &json!("editor")).is_empty());
+    }
+}
@@ -0,0 +1,96 @@
+use aw_models::Event;
+use chrono::Duration;
+
+/// Sums the duration of the events where the value of any of `keys` contains `substring`, for
+/// e.g. the time spent on anything mentioning a project
+///
+/// Only string values are searched, with `ignore_case` case-insensitively. Events which have
+/// none of the keys as a string are excluded. An empty `substring` is contained in every string,
+/// so it matches all other events.
+///
+/// # Example
+/// ```ignore
+/// keys: [app, title], substring: invoice, ignore_case: true
+/// input:  [{title: "Invoice 12"} (10s)] [{app: "invoices"} (5s)] [{title: "News"} (2s)] [{} (1s)]
+/// output: 15s
+/// ```
+pub fn time_matching(
+    events: &[Event],
+    keys: &[String],
+    substring: &str,
+    ignore_case: bool,
+) -> Duration {
+    let substring = match ignore_case {
+        true => substring.to_lowercase(),
+        false => substring.to_string(),
+    };
+    events
+        .iter()
+        .filter(|event| {
+            keys.iter()
+                .filter_map(|key| event.data.get(key).and_then(|v| v.as_str()))
+                .any(|value| match ignore_case {
+                    true => value.to_lowercase().contains(&substring),
+                    false => value.contains(&substring),
+                })
+        })
+        .fold(Duration::zero(), |total, event| total + event.duration)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use serde_json::json;
+
+    use crate::test_util::event;
+
+    use super::time_matching;
+
+    #[test]
+    fn test_time_matching() {
+        let events = vec![
+            event(
+                0,
+                Duration::seconds(10),
+                json_map! {"app": "Mail", "title": "Invoice 12"},
+            ),
+            event(
+                0,
+                Duration::seconds(5),
+                json_map! {"app": "invoices", "title": "Overview"},
+            ),
+            event(
+                0,
+                Duration::seconds(2),
+                json_map! {"app": "Browser", "title": "News"},
+            ),
+            // Missing all keys
+            event(
+                0,
+                Duration::seconds(1),
+                json_map! {"url": "invoice.example.com"},
+            ),
+            // Not a string
+            event(
+                0,
+                Duration::seconds(3),
+                json_map! {"title": json!(["invoice"])},
+            ),
+        ];
+        let keys = vec!["app".to_string(), "title".to_string()];
+        assert_eq!(
+            time_matching(&events, &keys, "INVOICE", true),
+            Duration::seconds(15)
+        );
+        assert_eq!(
+            time_matching(&events, &keys, "Invoice", false),
+            Duration::seconds(10)
+        );
+        // Everything with one of the keys
+        assert_eq!(
+            time_matching(&events, &keys, "", false),
+            Duration::seconds(17)
+        );
+        assert_eq!(time_matching(&events, &[], "", false), Duration::zero());
+    }
+}
@@ -0,0 +1,85 @@
+use std::collections::HashMap;
+
+use aw_models::Event;
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+
+/// Counts how often the value of `key` changes from one value to another between consecutive
+/// events, returning the counts by `"from→to"`
+///
+/// Events are sorted by timestamp first. String values are used as is while other values are
+/// used as their JSON representation. Consecutive events with the same value are not a
+/// transition and events without the key are skipped.
+///
+/// # Example
+/// ```ignore
+/// key:    app
+/// input:  [editor] [browser] [browser] [editor] [browser]
+/// output: { "editor→browser": 2, "browser→editor": 1 }
+/// ```
+pub fn transition_counts(events: Vec<Event>, key: &str) -> HashMap<String, u64> {
+    let mut counts = HashMap::new();
+    for (from, to, _) in transitions(&events, key) {
+        *counts.entry(format!("{from}→{to}")).or_insert(0) += 1;
+    }
+    counts
+}
+
+/// The changes of the value of `key` between consecutive events as in `transition_counts`,
+/// oldest first, each with the timestamp of the event with the new value
+pub(crate) fn transitions(events: &[Event], key: &str) -> Vec<(String, String, DateTime<Utc>)> {
+    let mut events: Vec<&Event> = events.iter().collect();
+    events.sort_by_key(|e| e.timestamp);
+
+    let mut transitions = Vec::new();
+    let mut prev: Option<String> = None;
+    for event in events {
+        let value = match event.data.get(key) {
+            Some(Value::String(s)) => s.clone(),
+            Some(value) => value.to_string(),
+            None => continue,
+        };
+        if let Some(prev) = prev.as_ref().filter(|prev| **prev != value) {
+            transitions.push((prev.clone(), value.clone(), event.timestamp));
+        }
+        prev = Some(value);
+    }
+    transitions
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use serde_json::json;
+
+    use crate::test_util::event;
+
+    use super::transition_counts;
+
+    #[test]
+    fn test_transition_counts() {
+        // Out of order, sorted by timestamp it is editor, browser, browser, editor, browser, 1
+        let events = vec![
+            event(3, Duration::seconds(1), json_map! {"app": "editor"}),
+            event(0, Duration::seconds(1), json_map! {"app": "editor"}),
+            event(1, Duration::seconds(1), json_map! {"app": "browser"}),
+            event(2, Duration::seconds(1), json_map! {"app": "browser"}),
+            event(4, Duration::seconds(1), json_map! {"title": "no app"}),
+            event(5, Duration::seconds(1), json_map! {"app": "browser"}),
+            event(6, Duration::seconds(1), json_map! {"app": 1}),
+        ];
+        let res = transition_counts(events, "app");
+        assert_eq!(res.len(), 3);
+        assert_eq!(res["editor→browser"], 2);
+        assert_eq!(res["browser→editor"], 1);
+        assert_eq!(res["browser→1"], 1);
+
+        // A single value has no transitions
+        let events = vec![
+            event(0, Duration::seconds(1), json_map! {"app": "editor"}),
+            event(1, Duration::seconds(1), json_map! {"app": "editor"}),
+        ];
+        assert!(transition_counts(events, "app").is_empty());
+        assert!(transition_counts(vec![], "app").is_empty());
+    }
+}
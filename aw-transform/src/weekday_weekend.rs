@@ -0,0 +1,161 @@
+use aw_models::Event;
+use chrono::{DateTime, Datelike, TimeZone, Utc, Weekday};
+
+use crate::start_of_day;
+
+/// Splits events into the ones on weekdays and the ones on weekends in the given timezone,
+/// returned as `(weekday, weekend)`
+///
+/// An event is put in the list matching the local date it starts on. Events which span the
+/// midnight between a Friday and a Saturday or between a Sunday and a Monday are clipped there
+/// and each part is put in its own list, events spanning other midnights are kept whole.
+///
+/// # Example
+/// ```ignore
+///   timezone: UTC
+///   events:   [Fri 23:00 - Sat 01:00] [Sun 12:00 - 13:00]
+///   weekday:  [Fri 23:00 - Sat 00:00]
+///   weekend:  [Sat 00:00 - Sat 01:00] [Sun 12:00 - 13:00]
+/// ```
+pub fn split_weekday_weekend<Tz: TimeZone>(events: &[Event], tz: &Tz) -> (Vec<Event>, Vec<Event>) {
+    let mut weekday = Vec::new();
+    let mut weekend = Vec::new();
+    for event in events {
+        let end = event.calculate_endtime();
+        let mut start = event.timestamp;
+        loop {
+            let on_weekend = is_weekend(start, tz);
+            // Extend the part until the local day changes between weekday and weekend
+            let mut midnight = next_local_midnight(start, tz);
+            while midnight < end && is_weekend(midnight, tz) == on_weekend {
+                midnight = next_local_midnight(midnight, tz);
+            }
+            let part_end = if midnight < end { midnight } else { end };
+            let mut part = event.clone();
+            part.timestamp = start;
+            part.duration = part_end - start;
+            if on_weekend {
+                weekend.push(part);
+            } else {
+                weekday.push(part);
+            }
+            if part_end >= end {
+                break;
+            }
+            start = part_end;
+        }
+    }
+    (weekday, weekend)
+}
+
+fn is_weekend<Tz: TimeZone>(time: DateTime<Utc>, tz: &Tz) -> bool {
+    matches!(
+        time.with_timezone(tz).weekday(),
+        Weekday::Sat | Weekday::Sun
+    )
+}
+
+/// The start of the local day after the one of `time`
+pub(crate) fn next_local_midnight<Tz: TimeZone>(time: DateTime<Utc>, tz: &Tz) -> DateTime<Utc> {
+    let next_day = time.with_timezone(tz).date_naive().succ_opt().unwrap();
+    start_of_day(next_day, tz)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use chrono::FixedOffset;
+    use chrono::Utc;
+    use serde_json::json;
+
+    use crate::test_util::event;
+
+    use super::split_weekday_weekend;
+
+    #[test]
+    fn test_split_weekday_weekend() {
+        // 1999-12-31 is a Friday, 2000-01-01 a Saturday and 2000-01-03 a Monday
+        let events = vec![
+            event(
+                "1999-12-31T22:00:00Z",
+                Duration::hours(4),
+                json_map! {"test": json!(1)},
+            ),
+            event(
+                "2000-01-02T12:00:00Z",
+                Duration::hours(1),
+                json_map! {"test": json!(1)},
+            ),
+            event(
+                "2000-01-04T23:00:00Z",
+                Duration::hours(2),
+                json_map! {"test": json!(1)},
+            ),
+        ];
+        let (weekday, weekend) = split_weekday_weekend(&events, &Utc);
+        assert_eq!(
+            weekday,
+            vec![
+                event(
+                    "1999-12-31T22:00:00Z",
+                    Duration::hours(2),
+                    json_map! {"test": json!(1)}
+                ),
+                // Not clipped as both days are weekdays
+                event(
+                    "2000-01-04T23:00:00Z",
+                    Duration::hours(2),
+                    json_map! {"test": json!(1)}
+                ),
+            ]
+        );
+        assert_eq!(
+            weekend,
+            vec![
+                event(
+                    "2000-01-01T00:00:00Z",
+                    Duration::hours(2),
+                    json_map! {"test": json!(1)}
+                ),
+                event(
+                    "2000-01-02T12:00:00Z",
+                    Duration::hours(1),
+                    json_map! {"test": json!(1)}
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_split_weekday_weekend_sunday_to_monday() {
+        // Sunday 22:00 to Monday 02:00 in UTC+02:00
+        let events = vec![event(
+            "2000-01-02T20:00:00Z",
+            Duration::hours(4),
+            json_map! {"test": json!(1)},
+        )];
+        let tz = FixedOffset::east_opt(2 * 3600).unwrap();
+        let (weekday, weekend) = split_weekday_weekend(&events, &tz);
+        assert_eq!(
+            weekend,
+            vec![event(
+                "2000-01-02T20:00:00Z",
+                Duration::hours(2),
+                json_map! {"test": json!(1)}
+            )]
+        );
+        assert_eq!(
+            weekday,
+            vec![event(
+                "2000-01-02T22:00:00Z",
+                Duration::hours(2),
+                json_map! {"test": json!(1)}
+            )]
+        );
+
+        // The same event is only on the Sunday in UTC
+        let (weekday, weekend) = split_weekday_weekend(&events, &Utc);
+        assert!(weekday.is_empty());
+        assert_eq!(weekend, events);
+    }
+}